@@ -63,7 +63,7 @@ fn main() {
 
                 mesh = b4d.create_global_mesh(&data);
 
-                if let Some(mut recorder) = b4d.try_start_frame(current_size) {
+                if let Some(mut recorder) = b4d.try_start_frame(current_size).unwrap() {
 
                     recorder.update_uniform(&McUniformData::ProjectionMatrix(make_projection_matrix(current_size, 90f32)), shader);
 
@@ -141,6 +141,19 @@ tasks.register<CompileShaders>("compileUtilsShaders") {
         source("blit.frag")
         output("blit_frag.spv")
     });
+
+    addModule("MipmapFloat", {
+        source("mipmap_float.comp")
+        output("mipmap_float_comp.spv")
+    });
+    addModule("MipmapUint", {
+        source("mipmap_uint.comp")
+        output("mipmap_uint_comp.spv")
+    });
+    addModule("MipmapSint", {
+        source("mipmap_sint.comp")
+        output("mipmap_sint_comp.spv")
+    });
 }
 
 tasks.register<CompileShaders>("compileEmulatorShaders") {
@@ -12,5 +12,6 @@ layout(location=0) out vec4 out_color;
 
 void main() {
     gl_Position = mc_transform_position(in_position);
+    gl_PointSize = mc_point_size();
     out_color = in_color;
 }
\ No newline at end of file
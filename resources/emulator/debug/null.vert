@@ -11,5 +11,6 @@ layout(location=0) out vec4 out_color;
 
 void main() {
     gl_Position = mc_transform_position(in_position);
+    gl_PointSize = mc_point_size();
     out_color = vec4(0.0, 0.0, 0.0, 0.0);
 }
\ No newline at end of file
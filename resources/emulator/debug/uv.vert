@@ -13,6 +13,7 @@ layout(location=1) out vec2 out_uv;
 
 void main() {
     gl_Position = mc_transform_position(in_position);
+    gl_PointSize = mc_point_size();
     out_color = vec4(in_uv, 0.0, 1.0);
     out_uv = in_uv;
 }
\ No newline at end of file
@@ -24,6 +24,11 @@ uniform McSet1Binding0 {
     float line_width;
 } mc_set_1_binding_0;*/
 
+/**
+ * The size of points drawn with a point list topology. Set by the pipeline.
+ */
+layout(constant_id=16) const float _mc_point_size = 1.0;
+
 layout(push_constant)
 uniform _PushConstant {
     mat4 model_view_matrix;
@@ -87,6 +92,10 @@ float mc_game_time() {
     return _mc_static_uniforms.fog_range_and_game_time.z;
 }*/
 
+float mc_point_size() {
+    return _mc_point_size;
+}
+
 vec3 mc_chunk_offset() {
     return _push_constant.chunk_offset;
 }
@@ -0,0 +1,34 @@
+// Shared body of the mipmap generation compute shaders. Each invocation writes a single texel of
+// the destination mip level by averaging the 2x2 block of texels of the source mip level it
+// covers (box filter). For odd source sizes the last row or column is clamped to the edge.
+//
+// The including shader must define SRC_IMAGE_TYPE, DST_IMAGE_TYPE and VALUE_TYPE matching the
+// numeric type of the image format.
+
+layout(local_size_x=8, local_size_y=8, local_size_z=1) in;
+
+layout(set=0, binding=0) uniform SRC_IMAGE_TYPE src_image;
+layout(set=0, binding=1) writeonly uniform DST_IMAGE_TYPE dst_image;
+
+VALUE_TYPE load_src(ivec2 position, ivec2 src_max, int layer) {
+    return texelFetch(src_image, ivec3(min(position, src_max), layer), 0);
+}
+
+void main() {
+    ivec3 dst_size = imageSize(dst_image);
+    ivec3 dst_position = ivec3(gl_GlobalInvocationID);
+    if (dst_position.x >= dst_size.x || dst_position.y >= dst_size.y) {
+        return;
+    }
+
+    ivec2 src_max = textureSize(src_image, 0).xy - ivec2(1);
+    ivec2 src_position = dst_position.xy * 2;
+    int layer = dst_position.z;
+
+    VALUE_TYPE sum = load_src(src_position, src_max, layer)
+        + load_src(src_position + ivec2(1, 0), src_max, layer)
+        + load_src(src_position + ivec2(0, 1), src_max, layer)
+        + load_src(src_position + ivec2(1, 1), src_max, layer);
+
+    imageStore(dst_image, dst_position, sum / VALUE_TYPE(4));
+}
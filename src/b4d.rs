@@ -7,16 +7,16 @@ use crate::BUILD_INFO;
 
 use crate::instance::debug_messenger::RustLogDebugMessenger;
 use crate::device::init::{create_device, DeviceCreateConfig};
-use crate::device::surface::{DeviceSurface, SurfaceSwapchain, SwapchainConfig};
+use crate::device::surface::{CompositeAlphaPreference, DeviceSurface, SurfaceSwapchain, SwapchainConfig, SwapchainCreateError};
 use crate::instance::init::{create_instance, InstanceCreateConfig};
 use crate::vk::objects::surface::SurfaceProvider;
 
 use crate::prelude::*;
-use crate::renderer::emulator::{EmulatorRenderer, GlobalImage, GlobalMesh, MeshData};
+use crate::renderer::emulator::{EmulatorRenderer, GlobalImage, GlobalMesh, ImmediatePoolConfig, MeshData, WorkerConfig};
 use crate::renderer::emulator::debug_pipeline::{DebugPipeline, DebugPipelineMode};
 use crate::renderer::emulator::mc_shaders::{McUniform, ShaderId, VertexFormat};
 use crate::renderer::emulator::PassRecorder;
-use crate::renderer::emulator::pipeline::{EmulatorPipeline, SwapchainOutput};
+use crate::renderer::emulator::pipeline::{EmulatorPipeline, PresentError, SwapchainOutput};
 use crate::util::format::Format;
 
 pub struct Blaze4D {
@@ -31,7 +31,21 @@ impl Blaze4D {
     /// Creates a new Blaze4D instance and starts all engine modules.
     ///
     /// The supported vertex formats for the [`EmulatorRenderer`] must be provided here.
-    pub fn new(mut main_window: Box<dyn SurfaceProvider>, enable_validation: bool) -> Self {
+    pub fn new(main_window: Box<dyn SurfaceProvider>, enable_validation: bool) -> Self {
+        Self::new_with_immediate_pool(main_window, enable_validation, &ImmediatePoolConfig::default())
+    }
+
+    /// Creates a new Blaze4D instance using the specified immediate buffer pool configuration.
+    ///
+    /// Larger or more buffers avoid reallocations and stalls when many immediate meshes are drawn
+    /// at the cost of memory. See [`ImmediatePoolConfig`] for details.
+    pub fn new_with_immediate_pool(main_window: Box<dyn SurfaceProvider>, enable_validation: bool, immediate_pool: &ImmediatePoolConfig) -> Self {
+        Self::new_with_config(main_window, enable_validation, immediate_pool, &WorkerConfig::default())
+    }
+
+    /// Creates a new Blaze4D instance using the specified immediate buffer pool and emulator
+    /// worker thread configuration. See [`WorkerConfig`] for details.
+    pub fn new_with_config(mut main_window: Box<dyn SurfaceProvider>, enable_validation: bool, immediate_pool: &ImmediatePoolConfig, worker: &WorkerConfig) -> Self {
         log::info!("Creating Blaze4D instance {:?}", BUILD_INFO);
 
         let mut instance_config = InstanceCreateConfig::new(
@@ -42,7 +56,11 @@ impl Blaze4D {
             instance_config.enable_validation();
         }
         instance_config.add_debug_messenger(Box::new(RustLogDebugMessenger::new()));
-        for ext in main_window.get_required_instance_extensions() {
+        let required_extensions = main_window.get_required_instance_extensions().unwrap_or_else(|err| {
+            log::error!("Failed to query required surface extensions in Blaze4D::new(): {:?}", err);
+            panic!()
+        });
+        for ext in required_extensions {
             instance_config.add_required_extension(&ext);
         }
 
@@ -61,7 +79,7 @@ impl Blaze4D {
         });
         let main_surface = DeviceSurface::new(device.get_functions().clone(), main_window);
 
-        let emulator = Arc::new(EmulatorRenderer::new(device.clone()));
+        let emulator = Arc::new(EmulatorRenderer::new(device.clone(), immediate_pool, worker));
 
         let render_config = Mutex::new(RenderConfig::new(device.clone(), emulator.clone(), main_surface));
 
@@ -82,6 +100,16 @@ impl Blaze4D {
         self.render_config.lock().unwrap().set_debug_mode(mode);
     }
 
+    /// Configures additional usage flags for the images of the main window swapchain. For example
+    /// [`vk::ImageUsageFlags::TRANSFER_DST`] is needed to blit into swapchain images.
+    ///
+    /// [`vk::ImageUsageFlags::COLOR_ATTACHMENT`] is always enabled. If the surface does not support
+    /// the requested flags [`SwapchainCreateError::Unsupported`] is returned and the current flags
+    /// are kept. The swapchain is rebuilt on the next frame if the flags changed.
+    pub fn set_swapchain_usage(&self, usage: vk::ImageUsageFlags) -> Result<(), SwapchainCreateError> {
+        self.render_config.lock().unwrap().set_swapchain_usage(usage)
+    }
+
     pub fn create_global_mesh(&self, data: &MeshData) -> Arc<GlobalMesh> {
         self.emulator.create_global_mesh(data)
     }
@@ -98,15 +126,31 @@ impl Blaze4D {
         self.emulator.drop_shader(id);
     }
 
-    pub fn try_start_frame(&self, window_size: Vec2u32) -> Option<PassRecorder> {
-        if let Some(recorder) = self.render_config.lock().unwrap().try_start_frame(&self.emulator, window_size) {
-            Some(recorder)
-        } else {
-            None
-        }
+    /// Returns true if frames can currently be rendered to the main window.
+    ///
+    /// This is false while the window is minimized. [`Blaze4D::try_start_frame`] will return
+    /// [`None`] in this state so callers can poll this to avoid busy looping.
+    pub fn is_renderable(&self) -> bool {
+        self.render_config.lock().unwrap().main_surface.is_renderable()
+    }
+
+    /// Starts a new frame rendering to the main window.
+    ///
+    /// Returns [`None`] if no frame can be rendered right now, for example because the window is
+    /// minimized or the swapchain is being rebuilt. Returns [`FrameError::DeviceLost`] if the
+    /// device has been lost in which case no further frames can be rendered.
+    pub fn try_start_frame(&self, window_size: Vec2u32) -> Result<Option<PassRecorder>, FrameError> {
+        self.render_config.lock().unwrap().try_start_frame(&self.emulator, window_size)
     }
 }
 
+/// Errors returned by [`Blaze4D::try_start_frame`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FrameError {
+    /// The device has been lost. The Blaze4D instance must be recreated.
+    DeviceLost,
+}
+
 struct RenderConfig {
     device: Arc<DeviceContext>,
     emulator: Arc<EmulatorRenderer>,
@@ -116,6 +160,8 @@ struct RenderConfig {
     current_swapchain: Option<Arc<SurfaceSwapchain>>,
     current_pipeline: Option<(Arc<dyn EmulatorPipeline>, Arc<SwapchainOutput>)>,
 
+    swapchain_usage: vk::ImageUsageFlags,
+
     debug_mode: Option<DebugPipelineMode>,
     debug_pipeline: Option<(Arc<dyn EmulatorPipeline>, Arc<SwapchainOutput>)>,
 }
@@ -131,6 +177,8 @@ impl RenderConfig {
             current_swapchain: None,
             current_pipeline: None,
 
+            swapchain_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+
             debug_mode: Some(DebugPipelineMode::Color),
             debug_pipeline: None
         }
@@ -143,7 +191,31 @@ impl RenderConfig {
         }
     }
 
-    fn try_start_frame(&mut self, renderer: &EmulatorRenderer, size: Vec2u32) -> Option<PassRecorder> {
+    fn set_swapchain_usage(&mut self, usage: vk::ImageUsageFlags) -> Result<(), SwapchainCreateError> {
+        let usage = usage | vk::ImageUsageFlags::COLOR_ATTACHMENT;
+
+        let supported = self.main_surface.get_image_capabilities()?.supported_usage_flags;
+        if !supported.contains(usage) {
+            log::warn!("Requested swapchain usage {:?} but surface only supports {:?}", usage, supported);
+            return Err(SwapchainCreateError::Unsupported);
+        }
+
+        if self.swapchain_usage != usage {
+            self.swapchain_usage = usage;
+            self.current_pipeline = None;
+            self.debug_pipeline = None;
+            self.current_swapchain = None;
+        }
+        Ok(())
+    }
+
+    fn try_start_frame(&mut self, renderer: &EmulatorRenderer, size: Vec2u32) -> Result<Option<PassRecorder>, FrameError> {
+        // A minimized window has a zero extent and no swapchain can be created for it. We keep the
+        // current swapchain and skip the frame until the window is restored.
+        if size[0] == 0 || size[1] == 0 || !self.main_surface.is_renderable() {
+            return Ok(None);
+        }
+
         let mut force_rebuild = false;
 
         // This if block only exists because of wayland
@@ -155,7 +227,7 @@ impl RenderConfig {
 
         if self.current_swapchain.is_none() || force_rebuild {
             if !self.try_create_swapchain(size) {
-                return None;
+                return Ok(None);
             }
             self.current_pipeline = None;
             self.debug_pipeline = None;
@@ -164,13 +236,19 @@ impl RenderConfig {
         let (pipeline, output) = self.prepare_pipeline(size);
 
         let (output, suboptimal) = match output.next_image() {
-            None => {
+            Ok(result) => result,
+            Err(PresentError::Timeout) => return Ok(None),
+            Err(PresentError::DeviceLost) => {
+                log::error!("Device lost while presenting");
+                return Err(FrameError::DeviceLost);
+            }
+            Err(err) => {
+                log::info!("Rebuilding swapchain after present error {:?}", err);
                 self.current_pipeline = None;
                 self.debug_pipeline = None;
                 self.current_swapchain = None;
-                return None;
+                return Ok(None);
             }
-            Some(result) => result,
         };
 
         let mut recorder = renderer.start_pass(pipeline.clone());
@@ -182,7 +260,7 @@ impl RenderConfig {
             self.current_swapchain = None;
         }
 
-        Some(recorder)
+        Ok(Some(recorder))
     }
 
     fn prepare_pipeline(&mut self, output_size: Vec2u32) -> (Arc<dyn EmulatorPipeline>, &Arc<SwapchainOutput>) {
@@ -219,9 +297,11 @@ impl RenderConfig {
                 vk::SurfaceFormatKHR{ format: vk::Format::R8G8B8A8_SRGB, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR },
                 vk::SurfaceFormatKHR{ format: vk::Format::B8G8R8A8_SRGB, color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR },
             ]),
-            required_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            required_usage: self.swapchain_usage,
             optional_usage: vk::ImageUsageFlags::empty(),
-            clipped: true
+            clipped: true,
+            full_screen_exclusive: false,
+            composite_alpha: CompositeAlphaPreference::Opaque,
         };
 
         match self.main_surface.create_swapchain(&config, size) {
@@ -212,7 +212,10 @@ impl CImageData {
             data: std::slice::from_raw_parts(self.data_ptr, self.data_ptr_len),
             row_stride: 0,
             offset: Vec2u32::new(self.offset[0], self.offset[1]),
-            extent: Vec2u32::new(self.extent[0], self.extent[1])
+            extent: Vec2u32::new(self.extent[0], self.extent[1]),
+            layer: 0,
+            mip_level: 0,
+            encoding: None,
         }
     }
 }
@@ -307,7 +310,10 @@ impl CSamplerInfo {
             mipmap_mode: vk::SamplerMipmapMode::from_raw(self.mipmap_mode),
             address_mode_u: vk::SamplerAddressMode::from_raw(self.address_mode_u),
             address_mode_v: vk::SamplerAddressMode::from_raw(self.address_mode_v),
-            anisotropy_enable: self.anisotropy_enable != 0,
+            // The maximum supported anisotropy is used if enabled
+            max_anisotropy: if self.anisotropy_enable != 0 { Some(f32::MAX) } else { None },
+            mip_lod_bias: 0f32,
+            compare: None,
         }
     }
 }
@@ -439,7 +445,10 @@ unsafe extern "C" fn b4d_update_global_image(image: *mut Arc<GlobalImage>, write
         let writes = std::slice::from_raw_parts(writes, count as usize);
         let writes: Box<_> = writes.iter().map(|w| w.to_image_data()).collect();
 
-        image.update_regions(writes.as_ref());
+        if let Err(err) = image.update_regions(writes.as_ref()) {
+            log::error!("Failed to update global image in b4d_update_global_image {:?}", err);
+            exit(1);
+        }
     }).unwrap_or_else(|_| {
         log::error!("panic in b4d_update_global_image");
         exit(1);
@@ -497,9 +506,26 @@ unsafe extern "C" fn b4d_destroy_shader(b4d: *const Blaze4D, shader_id: u64) {
     })
 }
 
+/// Calls [`Blaze4D::is_renderable`].
+#[no_mangle]
+unsafe extern "C" fn b4d_is_renderable(b4d: *const Blaze4D) -> bool {
+    catch_unwind(|| {
+        let b4d = b4d.as_ref().unwrap_or_else(|| {
+            log::error!("Passed null b4d to b4d_is_renderable");
+            exit(1);
+        });
+
+        b4d.is_renderable()
+    }).unwrap_or_else(|_| {
+        log::error!("panic in b4d_is_renderable");
+        exit(1);
+    })
+}
+
 /// Calls [`Blaze4D::try_start_frame`].
 ///
-/// If [`Blaze4D::try_start_frame`] returns [`None`] this function returns null.
+/// If [`Blaze4D::try_start_frame`] returns [`None`] this function returns null. If the device has
+/// been lost the process is terminated.
 #[no_mangle]
 unsafe extern "C" fn b4d_start_frame(b4d: *mut Blaze4D, window_width: u32, window_height: u32) -> *mut PassRecorder {
     catch_unwind(|| {
@@ -508,7 +534,10 @@ unsafe extern "C" fn b4d_start_frame(b4d: *mut Blaze4D, window_width: u32, windo
             exit(1);
         });
 
-        let frame = b4d.try_start_frame(Vec2u32::new(window_width, window_height));
+        let frame = b4d.try_start_frame(Vec2u32::new(window_width, window_height)).unwrap_or_else(|err| {
+            log::error!("Failed to start frame in b4d_start_frame: {:?}", err);
+            exit(1);
+        });
         frame.map_or(std::ptr::null_mut(), |recorder| {
             Box::leak(Box::new(recorder))
         })
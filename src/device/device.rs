@@ -1,7 +1,9 @@
 use core::panic::{UnwindSafe, RefUnwindSafe};
 
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::AtomicU64;
 use ash::prelude::VkResult;
 
 use ash::vk;
@@ -12,16 +14,159 @@ use crate::instance::instance::InstanceContext;
 use crate::vk::objects::allocator::Allocator;
 
 use crate::prelude::*;
+use crate::util::format::{Format, select_depth_format};
 
 pub struct DeviceFunctions {
     pub instance: Arc<InstanceContext>,
     pub physical_device: vk::PhysicalDevice,
     pub vk: ash::Device,
-    pub synchronization_2_khr: ash::extensions::khr::Synchronization2,
+    pub synchronization_2: Synchronization2,
+    /// The dynamic rendering functions. [`None`] if the device uses render pass objects.
+    pub dynamic_rendering: Option<DynamicRendering>,
     pub timeline_semaphore_khr: ash::extensions::khr::TimelineSemaphore,
     pub push_descriptor_khr: ash::extensions::khr::PushDescriptor,
     pub swapchain_khr: Option<ash::extensions::khr::Swapchain>,
     pub maintenance_4_khr: Option<ash::extensions::khr::Maintenance4>,
+    pub display_timing_google: Option<ash::extensions::google::DisplayTiming>,
+    /// True if VK_KHR_incremental_present is enabled. The extension does not add any functions.
+    pub incremental_present_khr: bool,
+    /// True if VK_EXT_full_screen_exclusive is enabled. Only the swapchain create info extension
+    /// is used so no functions are loaded.
+    pub full_screen_exclusive_ext: bool,
+    /// True if VK_EXT_memory_budget is enabled. The extension does not add any functions.
+    pub memory_budget_ext: bool,
+    /// True if VK_EXT_conservative_rasterization is enabled. Only pipeline create info extensions
+    /// are used so no functions are loaded.
+    pub conservative_rasterization_ext: bool,
+    /// True if the sparseBinding and sparseResidencyImage2D features are enabled and the main
+    /// queue supports sparse binding operations.
+    pub sparse_residency_image_2d: bool,
+    /// True if the shaderStorageImageWriteWithoutFormat feature is enabled.
+    pub storage_image_write_without_format: bool,
+    pub main_queue_family: u32,
+    /// The queue family of the present queue if it differs from the main queue family.
+    pub present_queue_family: Option<u32>,
+}
+
+/// How synchronization2 functionality is provided by the device.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Sync2Mode {
+    /// The core Vulkan 1.3 functions are used.
+    Native,
+    /// The functions of VK_KHR_synchronization2 are used.
+    Extension,
+}
+
+/// Dispatches synchronization2 commands to either the Vulkan 1.3 core functions or the
+/// VK_KHR_synchronization2 functions depending on the [`Sync2Mode`] of the device.
+pub struct Synchronization2 {
+    vk: ash::Device,
+    khr: Option<ash::extensions::khr::Synchronization2>,
+}
+
+impl Synchronization2 {
+    pub fn new(instance: &ash::Instance, device: &ash::Device, mode: Sync2Mode) -> Self {
+        let khr = match mode {
+            Sync2Mode::Native => None,
+            Sync2Mode::Extension => Some(ash::extensions::khr::Synchronization2::new(instance, device)),
+        };
+
+        Self {
+            vk: device.clone(),
+            khr,
+        }
+    }
+
+    pub fn get_mode(&self) -> Sync2Mode {
+        if self.khr.is_some() {
+            Sync2Mode::Extension
+        } else {
+            Sync2Mode::Native
+        }
+    }
+
+    pub unsafe fn cmd_pipeline_barrier2(&self, command_buffer: vk::CommandBuffer, dependency_info: &vk::DependencyInfo) {
+        match &self.khr {
+            Some(khr) => khr.cmd_pipeline_barrier2(command_buffer, dependency_info),
+            None => self.vk.cmd_pipeline_barrier2(command_buffer, dependency_info),
+        }
+    }
+
+    pub unsafe fn cmd_write_timestamp2(&self, command_buffer: vk::CommandBuffer, stage: vk::PipelineStageFlags2, query_pool: vk::QueryPool, query: u32) {
+        match &self.khr {
+            Some(khr) => khr.cmd_write_timestamp2(command_buffer, stage, query_pool, query),
+            None => self.vk.cmd_write_timestamp2(command_buffer, stage, query_pool, query),
+        }
+    }
+
+    pub unsafe fn queue_submit2(&self, queue: vk::Queue, submits: &[vk::SubmitInfo2], fence: vk::Fence) -> VkResult<()> {
+        match &self.khr {
+            Some(khr) => khr.queue_submit2(queue, submits, fence),
+            None => self.vk.queue_submit2(queue, submits, fence),
+        }
+    }
+}
+
+/// How render pass instances are recorded on the device.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RenderingMode {
+    /// Dynamic rendering is provided by Vulkan 1.3.
+    DynamicRenderingNative,
+    /// Dynamic rendering is provided by VK_KHR_dynamic_rendering.
+    DynamicRenderingExtension,
+    /// Dynamic rendering is not available so render pass and framebuffer objects are used.
+    RenderPass,
+}
+
+impl RenderingMode {
+    pub fn is_dynamic_rendering(&self) -> bool {
+        *self != RenderingMode::RenderPass
+    }
+}
+
+/// Dispatches dynamic rendering commands to either the Vulkan 1.3 core functions or the
+/// VK_KHR_dynamic_rendering functions depending on the [`RenderingMode`] of the device.
+pub struct DynamicRendering {
+    vk: ash::Device,
+    khr: Option<ash::extensions::khr::DynamicRendering>,
+}
+
+impl DynamicRendering {
+    /// Returns [`None`] if the mode is [`RenderingMode::RenderPass`].
+    pub fn new(instance: &ash::Instance, device: &ash::Device, mode: RenderingMode) -> Option<Self> {
+        let khr = match mode {
+            RenderingMode::DynamicRenderingNative => None,
+            RenderingMode::DynamicRenderingExtension => Some(ash::extensions::khr::DynamicRendering::new(instance, device)),
+            RenderingMode::RenderPass => return None,
+        };
+
+        Some(Self {
+            vk: device.clone(),
+            khr,
+        })
+    }
+
+    pub fn get_mode(&self) -> RenderingMode {
+        if self.khr.is_some() {
+            RenderingMode::DynamicRenderingExtension
+        } else {
+            RenderingMode::DynamicRenderingNative
+        }
+    }
+
+    pub unsafe fn cmd_begin_rendering(&self, command_buffer: vk::CommandBuffer, rendering_info: &vk::RenderingInfo) {
+        match &self.khr {
+            Some(khr) => khr.cmd_begin_rendering(command_buffer, rendering_info),
+            None => self.vk.cmd_begin_rendering(command_buffer, rendering_info),
+        }
+    }
+
+    pub unsafe fn cmd_end_rendering(&self, command_buffer: vk::CommandBuffer) {
+        match &self.khr {
+            Some(khr) => khr.cmd_end_rendering(command_buffer),
+            None => self.vk.cmd_end_rendering(command_buffer),
+        }
+    }
 }
 
 impl Drop for DeviceFunctions {
@@ -32,37 +177,123 @@ impl Drop for DeviceFunctions {
     }
 }
 
+/// Limits which apply to samplers created on a device.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SamplerLimits {
+    /// The maximum supported anisotropy or [`None`] if the device does not support anisotropic
+    /// filtering.
+    pub max_anisotropy: Option<f32>,
+
+    /// The absolute maximum mip lod bias. Valid biases are in the range
+    /// `[-max_lod_bias, max_lod_bias]`.
+    pub max_lod_bias: f32,
+
+    /// The maximum number of samplers which may exist at the same time.
+    pub max_allocation_count: u32,
+
+    /// The maximum number of samplers accessible by a single shader stage.
+    pub max_per_stage_samplers: u32,
+}
+
+impl SamplerLimits {
+    /// Clamps the requested anisotropy to the supported range. Returns [`None`] if anisotropic
+    /// filtering is not supported.
+    pub fn clamp_anisotropy(&self, anisotropy: f32) -> Option<f32> {
+        self.max_anisotropy.map(|max| anisotropy.clamp(1f32, max))
+    }
+
+    /// Clamps the requested mip lod bias to the supported range.
+    pub fn clamp_lod_bias(&self, bias: f32) -> f32 {
+        bias.clamp(-self.max_lod_bias, self.max_lod_bias)
+    }
+}
+
+/// A snapshot of the number of live resources created through a device. Intended for debug overlays
+/// and to detect leaks.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct ResourceStats {
+    /// The number of buffers with memory from the device allocator.
+    pub buffers: u64,
+
+    /// The number of images with memory from the device allocator. Each page of a sparse image is
+    /// counted separately.
+    pub images: u64,
+
+    /// The number of samplers cached by global images. Samplers owned by the device utilities
+    /// ([`BlitUtils`] and [`MipmapUtils`]) are not counted.
+    ///
+    /// [`BlitUtils`]: crate::device::device_utils::BlitUtils
+    /// [`MipmapUtils`]: crate::device::device_utils::MipmapUtils
+    pub samplers: u64,
+
+    /// The number of shaders registered with the emulator.
+    pub shaders: u64,
+
+    /// The number of graphics pipelines created by emulator pipelines. Pipelines created by the
+    /// device utilities ([`BlitUtils`], [`MipmapUtils`] and their passes) are not counted.
+    ///
+    /// [`BlitUtils`]: crate::device::device_utils::BlitUtils
+    /// [`MipmapUtils`]: crate::device::device_utils::MipmapUtils
+    pub pipelines: u64,
+
+    /// The total size of all live allocations of the device allocator in bytes.
+    pub allocated_memory: vk::DeviceSize,
+}
+
+/// Objects without a memory allocation counted in [`ResourceStats`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum TrackedObject {
+    Sampler,
+    Shader,
+    Pipeline,
+}
+
 pub struct DeviceContext {
     id: NamedUUID,
     functions: Arc<DeviceFunctions>,
     main_queue: Arc<Queue>,
+    present_queue: Option<Arc<Queue>>,
     async_compute_queue: Option<Arc<Queue>>,
     async_transfer_queue: Option<Arc<Queue>>,
     allocator: Arc<Allocator>,
     transfer: Arc<Transfer>,
     utils: Arc<DeviceUtils>,
+    properties: vk::PhysicalDeviceProperties,
+    features: vk::PhysicalDeviceFeatures,
+    format_properties: Mutex<HashMap<vk::Format, vk::FormatProperties>>,
+    tracked_objects: [AtomicU64; 3],
 }
 
 impl DeviceContext {
     pub(crate) fn new(
         functions: Arc<DeviceFunctions>,
         main_queue: Arc<Queue>,
+        present_queue: Option<Arc<Queue>>,
         async_compute_queue: Option<Arc<Queue>>,
         async_transfer_queue: Option<Arc<Queue>>,
+        features: vk::PhysicalDeviceFeatures,
     ) -> Arc<Self> {
         let allocator = Arc::new(Allocator::new(functions.clone()));
         let transfer = Transfer::new(functions.clone(), allocator.clone(), async_transfer_queue.as_ref().unwrap_or(&main_queue).clone());
         let utils = DeviceUtils::new(functions.clone(), allocator.clone());
+        let properties = unsafe {
+            functions.instance.vk().get_physical_device_properties(functions.physical_device)
+        };
 
         Arc::new(Self {
             id: NamedUUID::with_str("Device"),
             functions,
             main_queue,
+            present_queue,
             async_compute_queue,
             async_transfer_queue,
             allocator,
             transfer,
-            utils
+            utils,
+            properties,
+            features,
+            format_properties: Mutex::new(HashMap::new()),
+            tracked_objects: Default::default(),
         })
     }
 
@@ -86,8 +317,26 @@ impl DeviceContext {
         &self.functions.vk
     }
 
-    pub fn synchronization_2_khr(&self) -> &ash::extensions::khr::Synchronization2 {
-        &self.functions.synchronization_2_khr
+    pub fn synchronization_2(&self) -> &Synchronization2 {
+        &self.functions.synchronization_2
+    }
+
+    /// Returns whether synchronization2 is provided by Vulkan 1.3 or VK_KHR_synchronization2.
+    /// All synchronization2 commands should be recorded through
+    /// [`DeviceContext::synchronization_2`] which dispatches to the correct functions.
+    pub fn synchronization2_mode(&self) -> Sync2Mode {
+        self.functions.synchronization_2.get_mode()
+    }
+
+    /// Returns the dynamic rendering functions or [`None`] if the device uses render pass objects.
+    pub fn dynamic_rendering(&self) -> Option<&DynamicRendering> {
+        self.functions.dynamic_rendering.as_ref()
+    }
+
+    /// Returns whether render pass instances are recorded using dynamic rendering or render pass
+    /// objects. The mode is selected during device creation.
+    pub fn rendering_mode(&self) -> RenderingMode {
+        self.functions.dynamic_rendering.as_ref().map(DynamicRendering::get_mode).unwrap_or(RenderingMode::RenderPass)
     }
 
     pub fn timeline_semaphore_khr(&self) -> &ash::extensions::khr::TimelineSemaphore {
@@ -106,10 +355,59 @@ impl DeviceContext {
         self.functions.maintenance_4_khr.as_ref()
     }
 
+    /// Returns the VK_GOOGLE_display_timing functions if the extension is enabled.
+    pub fn display_timing_google(&self) -> Option<&ash::extensions::google::DisplayTiming> {
+        self.functions.display_timing_google.as_ref()
+    }
+
+    /// Returns true if VK_KHR_incremental_present is enabled.
+    pub fn supports_incremental_present(&self) -> bool {
+        self.functions.incremental_present_khr
+    }
+
+    /// Returns true if VK_EXT_full_screen_exclusive is enabled.
+    pub fn supports_full_screen_exclusive(&self) -> bool {
+        self.functions.full_screen_exclusive_ext
+    }
+
+    /// Returns true if VK_EXT_memory_budget is enabled.
+    pub fn supports_memory_budget(&self) -> bool {
+        self.functions.memory_budget_ext
+    }
+
+    /// Returns true if VK_EXT_conservative_rasterization is enabled.
+    pub fn supports_conservative_rasterization(&self) -> bool {
+        self.functions.conservative_rasterization_ext
+    }
+
+    /// Returns true if sparse 2D images can be created and bound using the main queue.
+    pub fn supports_sparse_residency_image_2d(&self) -> bool {
+        self.functions.sparse_residency_image_2d
+    }
+
+    /// Returns true if shaders can write to storage images declared without a format.
+    pub fn supports_storage_image_write_without_format(&self) -> bool {
+        self.functions.storage_image_write_without_format
+    }
+
     pub fn get_main_queue(&self) -> &Arc<Queue> {
         &self.main_queue
     }
 
+    /// Returns the queue which must be used for presentation. This is the main queue unless the
+    /// main queue family does not support presentation to all surfaces.
+    pub fn get_present_queue(&self) -> &Arc<Queue> {
+        self.present_queue.as_ref().unwrap_or(&self.main_queue)
+    }
+
+    /// Returns true if presentation uses a different queue family than the main queue.
+    ///
+    /// In this case swapchain images are created with [`vk::SharingMode::CONCURRENT`] so no queue
+    /// family ownership transfers are necessary.
+    pub fn has_separate_present_queue(&self) -> bool {
+        self.present_queue.is_some()
+    }
+
     pub fn get_async_compute_queue(&self) -> Option<&Arc<Queue>> {
         self.async_compute_queue.as_ref()
     }
@@ -129,6 +427,84 @@ impl DeviceContext {
     pub fn get_utils(&self) -> &Arc<DeviceUtils> {
         &self.utils
     }
+
+    pub fn get_properties(&self) -> &vk::PhysicalDeviceProperties {
+        &self.properties
+    }
+
+    pub fn get_limits(&self) -> &vk::PhysicalDeviceLimits {
+        &self.properties.limits
+    }
+
+    /// Returns the sampler related limits of the device.
+    pub fn sampler_limits(&self) -> SamplerLimits {
+        let limits = self.get_limits();
+        SamplerLimits {
+            max_anisotropy: if self.features.sampler_anisotropy == vk::TRUE { Some(limits.max_sampler_anisotropy) } else { None },
+            max_lod_bias: limits.max_sampler_lod_bias,
+            max_allocation_count: limits.max_sampler_allocation_count,
+            max_per_stage_samplers: limits.max_per_stage_descriptor_samplers,
+        }
+    }
+
+    /// Returns the core features enabled on the device. These are the features required by the
+    /// used profile and the optional features used by Blaze4D if they are supported.
+    pub fn get_features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.features
+    }
+
+    /// Returns the format properties of the physical device for some format.
+    ///
+    /// The results are cached so repeated calls are cheap.
+    pub fn get_format_properties(&self, format: &Format) -> vk::FormatProperties {
+        let mut guard = self.format_properties.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned format properties mutex in DeviceContext::get_format_properties");
+            panic!()
+        });
+
+        *guard.entry(format.get_format()).or_insert_with(|| unsafe {
+            self.functions.instance.vk().get_physical_device_format_properties(self.functions.physical_device, format.get_format())
+        })
+    }
+
+    /// Returns the first format in `preferences` which supports being used as a depth attachment
+    /// and being sampled with optimal tiling. If none is supported a default chain of depth formats is tried with
+    /// formats that have a stencil aspect tried first if `prefer_stencil` is true.
+    ///
+    /// See [`select_depth_format`] for details.
+    pub fn choose_depth_format(&self, prefer_stencil: bool, preferences: &[&'static Format]) -> Option<&'static Format> {
+        select_depth_format(prefer_stencil, preferences, |format| {
+            self.get_format_properties(format).optimal_tiling_features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE)
+        })
+    }
+
+    /// Returns the number of live resources and the total allocated memory.
+    ///
+    /// The values are read independently of each other so they may be slightly inconsistent if
+    /// resources are created or destroyed concurrently.
+    pub fn resource_stats(&self) -> ResourceStats {
+        let allocation_stats = self.allocator.get_allocation_stats();
+        let load = |object: TrackedObject| self.tracked_objects[object as usize].load(std::sync::atomic::Ordering::Relaxed);
+
+        ResourceStats {
+            buffers: allocation_stats.buffer_allocations,
+            images: allocation_stats.image_allocations,
+            samplers: load(TrackedObject::Sampler),
+            shaders: load(TrackedObject::Shader),
+            pipelines: load(TrackedObject::Pipeline),
+            allocated_memory: allocation_stats.allocated_memory,
+        }
+    }
+
+    /// Must be called whenever an object counted in [`ResourceStats`] is created.
+    pub(crate) fn on_object_created(&self, object: TrackedObject) {
+        self.tracked_objects[object as usize].fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Must be called whenever an object counted in [`ResourceStats`] is destroyed.
+    pub(crate) fn on_object_destroyed(&self, object: TrackedObject) {
+        self.tracked_objects[object as usize].fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+    }
 }
 
 impl PartialEq for DeviceContext {
@@ -184,7 +560,7 @@ impl Queue {
         let fence = fence.unwrap_or(vk::Fence::null());
 
         let queue = self.queue.lock().unwrap();
-        self.functions.synchronization_2_khr.queue_submit2(*queue, submits, fence)
+        self.functions.synchronization_2.queue_submit2(*queue, submits, fence)
     }
 
     pub unsafe fn wait_idle(&self) -> VkResult<()> {
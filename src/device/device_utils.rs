@@ -10,6 +10,7 @@ use include_bytes_aligned::include_bytes_aligned;
 use crate::vk::objects::allocator::Allocator;
 
 use crate::prelude::*;
+use crate::util::format::ClearColorType;
 
 pub fn create_shader_from_bytes(device: &DeviceFunctions, code: &[u8]) -> VkResult<vk::ShaderModule> {
     let info = vk::ShaderModuleCreateInfo::builder()
@@ -22,13 +23,15 @@ pub fn create_shader_from_bytes(device: &DeviceFunctions, code: &[u8]) -> VkResu
 
 pub struct DeviceUtils {
     blit_utils: BlitUtils,
+    mipmap_utils: MipmapUtils,
 }
 
 impl DeviceUtils {
     pub fn new(device: Arc<DeviceFunctions>, _: Arc<Allocator>) -> Arc<Self> {
         Arc::new_cyclic(|weak| {
             Self {
-                blit_utils: BlitUtils::new(weak.clone(), device)
+                blit_utils: BlitUtils::new(weak.clone(), device.clone()),
+                mipmap_utils: MipmapUtils::new(device),
             }
         })
     }
@@ -36,6 +39,10 @@ impl DeviceUtils {
     pub fn blit_utils(&self) -> &BlitUtils {
         &self.blit_utils
     }
+
+    pub fn mipmap_utils(&self) -> &MipmapUtils {
+        &self.mipmap_utils
+    }
 }
 
 pub struct BlitUtils {
@@ -68,44 +75,17 @@ impl BlitUtils {
     }
 
     pub fn create_blit_pass(&self, dst_format: vk::Format, load_op: vk::AttachmentLoadOp, initial_layout: vk::ImageLayout, final_layout: vk::ImageLayout) -> BlitPass {
-        let render_pass = self.create_render_pass(dst_format, load_op, initial_layout, final_layout);
-        let pipeline = self.create_pipeline(render_pass);
+        let pass = ColorAttachmentPass::new(self.device.clone(), dst_format, load_op, initial_layout, final_layout);
+        let pipeline = self.create_pipeline(&pass);
 
         BlitPass {
             utils: self.utils.upgrade().unwrap(),
-            render_pass,
+            pass,
             pipeline,
         }
     }
 
-    fn create_render_pass(&self, dst_format: vk::Format, load_op: vk::AttachmentLoadOp, initial_layout: vk::ImageLayout, final_layout: vk::ImageLayout) -> vk::RenderPass {
-        let attachment = vk::AttachmentDescription::builder()
-            .format(dst_format)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(load_op)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .initial_layout(initial_layout)
-            .final_layout(final_layout);
-
-        let attachment_reference = vk::AttachmentReference {
-            attachment: 0,
-            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
-        };
-
-        let subpass = vk::SubpassDescription::builder()
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(std::slice::from_ref(&attachment_reference));
-
-        let info = vk::RenderPassCreateInfo::builder()
-            .attachments(std::slice::from_ref(&attachment))
-            .subpasses(std::slice::from_ref(&subpass));
-
-        unsafe {
-            self.device.vk.create_render_pass(&info, None)
-        }.unwrap()
-    }
-
-    fn create_pipeline(&self, render_pass: vk::RenderPass) -> vk::Pipeline {
+    fn create_pipeline(&self, pass: &ColorAttachmentPass) -> vk::Pipeline {
         let shader_stages = [
             vk::PipelineShaderStageCreateInfo::builder()
                 .stage(vk::ShaderStageFlags::VERTEX)
@@ -172,7 +152,16 @@ impl BlitUtils {
             .color_blend_state(&color_blend)
             .dynamic_state(&dynamic_state)
             .layout(self.pipeline_layout)
-            .render_pass(render_pass);
+            .render_pass(pass.get_render_pass());
+
+        let color_formats = [pass.get_format()];
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::builder()
+            .color_attachment_formats(&color_formats);
+        let info = if pass.get_render_pass() == vk::RenderPass::null() {
+            info.push_next(&mut rendering_info)
+        } else {
+            info
+        };
 
         let pipeline = * unsafe {
             self.device.vk.create_graphics_pipelines(vk::PipelineCache::null(), std::slice::from_ref(&info), None)
@@ -235,9 +224,417 @@ impl Drop for BlitUtils {
     }
 }
 
+/// Generates mip levels using a compute shader for formats which do not support blits.
+///
+/// Each texel of a mip level is the average of the 2x2 texels of the previous level it covers
+/// (box filter). This is cheaper but of lower quality than the linear filtered blits used for
+/// other formats. For odd sizes the last row or column of the previous level is weighted twice
+/// instead of being distributed across the neighbouring texels, and integer formats are averaged
+/// using integer division which truncates towards zero. Large unsigned values may overflow.
+///
+/// Requires the shaderStorageImageWriteWithoutFormat feature. If it is not enabled no pipelines are
+/// created and [`MipmapUtils::is_supported`] returns false.
+pub struct MipmapUtils {
+    device: Arc<DeviceFunctions>,
+    sampler: vk::Sampler,
+    set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    /// The float, sint and uint pipelines in this order.
+    pipelines: Option<[vk::Pipeline; 3]>,
+}
+
+impl MipmapUtils {
+    const GROUP_SIZE: u32 = 8;
+
+    fn new(device: Arc<DeviceFunctions>) -> Self {
+        let sampler = Self::create_sampler(&device);
+        let set_layout = Self::create_descriptor_set_layout(&device, sampler);
+        let pipeline_layout = BlitUtils::create_pipeline_layout(&device, set_layout);
+
+        let pipelines = if device.storage_image_write_without_format {
+            Some([
+                Self::create_pipeline(&device, pipeline_layout, MIPMAP_FLOAT_COMPUTE_SHADER),
+                Self::create_pipeline(&device, pipeline_layout, MIPMAP_SINT_COMPUTE_SHADER),
+                Self::create_pipeline(&device, pipeline_layout, MIPMAP_UINT_COMPUTE_SHADER),
+            ])
+        } else {
+            None
+        };
+
+        Self {
+            device,
+            sampler,
+            set_layout,
+            pipeline_layout,
+            pipelines
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.pipelines.is_some()
+    }
+
+    /// Records the generation of a single mip level. No memory barriers are generated.
+    ///
+    /// The views must be 2D array views of a single mip level with `layer_count` layers. Both
+    /// images must be in the [`vk::ImageLayout::GENERAL`] layout. The source is read as a sampled
+    /// image and the destination written as a storage image in the COMPUTE_SHADER stage.
+    pub fn record_generate_level(&self, command_buffer: vk::CommandBuffer, numeric_type: ClearColorType, src_view: vk::ImageView, dst_view: vk::ImageView, dst_size: Vec2u32, layer_count: u32) {
+        let pipelines = self.pipelines.as_ref().unwrap_or_else(|| {
+            log::error!("Attempted to generate mipmaps using compute shaders but shaderStorageImageWriteWithoutFormat is not supported");
+            panic!()
+        });
+        let pipeline = match numeric_type {
+            ClearColorType::Float => pipelines[0],
+            ClearColorType::Int32 => pipelines[1],
+            ClearColorType::Uint32 => pipelines[2],
+        };
+
+        let src_info = vk::DescriptorImageInfo::builder()
+            .image_view(src_view)
+            .image_layout(vk::ImageLayout::GENERAL);
+        let dst_info = vk::DescriptorImageInfo::builder()
+            .image_view(dst_view)
+            .image_layout(vk::ImageLayout::GENERAL);
+
+        let writes = [
+            vk::WriteDescriptorSet::builder()
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(std::slice::from_ref(&src_info))
+                .build(),
+            vk::WriteDescriptorSet::builder()
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(std::slice::from_ref(&dst_info))
+                .build(),
+        ];
+
+        let group_count = |size: u32| (size + Self::GROUP_SIZE - 1) / Self::GROUP_SIZE;
+
+        unsafe {
+            self.device.vk.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+            self.device.push_descriptor_khr.cmd_push_descriptor_set(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline_layout, 0, &writes);
+            self.device.vk.cmd_dispatch(command_buffer, group_count(dst_size[0]), group_count(dst_size[1]), layer_count);
+        }
+    }
+
+    fn create_pipeline(device: &DeviceFunctions, layout: vk::PipelineLayout, code: &[u8]) -> vk::Pipeline {
+        let module = create_shader_from_bytes(device, code).unwrap();
+
+        let stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(module)
+            .name(CStr::from_bytes_with_nul(b"main\0").unwrap());
+
+        let info = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage.build())
+            .layout(layout);
+
+        let pipeline = *unsafe {
+            device.vk.create_compute_pipelines(vk::PipelineCache::null(), std::slice::from_ref(&info), None)
+        }.unwrap().get(0).unwrap();
+
+        unsafe {
+            device.vk.destroy_shader_module(module, None);
+        }
+
+        pipeline
+    }
+
+    fn create_sampler(device: &DeviceFunctions) -> vk::Sampler {
+        // Texels are only fetched so the filter is irrelevant
+        let info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .anisotropy_enable(false)
+            .compare_enable(false)
+            .unnormalized_coordinates(false);
+
+        unsafe {
+            device.vk.create_sampler(&info, None)
+        }.unwrap()
+    }
+
+    fn create_descriptor_set_layout(device: &DeviceFunctions, sampler: vk::Sampler) -> vk::DescriptorSetLayout {
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .immutable_samplers(std::slice::from_ref(&sampler))
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+        ];
+
+        let info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR)
+            .bindings(&bindings);
+
+        unsafe {
+            device.vk.create_descriptor_set_layout(&info, None)
+        }.unwrap()
+    }
+}
+
+impl Drop for MipmapUtils {
+    fn drop(&mut self) {
+        unsafe {
+            for pipeline in self.pipelines.iter().flatten() {
+                self.device.vk.destroy_pipeline(*pipeline, None);
+            }
+            self.device.vk.destroy_pipeline_layout(self.pipeline_layout, None);
+            self.device.vk.destroy_descriptor_set_layout(self.set_layout, None);
+            self.device.vk.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+/// A render pass instance drawing to a single color attachment.
+///
+/// If the device uses dynamic rendering no render pass object is created. The transitions from
+/// the initial layout and into the final layout are instead recorded as barriers by
+/// [`ColorAttachmentPass::begin`] and [`ColorAttachmentPass::end`] with the same synchronization
+/// scopes as the implicit external subpass dependencies of a render pass.
+pub struct ColorAttachmentPass {
+    device: Arc<DeviceFunctions>,
+    format: vk::Format,
+    load_op: vk::AttachmentLoadOp,
+    initial_layout: vk::ImageLayout,
+    final_layout: vk::ImageLayout,
+    /// Null if dynamic rendering is used.
+    render_pass: vk::RenderPass,
+}
+
+impl ColorAttachmentPass {
+    pub fn new(device: Arc<DeviceFunctions>, format: vk::Format, load_op: vk::AttachmentLoadOp, initial_layout: vk::ImageLayout, final_layout: vk::ImageLayout) -> Self {
+        let render_pass = if device.dynamic_rendering.is_some() {
+            vk::RenderPass::null()
+        } else {
+            Self::create_render_pass(&device, format, load_op, initial_layout, final_layout)
+        };
+
+        Self {
+            device,
+            format,
+            load_op,
+            initial_layout,
+            final_layout,
+            render_pass
+        }
+    }
+
+    /// Returns the render pass object or a null handle if dynamic rendering is used. Pipelines
+    /// used within this pass must be created with a [`vk::PipelineRenderingCreateInfo`] in that
+    /// case.
+    pub fn get_render_pass(&self) -> vk::RenderPass {
+        self.render_pass
+    }
+
+    pub fn get_format(&self) -> vk::Format {
+        self.format
+    }
+
+    /// Creates a target for a image view which can be used with any pass of the same format.
+    ///
+    /// The view must cover a single mip level and array layer of the image. The target is fully
+    /// owned by the calling code and must be destroyed using [`ColorAttachmentTarget::destroy`]
+    /// before this struct is dropped.
+    pub fn create_target(&self, image: vk::Image, image_view: vk::ImageView, size: Vec2u32) -> VkResult<ColorAttachmentTarget> {
+        let framebuffer = if self.render_pass != vk::RenderPass::null() {
+            let info = vk::FramebufferCreateInfo::builder()
+                .render_pass(self.render_pass)
+                .attachments(std::slice::from_ref(&image_view))
+                .width(size[0])
+                .height(size[1])
+                .layers(1);
+
+            unsafe {
+                self.device.vk.create_framebuffer(&info, None)
+            }?
+        } else {
+            vk::Framebuffer::null()
+        };
+
+        Ok(ColorAttachmentTarget {
+            image,
+            image_view,
+            size,
+            framebuffer
+        })
+    }
+
+    /// Begins the pass with inline contents. The clear value is only used if the load op is
+    /// [`vk::AttachmentLoadOp::CLEAR`].
+    pub fn begin(&self, command_buffer: vk::CommandBuffer, target: &ColorAttachmentTarget, clear_value: Option<&vk::ClearValue>) {
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: vk::Extent2D { width: target.size[0], height: target.size[1] }
+        };
+
+        if let Some(dynamic_rendering) = &self.device.dynamic_rendering {
+            let barrier = self.make_barrier(
+                target,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::NONE,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                self.initial_layout,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            );
+
+            let mut attachment = vk::RenderingAttachmentInfo::builder()
+                .image_view(target.image_view)
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .load_op(self.load_op)
+                .store_op(vk::AttachmentStoreOp::STORE);
+            if let Some(clear_value) = clear_value {
+                attachment = attachment.clear_value(*clear_value);
+            }
+
+            let info = vk::RenderingInfo::builder()
+                .render_area(render_area)
+                .layer_count(1)
+                .color_attachments(std::slice::from_ref(&attachment));
+
+            unsafe {
+                self.device.synchronization_2.cmd_pipeline_barrier2(command_buffer, &vk::DependencyInfo::builder().image_memory_barriers(std::slice::from_ref(&barrier)));
+                dynamic_rendering.cmd_begin_rendering(command_buffer, &info);
+            }
+        } else {
+            let mut info = vk::RenderPassBeginInfo::builder()
+                .render_pass(self.render_pass)
+                .framebuffer(target.framebuffer)
+                .render_area(render_area);
+
+            if let Some(clear_value) = clear_value {
+                info = info.clear_values(std::slice::from_ref(clear_value))
+            }
+
+            unsafe {
+                self.device.vk.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+            }
+        }
+    }
+
+    /// Ends the pass transitioning the target into the final layout.
+    pub fn end(&self, command_buffer: vk::CommandBuffer, target: &ColorAttachmentTarget) {
+        if let Some(dynamic_rendering) = &self.device.dynamic_rendering {
+            let barrier = self.make_barrier(
+                target,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+                vk::AccessFlags2::NONE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                self.final_layout
+            );
+
+            unsafe {
+                dynamic_rendering.cmd_end_rendering(command_buffer);
+                self.device.synchronization_2.cmd_pipeline_barrier2(command_buffer, &vk::DependencyInfo::builder().image_memory_barriers(std::slice::from_ref(&barrier)));
+            }
+        } else {
+            unsafe {
+                self.device.vk.cmd_end_render_pass(command_buffer);
+            }
+        }
+    }
+
+    fn make_barrier(&self, target: &ColorAttachmentTarget, src_stage_mask: vk::PipelineStageFlags2, src_access_mask: vk::AccessFlags2, dst_stage_mask: vk::PipelineStageFlags2, dst_access_mask: vk::AccessFlags2, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) -> vk::ImageMemoryBarrier2 {
+        vk::ImageMemoryBarrier2::builder()
+            .src_stage_mask(src_stage_mask)
+            .src_access_mask(src_access_mask)
+            .dst_stage_mask(dst_stage_mask)
+            .dst_access_mask(dst_access_mask)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .image(target.image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1
+            })
+            .build()
+    }
+
+    fn create_render_pass(device: &DeviceFunctions, format: vk::Format, load_op: vk::AttachmentLoadOp, initial_layout: vk::ImageLayout, final_layout: vk::ImageLayout) -> vk::RenderPass {
+        let attachment = vk::AttachmentDescription::builder()
+            .format(format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(load_op)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .initial_layout(initial_layout)
+            .final_layout(final_layout);
+
+        let attachment_reference = vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        };
+
+        let subpass = vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(std::slice::from_ref(&attachment_reference));
+
+        let info = vk::RenderPassCreateInfo::builder()
+            .attachments(std::slice::from_ref(&attachment))
+            .subpasses(std::slice::from_ref(&subpass));
+
+        unsafe {
+            device.vk.create_render_pass(&info, None)
+        }.unwrap()
+    }
+}
+
+impl Drop for ColorAttachmentPass {
+    fn drop(&mut self) {
+        if self.render_pass != vk::RenderPass::null() {
+            unsafe {
+                self.device.vk.destroy_render_pass(self.render_pass, None);
+            }
+        }
+    }
+}
+
+/// A draw target of a [`ColorAttachmentPass`]. Only contains a framebuffer if render pass objects
+/// are used.
+pub struct ColorAttachmentTarget {
+    image: vk::Image,
+    image_view: vk::ImageView,
+    size: Vec2u32,
+    framebuffer: vk::Framebuffer,
+}
+
+impl ColorAttachmentTarget {
+    pub fn get_size(&self) -> Vec2u32 {
+        self.size
+    }
+
+    pub fn destroy(&mut self, device: &DeviceFunctions) {
+        if self.framebuffer != vk::Framebuffer::null() {
+            unsafe {
+                device.vk.destroy_framebuffer(self.framebuffer, None);
+            }
+            self.framebuffer = vk::Framebuffer::null();
+        }
+    }
+}
+
 pub struct BlitPass {
     utils: Arc<DeviceUtils>,
-    render_pass: vk::RenderPass,
+    pass: ColorAttachmentPass,
     pipeline: vk::Pipeline,
 }
 
@@ -282,42 +679,22 @@ impl BlitPass {
         Ok(sets)
     }
 
-    /// Creates a framebuffer for a image view which can be used for this blit operation.
+    /// Creates a target for a image view which can be used for this blit operation.
     ///
-    /// The framebuffer is fully owned by the calling code and must be destroyed by it. In particular
-    /// it must be guaranteed that the framebuffer is destroyed before this struct is dropped.
-    pub fn create_framebuffer(&self, image_view: vk::ImageView, size: Vec2u32) -> VkResult<vk::Framebuffer> {
-        let info = vk::FramebufferCreateInfo::builder()
-            .render_pass(self.render_pass)
-            .attachments(std::slice::from_ref(&image_view))
-            .width(size[0])
-            .height(size[1])
-            .layers(1);
-
-        unsafe {
-            self.utils.blit_utils.device.vk.create_framebuffer(&info, None)
-        }
+    /// The target is fully owned by the calling code and must be destroyed by it. In particular
+    /// it must be guaranteed that the target is destroyed before this struct is dropped.
+    pub fn create_target(&self, image: vk::Image, image_view: vk::ImageView, size: Vec2u32) -> VkResult<ColorAttachmentTarget> {
+        self.pass.create_target(image, image_view, size)
     }
 
-    /// Records a blit operation using a descriptor set and framebuffer previously created from this
-    /// struct. No memory barriers are generated.
+    /// Records a blit operation using a descriptor set and target previously created from this
+    /// struct. No memory barriers are generated other than the layout transitions of the pass.
     ///
-    /// The framebuffer image will be used in the COLOR_ATTACHMENT_OUTPUT stage and the sampled image
+    /// The target image will be used in the COLOR_ATTACHMENT_OUTPUT stage and the sampled image
     /// in the FRAGMENT_SHADER stage. The sampled image must be in the SHADER_READ_OPTIMAL layout.
-    pub fn record_blit(&self, command_buffer: vk::CommandBuffer, descriptor_set: vk::DescriptorSet, framebuffer: vk::Framebuffer, size: Vec2u32, clear_value: Option<&vk::ClearValue>) {
+    pub fn record_blit(&self, command_buffer: vk::CommandBuffer, descriptor_set: vk::DescriptorSet, target: &ColorAttachmentTarget, clear_value: Option<&vk::ClearValue>) {
         let device = &self.utils.blit_utils.device;
-
-        let mut info = vk::RenderPassBeginInfo::builder()
-            .render_pass(self.render_pass)
-            .framebuffer(framebuffer)
-            .render_area(vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: vk::Extent2D { width: size[0], height: size[1] }
-            });
-
-        if let Some(clear_value) = clear_value {
-            info = info.clear_values(std::slice::from_ref(clear_value))
-        }
+        let size = target.get_size();
 
         let viewport = vk::Viewport::builder()
             .x(0f32)
@@ -336,7 +713,7 @@ impl BlitPass {
             device.vk.cmd_set_viewport(command_buffer, 0, std::slice::from_ref(&viewport));
             device.vk.cmd_set_scissor(command_buffer, 0, std::slice::from_ref(&scissor));
 
-            device.vk.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+            self.pass.begin(command_buffer, target, clear_value);
 
             device.vk.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
 
@@ -351,7 +728,7 @@ impl BlitPass {
 
             device.vk.cmd_draw(command_buffer, 4, 1, 0, 0);
 
-            device.vk.cmd_end_render_pass(command_buffer);
+            self.pass.end(command_buffer, target);
         }
     }
 
@@ -364,10 +741,12 @@ impl Drop for BlitPass {
     fn drop(&mut self) {
         unsafe {
             self.utils.blit_utils.device.vk.destroy_pipeline(self.pipeline, None);
-            self.utils.blit_utils.device.vk.destroy_render_pass(self.render_pass, None);
         }
     }
 }
 
 static FULL_SCREEN_QUAD_VERTEX_SHADER: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "utils/full_screen_quad_vert.spv"));
-static BLIT_FRAGMENT_SHADER: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "utils/blit_frag.spv"));
\ No newline at end of file
+static BLIT_FRAGMENT_SHADER: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "utils/blit_frag.spv"));
+static MIPMAP_FLOAT_COMPUTE_SHADER: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "utils/mipmap_float_comp.spv"));
+static MIPMAP_SINT_COMPUTE_SHADER: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "utils/mipmap_sint_comp.spv"));
+static MIPMAP_UINT_COMPUTE_SHADER: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "utils/mipmap_uint_comp.spv"));
\ No newline at end of file
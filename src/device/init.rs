@@ -7,7 +7,7 @@ use ash::vk;
 use bumpalo::Bump;
 use vk_profiles_rs::{vp, VulkanProfiles};
 
-use crate::device::device::{DeviceFunctions, Queue};
+use crate::device::device::{DeviceFunctions, DynamicRendering, Queue, RenderingMode, Sync2Mode, Synchronization2};
 use crate::instance::instance::{InstanceContext, VulkanVersion};
 
 use crate::prelude::*;
@@ -16,7 +16,9 @@ use crate::prelude::*;
 pub struct DeviceCreateConfig {
     used_surfaces: Vec<vk::SurfaceKHR>,
     disable_robustness: bool,
+    disable_dynamic_rendering: bool,
     required_extensions: HashSet<CString>,
+    device_override: Option<DeviceOverride>,
 }
 
 impl DeviceCreateConfig {
@@ -25,9 +27,17 @@ impl DeviceCreateConfig {
             used_surfaces: Vec::new(),
             required_extensions: HashSet::new(),
             disable_robustness: false,
+            disable_dynamic_rendering: false,
+            device_override: None,
         }
     }
 
+    /// Forces a specific physical device to be used. If no supported device matches the override
+    /// the best rated device is selected instead.
+    pub fn set_device_override(&mut self, device_override: DeviceOverride) {
+        self.device_override = Some(device_override);
+    }
+
     pub fn add_surface(&mut self, surface: vk::SurfaceKHR) {
         self.used_surfaces.push(surface);
     }
@@ -36,6 +46,11 @@ impl DeviceCreateConfig {
         self.disable_robustness = true;
     }
 
+    /// Forces render pass objects to be used even if the device supports dynamic rendering.
+    pub fn disable_dynamic_rendering(&mut self) {
+        self.disable_dynamic_rendering = true;
+    }
+
     pub fn add_required_extension(&mut self, extension: &CStr) {
         self.required_extensions.insert(CString::from(extension));
     }
@@ -45,6 +60,148 @@ impl DeviceCreateConfig {
     }
 }
 
+/// Selects a physical device either by its name or its uuid.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DeviceOverride {
+    Name(String),
+    Uuid([u8; vk::UUID_SIZE]),
+}
+
+/// Describes a physical device and how suitable it is to be used by Blaze4D.
+#[derive(Clone, Debug)]
+pub struct PhysicalDeviceInfo {
+    pub physical_device: vk::PhysicalDevice,
+    pub device_name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub device_uuid: [u8; vk::UUID_SIZE],
+    pub api_version: VulkanVersion,
+
+    /// The combined size of all device local memory heaps in bytes.
+    pub device_local_memory: u64,
+    pub limits: vk::PhysicalDeviceLimits,
+
+    /// True if the device supports all features required by Blaze4D and the create config.
+    pub supported: bool,
+
+    /// Rates the device based on its type and available device local memory. Higher is better.
+    pub score: u64,
+}
+
+impl PhysicalDeviceInfo {
+    fn query(instance: &InstanceContext, physical_device: vk::PhysicalDevice) -> Self {
+        let mut id_properties = vk::PhysicalDeviceIDProperties::builder();
+        let mut properties = vk::PhysicalDeviceProperties2::builder()
+            .push_next(&mut id_properties);
+        unsafe {
+            instance.vk().get_physical_device_properties2(physical_device, &mut properties)
+        };
+        let properties = properties.properties;
+        let id_properties = id_properties.build();
+
+        let memory_properties = unsafe {
+            instance.vk().get_physical_device_memory_properties(physical_device)
+        };
+        let device_local_memory = memory_properties.memory_heaps[0..(memory_properties.memory_heap_count as usize)].iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+
+        let device_name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }.to_string_lossy().into_owned();
+
+        Self {
+            physical_device,
+            device_name,
+            device_type: properties.device_type,
+            device_uuid: id_properties.device_uuid,
+            api_version: VulkanVersion::from_raw(properties.api_version),
+            device_local_memory,
+            limits: properties.limits,
+            supported: false,
+            score: compute_score(properties.device_type, device_local_memory),
+        }
+    }
+
+    pub fn matches(&self, device_override: &DeviceOverride) -> bool {
+        match device_override {
+            DeviceOverride::Name(name) => &self.device_name == name,
+            DeviceOverride::Uuid(uuid) => &self.device_uuid == uuid,
+        }
+    }
+}
+
+/// Enumerates all physical devices and determines if they are supported for the specified config.
+///
+/// The returned list is ranked with supported devices first sorted by descending score.
+pub fn enumerate_physical_devices(config: &DeviceCreateConfig, instance: &InstanceContext) -> Result<Vec<PhysicalDeviceInfo>, DeviceCreateError> {
+    let vk_vp = VulkanProfiles::linked();
+    let profile = instance.get_profile();
+
+    let mut infos = Vec::new();
+    for device in unsafe { instance.vk().enumerate_physical_devices()? } {
+        let mut info = PhysicalDeviceInfo::query(instance, device);
+
+        let allocator = Bump::new();
+        if let Some(mut configurator) = DeviceConfigurator::new(instance, &vk_vp, config, profile, device, &allocator)? {
+            info.supported = configure_device(&mut configurator)?.is_some();
+        }
+
+        infos.push(info);
+    }
+
+    infos.sort_by(|a, b| b.supported.cmp(&a.supported).then(b.score.cmp(&a.score)));
+    Ok(infos)
+}
+
+/// Picks the device to use from a list of devices. If a override is specified and a supported
+/// device matches it, that device is returned. Otherwise the supported device with the highest
+/// score is returned.
+pub fn pick_physical_device<'a>(devices: &'a [PhysicalDeviceInfo], device_override: Option<&DeviceOverride>) -> Option<&'a PhysicalDeviceInfo> {
+    if let Some(device_override) = device_override {
+        if let Some(device) = devices.iter().find(|device| device.supported && device.matches(device_override)) {
+            return Some(device);
+        }
+        log::warn!("No supported physical device matches override {:?}. Falling back to default selection", device_override);
+    }
+
+    let mut best: Option<&PhysicalDeviceInfo> = None;
+    for device in devices.iter().filter(|device| device.supported) {
+        if best.map(|best| device.score > best.score).unwrap_or(true) {
+            best = Some(device);
+        }
+    }
+    best
+}
+
+/// Selects the core features to enable starting from the features required by the profile.
+///
+/// Robust buffer access is disabled if robustness is disabled. The only optional features which
+/// are enabled in addition to the profile features are the sparse and storage image features if
+/// they are used.
+fn select_core_features(required: &vk::PhysicalDeviceFeatures, disable_robustness: bool, sparse_residency_image_2d: bool, storage_image_write_without_format: bool) -> vk::PhysicalDeviceFeatures {
+    let as_bool32 = |enabled: bool| if enabled { vk::TRUE } else { vk::FALSE };
+    vk::PhysicalDeviceFeatures {
+        robust_buffer_access: as_bool32(required.robust_buffer_access == vk::TRUE && !disable_robustness),
+        sparse_binding: as_bool32(required.sparse_binding == vk::TRUE || sparse_residency_image_2d),
+        sparse_residency_image2_d: as_bool32(required.sparse_residency_image2_d == vk::TRUE || sparse_residency_image_2d),
+        shader_storage_image_write_without_format: as_bool32(required.shader_storage_image_write_without_format == vk::TRUE || storage_image_write_without_format),
+        ..*required
+    }
+}
+
+fn compute_score(device_type: vk::PhysicalDeviceType, device_local_memory: u64) -> u64 {
+    let type_score = match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 4u64,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 3u64,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2u64,
+        vk::PhysicalDeviceType::CPU => 1u64,
+        _ => 0u64,
+    };
+
+    // The device type always takes precedence over the memory size
+    let memory_score = std::cmp::min(device_local_memory >> 20, (1u64 << 48) - 1);
+    (type_score << 48) | memory_score
+}
+
 #[derive(Debug)]
 pub enum DeviceCreateError {
     Vulkan(vk::Result),
@@ -76,12 +233,19 @@ pub fn create_device(config: DeviceCreateConfig, instance: Arc<InstanceContext>)
     )?;
 
     let priority = 1f32;
-    let mut queue_create_infos = Vec::with_capacity(3);
+    let mut queue_create_infos = Vec::with_capacity(4);
     queue_create_infos.push(vk::DeviceQueueCreateInfo::builder()
         .queue_family_index(device_config.main_queue_family)
         .queue_priorities(std::slice::from_ref(&priority))
         .build()
     );
+    if let Some(family) = &device_config.present_queue_family {
+        queue_create_infos.push(vk::DeviceQueueCreateInfo::builder()
+            .queue_family_index(*family)
+            .queue_priorities(std::slice::from_ref(&priority))
+            .build()
+        );
+    }
     if let Some(family) = &device_config.async_compute_family {
         queue_create_infos.push(vk::DeviceQueueCreateInfo::builder()
             .queue_family_index(*family)
@@ -114,7 +278,8 @@ pub fn create_device(config: DeviceCreateConfig, instance: Arc<InstanceContext>)
     log::info!("Selected device {:?} with config {:?}", selected_device_name, device_config);
     let device = unsafe { vk_vp.create_device(instance.vk(), physical_device, &vp_device_create_info, None)? };
 
-    let synchronization_2_khr = ash::extensions::khr::Synchronization2::new(instance.vk(), &device);
+    let synchronization_2 = Synchronization2::new(instance.vk(), &device, device_config.synchronization2_mode);
+    let dynamic_rendering = DynamicRendering::new(instance.vk(), &device, device_config.rendering_mode);
     let timeline_semaphore_khr = ash::extensions::khr::TimelineSemaphore::new(instance.vk(), &device);
     let push_descriptor_khr = ash::extensions::khr::PushDescriptor::new(instance.vk(), &device);
 
@@ -130,18 +295,37 @@ pub fn create_device(config: DeviceCreateConfig, instance: Arc<InstanceContext>)
         None
     };
 
+    let display_timing_google = if device_config.has_display_timing {
+        Some(ash::extensions::google::DisplayTiming::new(instance.vk(), &device))
+    } else {
+        None
+    };
+
     let functions = Arc::new(DeviceFunctions {
         instance,
         physical_device,
         vk: device,
-        synchronization_2_khr,
+        synchronization_2,
+        dynamic_rendering,
         timeline_semaphore_khr,
         push_descriptor_khr,
         swapchain_khr,
-        maintenance_4_khr
+        maintenance_4_khr,
+        display_timing_google,
+        incremental_present_khr: device_config.has_incremental_present,
+        full_screen_exclusive_ext: device_config.has_full_screen_exclusive,
+        memory_budget_ext: device_config.has_memory_budget,
+        conservative_rasterization_ext: device_config.has_conservative_rasterization,
+        sparse_residency_image_2d: device_config.has_sparse_residency_image_2d,
+        storage_image_write_without_format: device_config.has_storage_image_write_without_format,
+        main_queue_family: device_config.main_queue_family,
+        present_queue_family: device_config.present_queue_family
     });
 
     let main_queue = Arc::new(Queue::new(functions.clone(), device_config.main_queue_family, 0));
+    let present_queue = device_config.present_queue_family.map(|family| {
+        Arc::new(Queue::new(functions.clone(), family, 0))
+    });
     let async_compute_queue = device_config.async_compute_family.map(|family| {
         Arc::new(Queue::new(functions.clone(), family, 0))
     });
@@ -152,8 +336,10 @@ pub fn create_device(config: DeviceCreateConfig, instance: Arc<InstanceContext>)
     Ok(DeviceContext::new(
         functions,
         main_queue,
+        present_queue,
         async_compute_queue,
-        async_transfer_queue
+        async_transfer_queue,
+        device_config.enabled_features
     ))
 }
 
@@ -166,8 +352,10 @@ fn filter_devices<'a>(
 ) -> Result<(DeviceConfigInfo, vk::DeviceCreateInfoBuilder<'a>, vk::PhysicalDevice), DeviceCreateError> {
     let profile = instance.get_profile();
 
-    let mut best_device: Option<(DeviceConfigInfo, vk::DeviceCreateInfoBuilder, vk::PhysicalDevice)> = None;
+    let mut infos = Vec::with_capacity(devices.len());
+    let mut candidates = Vec::with_capacity(devices.len());
     for device in devices {
+        let mut info = PhysicalDeviceInfo::query(instance, device);
         if let Some(mut configurator) = DeviceConfigurator::new(
             instance,
             vk_vp,
@@ -177,20 +365,21 @@ fn filter_devices<'a>(
             allocator
         )? {
             if let Some(device_config) = configure_device(&mut configurator)? {
-                best_device = if let Some(old) = best_device {
-                    if device_config.rating > old.0.rating {
-                        Some((device_config, configurator.build(), device))
-                    } else {
-                        Some(old)
-                    }
-                } else {
-                    Some((device_config, configurator.build(), device))
-                }
+                info.supported = true;
+                candidates.push(Some((device_config, configurator.build(), device)));
+            } else {
+                candidates.push(None);
             }
+        } else {
+            candidates.push(None);
         }
+        infos.push(info);
     }
 
-    best_device.ok_or(DeviceCreateError::NoSupportedDevice)
+    let selected = pick_physical_device(&infos, config.device_override.as_ref()).ok_or(DeviceCreateError::NoSupportedDevice)?;
+    let index = infos.iter().position(|info| info.physical_device == selected.physical_device).unwrap();
+
+    Ok(candidates[index].take().unwrap())
 }
 
 struct DeviceConfigurator<'a, 'b> {
@@ -198,6 +387,9 @@ struct DeviceConfigurator<'a, 'b> {
     config: &'a DeviceCreateConfig,
     physical_device: vk::PhysicalDevice,
     device_name: CString,
+    api_version: VulkanVersion,
+    /// The core features required by the profile.
+    profile_features: vk::PhysicalDeviceFeatures,
     available_extensions: HashSet<CString>,
     used_extensions: HashSet<CString>,
     queue_family_surface_support: Box<[bool]>,
@@ -234,6 +426,11 @@ impl<'a, 'b> DeviceConfigurator<'a, 'b> {
             return Ok(None);
         }
 
+        let mut profile_features = vk::PhysicalDeviceFeatures2::default();
+        unsafe {
+            vk_vp.get_profile_features(profile, &mut *(&mut profile_features as *mut vk::PhysicalDeviceFeatures2 as *mut vk::BaseOutStructure))
+        };
+
         let queue_family_count = unsafe {
             instance.vk().get_physical_device_queue_family_properties(physical_device)
         }.len();
@@ -258,6 +455,8 @@ impl<'a, 'b> DeviceConfigurator<'a, 'b> {
             config,
             physical_device,
             device_name,
+            api_version: VulkanVersion::from_raw(properties.api_version),
+            profile_features: profile_features.features,
             available_extensions,
             used_extensions,
             queue_family_surface_support,
@@ -302,6 +501,11 @@ impl<'a, 'b> DeviceConfigurator<'a, 'b> {
         families.into_iter().map(|(_, family)| family).collect()
     }
 
+    /// Returns true if the queue family supports presentation to all used surfaces.
+    fn is_surface_supported(&self, family: u32) -> bool {
+        self.queue_family_surface_support[family as usize]
+    }
+
     fn is_extension_supported(&self, name: &CStr) -> bool {
         self.available_extensions.contains(name)
     }
@@ -339,13 +543,43 @@ impl<'a, 'b> DeviceConfigurator<'a, 'b> {
 
 #[derive(Debug)]
 struct DeviceConfigInfo {
-    rating: f32,
+    synchronization2_mode: Sync2Mode,
+
+    /// Dynamic rendering is used if available unless disabled in the config.
+    rendering_mode: RenderingMode,
+
     has_maintenance4: bool,
 
-    /// The main queue family. It is guaranteed to support presentation to all surfaces as well as
-    /// graphics, compute and transfer operations.
+    /// Only enabled if the swapchain extension is used.
+    has_display_timing: bool,
+
+    /// Only enabled if the swapchain extension is used.
+    has_incremental_present: bool,
+
+    /// Only enabled if the swapchain extension is used and the instance supports
+    /// VK_KHR_get_surface_capabilities2.
+    has_full_screen_exclusive: bool,
+
+    has_memory_budget: bool,
+
+    has_conservative_rasterization: bool,
+
+    /// Only enabled if the main queue family supports sparse binding operations.
+    has_sparse_residency_image_2d: bool,
+
+    has_storage_image_write_without_format: bool,
+
+    /// The core features enabled on the device.
+    enabled_features: vk::PhysicalDeviceFeatures,
+
+    /// The main queue family. It is guaranteed to support graphics, compute and transfer
+    /// operations. If possible a family supporting presentation to all surfaces is selected.
     main_queue_family: u32,
 
+    /// The queue family used for presentation. Only set if the main queue family does not support
+    /// presentation to all surfaces. It is guaranteed to support presentation to all surfaces.
+    present_queue_family: Option<u32>,
+
     /// The queue family used for async compute operations. It is guaranteed to support compute and
     /// transfer operations and must be a different queue family than the main queue family.
     async_compute_family: Option<u32>,
@@ -360,12 +594,45 @@ fn configure_device(device: &mut DeviceConfigurator) -> Result<Option<DeviceConf
     let mut features = vk::PhysicalDeviceFeatures2::builder();
     let mut properties = vk::PhysicalDeviceProperties2::builder();
 
+    // Synchronization2 is core since Vulkan 1.3. The core functions can only be used if the
+    // instance has been created with Vulkan 1.3 as well
     let synchronization_2_name = CString::new("VK_KHR_synchronization2").unwrap();
-    if !device.is_extension_supported(&synchronization_2_name) {
-        log::info!("Physical device {:?} does not support VK_KHR_synchronization2", device.get_name());
+    let synchronization2_mode;
+    if device.api_version >= VulkanVersion::VK_1_3 && device.instance.get_version() >= VulkanVersion::VK_1_3 {
+        synchronization2_mode = Sync2Mode::Native;
+    } else if device.is_extension_supported(&synchronization_2_name) {
+        synchronization2_mode = Sync2Mode::Extension;
+        device.add_extension(&synchronization_2_name);
+    } else {
+        log::warn!("Physical device {:?} does not support synchronization2. Vulkan 1.3 or VK_KHR_synchronization2 is required", device.get_name());
         return Ok(None);
     }
-    device.add_extension(&synchronization_2_name);
+
+    // Dynamic rendering is optional. Render pass objects are used if it is not available. The
+    // extension depends on VK_KHR_create_renderpass2 and VK_KHR_depth_stencil_resolve.
+    let dynamic_rendering_extensions = [
+        CString::new("VK_KHR_dynamic_rendering").unwrap(),
+        CString::new("VK_KHR_create_renderpass2").unwrap(),
+        CString::new("VK_KHR_depth_stencil_resolve").unwrap(),
+    ];
+    let requested_rendering_mode = if device.config.disable_dynamic_rendering {
+        RenderingMode::RenderPass
+    } else if device.api_version >= VulkanVersion::VK_1_3 && device.instance.get_version() >= VulkanVersion::VK_1_3 {
+        RenderingMode::DynamicRenderingNative
+    } else if dynamic_rendering_extensions.iter().all(|name| device.is_extension_supported(name)) {
+        RenderingMode::DynamicRenderingExtension
+    } else {
+        RenderingMode::RenderPass
+    };
+
+    let mut dynamic_rendering_features = if requested_rendering_mode.is_dynamic_rendering() {
+        Some(vk::PhysicalDeviceDynamicRenderingFeatures::builder())
+    } else {
+        None
+    };
+    if let Some(f) = dynamic_rendering_features.as_mut() {
+        features = features.push_next(f);
+    }
 
     let push_descriptor_name = CString::new("VK_KHR_push_descriptor").unwrap();
     if !device.is_extension_supported(&push_descriptor_name) {
@@ -401,13 +668,14 @@ fn configure_device(device: &mut DeviceConfigurator) -> Result<Option<DeviceConf
     properties = properties.push_next(&mut push_descriptor_properties);
 
     // Read supported features and properties
-    device.get_features(features);
+    let core_features = device.get_features(features);
     device.get_properties(properties);
     let timeline_features = timeline_features.build();
     let timeline_properties = timeline_properties.build();
     let synchronization2_features = synchronization2_features.build();
     let push_descriptor_properties = push_descriptor_properties.build();
     let maintenance4 = maintenance4.map(|(f, p)| (f.build(), p.build()));
+    let dynamic_rendering_features = dynamic_rendering_features.map(|f| f.build());
 
     // Process the supported features and properties
     if timeline_features.timeline_semaphore != vk::TRUE {
@@ -425,7 +693,7 @@ fn configure_device(device: &mut DeviceConfigurator) -> Result<Option<DeviceConf
     }
 
     if synchronization2_features.synchronization2 != vk::TRUE {
-        log::info!("Physical device {:?} does not support the synchronization2 feature", device.get_name());
+        log::warn!("Physical device {:?} does not support the synchronization2 feature ({:?})", device.get_name(), synchronization2_mode);
         return Ok(None);
     } else {
         device.push_next(vk::PhysicalDeviceSynchronization2Features::builder()
@@ -438,6 +706,25 @@ fn configure_device(device: &mut DeviceConfigurator) -> Result<Option<DeviceConf
         return Ok(None);
     }
 
+    let rendering_mode = match dynamic_rendering_features {
+        Some(f) if f.dynamic_rendering == vk::TRUE => {
+            if requested_rendering_mode == RenderingMode::DynamicRenderingExtension {
+                for name in &dynamic_rendering_extensions {
+                    device.add_extension(name);
+                }
+            }
+            device.push_next(vk::PhysicalDeviceDynamicRenderingFeatures::builder()
+                .dynamic_rendering(true)
+            );
+            requested_rendering_mode
+        },
+        Some(_) => {
+            log::info!("Physical device {:?} does not support the dynamic rendering feature. Falling back to render passes", device.get_name());
+            RenderingMode::RenderPass
+        },
+        None => RenderingMode::RenderPass,
+    };
+
     let has_maintenance4;
     if let Some((f, p)) = maintenance4.as_ref() {
         if f.maintenance4 == vk::TRUE {
@@ -453,23 +740,155 @@ fn configure_device(device: &mut DeviceConfigurator) -> Result<Option<DeviceConf
         has_maintenance4 = false;
     }
 
+    // Display timing is optional and only useful if we present
+    let display_timing_name = CString::new("VK_GOOGLE_display_timing").unwrap();
+    let has_display_timing = device.config.required_extensions.contains(&CString::new("VK_KHR_swapchain").unwrap())
+        && device.is_extension_supported(&display_timing_name);
+    if has_display_timing {
+        device.add_extension(&display_timing_name);
+    }
+
+    // Incremental present is optional and only useful if we present
+    let incremental_present_name = CString::new("VK_KHR_incremental_present").unwrap();
+    let has_incremental_present = device.config.required_extensions.contains(&CString::new("VK_KHR_swapchain").unwrap())
+        && device.is_extension_supported(&incremental_present_name);
+    if has_incremental_present {
+        device.add_extension(&incremental_present_name);
+    }
+
+    // Full screen exclusive is optional and only useful if we present
+    let full_screen_exclusive_name = CString::new("VK_EXT_full_screen_exclusive").unwrap();
+    let has_full_screen_exclusive = device.config.required_extensions.contains(&CString::new("VK_KHR_swapchain").unwrap())
+        && device.instance.supports_surface_capabilities_2()
+        && device.is_extension_supported(&full_screen_exclusive_name);
+    if has_full_screen_exclusive {
+        device.add_extension(&full_screen_exclusive_name);
+    }
+
+    // Memory budget is optional and only used to query heap usage
+    let memory_budget_name = CString::new("VK_EXT_memory_budget").unwrap();
+    let has_memory_budget = device.is_extension_supported(&memory_budget_name);
+    if has_memory_budget {
+        device.add_extension(&memory_budget_name);
+    }
+
+    // Conservative rasterization is optional and only used by draws requesting it
+    let conservative_rasterization_name = CString::new("VK_EXT_conservative_rasterization").unwrap();
+    let has_conservative_rasterization = device.is_extension_supported(&conservative_rasterization_name);
+    if has_conservative_rasterization {
+        device.add_extension(&conservative_rasterization_name);
+    }
+
     // Calculate queue family assignments
-    let main_families = device.filter_sort_queues(|family, properties, surface_support| {
-        Some(family)
+    let main_families = device.filter_sort_queues(|family, properties, _| {
+        if properties.queue_flags.contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE) {
+            Some(family)
+        } else {
+            None
+        }
     });
+    // Prefer a main queue family which can also present to avoid a separate present queue
     let main_queue_family;
-    if let Some(family) = main_families.get(0) {
+    if let Some(family) = main_families.iter().find(|family| device.is_surface_supported(**family)).or(main_families.get(0)) {
         main_queue_family = *family;
     } else {
         log::info!("Physical device {:?} does not have suitable main queue family", device.get_name());
         return Ok(None);
     }
 
+    let present_queue_family;
+    if device.is_surface_supported(main_queue_family) {
+        present_queue_family = None;
+    } else {
+        // DeviceConfigurator::new guarantees that at least one family supports all surfaces
+        present_queue_family = device.filter_sort_queues(|family, _, surface_support| {
+            if surface_support { Some(family) } else { None }
+        }).get(0).copied();
+        log::info!("Physical device {:?} requires a separate present queue family {:?}", device.get_name(), present_queue_family);
+    }
+
+    // Sparse residency is optional and only used by sparse images
+    let has_sparse_residency_image_2d = core_features.sparse_binding == vk::TRUE
+        && core_features.sparse_residency_image2_d == vk::TRUE
+        && !device.filter_sort_queues(|family, properties, _| {
+            if family == main_queue_family && properties.queue_flags.contains(vk::QueueFlags::SPARSE_BINDING) {
+                Some(family)
+            } else {
+                None
+            }
+        }).is_empty();
+
+    // Writing storage images without a format is optional and only used to generate mipmaps for
+    // formats which do not support blits
+    let has_storage_image_write_without_format = core_features.shader_storage_image_write_without_format == vk::TRUE;
+
+    // All optional core features must be enabled by a single struct. Since the struct replaces
+    // the core features of the profile it starts from the profile features. It is always passed
+    // so that the enabled core features do not depend on optional support.
+    let features = select_core_features(&device.profile_features, device.config.disable_robustness, has_sparse_residency_image_2d, has_storage_image_write_without_format);
+    device.push_next(vk::PhysicalDeviceFeatures2::builder()
+        .features(features)
+    );
+
     Ok(Some(DeviceConfigInfo {
-        rating: 0.0,
+        synchronization2_mode,
+        rendering_mode,
         has_maintenance4,
+        has_display_timing,
+        has_incremental_present,
+        has_full_screen_exclusive,
+        has_memory_budget,
+        has_conservative_rasterization,
+        has_sparse_residency_image_2d,
+        has_storage_image_write_without_format,
+        enabled_features: features,
         main_queue_family,
+        present_queue_family,
         async_compute_family: None,
         async_transfer_family: None
     }))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_scoring() {
+        let discrete = compute_score(vk::PhysicalDeviceType::DISCRETE_GPU, 2u64 << 30);
+        let integrated = compute_score(vk::PhysicalDeviceType::INTEGRATED_GPU, 16u64 << 30);
+        assert!(discrete > integrated);
+        assert!(compute_score(vk::PhysicalDeviceType::DISCRETE_GPU, 8u64 << 30) > discrete);
+        assert!(integrated > compute_score(vk::PhysicalDeviceType::CPU, 64u64 << 30));
+    }
+
+    #[test]
+    fn core_feature_selection() {
+        let required = vk::PhysicalDeviceFeatures {
+            robust_buffer_access: vk::TRUE,
+            sampler_anisotropy: vk::TRUE,
+            ..Default::default()
+        };
+
+        let features = select_core_features(&required, true, false, true);
+        assert_eq!(features.robust_buffer_access, vk::FALSE);
+        assert_eq!(features.sampler_anisotropy, vk::TRUE);
+        assert_eq!(features.sparse_binding, vk::FALSE);
+        assert_eq!(features.sparse_residency_buffer, vk::FALSE);
+        assert_eq!(features.sparse_residency_image2_d, vk::FALSE);
+        assert_eq!(features.shader_storage_image_write_without_format, vk::TRUE);
+
+        let features = select_core_features(&required, false, true, false);
+        assert_eq!(features.robust_buffer_access, vk::TRUE);
+        assert_eq!(features.sparse_binding, vk::TRUE);
+        assert_eq!(features.sparse_residency_buffer, vk::FALSE);
+        assert_eq!(features.sparse_residency_image2_d, vk::TRUE);
+        assert_eq!(features.shader_storage_image_write_without_format, vk::FALSE);
+        assert_eq!(select_core_features(&Default::default(), false, false, false).sampler_anisotropy, vk::FALSE);
+
+        // Features which are not required by the profile are not enabled
+        let features = select_core_features(&Default::default(), false, false, false);
+        assert_eq!(features.robust_buffer_access, vk::FALSE);
+        assert_eq!(features.multi_draw_indirect, vk::FALSE);
+    }
+}
@@ -50,6 +50,28 @@ impl DeviceSurface {
         }
     }
 
+    /// Returns true if a swapchain can currently be created for this surface.
+    ///
+    /// Some platforms (for example windows) report a maximum extent of 0 while the window is
+    /// minimized. No swapchain can be created in this state and calling code should skip rendering
+    /// until the window is restored.
+    pub fn is_renderable(&self) -> bool {
+        match self.get_surface_capabilities() {
+            Ok(capabilities) => capabilities.max_image_extent.width != 0 && capabilities.max_image_extent.height != 0,
+            Err(err) => {
+                log::warn!("vkGetPhysicalDeviceSurfaceCapabilitiesKHR returned {:?} in DeviceSurface::is_renderable", err);
+                false
+            }
+        }
+    }
+
+    /// Queries the current surface capabilities and returns them as a [`SurfaceImageCapabilities`].
+    ///
+    /// This should be used to determine valid image counts and extents before creating a swapchain.
+    pub fn get_image_capabilities(&self) -> VkResult<SurfaceImageCapabilities> {
+        self.get_surface_capabilities().map(SurfaceImageCapabilities::from)
+    }
+
     pub fn get_surface_formats(&self) -> VkResult<Vec<vk::SurfaceFormatKHR>> {
         unsafe {
             self.device.instance.surface_khr().unwrap().get_physical_device_surface_formats(self.device.physical_device, self.surface)
@@ -63,7 +85,7 @@ impl DeviceSurface {
     /// such calling code should use some platform dependant way to determine the desired extent.
     ///
     /// If the current surface capabilities report a max extent of 0 [`SwapchainCreateError::NoExtent`]
-    /// is returned.
+    /// is returned. If the requested extent is 0 [`SwapchainCreateError::ZeroExtent`] is returned.
     ///
     /// If some part of the config is not supported by the surface [`SwapchainCreateError::Unsupported`]
     /// is returned.
@@ -72,6 +94,18 @@ impl DeviceSurface {
 
         let format = self.find_best_format(&config)?;
 
+        // If rendering and presentation use different queue families the images are shared
+        // concurrently to avoid ownership transfers
+        let queue_families = self.device.present_queue_family.map(|family| [self.device.main_queue_family, family]);
+        let sharing_mode = if queue_families.is_some() {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        };
+
+        let mut full_screen_exclusive = vk::SurfaceFullScreenExclusiveInfoEXT::builder()
+            .full_screen_exclusive(vk::FullScreenExclusiveEXT::ALLOWED);
+
         let mut info = vk::SwapchainCreateInfoKHR::builder()
             .min_image_count(self.find_best_image_count(&capabilities, &config)?)
             .image_format(format.format)
@@ -79,12 +113,17 @@ impl DeviceSurface {
             .image_extent(self.validate_extent(&capabilities, extent)?)
             .image_array_layers(1)
             .image_usage(self.find_best_usage_flags(&capabilities, &config)?)
-            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .image_sharing_mode(sharing_mode)
+            .queue_family_indices(queue_families.as_ref().map(|f| f.as_slice()).unwrap_or(&[]))
             .pre_transform(self.find_best_transform(&capabilities, &config)?)
             .composite_alpha(self.find_best_composite_alpha(&capabilities, &config)?)
             .present_mode(self.find_best_present_mode(&config)?)
             .clipped(config.clipped);
 
+        if config.full_screen_exclusive && self.device.full_screen_exclusive_ext {
+            info = info.push_next(&mut full_screen_exclusive);
+        }
+
         Ok(self.create_swapchain_direct(&mut info)?)
     }
 
@@ -162,6 +201,10 @@ impl DeviceSurface {
             return Err(SwapchainCreateError::NoExtent)
         }
 
+        if extent[0] == 0 || extent[1] == 0 {
+            return Err(SwapchainCreateError::ZeroExtent)
+        }
+
         if capabilities.max_image_extent.width < extent[0] ||
             capabilities.min_image_extent.width > extent[0] ||
             capabilities.max_image_extent.height < extent[1] ||
@@ -174,6 +217,7 @@ impl DeviceSurface {
 
     fn find_best_usage_flags(&self, capabilities: &vk::SurfaceCapabilitiesKHR, config: &SwapchainConfig) -> Result<vk::ImageUsageFlags, SwapchainCreateError> {
         if !capabilities.supported_usage_flags.contains(config.required_usage) {
+            log::warn!("Surface does not support required swapchain usage {:?}. Supported: {:?}", config.required_usage, capabilities.supported_usage_flags);
             return Err(SwapchainCreateError::Unsupported);
         }
 
@@ -218,24 +262,14 @@ impl DeviceSurface {
         }
     }
 
-    fn find_best_composite_alpha(&self, capabilities: &vk::SurfaceCapabilitiesKHR, _: &SwapchainConfig) -> Result<vk::CompositeAlphaFlagsKHR, SwapchainCreateError> {
-        if capabilities.supported_composite_alpha.contains(vk::CompositeAlphaFlagsKHR::OPAQUE) {
-            Ok(vk::CompositeAlphaFlagsKHR::OPAQUE)
-
-        } else if capabilities.supported_composite_alpha.contains(vk::CompositeAlphaFlagsKHR::INHERIT) {
-            Ok(vk::CompositeAlphaFlagsKHR::INHERIT)
+    fn find_best_composite_alpha(&self, capabilities: &vk::SurfaceCapabilitiesKHR, config: &SwapchainConfig) -> Result<vk::CompositeAlphaFlagsKHR, SwapchainCreateError> {
+        let selected = select_composite_alpha(capabilities.supported_composite_alpha, config.composite_alpha)
+            .ok_or(SwapchainCreateError::Unsupported)?;
 
-        } else {
-            let mut flag = 1u32;
-            loop {
-                let comp = vk::CompositeAlphaFlagsKHR::from_raw(Flags::from(flag));
-                if capabilities.supported_composite_alpha.contains(comp) {
-                    return Ok(comp)
-                }
-                // The vulkan spec requires at least one bit to be set so we should panic if that's not the case
-                flag = flag.checked_shr(1).unwrap();
-            }
+        if selected != config.composite_alpha.to_vk() {
+            log::debug!("Composite alpha {:?} is not supported by surface. Falling back to {:?}", config.composite_alpha, selected);
         }
+        Ok(selected)
     }
 
     /// Locks the current swapchain info. This function **must not** be called from inside the [`SurfaceSwapchain`]
@@ -295,17 +329,131 @@ impl SurfaceSwapchainInfo {
     }
 }
 
+/// The capabilities of a surface relevant for swapchain creation.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SurfaceImageCapabilities {
+    /// The minimum number of images a swapchain must have.
+    pub min_image_count: u32,
+
+    /// The maximum number of images a swapchain may have or [`None`] if there is no limit.
+    pub max_image_count: Option<u32>,
+
+    /// The current extent of the surface or [`None`] if the extent is determined by the swapchain
+    /// (the surface reported a current extent of `0xFFFFFFFF`).
+    pub current_extent: Option<Vec2u32>,
+
+    pub min_image_extent: Vec2u32,
+    pub max_image_extent: Vec2u32,
+    pub max_image_array_layers: u32,
+    pub supported_transforms: vk::SurfaceTransformFlagsKHR,
+    pub current_transform: vk::SurfaceTransformFlagsKHR,
+    pub supported_composite_alpha: vk::CompositeAlphaFlagsKHR,
+    pub supported_usage_flags: vk::ImageUsageFlags,
+}
+
+impl SurfaceImageCapabilities {
+    /// Clamps a requested image count to the range supported by the surface.
+    pub fn clamp_image_count(&self, count: u32) -> u32 {
+        let count = std::cmp::max(count, self.min_image_count);
+        match self.max_image_count {
+            Some(max) => std::cmp::min(count, max),
+            None => count,
+        }
+    }
+
+    /// Clamps a requested extent to the range supported by the surface.
+    pub fn clamp_extent(&self, extent: Vec2u32) -> Vec2u32 {
+        Vec2u32::new(
+            extent[0].clamp(self.min_image_extent[0], self.max_image_extent[0]),
+            extent[1].clamp(self.min_image_extent[1], self.max_image_extent[1])
+        )
+    }
+}
+
+impl From<vk::SurfaceCapabilitiesKHR> for SurfaceImageCapabilities {
+    fn from(capabilities: vk::SurfaceCapabilitiesKHR) -> Self {
+        let current_extent = if capabilities.current_extent.width == u32::MAX && capabilities.current_extent.height == u32::MAX {
+            None
+        } else {
+            Some(Vec2u32::new(capabilities.current_extent.width, capabilities.current_extent.height))
+        };
+
+        Self {
+            min_image_count: capabilities.min_image_count,
+            max_image_count: if capabilities.max_image_count == 0 { None } else { Some(capabilities.max_image_count) },
+            current_extent,
+            min_image_extent: Vec2u32::new(capabilities.min_image_extent.width, capabilities.min_image_extent.height),
+            max_image_extent: Vec2u32::new(capabilities.max_image_extent.width, capabilities.max_image_extent.height),
+            max_image_array_layers: capabilities.max_image_array_layers,
+            supported_transforms: capabilities.supported_transforms,
+            current_transform: capabilities.current_transform,
+            supported_composite_alpha: capabilities.supported_composite_alpha,
+            supported_usage_flags: capabilities.supported_usage_flags,
+        }
+    }
+}
+
+/// How the presentation engine should treat the alpha channel of swapchain images.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CompositeAlphaPreference {
+    /// The alpha channel is ignored and images are treated as fully opaque.
+    Opaque,
+    /// The color channels are expected to already be multiplied by the alpha value.
+    PreMultiplied,
+    /// The color channels are not multiplied by the alpha value and the compositor will do so.
+    PostMultiplied,
+    /// Alpha handling is left to the native window system.
+    Inherit,
+}
+
+impl CompositeAlphaPreference {
+    pub const fn to_vk(&self) -> vk::CompositeAlphaFlagsKHR {
+        match self {
+            Self::Opaque => vk::CompositeAlphaFlagsKHR::OPAQUE,
+            Self::PreMultiplied => vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+            Self::PostMultiplied => vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+            Self::Inherit => vk::CompositeAlphaFlagsKHR::INHERIT,
+        }
+    }
+}
+
+/// Selects the composite alpha mode to use for a swapchain. The preferred mode is used if supported,
+/// otherwise the first supported mode of opaque, inherit, pre multiplied and post multiplied is
+/// returned. Returns [`None`] if no known mode is supported.
+fn select_composite_alpha(supported: vk::CompositeAlphaFlagsKHR, preference: CompositeAlphaPreference) -> Option<vk::CompositeAlphaFlagsKHR> {
+    let preferred = preference.to_vk();
+    if supported.contains(preferred) {
+        return Some(preferred);
+    }
+
+    [
+        vk::CompositeAlphaFlagsKHR::OPAQUE,
+        vk::CompositeAlphaFlagsKHR::INHERIT,
+        vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+        vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+    ].into_iter().find(|mode| supported.contains(*mode))
+}
+
 pub struct SwapchainConfig {
     pub allow_tearing: bool,
     pub formats: Box<[vk::SurfaceFormatKHR]>,
     pub required_usage: vk::ImageUsageFlags,
     pub optional_usage: vk::ImageUsageFlags,
     pub clipped: bool,
+    /// If true and VK_EXT_full_screen_exclusive is enabled the implementation is allowed to use
+    /// exclusive full screen for the swapchain. Should only be set if the window covers a whole
+    /// monitor. Ignored if the extension is not enabled.
+    pub full_screen_exclusive: bool,
+    /// The preferred composite alpha mode. If the surface does not support it a supported mode is
+    /// selected instead.
+    pub composite_alpha: CompositeAlphaPreference,
 }
 
 #[derive(Debug)]
 pub enum SwapchainCreateError {
     NoExtent,
+    /// The requested extent is 0 which usually means the window is minimized.
+    ZeroExtent,
     Unsupported,
     Vulkan(vk::Result),
 }
@@ -421,6 +569,18 @@ impl SurfaceSwapchain {
         &self.surface.device
     }
 
+    /// Queries the timing of past presents to this swapchain which have not been queried yet.
+    ///
+    /// Returns [`None`] if VK_GOOGLE_display_timing is not enabled on the device.
+    pub fn get_past_presentation_timing(&self) -> Option<VkResult<Vec<vk::PastPresentationTimingGOOGLE>>> {
+        let display_timing = self.surface.device.display_timing_google.as_ref()?;
+
+        let guard = self.swapchain.lock().unwrap();
+        Some(unsafe {
+            display_timing.get_past_presentation_timing(*guard)
+        })
+    }
+
     fn get_next_acquire(&self) -> usize {
         loop {
             let old = self.acquire_next_index.load(Ordering::SeqCst);
@@ -601,4 +761,21 @@ pub struct AcquiredImageInfo {
     pub acquire_ready_semaphore: SemaphoreOp,
     /// The index of the swapchain image acquired.
     pub image_index: u32,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composite_alpha_selection() {
+        let all = vk::CompositeAlphaFlagsKHR::OPAQUE | vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED | vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED | vk::CompositeAlphaFlagsKHR::INHERIT;
+        assert_eq!(select_composite_alpha(all, CompositeAlphaPreference::PreMultiplied), Some(vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED));
+        assert_eq!(select_composite_alpha(all, CompositeAlphaPreference::Inherit), Some(vk::CompositeAlphaFlagsKHR::INHERIT));
+
+        let no_opaque = vk::CompositeAlphaFlagsKHR::INHERIT | vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED;
+        assert_eq!(select_composite_alpha(no_opaque, CompositeAlphaPreference::Opaque), Some(vk::CompositeAlphaFlagsKHR::INHERIT));
+        assert_eq!(select_composite_alpha(vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED, CompositeAlphaPreference::PreMultiplied), Some(vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED));
+        assert_eq!(select_composite_alpha(vk::CompositeAlphaFlagsKHR::empty(), CompositeAlphaPreference::Opaque), None);
+    }
+}
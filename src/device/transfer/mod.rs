@@ -1,4 +1,5 @@
-mod resource_state;
+pub mod resource_state;
+pub mod one_off;
 mod worker;
 mod allocator;
 mod recorder;
@@ -0,0 +1,398 @@
+//! Standalone uploads submitted directly to the main queue.
+//!
+//! Used to upload data outside of the render loop, for example while loading resources. Every
+//! upload records and submits its own command buffer and returns a [`TransferHandle`] which can be
+//! used to wait for or poll its completion.
+
+use std::sync::Arc;
+
+use ash::vk;
+
+use crate::device::transfer::resource_state::ImageStateTracker;
+use crate::util::format::Format;
+use crate::vk::objects::allocator::{Allocation, AllocationStrategy};
+use crate::vk::objects::image::Image;
+
+use crate::prelude::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OneOffTransferError {
+    /// Staging memory could not be allocated.
+    Allocation,
+    /// The upload does not contain any data.
+    Empty,
+    /// A copy region reads outside of the upload data or its size cannot be determined.
+    InvalidRegion,
+    Vulkan(vk::Result),
+}
+
+impl From<vk::Result> for OneOffTransferError {
+    fn from(err: vk::Result) -> Self {
+        OneOffTransferError::Vulkan(err)
+    }
+}
+
+/// Uploads data to a buffer.
+///
+/// The buffer must have been created with [`vk::BufferUsageFlags::TRANSFER_DST`], must be owned
+/// by the main queue family and must not be accessed by the device until the upload has
+/// completed. Earlier accesses submitted to the main queue are waited on before the data is
+/// written. After completion the written data is visible to all later commands.
+pub fn upload_buffer(device: Arc<DeviceContext>, dst: vk::Buffer, dst_offset: vk::DeviceSize, data: &[u8]) -> Result<TransferHandle, OneOffTransferError> {
+    let size = data.len() as vk::DeviceSize;
+
+    TransferHandle::submit(device, data, |device, cmd, staging_buffer| {
+        // The buffer may still be in use by previous submissions to the main queue
+        let barrier = vk::BufferMemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .buffer(dst)
+            .offset(dst_offset)
+            .size(size);
+        let info = vk::DependencyInfo::builder()
+            .buffer_memory_barriers(std::slice::from_ref(&barrier));
+        unsafe {
+            device.synchronization_2().cmd_pipeline_barrier2(cmd, &info);
+        }
+
+        let region = vk::BufferCopy {
+            src_offset: 0,
+            dst_offset,
+            size
+        };
+        unsafe {
+            device.vk().cmd_copy_buffer(cmd, staging_buffer, dst, std::slice::from_ref(&region));
+        }
+
+        let barrier = vk::BufferMemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+            .buffer(dst)
+            .offset(dst_offset)
+            .size(size);
+        let info = vk::DependencyInfo::builder()
+            .buffer_memory_barriers(std::slice::from_ref(&barrier));
+        unsafe {
+            device.synchronization_2().cmd_pipeline_barrier2(cmd, &info);
+        }
+    })
+}
+
+/// Uploads data to regions of an image.
+///
+/// The buffer offsets of the `regions` are relative to the start of `data`. Every region must be
+/// fully contained in `data`, otherwise [`OneOffTransferError::InvalidRegion`] is returned. The
+/// image is assumed
+/// to be in `current_layout` and is transitioned into `final_layout` after the upload. If
+/// `current_layout` is [`vk::ImageLayout::UNDEFINED`] the previous content of the image is
+/// discarded.
+///
+/// The image must have been created with [`vk::ImageUsageFlags::TRANSFER_DST`], must be owned by
+/// the main queue family and must not be accessed by the device until the upload has completed.
+pub fn upload_image(device: Arc<DeviceContext>, dst: Image, format: &Format, aspect_mask: vk::ImageAspectFlags, regions: &[vk::BufferImageCopy], data: &[u8], current_layout: vk::ImageLayout, final_layout: vk::ImageLayout) -> Result<TransferHandle, OneOffTransferError> {
+    if regions.is_empty() {
+        return Err(OneOffTransferError::Empty);
+    }
+    for region in regions {
+        match get_region_end(format, region) {
+            Some(end) if end <= data.len() as vk::DeviceSize => {},
+            _ => {
+                log::error!("Image upload region {:?} does not fit into {} bytes of data", region, data.len());
+                return Err(OneOffTransferError::InvalidRegion);
+            }
+        }
+    }
+
+    TransferHandle::submit(device, data, |device, cmd, staging_buffer| {
+        let mut tracker = ImageStateTracker::new();
+        tracker.register_with_format(dst, format, aspect_mask, current_layout).unwrap();
+
+        let mut barriers = Vec::new();
+        let handle = tracker.update_state_write(dst.get_id(), &mut barriers).unwrap();
+        unsafe {
+            device.synchronization_2().cmd_pipeline_barrier2(cmd, &vk::DependencyInfo::builder().image_memory_barriers(&barriers));
+            device.vk().cmd_copy_buffer_to_image(cmd, staging_buffer, handle, vk::ImageLayout::TRANSFER_DST_OPTIMAL, regions);
+        }
+
+        barriers.clear();
+        tracker.release_to(dst.get_id(), final_layout, &mut barriers).unwrap();
+        unsafe {
+            device.synchronization_2().cmd_pipeline_barrier2(cmd, &vk::DependencyInfo::builder().image_memory_barriers(&barriers));
+        }
+    })
+}
+
+/// Returns the format describing the buffer layout of a copy to the specified aspect of an image.
+/// Depth and stencil aspects are copied as tightly packed separate components.
+fn get_copy_format(format: &Format, aspect_mask: vk::ImageAspectFlags) -> Option<&Format> {
+    match aspect_mask {
+        vk::ImageAspectFlags::COLOR => Some(format),
+        vk::ImageAspectFlags::DEPTH => match format.depth_bits()? {
+            16 => Some(&Format::D16_UNORM),
+            24 => Some(&Format::X8_D24_UNORM_PACK32),
+            _ => Some(&Format::D32_SFLOAT),
+        },
+        vk::ImageAspectFlags::STENCIL if format.has_stencil() => Some(&Format::S8_UINT),
+        vk::ImageAspectFlags::PLANE_0 => Some(format.plane_info(0)?.format),
+        vk::ImageAspectFlags::PLANE_1 => Some(format.plane_info(1)?.format),
+        vk::ImageAspectFlags::PLANE_2 => Some(format.plane_info(2)?.format),
+        _ => None,
+    }
+}
+
+/// Returns the offset one past the last byte read by a buffer to image copy region or [`None`] if
+/// the region is empty or its size cannot be determined.
+fn get_region_end(format: &Format, region: &vk::BufferImageCopy) -> Option<vk::DeviceSize> {
+    let format = get_copy_format(format, region.image_subresource.aspect_mask)?;
+    let extent = region.image_extent;
+    if extent.width == 0 || extent.height == 0 || extent.depth == 0 || region.image_subresource.layer_count == 0 {
+        return None;
+    }
+
+    let row_length = if region.buffer_row_length == 0 { extent.width } else { region.buffer_row_length };
+    let image_height = if region.buffer_image_height == 0 { extent.height } else { region.buffer_image_height };
+
+    let [_, block_height, block_depth] = format.get_block_extent();
+    let block_height = block_height as vk::DeviceSize;
+    let block_depth = block_depth as vk::DeviceSize;
+
+    let row_pitch = format.row_pitch(row_length);
+    let slice_pitch = ((image_height as vk::DeviceSize + block_height - 1) / block_height).checked_mul(row_pitch)?;
+    let rows = (extent.height as vk::DeviceSize + block_height - 1) / block_height;
+    let slices = ((extent.depth as vk::DeviceSize + block_depth - 1) / block_depth).checked_mul(region.image_subresource.layer_count as vk::DeviceSize)?;
+
+    (slices - 1).checked_mul(slice_pitch)?
+        .checked_add((rows - 1).checked_mul(row_pitch)?)?
+        .checked_add(format.row_pitch(extent.width))?
+        .checked_add(region.buffer_offset)
+}
+
+/// A submitted one-off upload.
+///
+/// Owns the staging memory and command buffer of the upload. Dropping the handle blocks until the
+/// upload has completed.
+pub struct TransferHandle {
+    device: Arc<DeviceContext>,
+    command_pool: vk::CommandPool,
+    fence: vk::Fence,
+    /// False if an error occurred before the upload was submitted. The fence will never be
+    /// signaled in that case.
+    submitted: bool,
+    staging_buffer: vk::Buffer,
+    staging_allocation: Option<Allocation>,
+}
+
+impl TransferHandle {
+    /// Copies `data` into a new staging buffer, records the upload using `record` and submits it
+    /// to the main queue.
+    fn submit<F: FnOnce(&DeviceContext, vk::CommandBuffer, vk::Buffer)>(device: Arc<DeviceContext>, data: &[u8], record: F) -> Result<Self, OneOffTransferError> {
+        if data.is_empty() {
+            return Err(OneOffTransferError::Empty);
+        }
+
+        let queue = device.get_main_queue().clone();
+
+        let pool_info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(queue.get_queue_family_index());
+
+        let command_pool = unsafe {
+            device.vk().create_command_pool(&pool_info, None)
+        }.map_err(|err| {
+            log::error!("vkCreateCommandPool returned {:?} in TransferHandle::submit", err);
+            err
+        })?;
+
+        // From here on the handle takes care of destroying everything on error
+        let mut handle = Self {
+            device,
+            command_pool,
+            fence: vk::Fence::null(),
+            submitted: false,
+            staging_buffer: vk::Buffer::null(),
+            staging_allocation: None,
+        };
+        handle.create_staging(data)?;
+
+        let device = handle.device.clone();
+        handle.fence = unsafe {
+            device.vk().create_fence(&vk::FenceCreateInfo::builder(), None)
+        }.map_err(|err| {
+            log::error!("vkCreateFence returned {:?} in TransferHandle::submit", err);
+            err
+        })?;
+
+        let alloc_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+
+        let cmd = unsafe {
+            device.vk().allocate_command_buffers(&alloc_info)
+        }.map_err(|err| {
+            log::error!("vkAllocateCommandBuffers returned {:?} in TransferHandle::submit", err);
+            err
+        })?[0];
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            device.vk().begin_command_buffer(cmd, &begin_info)
+        }?;
+
+        record(&device, cmd, handle.staging_buffer);
+
+        unsafe {
+            device.vk().end_command_buffer(cmd)
+        }?;
+
+        let cmd_info = vk::CommandBufferSubmitInfo::builder()
+            .command_buffer(cmd);
+        let submit_info = vk::SubmitInfo2::builder()
+            .command_buffer_infos(std::slice::from_ref(&cmd_info));
+
+        unsafe {
+            queue.submit_2(std::slice::from_ref(&submit_info), Some(handle.fence))
+        }.map_err(|err| {
+            log::error!("vkQueueSubmit2 returned {:?} in TransferHandle::submit", err);
+            err
+        })?;
+        handle.submitted = true;
+
+        Ok(handle)
+    }
+
+    fn create_staging(&mut self, data: &[u8]) -> Result<(), OneOffTransferError> {
+        let device = &self.device;
+
+        let info = vk::BufferCreateInfo::builder()
+            .size(data.len() as vk::DeviceSize)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        self.staging_buffer = unsafe {
+            device.vk().create_buffer(&info, None)
+        }.map_err(|err| {
+            log::error!("vkCreateBuffer returned {:?} in TransferHandle::create_staging", err);
+            err
+        })?;
+
+        let allocation = device.get_allocator().allocate_buffer_memory(self.staging_buffer, &AllocationStrategy::AutoGpuCpu).map_err(|_| {
+            log::error!("Failed to allocate staging memory in TransferHandle::create_staging");
+            OneOffTransferError::Allocation
+        })?;
+
+        let bind_result = unsafe {
+            device.vk().bind_buffer_memory(self.staging_buffer, allocation.memory(), allocation.offset())
+        };
+        let mapped = allocation.mapped_ptr();
+        self.staging_allocation = Some(allocation);
+
+        if let Err(err) = bind_result {
+            log::error!("vkBindBufferMemory returned {:?} in TransferHandle::create_staging", err);
+            return Err(OneOffTransferError::Vulkan(err));
+        }
+
+        let mapped = mapped.unwrap_or_else(|| {
+            log::error!("Staging memory is not mapped in TransferHandle::create_staging");
+            panic!()
+        });
+        unsafe {
+            std::slice::from_raw_parts_mut(mapped.as_ptr() as *mut u8, data.len()).copy_from_slice(data);
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the upload has completed execution.
+    pub fn is_complete(&self) -> bool {
+        unsafe {
+            self.device.vk().get_fence_status(self.fence)
+        }.unwrap_or_else(|err| {
+            log::error!("vkGetFenceStatus returned {:?} in TransferHandle::is_complete", err);
+            panic!()
+        })
+    }
+
+    /// Waits until the upload has completed execution or the timeout in nanoseconds has passed.
+    /// Returns true if the upload has completed.
+    pub fn wait(&self, timeout: u64) -> bool {
+        match unsafe {
+            self.device.vk().wait_for_fences(std::slice::from_ref(&self.fence), true, timeout)
+        } {
+            Ok(_) => true,
+            Err(vk::Result::TIMEOUT) => false,
+            Err(err) => {
+                log::error!("vkWaitForFences returned {:?} in TransferHandle::wait", err);
+                panic!()
+            }
+        }
+    }
+}
+
+impl Drop for TransferHandle {
+    fn drop(&mut self) {
+        if self.submitted {
+            self.wait(u64::MAX);
+        }
+
+        let device = &self.device;
+        unsafe {
+            if self.fence != vk::Fence::null() {
+                device.vk().destroy_fence(self.fence, None);
+            }
+            device.vk().destroy_command_pool(self.command_pool, None);
+            if self.staging_buffer != vk::Buffer::null() {
+                device.vk().destroy_buffer(self.staging_buffer, None);
+            }
+        }
+        if let Some(allocation) = self.staging_allocation.take() {
+            device.get_allocator().free(allocation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(aspect_mask: vk::ImageAspectFlags, buffer_offset: vk::DeviceSize, row_length: u32, image_height: u32, width: u32, height: u32, layer_count: u32) -> vk::BufferImageCopy {
+        vk::BufferImageCopy {
+            buffer_offset,
+            buffer_row_length: row_length,
+            buffer_image_height: image_height,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: vk::Extent3D { width, height, depth: 1 },
+        }
+    }
+
+    #[test]
+    fn region_end() {
+        let color = vk::ImageAspectFlags::COLOR;
+        assert_eq!(get_region_end(&Format::R8G8B8A8_UNORM, &region(color, 0, 0, 0, 4, 4, 1)), Some(64));
+        assert_eq!(get_region_end(&Format::R8G8B8A8_UNORM, &region(color, 16, 0, 0, 4, 4, 1)), Some(80));
+        // Padding after the last row is not read
+        assert_eq!(get_region_end(&Format::R8G8B8A8_UNORM, &region(color, 0, 8, 0, 4, 4, 1)), Some(3 * 32 + 16));
+        assert_eq!(get_region_end(&Format::R8G8B8A8_UNORM, &region(color, 0, 0, 8, 4, 4, 2)), Some(8 * 16 + 64));
+        assert_eq!(get_region_end(&Format::BC1_RGB_UNORM_BLOCK, &region(color, 0, 0, 0, 5, 5, 1)), Some(32));
+
+        assert_eq!(get_region_end(&Format::D24_UNORM_S8_UINT, &region(vk::ImageAspectFlags::DEPTH, 0, 0, 0, 4, 4, 1)), Some(64));
+        assert_eq!(get_region_end(&Format::D24_UNORM_S8_UINT, &region(vk::ImageAspectFlags::STENCIL, 0, 0, 0, 4, 4, 1)), Some(16));
+        assert_eq!(get_region_end(&Format::G8_B8R8_2PLANE_420_UNORM, &region(vk::ImageAspectFlags::PLANE_1, 0, 0, 0, 2, 2, 1)), Some(8));
+
+        assert_eq!(get_region_end(&Format::R8G8B8A8_UNORM, &region(vk::ImageAspectFlags::DEPTH, 0, 0, 0, 4, 4, 1)), None);
+        assert_eq!(get_region_end(&Format::R8G8B8A8_UNORM, &region(color, 0, 0, 0, 0, 4, 1)), None);
+        assert_eq!(get_region_end(&Format::R32G32B32A32_SFLOAT, &region(color, u64::MAX, 0, 0, 4, 4, 1)), None);
+    }
+}
@@ -127,7 +127,7 @@ impl Recorder {
                 .image_memory_barriers(self.pending_image_barriers.as_slice());
 
             unsafe {
-                self.device.synchronization_2_khr.cmd_pipeline_barrier2(cmd, &info)
+                self.device.synchronization_2.cmd_pipeline_barrier2(cmd, &info)
             };
 
             self.pending_buffer_barriers.clear();
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use ash::vk;
 use crate::objects::id::ImageId;
+use crate::util::format::Format;
 
 use crate::vk::objects::buffer::Buffer;
 use crate::vk::objects::image::Image;
@@ -17,8 +18,14 @@ pub struct BufferState {
 
 impl BufferState {
     pub fn new(buffer: Buffer, offset: vk::DeviceSize, size: vk::DeviceSize) -> Self {
+        Self::new_raw(buffer.get_handle(), offset, size)
+    }
+
+    /// Creates a new buffer state for a raw buffer handle. This can be used to track buffers which
+    /// are not owned by this crate.
+    pub fn new_raw(handle: vk::Buffer, offset: vk::DeviceSize, size: vk::DeviceSize) -> Self {
         Self {
-            handle: buffer.get_handle(),
+            handle,
             read_pending: false,
             write_pending: false,
             access_offset: offset,
@@ -56,6 +63,13 @@ impl BufferState {
     }
 }
 
+/// Tracks the layout and pending transfer accesses of images to generate the necessary barriers.
+///
+/// In debug builds the following invariants are checked:
+/// - The aspect mask passed when registering an image must be non empty. Images registered with
+///   [`ImageStateTracker::register_with_format`] must only use aspects of the image format.
+/// - An image released by [`ImageStateTracker::release`] must not be in a transfer layout. Use
+///   [`ImageStateTracker::release_to`] to transition the image out of the transfer layout instead.
 pub struct ImageStateTracker {
     images: HashMap<ImageId, ImageState>,
 }
@@ -68,13 +82,37 @@ impl ImageStateTracker {
     }
 
     pub fn register(&mut self, image: Image, aspect_mask: vk::ImageAspectFlags, layout: vk::ImageLayout) -> Result<(), ()> {
-        if self.images.contains_key(&image.get_id()) {
+        self.register_raw(image.get_id(), image.get_handle(), aspect_mask, layout)
+    }
+
+    /// Same as [`ImageStateTracker::register`] but additionally validates the aspect mask against
+    /// the format of the image in debug builds.
+    pub fn register_with_format(&mut self, image: Image, format: &Format, aspect_mask: vk::ImageAspectFlags, layout: vk::ImageLayout) -> Result<(), ()> {
+        self.register_raw_with_format(image.get_id(), image.get_handle(), format, aspect_mask, layout)
+    }
+
+    /// Registers a raw image handle. This can be used to track images which are not owned by this
+    /// crate (for example swapchain images).
+    pub fn register_raw(&mut self, id: ImageId, handle: vk::Image, aspect_mask: vk::ImageAspectFlags, layout: vk::ImageLayout) -> Result<(), ()> {
+        debug_assert!(!aspect_mask.is_empty(), "Registered image {:?} with an empty aspect mask", id);
+
+        if self.images.contains_key(&id) {
             return Err(());
         }
-        self.images.insert(image.get_id(), ImageState::new(image, aspect_mask, layout));
+        self.images.insert(id, ImageState::new(handle, aspect_mask, layout));
         Ok(())
     }
 
+    /// Same as [`ImageStateTracker::register_raw`] but additionally validates the aspect mask
+    /// against the format of the image in debug builds.
+    pub fn register_raw_with_format(&mut self, id: ImageId, handle: vk::Image, format: &Format, aspect_mask: vk::ImageAspectFlags, layout: vk::ImageLayout) -> Result<(), ()> {
+        // Multi-planar formats are accessed through plane aspects which are not part of the format aspect mask
+        debug_assert!(format.get_texel_block_size() == 0 || format.aspect_mask().contains(aspect_mask),
+            "Registered image {:?} with aspect mask {:?} which does not match the format {:?}", id, aspect_mask, format.get_format());
+
+        self.register_raw(id, handle, aspect_mask, layout)
+    }
+
     pub fn update_state_read(&mut self, image: ImageId, barriers: &mut Vec<vk::ImageMemoryBarrier2>) -> Option<vk::Image> {
         if let Some(image) = self.images.get_mut(&image) {
             image.update_state_read(barriers);
@@ -93,8 +131,15 @@ impl ImageStateTracker {
         }
     }
 
+    /// Stops tracking the image and returns its handle, aspect mask, pending accesses and layout.
+    ///
+    /// The image must not be in a transfer layout. Use [`ImageStateTracker::release_to`] after
+    /// the image has been used for transfer operations.
     pub fn release(&mut self, id: ImageId) -> Option<(vk::Image, vk::ImageAspectFlags, vk::AccessFlags2, vk::ImageLayout)> {
         if let Some(image) = self.images.remove(&id) {
+            debug_assert!(image.layout != vk::ImageLayout::TRANSFER_SRC_OPTIMAL && image.layout != vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                "Released image {:?} while it is still in transfer layout {:?}", id, image.layout);
+
             let mut access_mask = vk::AccessFlags2::empty();
             if image.read_pending {
                 access_mask |= vk::AccessFlags2::TRANSFER_READ;
@@ -108,6 +153,56 @@ impl ImageStateTracker {
             None
         }
     }
+
+    /// Stops tracking the image and pushes a barrier transitioning it into `layout`. The barrier
+    /// makes all pending transfer accesses available to any later command.
+    pub fn release_to(&mut self, id: ImageId, layout: vk::ImageLayout, barriers: &mut Vec<vk::ImageMemoryBarrier2>) -> Option<vk::Image> {
+        self.release_with(id, layout, vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE, barriers)
+    }
+
+    /// Stops tracking a swapchain image and pushes the barrier transitioning it into
+    /// [`vk::ImageLayout::PRESENT_SRC_KHR`].
+    ///
+    /// The presentation engine does not access the image through a pipeline stage so the barrier
+    /// has no destination scope. Visibility is provided by the semaphore passed to the present
+    /// call which must be signaled by a submission containing this barrier.
+    pub fn release_for_present(&mut self, id: ImageId, barriers: &mut Vec<vk::ImageMemoryBarrier2>) -> Option<vk::Image> {
+        self.release_with(id, vk::ImageLayout::PRESENT_SRC_KHR, vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE, barriers)
+    }
+
+    fn release_with(&mut self, id: ImageId, layout: vk::ImageLayout, dst_stage_mask: vk::PipelineStageFlags2, dst_access_mask: vk::AccessFlags2, barriers: &mut Vec<vk::ImageMemoryBarrier2>) -> Option<vk::Image> {
+        if let Some(image) = self.images.remove(&id) {
+            let mut access_mask = vk::AccessFlags2::empty();
+            if image.read_pending {
+                access_mask |= vk::AccessFlags2::TRANSFER_READ;
+            }
+            if image.write_pending {
+                access_mask |= vk::AccessFlags2::TRANSFER_WRITE;
+            }
+
+            barriers.push(vk::ImageMemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .src_access_mask(access_mask)
+                .dst_stage_mask(dst_stage_mask)
+                .dst_access_mask(dst_access_mask)
+                .old_layout(image.layout)
+                .new_layout(layout)
+                .image(image.handle)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: image.aspect_mask,
+                    base_mip_level: 0,
+                    level_count: vk::REMAINING_MIP_LEVELS,
+                    base_array_layer: 0,
+                    layer_count: vk::REMAINING_ARRAY_LAYERS
+                })
+                .build()
+            );
+
+            Some(image.handle)
+        } else {
+            None
+        }
+    }
 }
 
 struct ImageState {
@@ -119,9 +214,9 @@ struct ImageState {
 }
 
 impl ImageState {
-    fn new(image: Image, aspect_mask: vk::ImageAspectFlags, layout: vk::ImageLayout) -> Self {
+    fn new(handle: vk::Image, aspect_mask: vk::ImageAspectFlags, layout: vk::ImageLayout) -> Self {
         Self {
-            handle: image.get_handle(),
+            handle,
             aspect_mask,
             layout,
             read_pending: false,
@@ -180,4 +275,444 @@ impl ImageState {
             self.read_pending = false;
         }
     }
-}
\ No newline at end of file
+}
+
+/// Identifies a pass within a [`PassDependencyTracker`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PassId(u32);
+
+/// Describes how a pass accesses an image.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ImageUsage {
+    TransferRead,
+    TransferWrite,
+    ColorAttachmentWrite,
+    DepthStencilAttachmentWrite,
+    /// Read only depth stencil attachment (depth test without depth writes).
+    DepthStencilAttachmentRead,
+    /// Sampled by shaders in the specified stages.
+    Sampled(vk::PipelineStageFlags2),
+    StorageRead(vk::PipelineStageFlags2),
+    StorageWrite(vk::PipelineStageFlags2),
+}
+
+impl ImageUsage {
+    pub fn is_write(&self) -> bool {
+        match self {
+            ImageUsage::TransferWrite |
+            ImageUsage::ColorAttachmentWrite |
+            ImageUsage::DepthStencilAttachmentWrite |
+            ImageUsage::StorageWrite(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn get_stage_mask(&self) -> vk::PipelineStageFlags2 {
+        match self {
+            ImageUsage::TransferRead |
+            ImageUsage::TransferWrite => vk::PipelineStageFlags2::TRANSFER,
+            ImageUsage::ColorAttachmentWrite => vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            ImageUsage::DepthStencilAttachmentWrite |
+            ImageUsage::DepthStencilAttachmentRead => vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+            ImageUsage::Sampled(stages) |
+            ImageUsage::StorageRead(stages) |
+            ImageUsage::StorageWrite(stages) => *stages,
+        }
+    }
+
+    pub fn get_access_mask(&self) -> vk::AccessFlags2 {
+        match self {
+            ImageUsage::TransferRead => vk::AccessFlags2::TRANSFER_READ,
+            ImageUsage::TransferWrite => vk::AccessFlags2::TRANSFER_WRITE,
+            ImageUsage::ColorAttachmentWrite => vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            ImageUsage::DepthStencilAttachmentWrite => vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ImageUsage::DepthStencilAttachmentRead => vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+            ImageUsage::Sampled(_) => vk::AccessFlags2::SHADER_SAMPLED_READ,
+            ImageUsage::StorageRead(_) => vk::AccessFlags2::SHADER_STORAGE_READ,
+            ImageUsage::StorageWrite(_) => vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+        }
+    }
+
+    pub fn get_layout(&self) -> vk::ImageLayout {
+        match self {
+            ImageUsage::TransferRead => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageUsage::TransferWrite => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageUsage::ColorAttachmentWrite => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageUsage::DepthStencilAttachmentWrite => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ImageUsage::DepthStencilAttachmentRead => vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ImageUsage::Sampled(_) => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ImageUsage::StorageRead(_) |
+            ImageUsage::StorageWrite(_) => vk::ImageLayout::GENERAL,
+        }
+    }
+}
+
+/// Tracks image accesses across a sequence of passes and generates the barriers needed between
+/// them.
+///
+/// Each pass declares the images it reads and writes together with their [`ImageUsage`]. The
+/// tracker records the last writer of every image as well as all reads since that write and
+/// generates the layout transitions and memory dependencies required by the declared usage.
+/// Accesses within the same pass using the same usage are assumed to be synchronized by the pass
+/// itself (for example attachment writes inside a render pass) and do not generate barriers.
+///
+/// Images used for transfer operations through an [`ImageStateTracker`] can be handed over with
+/// [`PassDependencyTracker::adopt`].
+///
+/// The emulator worker uses a tracker per pass for the global images written as storage images.
+/// Global images are in the ready state outside of passes so they are registered with
+/// [`PassDependencyTracker::register_raw_read`] and released back into the ready layout once the
+/// pass has been recorded.
+pub struct PassDependencyTracker {
+    images: HashMap<ImageId, PassImageState>,
+    next_pass: u32,
+    current_pass: Option<PassId>,
+}
+
+impl PassDependencyTracker {
+    pub fn new() -> Self {
+        Self {
+            images: HashMap::new(),
+            next_pass: 0,
+            current_pass: None,
+        }
+    }
+
+    /// Starts a new pass. All following declarations are attributed to this pass.
+    pub fn begin_pass(&mut self) -> PassId {
+        let pass = PassId(self.next_pass);
+        self.next_pass += 1;
+        self.current_pass = Some(pass);
+        pass
+    }
+
+    pub fn get_current_pass(&self) -> Option<PassId> {
+        self.current_pass
+    }
+
+    pub fn register(&mut self, image: Image, aspect_mask: vk::ImageAspectFlags, layout: vk::ImageLayout) -> Result<(), ()> {
+        self.register_raw(image.get_id(), image.get_handle(), aspect_mask, layout)
+    }
+
+    /// Registers a raw image handle. The image is assumed to be in `layout` with all previous
+    /// accesses being visible.
+    pub fn register_raw(&mut self, id: ImageId, handle: vk::Image, aspect_mask: vk::ImageAspectFlags, layout: vk::ImageLayout) -> Result<(), ()> {
+        debug_assert!(!aspect_mask.is_empty(), "Registered image {:?} with an empty aspect mask", id);
+
+        if self.images.contains_key(&id) {
+            return Err(());
+        }
+        self.images.insert(id, PassImageState::new(handle, aspect_mask, layout));
+        Ok(())
+    }
+
+    /// Registers a raw image handle which is in the layout of `usage` and may still be read by
+    /// previously submitted work. The first write or layout transition waits for those reads.
+    pub fn register_raw_read(&mut self, id: ImageId, handle: vk::Image, aspect_mask: vk::ImageAspectFlags, usage: ImageUsage) -> Result<(), ()> {
+        debug_assert!(!usage.is_write(), "Registered image {:?} with write usage {:?}", id, usage);
+
+        self.register_raw(id, handle, aspect_mask, usage.get_layout())?;
+        let image = self.images.get_mut(&id).unwrap();
+        image.read_stages = usage.get_stage_mask();
+        image.read_access = usage.get_access_mask();
+        Ok(())
+    }
+
+    /// Takes over an image from a [`ImageStateTracker`]. Pending transfer writes are treated as
+    /// if they were performed by a pass preceding all passes of this tracker.
+    pub fn adopt(&mut self, tracker: &mut ImageStateTracker, id: ImageId) -> Result<(), ()> {
+        if self.images.contains_key(&id) {
+            return Err(());
+        }
+        let (handle, aspect_mask, access_mask, layout) = tracker.release(id).ok_or(())?;
+
+        let mut state = PassImageState::new(handle, aspect_mask, layout);
+        if access_mask.contains(vk::AccessFlags2::TRANSFER_WRITE) {
+            state.last_write = Some((None, ImageUsage::TransferWrite));
+            state.write_pending = true;
+        } else if access_mask.contains(vk::AccessFlags2::TRANSFER_READ) {
+            state.read_stages = vk::PipelineStageFlags2::TRANSFER;
+            state.read_access = vk::AccessFlags2::TRANSFER_READ;
+        }
+        self.images.insert(id, state);
+        Ok(())
+    }
+
+    /// Returns the pass which last wrote to the image if any.
+    pub fn get_last_writer(&self, id: ImageId) -> Option<PassId> {
+        self.images.get(&id).and_then(|image| image.last_write).and_then(|(pass, _)| pass)
+    }
+
+    /// Declares a read of the image by the current pass and pushes any barrier required before
+    /// the access.
+    pub fn declare_read(&mut self, id: ImageId, usage: ImageUsage, barriers: &mut Vec<vk::ImageMemoryBarrier2>) -> Option<vk::Image> {
+        debug_assert!(!usage.is_write(), "Declared read of image {:?} with write usage {:?}", id, usage);
+        self.declare(id, usage, barriers)
+    }
+
+    /// Declares a write of the image by the current pass and pushes any barrier required before
+    /// the access.
+    pub fn declare_write(&mut self, id: ImageId, usage: ImageUsage, barriers: &mut Vec<vk::ImageMemoryBarrier2>) -> Option<vk::Image> {
+        debug_assert!(usage.is_write(), "Declared write of image {:?} with read usage {:?}", id, usage);
+        self.declare(id, usage, barriers)
+    }
+
+    /// Stops tracking the image and pushes a barrier transitioning it into `layout`. The barrier
+    /// makes all pending accesses available to any later command.
+    pub fn release_to(&mut self, id: ImageId, layout: vk::ImageLayout, barriers: &mut Vec<vk::ImageMemoryBarrier2>) -> Option<vk::Image> {
+        self.release_with(id, layout, vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE, barriers)
+    }
+
+    /// Stops tracking a swapchain image and pushes the barrier transitioning it into
+    /// [`vk::ImageLayout::PRESENT_SRC_KHR`]. The source scope covers all pending accesses (for
+    /// example the color attachment writes of the last pass).
+    ///
+    /// See [`ImageStateTracker::release_for_present`] for the requirements on the submission.
+    pub fn release_for_present(&mut self, id: ImageId, barriers: &mut Vec<vk::ImageMemoryBarrier2>) -> Option<vk::Image> {
+        self.release_with(id, vk::ImageLayout::PRESENT_SRC_KHR, vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE, barriers)
+    }
+
+    fn release_with(&mut self, id: ImageId, layout: vk::ImageLayout, dst_stage_mask: vk::PipelineStageFlags2, dst_access_mask: vk::AccessFlags2, barriers: &mut Vec<vk::ImageMemoryBarrier2>) -> Option<vk::Image> {
+        if let Some(image) = self.images.remove(&id) {
+            let (src_stage_mask, src_access_mask) = image.get_pending_access();
+            barriers.push(image.make_barrier(src_stage_mask, src_access_mask, dst_stage_mask, dst_access_mask, layout));
+            Some(image.handle)
+        } else {
+            None
+        }
+    }
+
+    fn declare(&mut self, id: ImageId, usage: ImageUsage, barriers: &mut Vec<vk::ImageMemoryBarrier2>) -> Option<vk::Image> {
+        let pass = self.current_pass;
+        debug_assert!(pass.is_some(), "Declared access to image {:?} outside of a pass", id);
+
+        let image = self.images.get_mut(&id)?;
+        image.update_state(pass, usage, barriers);
+        Some(image.handle)
+    }
+}
+
+struct PassImageState {
+    handle: vk::Image,
+    aspect_mask: vk::ImageAspectFlags,
+    layout: vk::ImageLayout,
+    /// The pass and usage of the last write. The pass is [`None`] for writes performed before the
+    /// image was handed to the tracker.
+    last_write: Option<(Option<PassId>, ImageUsage)>,
+    /// Set if the last write has not yet been made visible to any later access.
+    write_pending: bool,
+    /// The stages and accesses which read the image since the last write or layout transition.
+    /// The last write is visible to all of them.
+    read_stages: vk::PipelineStageFlags2,
+    read_access: vk::AccessFlags2,
+}
+
+impl PassImageState {
+    fn new(handle: vk::Image, aspect_mask: vk::ImageAspectFlags, layout: vk::ImageLayout) -> Self {
+        Self {
+            handle,
+            aspect_mask,
+            layout,
+            last_write: None,
+            write_pending: false,
+            read_stages: vk::PipelineStageFlags2::NONE,
+            read_access: vk::AccessFlags2::NONE,
+        }
+    }
+
+    fn update_state(&mut self, pass: Option<PassId>, usage: ImageUsage, barriers: &mut Vec<vk::ImageMemoryBarrier2>) {
+        let layout = usage.get_layout();
+        let layout_change = layout != self.layout;
+
+        if usage.is_write() {
+            let same_scope = self.last_write == Some((pass, usage)) && self.read_stages.is_empty();
+            if layout_change || !same_scope {
+                let (src_stage_mask, src_access_mask) = self.get_pending_access();
+                if layout_change || !src_stage_mask.is_empty() {
+                    barriers.push(self.make_barrier(src_stage_mask, src_access_mask, usage.get_stage_mask(), usage.get_access_mask(), layout));
+                }
+            }
+
+            self.last_write = Some((pass, usage));
+            self.write_pending = true;
+            self.read_stages = vk::PipelineStageFlags2::NONE;
+            self.read_access = vk::AccessFlags2::NONE;
+        } else {
+            let stage_mask = usage.get_stage_mask();
+            let access_mask = usage.get_access_mask();
+            if layout_change {
+                // The transition must wait for both the last write and any reads in the old layout
+                let (src_stage_mask, src_access_mask) = self.get_pending_access();
+                barriers.push(self.make_barrier(src_stage_mask, src_access_mask, stage_mask, access_mask, layout));
+                self.read_stages = vk::PipelineStageFlags2::NONE;
+                self.read_access = vk::AccessFlags2::NONE;
+            } else if let Some((_, write_usage)) = self.last_write {
+                if self.write_pending || !self.read_stages.contains(stage_mask) || !self.read_access.contains(access_mask) {
+                    barriers.push(self.make_barrier(write_usage.get_stage_mask(), write_usage.get_access_mask() & Self::WRITE_ACCESS, stage_mask, access_mask, layout));
+                }
+            }
+
+            self.write_pending = false;
+            self.read_stages |= stage_mask;
+            self.read_access |= access_mask;
+        }
+        self.layout = layout;
+    }
+
+    /// Returns the source stage and access masks covering all accesses which have to complete
+    /// before the image may be written or transitioned.
+    fn get_pending_access(&self) -> (vk::PipelineStageFlags2, vk::AccessFlags2) {
+        let mut stage_mask = self.read_stages;
+        let mut access_mask = vk::AccessFlags2::NONE;
+        if let Some((_, usage)) = self.last_write {
+            if self.write_pending {
+                stage_mask |= usage.get_stage_mask();
+                access_mask |= usage.get_access_mask() & Self::WRITE_ACCESS;
+            }
+        }
+        (stage_mask, access_mask)
+    }
+
+    const WRITE_ACCESS: vk::AccessFlags2 = vk::AccessFlags2::from_raw(
+        vk::AccessFlags2::TRANSFER_WRITE.as_raw() |
+        vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw() |
+        vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw() |
+        vk::AccessFlags2::SHADER_STORAGE_WRITE.as_raw()
+    );
+
+    fn make_barrier(&self, src_stage_mask: vk::PipelineStageFlags2, src_access_mask: vk::AccessFlags2, dst_stage_mask: vk::PipelineStageFlags2, dst_access_mask: vk::AccessFlags2, new_layout: vk::ImageLayout) -> vk::ImageMemoryBarrier2 {
+        vk::ImageMemoryBarrier2::builder()
+            .src_stage_mask(src_stage_mask)
+            .src_access_mask(src_access_mask)
+            .dst_stage_mask(dst_stage_mask)
+            .dst_access_mask(dst_access_mask)
+            .old_layout(self.layout)
+            .new_layout(new_layout)
+            .image(self.handle)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: self.aspect_mask,
+                base_mip_level: 0,
+                level_count: vk::REMAINING_MIP_LEVELS,
+                base_array_layer: 0,
+                layer_count: vk::REMAINING_ARRAY_LAYERS
+            })
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker_with_image(layout: vk::ImageLayout) -> (PassDependencyTracker, ImageId) {
+        let id = ImageId::new();
+        let mut tracker = PassDependencyTracker::new();
+        tracker.register_raw(id, vk::Image::null(), vk::ImageAspectFlags::COLOR, layout).unwrap();
+        (tracker, id)
+    }
+
+    #[test]
+    fn write_then_sample() {
+        let (mut tracker, id) = tracker_with_image(vk::ImageLayout::UNDEFINED);
+        let mut barriers = Vec::new();
+
+        let render = tracker.begin_pass();
+        tracker.declare_write(id, ImageUsage::ColorAttachmentWrite, &mut barriers).unwrap();
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(barriers[0].new_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+        // Repeated writes within the same pass are synchronized by the pass itself
+        tracker.declare_write(id, ImageUsage::ColorAttachmentWrite, &mut barriers).unwrap();
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(tracker.get_last_writer(id), Some(render));
+
+        barriers.clear();
+        tracker.begin_pass();
+        tracker.declare_read(id, ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER), &mut barriers).unwrap();
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(barriers[0].old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(barriers[0].new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(barriers[0].src_stage_mask, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+        assert_eq!(barriers[0].src_access_mask, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+
+        // The write is already visible to the fragment shader
+        barriers.clear();
+        tracker.begin_pass();
+        tracker.declare_read(id, ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER), &mut barriers).unwrap();
+        assert!(barriers.is_empty());
+
+        tracker.declare_read(id, ImageUsage::Sampled(vk::PipelineStageFlags2::VERTEX_SHADER), &mut barriers).unwrap();
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(barriers[0].old_layout, barriers[0].new_layout);
+    }
+
+    #[test]
+    fn write_after_read() {
+        let (mut tracker, id) = tracker_with_image(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        let mut barriers = Vec::new();
+
+        tracker.begin_pass();
+        tracker.declare_read(id, ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER), &mut barriers).unwrap();
+        assert!(barriers.is_empty());
+
+        tracker.begin_pass();
+        tracker.declare_write(id, ImageUsage::ColorAttachmentWrite, &mut barriers).unwrap();
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(barriers[0].src_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+        assert_eq!(barriers[0].src_access_mask, vk::AccessFlags2::NONE);
+
+        barriers.clear();
+        tracker.release_to(id, vk::ImageLayout::PRESENT_SRC_KHR, &mut barriers).unwrap();
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(barriers[0].src_access_mask, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+        assert!(tracker.declare_read(id, ImageUsage::TransferRead, &mut barriers).is_none());
+    }
+
+    #[test]
+    fn write_after_external_read() {
+        let id = ImageId::new();
+        let mut tracker = PassDependencyTracker::new();
+        tracker.register_raw_read(id, vk::Image::null(), vk::ImageAspectFlags::COLOR, ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER)).unwrap();
+        assert!(tracker.register_raw_read(id, vk::Image::null(), vk::ImageAspectFlags::COLOR, ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER)).is_err());
+
+        let mut barriers = Vec::new();
+        tracker.begin_pass();
+        tracker.declare_write(id, ImageUsage::StorageWrite(vk::PipelineStageFlags2::COMPUTE_SHADER), &mut barriers).unwrap();
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(barriers[0].old_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(barriers[0].new_layout, vk::ImageLayout::GENERAL);
+        assert_eq!(barriers[0].src_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+    }
+
+    #[test]
+    fn release_for_present() {
+        let (mut tracker, id) = tracker_with_image(vk::ImageLayout::UNDEFINED);
+        let mut barriers = Vec::new();
+
+        tracker.begin_pass();
+        tracker.declare_write(id, ImageUsage::ColorAttachmentWrite, &mut barriers).unwrap();
+
+        barriers.clear();
+        tracker.release_for_present(id, &mut barriers).unwrap();
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(barriers[0].old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(barriers[0].new_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+        assert_eq!(barriers[0].src_stage_mask, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+        assert_eq!(barriers[0].src_access_mask, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+        assert_eq!(barriers[0].dst_stage_mask, vk::PipelineStageFlags2::NONE);
+        assert_eq!(barriers[0].dst_access_mask, vk::AccessFlags2::NONE);
+
+        let id = ImageId::new();
+        let mut tracker = ImageStateTracker::new();
+        tracker.register_raw_with_format(id, vk::Image::null(), &Format::B8G8R8A8_SRGB, vk::ImageAspectFlags::COLOR, vk::ImageLayout::UNDEFINED).unwrap();
+        barriers.clear();
+        tracker.update_state_write(id, &mut barriers).unwrap();
+
+        barriers.clear();
+        tracker.release_for_present(id, &mut barriers).unwrap();
+        assert_eq!(barriers[0].old_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        assert_eq!(barriers[0].new_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+        assert_eq!(barriers[0].src_access_mask, vk::AccessFlags2::TRANSFER_WRITE);
+        assert_eq!(barriers[0].dst_stage_mask, vk::PipelineStageFlags2::NONE);
+    }
+}
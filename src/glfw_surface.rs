@@ -49,8 +49,8 @@ impl GLFWSurfaceProvider {
 }
 
 impl SurfaceProvider for GLFWSurfaceProvider {
-    fn get_required_instance_extensions(&self) -> Vec<CString> {
-        self.required_extension.clone()
+    fn get_required_instance_extensions(&self) -> Result<Vec<CString>, SurfaceInitError> {
+        Ok(self.required_extension.clone())
     }
 
     fn init(&mut self, entry: &ash::Entry, instance: &ash::Instance) -> Result<vk::SurfaceKHR, SurfaceInitError> {
@@ -13,27 +13,42 @@ pub trait DebugMessengerCallback: Send + Sync + UnwindSafe + RefUnwindSafe + Deb
     );
 }
 
+/// Forwards debug messages to the rust [`log`] crate.
 #[derive(Debug)]
 pub struct RustLogDebugMessenger {
+    target: String,
 }
 
 impl RustLogDebugMessenger {
+    /// Creates a new messenger logging to the target of this module.
     pub fn new() -> Self {
+        Self::with_target(module_path!())
+    }
+
+    /// Creates a new messenger logging to a custom target. This can be used to filter validation
+    /// messages independently of other logs.
+    pub fn with_target(target: &str) -> Self {
         Self {
+            target: target.to_string(),
         }
     }
+
+    pub fn get_target(&self) -> &str {
+        &self.target
+    }
 }
 
 impl DebugMessengerCallback for RustLogDebugMessenger {
     fn on_message(&self, message_severity: vk::DebugUtilsMessageSeverityFlagsEXT, _: vk::DebugUtilsMessageTypeFlagsEXT, message: &CStr, _: &vk::DebugUtilsMessengerCallbackDataEXT) {
+        let target = self.target.as_str();
         if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
-            log::error!("{:?}", message);
+            log::log!(target: target, log::Level::Error, "{:?}", message);
         } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
-            log::warn!("{:?}", message);
+            log::log!(target: target, log::Level::Warn, "{:?}", message);
         } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
-            log::info!("{:?}", message);
+            log::log!(target: target, log::Level::Info, "{:?}", message);
         } else {
-            log::info!("Unknown severity: {:?}", message);
+            log::log!(target: target, log::Level::Info, "Unknown severity: {:?}", message);
         }
     }
 }
\ No newline at end of file
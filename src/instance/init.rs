@@ -21,6 +21,9 @@ pub struct InstanceCreateConfig {
     debug_messengers: Vec<DebugUtilsMessengerWrapper>,
     enable_validation: bool,
     required_extensions: HashSet<CString>,
+    optional_extensions: HashSet<CString>,
+    required_layers: HashSet<CString>,
+    optional_layers: HashSet<CString>,
     require_surface_khr: bool,
 }
 
@@ -32,6 +35,9 @@ impl InstanceCreateConfig {
             debug_messengers: Vec::new(),
             enable_validation: false,
             required_extensions: HashSet::new(),
+            optional_extensions: HashSet::new(),
+            required_layers: HashSet::new(),
+            optional_layers: HashSet::new(),
             require_surface_khr: false,
         }
     }
@@ -40,14 +46,35 @@ impl InstanceCreateConfig {
         self.debug_messengers.push(DebugUtilsMessengerWrapper{ callback: messenger });
     }
 
+    /// Requires the `VK_LAYER_KHRONOS_validation` layer.
     pub fn enable_validation(&mut self) {
         self.enable_validation = true;
     }
 
+    /// Adds an instance extension which must be supported. If it is not supported instance
+    /// creation fails with [`InstanceCreateError::MissingExtensions`].
     pub fn add_required_extension(&mut self, extension: &CStr) {
         self.required_extensions.insert(CString::from(extension));
     }
 
+    /// Adds an instance extension which is enabled if it is supported. Use
+    /// [`InstanceContext::is_extension_enabled`] to check if it was enabled.
+    pub fn add_optional_extension(&mut self, extension: &CStr) {
+        self.optional_extensions.insert(CString::from(extension));
+    }
+
+    /// Adds a layer which must be available. If it is not available instance creation fails with
+    /// [`InstanceCreateError::MissingLayers`].
+    pub fn add_required_layer(&mut self, layer: &CStr) {
+        self.required_layers.insert(CString::from(layer));
+    }
+
+    /// Adds a layer which is enabled if it is available. Use [`InstanceContext::is_layer_enabled`]
+    /// to check if it was enabled.
+    pub fn add_optional_layer(&mut self, layer: &CStr) {
+        self.optional_layers.insert(CString::from(layer));
+    }
+
     pub fn require_surface_khr(&mut self) {
         self.require_surface_khr = true;
     }
@@ -57,7 +84,10 @@ impl InstanceCreateConfig {
 pub enum InstanceCreateError {
     Vulkan(vk::Result),
     ProfileNotSupported,
-    MissingExtension(CString),
+    /// All required extensions which are not supported.
+    MissingExtensions(Vec<CString>),
+    /// All required layers which are not available.
+    MissingLayers(Vec<CString>),
     Utf8Error(Utf8Error),
 }
 
@@ -94,6 +124,19 @@ pub fn create_instance(config: InstanceCreateConfig) -> Result<Arc<InstanceConte
         return Err(InstanceCreateError::ProfileNotSupported)
     }
 
+    let mut required_layers = config.required_layers;
+    if config.enable_validation {
+        required_layers.insert(CString::from(CStr::from_bytes_with_nul(b"VK_LAYER_KHRONOS_validation\0").unwrap()));
+    }
+
+    let available_layers: HashSet<_> = entry.enumerate_instance_layer_properties()?
+        .into_iter().map(|layer| {
+            CString::from(unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) })
+        }).collect();
+
+    let enabled_layers = select_names("layer", &required_layers, &config.optional_layers, &available_layers)
+        .map_err(InstanceCreateError::MissingLayers)?;
+
     let mut required_extensions = config.required_extensions;
     if config.require_surface_khr {
         required_extensions.insert(CString::from(CStr::from_bytes_with_nul(b"VK_KHR_surface\0").unwrap()));
@@ -103,26 +146,35 @@ pub fn create_instance(config: InstanceCreateConfig) -> Result<Arc<InstanceConte
         required_extensions.insert(CString::from(CStr::from_bytes_with_nul(b"VK_EXT_debug_utils\0").unwrap()));
     }
 
-    let available_extensions: HashSet<_> = entry.enumerate_instance_extension_properties(None)?
-        .into_iter().map(|ext| {
-            CString::from(unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) })
-        }).collect();
+    // Extensions may also be provided by enabled layers (for example VK_EXT_debug_utils by the validation layer)
+    let mut available_extensions = HashSet::new();
+    for layer in std::iter::once(None).chain(enabled_layers.iter().map(|layer| Some(layer.as_c_str()))) {
+        available_extensions.extend(entry.enumerate_instance_extension_properties(layer)?
+            .into_iter().map(|ext| {
+                CString::from(unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) })
+            }));
+    }
 
-    let mut required_extensions_str = Vec::with_capacity(required_extensions.len());
-    for name in &required_extensions {
-        if available_extensions.contains(name) {
-            required_extensions_str.push(name.as_c_str().as_ptr())
-        } else {
-            return Err(InstanceCreateError::MissingExtension(name.clone()));
-        }
+    // Surface capabilities 2 is optional and only needed for full screen exclusive support
+    let surface_capabilities_2_name = CString::from(CStr::from_bytes_with_nul(b"VK_KHR_get_surface_capabilities2\0").unwrap());
+    let mut optional_extensions = config.optional_extensions;
+    if config.require_surface_khr {
+        optional_extensions.insert(surface_capabilities_2_name.clone());
     }
 
-    let required_layers = if config.enable_validation {
-        log::info!("Validation layers enabled");
-        vec![CStr::from_bytes_with_nul(b"VK_LAYER_KHRONOS_validation\0").unwrap().as_ptr()]
+    let enabled_extensions = select_names("extension", &required_extensions, &optional_extensions, &available_extensions)
+        .map_err(InstanceCreateError::MissingExtensions)?;
+    let has_surface_capabilities_2 = enabled_extensions.contains(&surface_capabilities_2_name);
+
+    let enabled_extensions_str: Vec<_> = enabled_extensions.iter().map(|name| name.as_c_str().as_ptr()).collect();
+    let enabled_layers_str: Vec<_> = enabled_layers.iter().map(|name| name.as_c_str().as_ptr()).collect();
+
+    // Vulkan 1.3 is requested if the loader supports it so that devices can use synchronization2
+    // without the extension.
+    let api_version = if vulkan_version >= VulkanVersion::VK_1_3 {
+        VulkanVersion::VK_1_3
     } else {
-        log::info!("Validation layers disabled");
-        Vec::new()
+        VulkanVersion::VK_1_1
     };
 
     let name = CString::new(CRATE_NAME).unwrap();
@@ -131,12 +183,12 @@ pub fn create_instance(config: InstanceCreateConfig) -> Result<Arc<InstanceConte
         .application_version(config.application_version)
         .engine_name(&name)
         .engine_version(vk::make_api_version(0, BUILD_INFO.version_major, BUILD_INFO.version_minor, BUILD_INFO.version_patch))
-        .api_version(VulkanVersion::VK_1_1.into());
+        .api_version(api_version.into());
 
     let mut instance_create_info = vk::InstanceCreateInfo::builder()
         .application_info(&application_info)
-        .enabled_layer_names(required_layers.as_slice())
-        .enabled_extension_names(required_extensions_str.as_slice());
+        .enabled_layer_names(enabled_layers_str.as_slice())
+        .enabled_extension_names(enabled_extensions_str.as_slice());
 
     let debug_messengers = config.debug_messengers.into_boxed_slice();
     let mut debug_messenger_create_infos: Vec<_> = debug_messengers.iter().map(|messenger| {
@@ -158,22 +210,56 @@ pub fn create_instance(config: InstanceCreateConfig) -> Result<Arc<InstanceConte
 
     let instance = unsafe { vp_fn.create_instance(&entry, &vp_instance_create_info, None) }?;
 
-    let surface_khr = if required_extensions.contains(CStr::from_bytes_with_nul(b"VK_KHR_surface\0").unwrap()) {
+    let surface_khr = if enabled_extensions.contains(CStr::from_bytes_with_nul(b"VK_KHR_surface\0").unwrap()) {
         Some(ash::extensions::khr::Surface::new(&entry, &instance))
     } else {
         None
     };
 
+    let debug_utils_ext = if enabled_extensions.contains(CStr::from_bytes_with_nul(b"VK_EXT_debug_utils\0").unwrap()) {
+        Some(ash::extensions::ext::DebugUtils::new(&entry, &instance))
+    } else {
+        None
+    };
+
     Ok(InstanceContext::new(
         vulkan_version,
         profile,
         entry,
         instance,
         surface_khr,
+        debug_utils_ext,
+        has_surface_capabilities_2,
+        enabled_extensions,
+        enabled_layers,
         debug_messengers
     ))
 }
 
+/// Selects the names to enable from the required and optional sets.
+///
+/// Returns all missing required names as the error. Unavailable optional names are logged and
+/// skipped.
+fn select_names(kind: &str, required: &HashSet<CString>, optional: &HashSet<CString>, available: &HashSet<CString>) -> Result<HashSet<CString>, Vec<CString>> {
+    let missing: Vec<_> = required.iter().filter(|name| !available.contains(*name)).cloned().collect();
+    if !missing.is_empty() {
+        log::error!("Missing required instance {}s: {:?}", kind, missing);
+        return Err(missing);
+    }
+
+    let mut enabled = required.clone();
+    for name in optional {
+        if available.contains(name) {
+            enabled.insert(name.clone());
+        } else if !required.contains(name) {
+            log::info!("Optional instance {} {:?} is not available", kind, name);
+        }
+    }
+    log::info!("Enabled instance {}s: {:?}", kind, enabled);
+
+    Ok(enabled)
+}
+
 pub struct DebugUtilsMessengerWrapper {
     callback: Box<dyn DebugMessengerCallback>
 }
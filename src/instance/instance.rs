@@ -1,6 +1,8 @@
 use core::panic::{UnwindSafe, RefUnwindSafe};
 
 use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::ffi::{CStr, CString};
 use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
 
@@ -63,6 +65,10 @@ pub struct InstanceContext {
     entry: ash::Entry,
     instance: ash::Instance,
     surface_khr: Option<ash::extensions::khr::Surface>,
+    debug_utils_ext: Option<ash::extensions::ext::DebugUtils>,
+    surface_capabilities_2_khr: bool,
+    enabled_extensions: HashSet<CString>,
+    enabled_layers: HashSet<CString>,
     _debug_messengers: Box<[DebugUtilsMessengerWrapper]>,
 }
 
@@ -73,6 +79,10 @@ impl InstanceContext {
         entry: ash::Entry,
         instance: ash::Instance,
         surface_khr: Option<ash::extensions::khr::Surface>,
+        debug_utils_ext: Option<ash::extensions::ext::DebugUtils>,
+        surface_capabilities_2_khr: bool,
+        enabled_extensions: HashSet<CString>,
+        enabled_layers: HashSet<CString>,
         debug_messengers: Box<[DebugUtilsMessengerWrapper]>
     ) -> Arc<Self> {
         Arc::new(Self {
@@ -82,6 +92,10 @@ impl InstanceContext {
             entry,
             instance,
             surface_khr,
+            debug_utils_ext,
+            surface_capabilities_2_khr,
+            enabled_extensions,
+            enabled_layers,
             _debug_messengers: debug_messengers,
         })
     }
@@ -102,6 +116,35 @@ impl InstanceContext {
         self.surface_khr.as_ref()
     }
 
+    pub fn debug_utils_ext(&self) -> Option<&ash::extensions::ext::DebugUtils> {
+        self.debug_utils_ext.as_ref()
+    }
+
+    /// Returns true if VK_KHR_get_surface_capabilities2 is enabled.
+    pub fn supports_surface_capabilities_2(&self) -> bool {
+        self.surface_capabilities_2_khr
+    }
+
+    /// Returns true if the instance extension has been enabled.
+    pub fn is_extension_enabled(&self, extension: &CStr) -> bool {
+        self.enabled_extensions.contains(extension)
+    }
+
+    /// Returns all instance extensions explicitly enabled. Extensions added by the profile are not
+    /// included.
+    pub fn get_enabled_extensions(&self) -> &HashSet<CString> {
+        &self.enabled_extensions
+    }
+
+    /// Returns true if the layer has been enabled.
+    pub fn is_layer_enabled(&self, layer: &CStr) -> bool {
+        self.enabled_layers.contains(layer)
+    }
+
+    pub fn get_enabled_layers(&self) -> &HashSet<CString> {
+        &self.enabled_layers
+    }
+
     pub fn get_version(&self) -> VulkanVersion {
         self.version
     }
@@ -14,6 +14,14 @@ pub trait ObjectSetProvider: Debug {
 
     fn get_handle(&self, id: UUID) -> Option<u64>;
 
+    /// Returns true if the set contains an object with the specified id.
+    ///
+    /// The default implementation calls [`ObjectSetProvider::get_handle`]. Providers should
+    /// override it if a cheaper check exists.
+    fn contains(&self, id: UUID) -> bool {
+        self.get_handle(id).is_some()
+    }
+
     fn get<ID: ObjectId>(&self, id: ID) -> Option<ID::HandleType> where Self: Sized {
         self.get_handle(id.as_uuid()).map(|handle| ID::HandleType::from_raw(handle))
     }
@@ -40,6 +48,10 @@ impl ObjectSetProvider for ObjectSet {
     fn get_handle(&self, id: UUID) -> Option<u64> {
         self.0.get_handle(id)
     }
+
+    fn contains(&self, id: UUID) -> bool {
+        self.0.contains(id)
+    }
 }
 
 impl PartialEq for ObjectSet {
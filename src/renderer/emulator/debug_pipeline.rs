@@ -9,15 +9,20 @@ use ash::vk;
 use bumpalo::Bump;
 use bytemuck::{bytes_of, cast_slice, Pod, Zeroable};
 use include_bytes_aligned::include_bytes_aligned;
-use crate::device::device::Queue;
+use crate::device::device::{Queue, TrackedObject};
 use crate::device::device_utils::create_shader_from_bytes;
 
 use crate::prelude::*;
 use crate::renderer::emulator::EmulatorRenderer;
-use crate::renderer::emulator::mc_shaders::{McUniform, McUniformData, ShaderDropListener, ShaderId, ShaderListener, VertexFormat, VertexFormatEntry};
-use crate::renderer::emulator::pipeline::{DrawTask, EmulatorPipeline, EmulatorPipelinePass, PipelineTask, PooledObjectProvider, SubmitRecorder};
+use crate::renderer::emulator::descriptors::{DescriptorPoolStats, DescriptorSetAllocator};
+use crate::renderer::emulator::mc_shaders::{McUniform, McUniformData, ShaderDropListener, ShaderId, ShaderListener, SpecializationData, VertexFormat, VertexFormatEntry};
+use crate::renderer::emulator::pipeline::{DrawIndirectTask, DrawTask, EmulatorPipeline, EmulatorPipelinePass, EmulatorPipelineSecondary, PipelineTask, PooledObjectProvider, SubmitRecorder};
+use crate::util::format::Format;
 use crate::util::vk::{make_full_rect, make_full_viewport};
+#[cfg(feature = "spirv-reflect")]
+use crate::util::spirv::{ExpectedBinding, ExpectedInterface, InterfaceError, InterfaceMismatch, validate_interface};
 use crate::vk::objects::allocator::{Allocation, AllocationStrategy};
+use crate::vk::objects::image::{self, ImageCreateError, ImageDescription, ImageSize, ImageSpec};
 
 pub struct DepthTypeInfo {
     pub vertex_stride: u32,
@@ -28,10 +33,18 @@ pub struct DepthTypeInfo {
     pub discard: bool,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum ObjectCreateError {
     Vulkan(vk::Result),
     Allocation,
+    UnsupportedSampleCount,
+    UnsupportedConfiguration,
+    /// A shader module is not valid spirv and cannot be reflected.
+    #[cfg(feature = "spirv-reflect")]
+    InvalidShaderCode,
+    /// A shader module does not match the pipeline layout. Contains all detected mismatches.
+    #[cfg(feature = "spirv-reflect")]
+    InvalidShaderInterface(Vec<InterfaceMismatch>),
 }
 
 impl From<vk::Result> for ObjectCreateError {
@@ -54,6 +67,90 @@ pub enum DebugPipelineMode {
     Textured2,
 }
 
+/// Configuration of the attachments used by a [`DebugPipeline`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct DebugPipelineConfig {
+    /// The number of samples per pixel used when drawing.
+    pub sample_count: vk::SampleCountFlags,
+
+    /// If false no depth attachment is created and depth testing and writing is disabled for all
+    /// draws. Useful for pure 2D passes.
+    pub depth_attachment: bool,
+
+    /// If true a reversed depth range is used. The depth attachment is cleared to 0.0 and all
+    /// depth tested draws use [`vk::CompareOp::GREATER`]. Callers must use a projection matrix
+    /// which maps the near plane to 1.0 and the far plane to 0.0.
+    pub reverse_z: bool,
+
+    /// The size in pixels of points drawn with [`vk::PrimitiveTopology::POINT_LIST`]. Sizes other
+    /// than 1 require the `largePoints` feature and must lie within the `pointSizeRange` limit.
+    /// The size is written to `gl_PointSize` by the debug vertex shaders.
+    pub point_size: u32,
+
+    /// The number of sets of attachments. Passes use the sets in turn so this limits the number of
+    /// passes which can be in flight at the same time. Must be at least 1.
+    ///
+    /// Passes can only load their attachments (see [`PassAttachmentOps`]) if this is 1 so that
+    /// they see the contents written by the previous pass.
+    pub concurrent_passes: u32,
+}
+
+impl Default for DebugPipelineConfig {
+    fn default() -> Self {
+        Self {
+            sample_count: vk::SampleCountFlags::TYPE_1,
+            depth_attachment: true,
+            reverse_z: false,
+            point_size: 1,
+            concurrent_passes: 2,
+        }
+    }
+}
+
+/// The load and store operations of the attachments of a [`DebugPipeline`] pass.
+///
+/// Load ops must be one of [`vk::AttachmentLoadOp::CLEAR`], [`vk::AttachmentLoadOp::LOAD`] or
+/// [`vk::AttachmentLoadOp::DONT_CARE`]. Store ops must be one of [`vk::AttachmentStoreOp::STORE`]
+/// or [`vk::AttachmentStoreOp::DONT_CARE`].
+///
+/// Multisampled attachments are transient. They cannot be loaded and their store ops are ignored.
+/// The depth output of a pass is undefined if the depth attachment is not stored. Loaded attachments
+/// must be stored so that the next pass loading them sees defined contents.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PassAttachmentOps {
+    pub color_load: vk::AttachmentLoadOp,
+    pub color_store: vk::AttachmentStoreOp,
+    pub depth_load: vk::AttachmentLoadOp,
+    pub depth_store: vk::AttachmentStoreOp,
+}
+
+impl PassAttachmentOps {
+    /// Returns a copy of these ops with all load ops replaced by clear ops.
+    fn without_load(&self) -> Self {
+        let replace = |op: vk::AttachmentLoadOp| if op == vk::AttachmentLoadOp::LOAD { vk::AttachmentLoadOp::CLEAR } else { op };
+        Self {
+            color_load: replace(self.color_load),
+            depth_load: replace(self.depth_load),
+            ..*self
+        }
+    }
+
+    fn has_load(&self) -> bool {
+        self.color_load == vk::AttachmentLoadOp::LOAD || self.depth_load == vk::AttachmentLoadOp::LOAD
+    }
+}
+
+impl Default for PassAttachmentOps {
+    fn default() -> Self {
+        Self {
+            color_load: vk::AttachmentLoadOp::CLEAR,
+            color_store: vk::AttachmentStoreOp::DONT_CARE,
+            depth_load: vk::AttachmentLoadOp::CLEAR,
+            depth_store: vk::AttachmentStoreOp::STORE,
+        }
+    }
+}
+
 /// A [`EmulatorPipeline`] which provides debug information.
 ///
 /// The following outputs are supported:
@@ -70,30 +167,117 @@ pub struct DebugPipeline {
     weak: Weak<Self>,
 
     framebuffer_size: Vec2u32,
+    sample_count: vk::SampleCountFlags,
+    has_depth: bool,
+    reverse_z: bool,
+    point_size: u32,
 
     shader_modules: ShaderModules,
+    depth_format: Option<vk::Format>,
+    /// The render pass using the default attachment ops. Used to create pipelines and framebuffers.
     render_pass: vk::RenderPass,
+    /// Compatible render passes using other attachment ops.
+    render_pass_variants: Mutex<HashMap<PassAttachmentOps, vk::RenderPass>>,
     draw_pipeline: DrawPipeline,
     background_pipeline: BackgroundPipeline,
-    descriptor_pool: vk::DescriptorPool,
+    descriptor_allocator: Mutex<DescriptorSetAllocator>,
+    /// Allocates the sets binding dynamic uniform buffers.
+    dynamic_uniform_allocator: Mutex<DescriptorSetAllocator>,
 
     pipelines: Mutex<HashMap<ShaderId, ShaderPipelines>>,
     next_index: AtomicUsize,
     pass_objects: Box<[PassObjects]>,
     output_views: Box<[vk::ImageView]>,
+    color_output: Option<(vk::Format, Box<[vk::Image]>)>,
+    depth_output: Option<(vk::Format, Box<[vk::Image]>)>,
 }
 assert_impl_all!(DebugPipeline: Send, Sync);
 
 impl DebugPipeline {
+    /// The format of the color attachments and the output image.
+    const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
     pub fn new(emulator: Arc<EmulatorRenderer>, mode: DebugPipelineMode, framebuffer_size: Vec2u32) -> Result<Arc<Self>, ObjectCreateError> {
-        let concurrent_passes = 2usize;
-        let depth_format = vk::Format::D32_SFLOAT;
+        Self::new_with_config(emulator, mode, framebuffer_size, DebugPipelineConfig::default())
+    }
 
+    /// Creates a new debug pipeline which renders using the specified number of samples per pixel.
+    ///
+    /// If the sample count is larger than 1 all draws are rendered into transient multisampled
+    /// attachments which are then resolved before being written to the output image. The sample
+    /// count must be supported by the device for both color and depth framebuffer attachments.
+    /// Multisampling is not supported in [`DebugPipelineMode::Depth`] since the depth buffer itself
+    /// is the output. Multisampled pipelines do not expose their depth buffer through
+    /// [`EmulatorPipeline::get_depth_output`] and can therefore not be used with a
+    /// [`DepthCopyOutput`](super::pipeline::DepthCopyOutput).
+    pub fn new_multisampled(emulator: Arc<EmulatorRenderer>, mode: DebugPipelineMode, framebuffer_size: Vec2u32, sample_count: vk::SampleCountFlags) -> Result<Arc<Self>, ObjectCreateError> {
+        Self::new_with_config(emulator, mode, framebuffer_size, DebugPipelineConfig {
+            sample_count,
+            ..Default::default()
+        })
+    }
+
+    /// Creates a new debug pipeline with the specified attachment configuration.
+    ///
+    /// See [`DebugPipeline::new_multisampled`] for restrictions on the sample count. Disabling the
+    /// depth attachment is not supported in [`DebugPipelineMode::Depth`].
+    pub fn new_with_config(emulator: Arc<EmulatorRenderer>, mode: DebugPipelineMode, framebuffer_size: Vec2u32, config: DebugPipelineConfig) -> Result<Arc<Self>, ObjectCreateError> {
+        if config.concurrent_passes == 0 {
+            log::error!("DebugPipeline requires at least 1 concurrent pass");
+            return Err(ObjectCreateError::UnsupportedConfiguration);
+        }
+        let concurrent_passes = config.concurrent_passes as usize;
+        let sample_count = config.sample_count;
         let device = emulator.get_device();
 
+        // The depth output is copied into global images which cannot have a stencil aspect
+        let depth_format = if config.depth_attachment {
+            let format = device.choose_depth_format(false, &[&Format::D32_SFLOAT]).ok_or_else(|| {
+                log::error!("No depth attachment format is supported by the device");
+                ObjectCreateError::UnsupportedConfiguration
+            })?;
+            if format.has_stencil() {
+                log::error!("Depth format {:?} selected for the debug pipeline has a stencil aspect", format);
+                return Err(ObjectCreateError::UnsupportedConfiguration);
+            }
+            Some(format.get_format())
+        } else {
+            None
+        };
+
+        if depth_format.is_none() && mode == DebugPipelineMode::Depth {
+            log::error!("Depth debug mode requires a depth attachment");
+            return Err(ObjectCreateError::UnsupportedConfiguration);
+        }
+
+        if !is_point_size_supported(config.point_size, device.get_features().large_points == vk::TRUE, device.get_limits().point_size_range) {
+            log::error!("Point size {:?} is not supported by the device (large points: {:?}, range: {:?})", config.point_size, device.get_features().large_points, device.get_limits().point_size_range);
+            return Err(ObjectCreateError::UnsupportedConfiguration);
+        }
+
+        if sample_count.as_raw().count_ones() != 1 {
+            log::error!("Sample count {:?} passed to DebugPipeline::new_with_config must have exactly one bit set", sample_count);
+            return Err(ObjectCreateError::UnsupportedSampleCount);
+        }
+        if sample_count != vk::SampleCountFlags::TYPE_1 {
+            let limits = device.get_limits();
+            let mut supported = limits.framebuffer_color_sample_counts;
+            if depth_format.is_some() {
+                supported &= limits.framebuffer_depth_sample_counts;
+            }
+            if !supported.contains(sample_count) {
+                log::error!("Sample count {:?} is not supported by the device (supported: {:?})", sample_count, supported);
+                return Err(ObjectCreateError::UnsupportedSampleCount);
+            }
+            if mode == DebugPipelineMode::Depth {
+                log::error!("Multisampling is not supported in depth debug mode");
+                return Err(ObjectCreateError::UnsupportedSampleCount);
+            }
+        }
+
         let mut shader_modules = ShaderModules::new(device, mode)?;
 
-        let render_pass = match Self::create_render_pass(&device, depth_format) {
+        let render_pass = match Self::create_render_pass(&device, depth_format, sample_count, &PassAttachmentOps::default()) {
             Ok(render_pass) => render_pass,
             Err(err) => {
                 shader_modules.destroy(device);
@@ -120,45 +304,19 @@ impl DebugPipeline {
             }
         };
 
-        let descriptor_pool = match Self::create_descriptor_pool(device, concurrent_passes) {
-            Ok(pool) => pool,
-            Err(err) => {
-                background_pipeline.destroy(device);
-                draw_pipeline.destroy(device);
-                unsafe { device.vk().destroy_render_pass(render_pass, None) };
-                shader_modules.destroy(device);
-                return Err(err);
-            }
-        };
-
-        let layouts: Box<[_]> = std::iter::repeat(background_pipeline.descriptor_set_layout).take(concurrent_passes).collect();
-        let info = vk::DescriptorSetAllocateInfo::builder()
-            .descriptor_pool(descriptor_pool)
-            .set_layouts(&layouts);
-
-        let descriptor_sets = match unsafe {
-            device.vk().allocate_descriptor_sets(&info)
-        } {
-            Ok(layouts) => layouts,
-            Err(err) => {
-                unsafe { device.vk().destroy_descriptor_pool(descriptor_pool, None) };
-                background_pipeline.destroy(device);
-                draw_pipeline.destroy(device);
-                unsafe { device.vk().destroy_render_pass(render_pass, None) };
-                shader_modules.destroy(device);
-                return Err(ObjectCreateError::Vulkan(err));
-            }
-        };
+        let mut descriptor_allocator = DescriptorSetAllocator::new(background_pipeline.descriptor_set_layout, &BackgroundPipeline::DESCRIPTOR_SET_SIZES);
 
-        let mut pass_objects: Vec<PassObjects> = Vec::with_capacity(layouts.len());
-        for descriptor_set in descriptor_sets {
-            let objects = match PassObjects::new(device, framebuffer_size, depth_format, vk::Format::R8G8B8A8_SRGB, render_pass, descriptor_set) {
+        let mut pass_objects: Vec<PassObjects> = Vec::with_capacity(concurrent_passes);
+        for _ in 0..concurrent_passes {
+            let objects = match descriptor_allocator.allocate(device).map_err(ObjectCreateError::Vulkan).and_then(|descriptor_set| {
+                PassObjects::new(device, framebuffer_size, sample_count, depth_format, Self::COLOR_FORMAT, render_pass, descriptor_set)
+            }) {
                 Ok(objects) => objects,
                 Err(err) => {
                     for mut pass_object in pass_objects {
                         pass_object.destroy(device);
                     }
-                    unsafe { device.vk().destroy_descriptor_pool(descriptor_pool, None) };
+                    descriptor_allocator.destroy(device);
                     background_pipeline.destroy(device);
                     draw_pipeline.destroy(device);
                     unsafe { device.vk().destroy_render_pass(render_pass, None) };
@@ -176,27 +334,57 @@ impl DebugPipeline {
             pass_objects.iter().map(|obj| obj.output_view).collect()
         };
 
+        let color_output = if mode == DebugPipelineMode::Depth {
+            None
+        } else {
+            Some((Self::COLOR_FORMAT, pass_objects.iter().map(|obj| obj.output_image).collect()))
+        };
+
+        // The multisampled depth image is transient and cannot be accessed after the pass
+        let depth_output = depth_format.filter(|_| sample_count == vk::SampleCountFlags::TYPE_1).map(|depth_format| {
+            (depth_format, pass_objects.iter().map(|obj| obj.depth_image).collect())
+        });
+
         Ok(Arc::new_cyclic(|weak| {
             Self {
                 emulator,
                 weak: weak.clone(),
 
                 framebuffer_size,
+                sample_count,
+                has_depth: depth_format.is_some(),
+                reverse_z: config.reverse_z,
+                point_size: config.point_size,
 
                 shader_modules,
+                depth_format,
                 render_pass,
+                render_pass_variants: Mutex::new(HashMap::new()),
                 draw_pipeline,
                 background_pipeline,
-                descriptor_pool,
+                descriptor_allocator: Mutex::new(descriptor_allocator),
+                dynamic_uniform_allocator: Mutex::new(DescriptorSetAllocator::new(draw_pipeline.set1_layout, &DrawPipeline::SET1_SIZES)),
 
                 pipelines: Mutex::new(HashMap::new()),
                 next_index: AtomicUsize::new(0),
                 pass_objects,
-                output_views
+                output_views,
+                color_output,
+                depth_output
             }
         }))
     }
 
+    /// Returns the inheritance info of secondary command buffers recording into the draw subpass.
+    /// The framebuffer may be null if it is not known.
+    fn get_draw_inheritance_info(&self, framebuffer: vk::Framebuffer) -> vk::CommandBufferInheritanceInfo {
+        vk::CommandBufferInheritanceInfo::builder()
+            .render_pass(self.render_pass)
+            .subpass(0)
+            .framebuffer(framebuffer)
+            .build()
+    }
+
     /// Returns the next index to be used for a pass and increments the internal counter.
     fn next_index(&self) -> usize {
         loop {
@@ -217,12 +405,12 @@ impl DebugPipeline {
             panic!()
         });
 
-        pipelines.get_or_create_pipeline(config, |format| self.create_pipeline(config, format))
+        pipelines.get_or_create_pipeline(config, |format, specialization| self.create_pipeline(config, format, specialization))
     }
 
-    fn create_pipeline(&self, config: &PipelineConfig, vertex_format: &VertexFormat) -> vk::Pipeline {
+    fn create_pipeline(&self, config: &PipelineConfig, vertex_format: &VertexFormat, specialization: &SpecializationData) -> vk::Pipeline {
         let alloc = Bump::new();
-        let (shader_stages, input_state) = self.shader_modules.configure_pipeline(vertex_format, &alloc);
+        let (shader_stages, input_state) = self.shader_modules.configure_pipeline(vertex_format, specialization, self.point_size, &alloc);
 
         let viewport = make_full_viewport(self.framebuffer_size);
         let scissor = make_full_rect(self.framebuffer_size);
@@ -231,14 +419,21 @@ impl DebugPipeline {
             .viewports(std::slice::from_ref(&viewport))
             .scissors(std::slice::from_ref(&scissor));
 
-        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        let mut conservative_state = vk::PipelineRasterizationConservativeStateCreateInfoEXT::builder()
+            .conservative_rasterization_mode(vk::ConservativeRasterizationModeEXT::OVERESTIMATE)
+            .extra_primitive_overestimation_size(0f32);
+
+        let mut rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
             .polygon_mode(vk::PolygonMode::FILL)
             .cull_mode(vk::CullModeFlags::BACK)
             .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
             .line_width(1f32);
+        if config.conservative_rasterization {
+            rasterization_state = rasterization_state.push_next(&mut conservative_state);
+        }
 
         let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+            .rasterization_samples(self.sample_count)
             .sample_shading_enable(false);
 
         let attachment_blend_state = [
@@ -267,7 +462,7 @@ impl DebugPipeline {
         let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
             .depth_test_enable(config.depth_test_enable)
             .depth_write_enable(config.depth_write_enable)
-            .depth_compare_op(vk::CompareOp::LESS);
+            .depth_compare_op(self.get_depth_compare_op());
 
         let info = vk::GraphicsPipelineCreateInfo::builder()
             .stages(shader_stages)
@@ -289,70 +484,149 @@ impl DebugPipeline {
             log::error!("Failed to create graphics pipeline {:?}", err);
             panic!();
         }).get(0).unwrap();
+        self.emulator.get_device().on_object_created(TrackedObject::Pipeline);
 
         pipeline
     }
 
-    fn create_render_pass(device: &DeviceContext, depth_format: vk::Format) -> Result<vk::RenderPass, ObjectCreateError> {
-        let attachments = [
-            vk::AttachmentDescription::builder()
-                .format(depth_format)
-                .samples(vk::SampleCountFlags::TYPE_1)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
-                .store_op(vk::AttachmentStoreOp::STORE)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                .build(),
-            vk::AttachmentDescription::builder()
-                .format(vk::Format::R8G8B8A8_SRGB)
+    /// Creates the render pass used by the pipeline.
+    ///
+    /// The render pass uses the attachments depth (if a depth format is specified), pass color and
+    /// output in that order. If the sample count is larger than 1 the depth and pass color
+    /// attachments are multisampled and an additional single sampled attachment is inserted before
+    /// the output into which the pass color is resolved at the end of subpass 0. The resolved image
+    /// is then used as the input attachment of subpass 1.
+    ///
+    /// A render pass object is used independent of the [`RenderingMode`] of the device since
+    /// dynamic rendering does not support input attachments.
+    ///
+    /// Render passes created with different attachment ops are compatible. Loaded attachments
+    /// must be in the final layout of the previous pass.
+    ///
+    /// [`RenderingMode`]: crate::device::device::RenderingMode
+    fn create_render_pass(device: &DeviceContext, depth_format: Option<vk::Format>, sample_count: vk::SampleCountFlags, ops: &PassAttachmentOps) -> Result<vk::RenderPass, ObjectCreateError> {
+        let multisampled = sample_count != vk::SampleCountFlags::TYPE_1;
+
+        let get_initial_layout = |load_op: vk::AttachmentLoadOp, layout: vk::ImageLayout| {
+            if load_op == vk::AttachmentLoadOp::LOAD {
+                layout
+            } else {
+                vk::ImageLayout::UNDEFINED
+            }
+        };
+
+        let mut attachments = Vec::with_capacity(4);
+        let depth_attachment = depth_format.map(|depth_format| {
+            attachments.push(if multisampled {
+                vk::AttachmentDescription::builder()
+                    .format(depth_format)
+                    .samples(sample_count)
+                    .load_op(ops.depth_load)
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                    .build()
+            } else {
+                vk::AttachmentDescription::builder()
+                    .format(depth_format)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .load_op(ops.depth_load)
+                    .store_op(ops.depth_store)
+                    .initial_layout(get_initial_layout(ops.depth_load, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+                    .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .build()
+            });
+            (attachments.len() - 1) as u32
+        });
+
+        let color_attachment = attachments.len() as u32;
+        attachments.push(vk::AttachmentDescription::builder()
+            .format(Self::COLOR_FORMAT)
+            .samples(sample_count)
+            .load_op(ops.color_load)
+            .store_op(if multisampled { vk::AttachmentStoreOp::DONT_CARE } else { ops.color_store })
+            .initial_layout(get_initial_layout(ops.color_load, vk::ImageLayout::GENERAL))
+            .final_layout(vk::ImageLayout::GENERAL)
+            .build()
+        );
+
+        let resolve_attachment = attachments.len() as u32;
+        if multisampled {
+            attachments.push(vk::AttachmentDescription::builder()
+                .format(Self::COLOR_FORMAT)
                 .samples(vk::SampleCountFlags::TYPE_1)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
                 .store_op(vk::AttachmentStoreOp::DONT_CARE)
                 .initial_layout(vk::ImageLayout::UNDEFINED)
                 .final_layout(vk::ImageLayout::GENERAL)
-                .build(),
-            vk::AttachmentDescription::builder()
-                .format(vk::Format::R8G8B8A8_SRGB)
-                .samples(vk::SampleCountFlags::TYPE_1)
-                .load_op(vk::AttachmentLoadOp::DONT_CARE)
-                .store_op(vk::AttachmentStoreOp::STORE)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
                 .build()
-        ];
+            );
+        }
+
+        let output_attachment = attachments.len() as u32;
+        attachments.push(vk::AttachmentDescription::builder()
+            .format(Self::COLOR_FORMAT)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .build()
+        );
+
+        let input_attachment = if multisampled { resolve_attachment } else { color_attachment };
 
         let pass_0_depth = vk::AttachmentReference {
-            attachment: 0,
+            attachment: depth_attachment.unwrap_or(vk::ATTACHMENT_UNUSED),
             layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
         };
 
         let pass_0_color = [
             vk::AttachmentReference {
-                attachment: 1,
+                attachment: color_attachment,
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            },
+        ];
+
+        let pass_0_resolve = [
+            vk::AttachmentReference {
+                attachment: resolve_attachment,
                 layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
             },
         ];
 
         let pass_1_input = [
             vk::AttachmentReference {
-                attachment: 1,
+                attachment: input_attachment,
                 layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
             },
         ];
 
         let pass_1_color = [
             vk::AttachmentReference {
-                attachment: 2,
+                attachment: output_attachment,
                 layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
             },
         ];
 
+        let pass_0 = vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&pass_0_color);
+
+        let pass_0 = if depth_attachment.is_some() {
+            pass_0.depth_stencil_attachment(&pass_0_depth)
+        } else {
+            pass_0
+        };
+
+        let pass_0 = if multisampled {
+            pass_0.resolve_attachments(&pass_0_resolve)
+        } else {
+            pass_0
+        };
+
         let subpasses = [
-            vk::SubpassDescription::builder()
-                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-                .color_attachments(&pass_0_color)
-                .depth_stencil_attachment(&pass_0_depth)
-                .build(),
+            pass_0.build(),
             vk::SubpassDescription::builder()
                 .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
                 .input_attachments(&pass_1_input)
@@ -360,7 +634,18 @@ impl DebugPipeline {
                 .build(),
         ];
 
+        // Loaded attachments were written by a previous pass. The dependency must be part of every
+        // variant since render passes with different dependencies are not compatible.
         let subpass_dependencies = [
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::ALL_COMMANDS,
+                dst_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::MEMORY_WRITE,
+                dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE | vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dependency_flags: vk::DependencyFlags::empty()
+            },
             vk::SubpassDependency {
                 src_subpass: 0,
                 dst_subpass: 1,
@@ -386,49 +671,141 @@ impl DebugPipeline {
 
         drop(pass_0_depth);
         drop(pass_0_color);
+        drop(pass_0_resolve);
         drop(pass_1_input);
         drop(pass_1_color);
 
         Ok(render_pass)
     }
 
-    fn create_descriptor_pool(device: &DeviceContext, concurrent_passes: usize) -> Result<vk::DescriptorPool, ObjectCreateError> {
-        let concurrent_passes = concurrent_passes as u32;
+    /// Returns statistics of the descriptor pools used by this pipeline.
+    ///
+    /// Textures are bound using push descriptors and do not allocate from these pools.
+    pub fn get_descriptor_pool_stats(&self) -> DescriptorPoolStats {
+        self.descriptor_allocator.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned descriptor allocator mutex in DebugPipeline::get_descriptor_pool_stats!");
+            panic!()
+        }).get_stats()
+    }
 
-        let sizes = [
-            vk::DescriptorPoolSize {
-                ty: vk::DescriptorType::INPUT_ATTACHMENT,
-                descriptor_count: concurrent_passes
-            },
-        ];
+    /// Returns a pipeline which renders using this pipeline with the specified load and store ops.
+    ///
+    /// Passes started with the returned pipeline use the ops while passes started with this
+    /// pipeline directly use the default ops. Both share all resources including outputs. If a pass
+    /// loads an attachment which has never been rendered to by a completed pass the attachment is
+    /// cleared instead. Loading requires [`DebugPipelineConfig::concurrent_passes`] to be 1.
+    pub fn with_attachment_ops(&self, ops: PassAttachmentOps) -> Result<Arc<dyn EmulatorPipeline>, ObjectCreateError> {
+        validate_attachment_ops(&ops, self.sample_count != vk::SampleCountFlags::TYPE_1, self.pass_objects.len() as u32)?;
+
+        // Created up front so that starting a pass cannot fail
+        self.get_render_pass(&ops)?;
+        self.get_render_pass(&ops.without_load())?;
+
+        Ok(Arc::new(DebugPipelineWithOps {
+            parent: self.weak.upgrade().unwrap(),
+            ops,
+        }))
+    }
 
-        let info = vk::DescriptorPoolCreateInfo::builder()
-            .max_sets(concurrent_passes)
-            .pool_sizes(&sizes);
+    fn start_pass_with_ops(&self, ops: &PassAttachmentOps, uses_secondaries: bool) -> Box<dyn EmulatorPipelinePass + Send> {
+        let index = self.next_index();
+        let objects = &self.pass_objects[index];
+        objects.wait_and_take();
 
-        let descriptor_pool = unsafe {
-            device.vk().create_descriptor_pool(&info, None)
-        }.map_err(|err| {
-            log::error!("vkCreateDescriptorPool returned {:?} in DebugPipeline::create_descriptor_pool", err);
-            err
-        })?;
+        let mut ops = *ops;
+        if ops.has_load() && !objects.initialized.load(Ordering::SeqCst) {
+            log::debug!("Attachments of debug pipeline pass objects {:?} have not been rendered to yet. Clearing instead of loading", index);
+            ops = ops.without_load();
+        }
+        let render_pass = self.get_render_pass(&ops).unwrap_or_else(|err| {
+            log::error!("Failed to create render pass for attachment ops {:?}: {:?}", ops, err);
+            panic!()
+        });
+
+        Box::new(DebugPipelinePass::new(self.weak.upgrade().unwrap(), index, render_pass, uses_secondaries))
+    }
+
+    /// Returns the render pass for the specified attachment ops creating it if necessary.
+    fn get_render_pass(&self, ops: &PassAttachmentOps) -> Result<vk::RenderPass, ObjectCreateError> {
+        if *ops == PassAttachmentOps::default() {
+            return Ok(self.render_pass);
+        }
+
+        let mut guard = self.render_pass_variants.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned render pass variants mutex in DebugPipeline::get_render_pass!");
+            panic!()
+        });
+        if let Some(render_pass) = guard.get(ops) {
+            return Ok(*render_pass);
+        }
+
+        let render_pass = Self::create_render_pass(self.emulator.get_device(), self.depth_format, self.sample_count, ops)?;
+        guard.insert(*ops, render_pass);
+        Ok(render_pass)
+    }
+
+    /// Returns true if this pipeline uses a reversed depth range.
+    pub fn is_reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+
+    /// Returns the value the depth attachment is cleared to at the start of each pass which does
+    /// not load it.
+    pub fn get_depth_clear_value(&self) -> f32 {
+        if self.reverse_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
 
-        Ok(descriptor_pool)
+    /// Returns the compare op used by all depth tested draws.
+    fn get_depth_compare_op(&self) -> vk::CompareOp {
+        if self.reverse_z {
+            vk::CompareOp::GREATER
+        } else {
+            vk::CompareOp::LESS
+        }
     }
 }
 
 impl EmulatorPipeline for DebugPipeline {
     fn start_pass(&self) -> Box<dyn EmulatorPipelinePass + Send> {
-        let index = self.next_index();
-        self.pass_objects[index].wait_and_take();
+        self.start_pass_with_ops(&PassAttachmentOps::default(), false)
+    }
+
+    fn start_pass_with_secondaries(&self) -> Option<Box<dyn EmulatorPipelinePass + Send>> {
+        Some(self.start_pass_with_ops(&PassAttachmentOps::default(), true))
+    }
 
-        Box::new(DebugPipelinePass::new(self.weak.upgrade().unwrap(), index))
+    fn create_secondary(&self, placeholder_image: vk::ImageView, placeholder_sampler: vk::Sampler) -> Option<Box<dyn EmulatorPipelineSecondary + Send>> {
+        Some(Box::new(DebugPipelineSecondary::new(self.weak.upgrade().unwrap(), placeholder_image, placeholder_sampler)))
     }
 
     fn get_output(&self) -> (Vec2u32, &[vk::ImageView]) {
         (self.framebuffer_size, &self.output_views)
     }
 
+    fn get_color_output(&self) -> Option<(vk::Format, &[vk::Image])> {
+        self.color_output.as_ref().map(|(format, images)| (*format, images.as_ref()))
+    }
+
+    fn get_depth_output(&self) -> Option<(vk::Format, &[vk::Image])> {
+        self.depth_output.as_ref().map(|(format, images)| (*format, images.as_ref()))
+    }
+
+    fn get_color_attachment_format(&self, index: u32) -> Option<vk::Format> {
+        if index == 0 {
+            Some(Self::COLOR_FORMAT)
+        } else {
+            None
+        }
+    }
+
+    fn get_depth_attachment_format(&self) -> Option<vk::Format> {
+        self.depth_format
+    }
+
     fn inc_shader_used(&self, shader: ShaderId) {
         let mut guard = self.pipelines.lock().unwrap();
         if let Some(pipelines) = guard.get_mut(&shader) {
@@ -442,8 +819,9 @@ impl EmulatorPipeline for DebugPipeline {
             let shader_obj = self.emulator.get_shader(shader).unwrap();
             let vertex_format = shader_obj.get_vertex_format().clone();
             let used_uniforms = shader_obj.get_used_uniforms();
+            let specialization = shader_obj.get_specialization_data().clone();
 
-            let mut  pipelines = ShaderPipelines::new(self.emulator.get_device().clone(), vertex_format, used_uniforms, listener);
+            let mut  pipelines = ShaderPipelines::new(self.emulator.get_device().clone(), vertex_format, used_uniforms, specialization, listener);
             pipelines.inc_used();
 
             guard.insert(shader, pipelines);
@@ -481,16 +859,20 @@ impl ShaderDropListener for DebugPipeline {
 impl Drop for DebugPipeline {
     fn drop(&mut self) {
         let device = self.emulator.get_device();
+        let descriptor_allocator = self.descriptor_allocator.get_mut().unwrap();
         for objects in self.pass_objects.iter_mut() {
+            descriptor_allocator.free(objects.bg_descriptor_set);
             objects.destroy(device);
         }
         self.pipelines.get_mut().unwrap().clear();
-        unsafe {
-            device.vk().destroy_descriptor_pool(self.descriptor_pool, None);
-        }
+        descriptor_allocator.destroy(device);
+        self.dynamic_uniform_allocator.get_mut().unwrap().destroy(device);
         self.background_pipeline.destroy(device);
         self.draw_pipeline.destroy(device);
         unsafe {
+            for render_pass in self.render_pass_variants.get_mut().unwrap().values() {
+                device.vk().destroy_render_pass(*render_pass, None);
+            }
             device.vk().destroy_render_pass(self.render_pass, None);
         }
         self.shader_modules.destroy(device);
@@ -508,24 +890,24 @@ struct ShaderModules {
 
 impl ShaderModules {
     fn new(device: &DeviceContext, mode: DebugPipelineMode) -> Result<Self, ObjectCreateError> {
-        let null_module = try_create_shader_module(device, DEBUG_NULL_VERTEX_BIN, "null_vertex")?;
+        let null_module = try_create_draw_shader_module(device, DEBUG_NULL_VERTEX_BIN, "null_vertex")?;
 
-        let fragment_module = try_create_shader_module(device, DEBUG_FRAGMENT_BIN, "fragment").map_err(|err| {
+        let fragment_module = try_create_draw_shader_module(device, DEBUG_FRAGMENT_BIN, "fragment").map_err(|err| {
             unsafe { device.vk().destroy_shader_module(null_module, None) };
             err
         })?;
 
         let vertex_module = match mode {
-            DebugPipelineMode::Depth => try_create_shader_module(device, DEBUG_POSITION_VERTEX_BIN, "position_vertex"),
-            DebugPipelineMode::Position => try_create_shader_module(device, DEBUG_POSITION_VERTEX_BIN, "position_vertex"),
-            DebugPipelineMode::Color => try_create_shader_module(device, DEBUG_COLOR_VERTEX_BIN, "color_vertex"),
+            DebugPipelineMode::Depth => try_create_draw_shader_module(device, DEBUG_POSITION_VERTEX_BIN, "position_vertex"),
+            DebugPipelineMode::Position => try_create_draw_shader_module(device, DEBUG_POSITION_VERTEX_BIN, "position_vertex"),
+            DebugPipelineMode::Color => try_create_draw_shader_module(device, DEBUG_COLOR_VERTEX_BIN, "color_vertex"),
             DebugPipelineMode::Normal => { todo!() }
             DebugPipelineMode::UV0 |
             DebugPipelineMode::UV1 |
             DebugPipelineMode::UV2 |
             DebugPipelineMode::Textured0 |
             DebugPipelineMode::Textured1 |
-            DebugPipelineMode::Textured2 => try_create_shader_module(device, DEBUG_UV_VERTEX_BIN, "uv_vertex"),
+            DebugPipelineMode::Textured2 => try_create_draw_shader_module(device, DEBUG_UV_VERTEX_BIN, "uv_vertex"),
         }.map_err(|err| {
             unsafe {
                 device.vk().destroy_shader_module(null_module, None);
@@ -535,7 +917,7 @@ impl ShaderModules {
         })?;
 
         let texture_module = match mode {
-            DebugPipelineMode::Textured0 => try_create_shader_module(device, TEXTURED_FRAGMENT_BIN, "textured_fragment").map(|val| Some(val)),
+            DebugPipelineMode::Textured0 => try_create_draw_shader_module(device, TEXTURED_FRAGMENT_BIN, "textured_fragment").map(|val| Some(val)),
             _ => Ok(None),
         }.map_err(|err| {
             unsafe {
@@ -555,7 +937,10 @@ impl ShaderModules {
         })
     }
 
-    fn configure_pipeline<'s, 'a: 's>(&'s self, vertex_format: &VertexFormat, alloc: &'a Bump) -> (&'a [vk::PipelineShaderStageCreateInfo], &'a vk::PipelineVertexInputStateCreateInfo) {
+    /// The specialization constants of the shader are applied to all stages. Constants used
+    /// internally by the debug shaders (0 for the texture index and 16 for the point size) take
+    /// precedence over shader constants with the same id.
+    fn configure_pipeline<'s, 'a: 's>(&'s self, vertex_format: &VertexFormat, specialization: &SpecializationData, point_size: u32, alloc: &'a Bump) -> (&'a [vk::PipelineShaderStageCreateInfo], &'a vk::PipelineVertexInputStateCreateInfo) {
         let input_bindings: &[_] = alloc.alloc([
             vk::VertexInputBindingDescription {
                 binding: 0,
@@ -603,34 +988,28 @@ impl ShaderModules {
             (DebugPipelineMode::Textured0, true) |
             (DebugPipelineMode::Textured1, true) |
             (DebugPipelineMode::Textured2, true) => {
-                let data = alloc.alloc(match self.mode {
+                let texture_index = match self.mode {
                     DebugPipelineMode::Textured0 => 0u32,
                     DebugPipelineMode::Textured1 => 1u32,
                     DebugPipelineMode::Textured2 => 2u32,
                     _ => panic!(),
-                });
-                let entries = alloc.alloc([
-                    vk::SpecializationMapEntry {
-                        constant_id: 0,
-                        offset: 0,
-                        size: 4
-                    }
-                ]);
-                (*self.texture_module.as_ref().unwrap(), alloc.alloc(vk::SpecializationInfo::builder()
-                    .map_entries(entries)
-                    .data(bytes_of(data))
-                ))
+                };
+                (*self.texture_module.as_ref().unwrap(), merge_specialization(specialization, &[(0, texture_index.to_ne_bytes())], alloc))
             }
             _ => {
-                (self.fragment_module, alloc.alloc(vk::SpecializationInfo::builder()))
+                (self.fragment_module, merge_specialization(specialization, &[], alloc))
             }
         };
 
+        // Vulkan requires the vertex shader to write the point size when drawing points
+        let vertex_specialization = merge_specialization(specialization, &[(16, (point_size as f32).to_ne_bytes())], alloc);
+
         let shader_stages: &[_] = alloc.alloc([
             vk::PipelineShaderStageCreateInfo::builder()
                 .stage(vk::ShaderStageFlags::VERTEX)
                 .module(vertex_module)
                 .name(SHADER_ENTRY)
+                .specialization_info(vertex_specialization)
                 .build(),
             vk::PipelineShaderStageCreateInfo::builder()
                 .stage(vk::ShaderStageFlags::FRAGMENT)
@@ -678,28 +1057,56 @@ impl ShaderModules {
 
 struct DrawPipeline {
     set0_layout: vk::DescriptorSetLayout,
+    /// Contains the dynamic uniform buffer. Push descriptor sets cannot contain dynamic
+    /// descriptors so this is a separate set.
+    set1_layout: vk::DescriptorSetLayout,
     pipeline_layout: vk::PipelineLayout,
 }
 
 impl DrawPipeline {
-    fn new(device: &DeviceContext) -> Result<Self, ObjectCreateError> {
-        let bindings = [
-            vk::DescriptorSetLayoutBinding {
-                binding: 0,
-                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                descriptor_count: 1,
-                stage_flags: vk::ShaderStageFlags::ALL,
-                p_immutable_samplers: std::ptr::null(),
-            },
-            vk::DescriptorSetLayoutBinding {
-                binding: 1,
-                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                descriptor_count: 3,
-                stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
-                p_immutable_samplers: std::ptr::null(),
-            },
-        ];
+    const SET1_SIZES: [vk::DescriptorPoolSize; 1] = [
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+            descriptor_count: 1,
+        }
+    ];
+
+    /// The bindings of the push descriptor set 0.
+    const SET0_BINDINGS: [vk::DescriptorSetLayoutBinding; 3] = [
+        vk::DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::ALL,
+            p_immutable_samplers: std::ptr::null(),
+        },
+        vk::DescriptorSetLayoutBinding {
+            binding: 1,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 3,
+            stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+            p_immutable_samplers: std::ptr::null(),
+        },
+        vk::DescriptorSetLayoutBinding {
+            binding: 2,
+            descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+            descriptor_count: STORAGE_IMAGE_COUNT as u32,
+            stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+            p_immutable_samplers: std::ptr::null(),
+        },
+    ];
+
+    /// The binding of set 1 containing the dynamic uniform buffer.
+    const SET1_BINDING: vk::DescriptorSetLayoutBinding = vk::DescriptorSetLayoutBinding {
+        binding: 0,
+        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+        descriptor_count: 1,
+        stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+        p_immutable_samplers: std::ptr::null(),
+    };
 
+    fn new(device: &DeviceContext) -> Result<Self, ObjectCreateError> {
+        let bindings = Self::SET0_BINDINGS;
         let info = vk::DescriptorSetLayoutCreateInfo::builder()
             .flags(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR)
             .bindings(&bindings);
@@ -711,6 +1118,18 @@ impl DrawPipeline {
             err
         })?;
 
+        let binding = Self::SET1_BINDING;
+        let info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(std::slice::from_ref(&binding));
+
+        let set1_layout = unsafe {
+            device.vk().create_descriptor_set_layout(&info, None)
+        }.map_err(|err| {
+            log::error!("vkCreateDescriptorSetLayout returned {:?} in DrawPipeline::new when creating set 1 layout", err);
+            unsafe { device.vk().destroy_descriptor_set_layout(set0_layout, None) };
+            err
+        })?;
+
         let push_constant_range = vk::PushConstantRange {
             stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
             offset: 0,
@@ -718,7 +1137,8 @@ impl DrawPipeline {
         };
 
         let layouts = [
-            set0_layout
+            set0_layout,
+            set1_layout,
         ];
 
         let info = vk::PipelineLayoutCreateInfo::builder()
@@ -729,12 +1149,16 @@ impl DrawPipeline {
             device.vk().create_pipeline_layout(&info, None)
         }.map_err(|err| {
             log::error!("vkCreatePipelineLayout returned {:?} in DrawPipeline::new", err);
-            unsafe { device.vk().destroy_descriptor_set_layout(set0_layout, None) };
+            unsafe {
+                device.vk().destroy_descriptor_set_layout(set1_layout, None);
+                device.vk().destroy_descriptor_set_layout(set0_layout, None);
+            }
             err
         })?;
 
         Ok(Self {
             set0_layout,
+            set1_layout,
             pipeline_layout
         })
     }
@@ -742,6 +1166,7 @@ impl DrawPipeline {
     fn destroy(&mut self, device: &DeviceContext) {
         unsafe {
             device.vk().destroy_pipeline_layout(self.pipeline_layout, None);
+            device.vk().destroy_descriptor_set_layout(self.set1_layout, None);
             device.vk().destroy_descriptor_set_layout(self.set0_layout, None);
         }
     }
@@ -754,6 +1179,14 @@ struct BackgroundPipeline {
 }
 
 impl BackgroundPipeline {
+    /// The descriptors needed by a single descriptor set of the background pipeline.
+    const DESCRIPTOR_SET_SIZES: [vk::DescriptorPoolSize; 1] = [
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: 1
+        },
+    ];
+
     fn new(device: &DeviceContext, render_pass: vk::RenderPass, subpass: u32, framebuffer_size: Vec2u32) -> Result<Self, ObjectCreateError> {
         let bindings = [
             vk::DescriptorSetLayoutBinding {
@@ -804,6 +1237,7 @@ impl BackgroundPipeline {
     fn destroy(&mut self, device: &DeviceContext) {
         unsafe {
             device.vk().destroy_pipeline(self.pipeline, None);
+            device.on_object_destroyed(TrackedObject::Pipeline);
             device.vk().destroy_pipeline_layout(self.pipeline_layout, None);
             device.vk().destroy_descriptor_set_layout(self.descriptor_set_layout, None);
         }
@@ -914,6 +1348,7 @@ impl BackgroundPipeline {
             }
             err
         })?.get(0).unwrap();
+        device.on_object_created(TrackedObject::Pipeline);
 
         unsafe {
             device.vk().destroy_shader_module(vertex_module, None);
@@ -927,6 +1362,9 @@ impl BackgroundPipeline {
 
 struct PassObjects {
     ready: AtomicBool,
+    /// True once a pass recorded using these objects has completed. Until then the attachments are
+    /// in an undefined layout and cannot be loaded.
+    initialized: AtomicBool,
 
     depth_image: vk::Image,
     depth_framebuffer_view: vk::ImageView,
@@ -935,6 +1373,10 @@ struct PassObjects {
     pass_image: vk::Image,
     pass_view: vk::ImageView,
 
+    /// Only used if multisampling is enabled. Single sampled image into which the pass image is resolved.
+    resolve_image: vk::Image,
+    resolve_view: vk::ImageView,
+
     output_image: vk::Image,
     output_view: vk::ImageView,
 
@@ -945,9 +1387,12 @@ struct PassObjects {
 }
 
 impl PassObjects {
-    fn new(device: &DeviceContext, framebuffer_size: Vec2u32, depth_format: vk::Format, color_format: vk::Format, render_pass: vk::RenderPass, bg_descriptor_set: vk::DescriptorSet) -> Result<Self, ObjectCreateError> {
+    fn new(device: &DeviceContext, framebuffer_size: Vec2u32, sample_count: vk::SampleCountFlags, depth_format: Option<vk::Format>, color_format: vk::Format, render_pass: vk::RenderPass, bg_descriptor_set: vk::DescriptorSet) -> Result<Self, ObjectCreateError> {
+        let multisampled = sample_count != vk::SampleCountFlags::TYPE_1;
+
         let mut result = PassObjects {
             ready: AtomicBool::new(true),
+            initialized: AtomicBool::new(false),
 
             depth_image: vk::Image::null(),
             depth_framebuffer_view: vk::ImageView::null(),
@@ -956,32 +1401,52 @@ impl PassObjects {
             pass_image: vk::Image::null(),
             pass_view: vk::ImageView::null(),
 
+            resolve_image: vk::Image::null(),
+            resolve_view: vk::ImageView::null(),
+
             output_image: vk::Image::null(),
             output_view: vk::ImageView::null(),
 
             bg_descriptor_set,
             framebuffer: vk::Framebuffer::null(),
 
-            allocations: Vec::with_capacity(3)
+            allocations: Vec::with_capacity(4)
         };
 
-        let (depth_image, allocation) = Self::create_image(device, framebuffer_size, depth_format, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)?;
-        result.depth_image = depth_image;
-        result.allocations.push(allocation);
-
-        let depth_framebuffer_view = Self::create_image_view(device, depth_image, depth_format, vk::ImageAspectFlags::DEPTH, false).map_err(|err| {
-            result.destroy(device);
-            err
-        })?;
-        result.depth_framebuffer_view = depth_framebuffer_view;
+        // Multisampled attachments are never read outside of the render pass
+        let (depth_usage, pass_usage) = if multisampled {
+            (
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+            )
+        } else {
+            (
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT
+            )
+        };
 
-        let depth_sampler_view = Self::create_image_view(device, depth_image, depth_format, vk::ImageAspectFlags::DEPTH, true).map_err(|err| {
-            result.destroy(device);
-            err
-        })?;
-        result.depth_sampler_view = depth_sampler_view;
+        if let Some(depth_format) = depth_format {
+            let (depth_image, allocation) = Self::create_image(device, framebuffer_size, sample_count, depth_format, depth_usage)?;
+            result.depth_image = depth_image;
+            result.allocations.push(allocation);
+
+            let depth_framebuffer_view = Self::create_image_view(device, depth_image, depth_format, vk::ImageAspectFlags::DEPTH, false).map_err(|err| {
+                result.destroy(device);
+                err
+            })?;
+            result.depth_framebuffer_view = depth_framebuffer_view;
+
+            if !multisampled {
+                let depth_sampler_view = Self::create_image_view(device, depth_image, depth_format, vk::ImageAspectFlags::DEPTH, true).map_err(|err| {
+                    result.destroy(device);
+                    err
+                })?;
+                result.depth_sampler_view = depth_sampler_view;
+            }
+        }
 
-        let (pass_image, allocation) = Self::create_image(device, framebuffer_size, color_format, vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT).map_err(|err| {
+        let (pass_image, allocation) = Self::create_image(device, framebuffer_size, sample_count, color_format, pass_usage).map_err(|err| {
             result.destroy(device);
             err
         })?;
@@ -994,7 +1459,22 @@ impl PassObjects {
         })?;
         result.pass_view = pass_view;
 
-        let (output_image, allocation) = Self::create_image(device, framebuffer_size, color_format, vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED).map_err(|err| {
+        if multisampled {
+            let (resolve_image, allocation) = Self::create_image(device, framebuffer_size, vk::SampleCountFlags::TYPE_1, color_format, vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT).map_err(|err| {
+                result.destroy(device);
+                err
+            })?;
+            result.resolve_image = resolve_image;
+            result.allocations.push(allocation);
+
+            let resolve_view = Self::create_image_view(device, resolve_image, color_format, vk::ImageAspectFlags::COLOR, false).map_err(|err| {
+                result.destroy(device);
+                err
+            })?;
+            result.resolve_view = resolve_view;
+        }
+
+        let (output_image, allocation) = Self::create_image(device, framebuffer_size, vk::SampleCountFlags::TYPE_1, color_format, vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC).map_err(|err| {
             result.destroy(device);
             err
         })?;
@@ -1007,14 +1487,27 @@ impl PassObjects {
         })?;
         result.output_view = output_view;
 
-        let framebuffer = Self::create_framebuffer(device, framebuffer_size, depth_framebuffer_view, pass_view, output_view, render_pass).map_err(|err| {
+        // Must match the attachment order of DebugPipeline::create_render_pass
+        let mut attachments = Vec::with_capacity(4);
+        if depth_format.is_some() {
+            attachments.push(result.depth_framebuffer_view);
+        }
+        attachments.push(pass_view);
+        if multisampled {
+            attachments.push(result.resolve_view);
+        }
+        attachments.push(output_view);
+
+        let framebuffer = Self::create_framebuffer(device, framebuffer_size, &attachments, render_pass).map_err(|err| {
             result.destroy(device);
             err
         })?;
         result.framebuffer = framebuffer;
 
+        let input_view = if multisampled { result.resolve_view } else { pass_view };
+
         let info = vk::DescriptorImageInfo::builder()
-            .image_view(pass_view)
+            .image_view(input_view)
             .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
 
         let write = vk::WriteDescriptorSet::builder()
@@ -1056,6 +1549,12 @@ impl PassObjects {
             if self.output_image != vk::Image::null() {
                 device.vk().destroy_image(self.output_image, None);
             }
+            if self.resolve_view != vk::ImageView::null() {
+                device.vk().destroy_image_view(self.resolve_view, None);
+            }
+            if self.resolve_image != vk::Image::null() {
+                device.vk().destroy_image(self.resolve_image, None);
+            }
             if self.pass_view != vk::ImageView::null() {
                 device.vk().destroy_image_view(self.pass_view, None);
             }
@@ -1077,46 +1576,18 @@ impl PassObjects {
         }
     }
 
-    fn create_image(device: &DeviceContext, size: Vec2u32, format: vk::Format, usage: vk::ImageUsageFlags) -> Result<(vk::Image, Allocation), ObjectCreateError> {
-        let info = vk::ImageCreateInfo::builder()
-            .image_type(vk::ImageType::TYPE_2D)
-            .format(format)
-            .extent(vk::Extent3D {
-                width: size[0],
-                height: size[1],
-                depth: 1
-            })
-            .mip_levels(1)
-            .array_layers(1)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(usage)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .initial_layout(vk::ImageLayout::UNDEFINED);
-
-        let image = unsafe {
-            device.vk().create_image(&info, None)
-        }.map_err(|err| {
-            log::error!("vkCreateImage returned {:?} in PassObjects::create_image", err);
-            err
-        })?;
-
-        let allocation = device.get_allocator().allocate_image_memory(image, &AllocationStrategy::AutoGpuOnly).map_err(|err| {
-            log::error!("Failed to allocate image memory in PassObjects::create_image {:?}", err);
-            unsafe { device.vk().destroy_image(image, None) };
-            ObjectCreateError::Allocation
-        })?;
-
-        if let Err(err) = unsafe {
-            device.vk().bind_image_memory(image, allocation.memory(), allocation.offset())
-        } {
-            log::error!("Failed to bind image memory in PassObjects::create_image {:?}", err);
-            unsafe { device.vk().destroy_image(image, None) };
-            device.get_allocator().free(allocation);
-            return Err(ObjectCreateError::Vulkan(err));
-        }
+    fn create_image(device: &DeviceContext, size: Vec2u32, samples: vk::SampleCountFlags, format: vk::Format, usage: vk::ImageUsageFlags) -> Result<(vk::Image, Allocation), ObjectCreateError> {
+        let spec = ImageSpec::new(ImageSize::make_2d(size[0], size[1]), Format::format_for(format), samples);
+        let description = ImageDescription::new_simple(spec, usage);
 
-        Ok((image, allocation))
+        image::create_image(device, &description, &AllocationStrategy::AutoGpuOnly).map_err(|err| {
+            log::error!("Failed to create image in PassObjects::create_image {:?}", err);
+            match err {
+                ImageCreateError::Vulkan(err) => ObjectCreateError::Vulkan(err),
+                ImageCreateError::Allocation => ObjectCreateError::Allocation,
+                ImageCreateError::InvalidTiling | ImageCreateError::UnsupportedFormat(_) => ObjectCreateError::UnsupportedConfiguration,
+            }
+        })
     }
 
     fn create_image_view(device: &DeviceContext, image: vk::Image, format: vk::Format, aspect_mask: vk::ImageAspectFlags, swizzle_r: bool) -> Result<vk::ImageView, ObjectCreateError> {
@@ -1160,14 +1631,10 @@ impl PassObjects {
         Ok(image_view)
     }
 
-    fn create_framebuffer(device: &DeviceContext, size: Vec2u32, depth_view: vk::ImageView, pass_view: vk::ImageView, output_view: vk::ImageView, render_pass: vk::RenderPass) -> Result<vk::Framebuffer, ObjectCreateError> {
-        let attachments = [
-            depth_view, pass_view, output_view
-        ];
-
+    fn create_framebuffer(device: &DeviceContext, size: Vec2u32, attachments: &[vk::ImageView], render_pass: vk::RenderPass) -> Result<vk::Framebuffer, ObjectCreateError> {
         let info = vk::FramebufferCreateInfo::builder()
             .render_pass(render_pass)
-            .attachments(&attachments)
+            .attachments(attachments)
             .width(size[0])
             .height(size[1])
             .layers(1);
@@ -1188,12 +1655,16 @@ struct PipelineConfig {
     primitive_topology: vk::PrimitiveTopology,
     depth_test_enable: bool,
     depth_write_enable: bool,
+    conservative_rasterization: bool,
 }
 
 struct ShaderPipelines {
     device: Arc<DeviceContext>,
     vertex_format: VertexFormat,
     used_uniforms: McUniform,
+    /// The specialization constants of the shader. They never change for a shader so they are not
+    /// part of the [`PipelineConfig`] used as key in `pipelines`.
+    specialization: SpecializationData,
     pipelines: HashMap<PipelineConfig, vk::Pipeline>,
     #[allow(unused)]
     listener: ShaderListener,
@@ -1202,11 +1673,12 @@ struct ShaderPipelines {
 }
 
 impl ShaderPipelines {
-    fn new(device: Arc<DeviceContext>, vertex_format: VertexFormat, used_uniforms: McUniform, listener: ShaderListener) -> Self {
+    fn new(device: Arc<DeviceContext>, vertex_format: VertexFormat, used_uniforms: McUniform, specialization: SpecializationData, listener: ShaderListener) -> Self {
         Self {
             device,
             vertex_format,
             used_uniforms,
+            specialization,
             pipelines: HashMap::new(),
             listener,
             used_counter: 0,
@@ -1214,11 +1686,11 @@ impl ShaderPipelines {
         }
     }
 
-    fn get_or_create_pipeline<T: FnOnce(&VertexFormat) -> vk::Pipeline>(&mut self, config: &PipelineConfig, create_fn: T) -> vk::Pipeline {
+    fn get_or_create_pipeline<T: FnOnce(&VertexFormat, &SpecializationData) -> vk::Pipeline>(&mut self, config: &PipelineConfig, create_fn: T) -> vk::Pipeline {
         if let Some(pipeline) = self.pipelines.get(config) {
             *pipeline
         } else {
-            let pipeline = create_fn(&self.vertex_format);
+            let pipeline = create_fn(&self.vertex_format, &self.specialization);
             self.pipelines.insert(*config, pipeline);
             pipeline
         }
@@ -1247,6 +1719,7 @@ impl Drop for ShaderPipelines {
             unsafe {
                 self.device.vk().destroy_pipeline(*pipeline, None);
             }
+            self.device.on_object_destroyed(TrackedObject::Pipeline);
         }
     }
 }
@@ -1254,42 +1727,264 @@ impl Drop for ShaderPipelines {
 struct DebugPipelinePass {
     parent: Arc<DebugPipeline>,
     index: usize,
-
-    placeholder_texture: vk::ImageView,
-    placeholder_sampler: vk::Sampler,
-    shader_uniforms: HashMap<ShaderId, UniformStateTracker>,
+    /// The render pass matching the attachment ops of this pass.
+    render_pass: vk::RenderPass,
 
     command_buffer: Option<vk::CommandBuffer>,
-    current_pipeline: Option<(ShaderId, PipelineConfig)>,
-    current_vertex_buffer: Option<vk::Buffer>,
-    current_index_buffer: Option<vk::Buffer>,
+    /// If true all draws are recorded into secondary command buffers so that command buffers
+    /// recorded by a [`DebugPipelineSecondary`] can be executed in between. Otherwise draws are
+    /// recorded inline into the primary command buffer.
+    uses_secondaries: bool,
+    /// The secondary command buffer used for draws processed by the pass itself.
+    draw_command_buffer: Option<vk::CommandBuffer>,
+    /// Secondary command buffers executed in order in the draw subpass.
+    secondary_command_buffers: Vec<vk::CommandBuffer>,
+    draws: DrawRecorder,
+    /// True if the pass has been recorded. Aborted passes are never recorded.
+    recorded: bool,
 }
 
 impl DebugPipelinePass {
-    fn new(parent: Arc<DebugPipeline>, index: usize) -> Self {
+    fn new(parent: Arc<DebugPipeline>, index: usize, render_pass: vk::RenderPass, uses_secondaries: bool) -> Self {
         Self {
+            draws: DrawRecorder::new(parent.clone(), vk::ImageView::null(), vk::Sampler::null()),
             parent,
             index,
-
-            placeholder_texture: vk::ImageView::null(),
-            placeholder_sampler: vk::Sampler::null(),
-            shader_uniforms: HashMap::new(),
+            render_pass,
 
             command_buffer: None,
-            current_pipeline: None,
-            current_vertex_buffer: None,
-            current_index_buffer: None
+            uses_secondaries,
+            draw_command_buffer: None,
+            secondary_command_buffers: Vec::with_capacity(4),
+            recorded: false,
         }
     }
 
-    fn update_uniform(&mut self, shader: ShaderId, data: &McUniformData) {
-        if !self.shader_uniforms.contains_key(&shader) {
-            let uniforms = self.parent.pipelines.lock().unwrap().get(&shader).unwrap().used_uniforms;
-            self.shader_uniforms.insert(shader, UniformStateTracker::new(uniforms, self.placeholder_texture, self.placeholder_sampler));
+    /// Returns the command buffer draws processed by the pass must be recorded into. This is the
+    /// primary command buffer if the pass does not use secondary command buffers.
+    fn get_draw_command_buffer(&mut self, obj: &mut PooledObjectProvider) -> vk::CommandBuffer {
+        if !self.uses_secondaries {
+            return self.command_buffer.unwrap();
+        }
+        if let Some(cmd) = self.draw_command_buffer {
+            return cmd;
         }
-        let tracker = self.shader_uniforms.get_mut(&shader).unwrap();
-        tracker.update_uniform(data);
-    }
+
+        let inheritance = self.parent.get_draw_inheritance_info(self.parent.pass_objects[self.index].framebuffer);
+        let cmd = obj.get_begin_secondary_command_buffer(&inheritance).unwrap_or_else(|err| {
+            log::error!("vkBeginCommandBuffer returned {:?} in DebugPipelinePass::get_draw_command_buffer", err);
+            panic!()
+        });
+
+        // A new command buffer does not inherit any bound state
+        self.draws.invalidate();
+        self.draw_command_buffer = Some(cmd);
+
+        cmd
+    }
+
+    /// Ends the current draw command buffer if one exists and queues it for execution.
+    fn end_draw_command_buffer(&mut self) {
+        if let Some(cmd) = self.draw_command_buffer.take() {
+            let device = self.parent.emulator.get_device();
+            unsafe {
+                device.vk().end_command_buffer(cmd)
+            }.unwrap_or_else(|err| {
+                log::error!("vkEndCommandBuffer returned {:?} in DebugPipelinePass::end_draw_command_buffer", err);
+                panic!()
+            });
+            self.secondary_command_buffers.push(cmd);
+        }
+    }
+}
+
+/// Records secondary command buffers for a [`DebugPipeline`] on the thread of a
+/// [`SecondaryRecorder`]. Each instance owns a command pool so that multiple instances can record
+/// in parallel.
+///
+/// [`SecondaryRecorder`]: crate::renderer::emulator::SecondaryRecorder
+struct DebugPipelineSecondary {
+    parent: Arc<DebugPipeline>,
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    draws: DrawRecorder,
+}
+assert_impl_all!(DebugPipelineSecondary: Send);
+
+impl DebugPipelineSecondary {
+    fn new(parent: Arc<DebugPipeline>, placeholder_texture: vk::ImageView, placeholder_sampler: vk::Sampler) -> Self {
+        let device = parent.emulator.get_device();
+
+        let info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(device.get_main_queue().get_queue_family_index());
+
+        let command_pool = unsafe {
+            device.vk().create_command_pool(&info, None)
+        }.unwrap_or_else(|err| {
+            log::error!("vkCreateCommandPool returned {:?} in DebugPipelineSecondary::new", err);
+            panic!()
+        });
+
+        let info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(command_pool)
+            .level(vk::CommandBufferLevel::SECONDARY)
+            .command_buffer_count(1);
+
+        let command_buffer = unsafe {
+            device.vk().allocate_command_buffers(&info)
+        }.unwrap_or_else(|err| {
+            log::error!("vkAllocateCommandBuffers returned {:?} in DebugPipelineSecondary::new", err);
+            panic!()
+        })[0];
+
+        // The framebuffer is not known since the recorder is not bound to a specific pass object
+        let inheritance = parent.get_draw_inheritance_info(vk::Framebuffer::null());
+        let info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+            .inheritance_info(&inheritance);
+
+        unsafe {
+            device.vk().begin_command_buffer(command_buffer, &info)
+        }.unwrap_or_else(|err| {
+            log::error!("vkBeginCommandBuffer returned {:?} in DebugPipelineSecondary::new", err);
+            panic!()
+        });
+
+        Self {
+            draws: DrawRecorder::new(parent.clone(), placeholder_texture, placeholder_sampler),
+            parent,
+            command_pool,
+            command_buffer,
+        }
+    }
+}
+
+impl EmulatorPipelineSecondary for DebugPipelineSecondary {
+    fn process_task(&mut self, task: &PipelineTask) {
+        let share = &self.parent.emulator.share;
+        self.draws.process_task(task, self.command_buffer, |data| share.allocate_uniform(data));
+    }
+
+    fn finish(&mut self) -> vk::CommandBuffer {
+        let device = self.parent.emulator.get_device();
+        unsafe {
+            device.vk().end_command_buffer(self.command_buffer)
+        }.unwrap_or_else(|err| {
+            log::error!("vkEndCommandBuffer returned {:?} in DebugPipelineSecondary::finish", err);
+            panic!()
+        });
+
+        self.command_buffer
+    }
+}
+
+impl Drop for DebugPipelineSecondary {
+    fn drop(&mut self) {
+        unsafe {
+            // Also frees the command buffer
+            self.parent.emulator.get_device().vk().destroy_command_pool(self.command_pool, None);
+        }
+    }
+}
+
+/// Records draws into the draw subpass of a [`DebugPipeline`] and keeps track of the state bound in
+/// the command buffer to avoid redundant binds.
+struct DrawRecorder {
+    parent: Arc<DebugPipeline>,
+
+    placeholder_texture: vk::ImageView,
+    placeholder_sampler: vk::Sampler,
+    shader_uniforms: HashMap<ShaderId, UniformStateTracker>,
+    dynamic_uniform_sets: HashMap<ShaderId, vk::DescriptorSet>,
+    /// All sets allocated for dynamic uniform buffers. They are freed once the recorder is
+    /// dropped since they may still be used by recorded draws.
+    allocated_sets: Vec<vk::DescriptorSet>,
+
+    current_pipeline: Option<(ShaderId, PipelineConfig)>,
+    current_vertex_buffer: Option<vk::Buffer>,
+    current_index_buffer: Option<(vk::Buffer, vk::IndexType)>,
+    current_dynamic_uniform: Option<(vk::DescriptorSet, u32)>,
+
+    /// Set once a draw requested depth writes without a depth attachment so that the warning is
+    /// only logged once per pass.
+    depth_write_warned: bool,
+}
+
+impl DrawRecorder {
+    fn new(parent: Arc<DebugPipeline>, placeholder_texture: vk::ImageView, placeholder_sampler: vk::Sampler) -> Self {
+        Self {
+            parent,
+
+            placeholder_texture,
+            placeholder_sampler,
+            shader_uniforms: HashMap::new(),
+            dynamic_uniform_sets: HashMap::new(),
+            allocated_sets: Vec::new(),
+
+            current_pipeline: None,
+            current_vertex_buffer: None,
+            current_index_buffer: None,
+            current_dynamic_uniform: None,
+
+            depth_write_warned: false,
+        }
+    }
+
+    /// Forgets all bound state. Must be called when switching to a new command buffer.
+    fn invalidate(&mut self) {
+        self.current_pipeline = None;
+        self.current_vertex_buffer = None;
+        self.current_index_buffer = None;
+        self.current_dynamic_uniform = None;
+        for tracker in self.shader_uniforms.values_mut() {
+            tracker.invalidate();
+        }
+    }
+
+    fn process_task<F: FnOnce(&[u8]) -> (vk::Buffer, vk::DeviceSize)>(&mut self, task: &PipelineTask, cmd: vk::CommandBuffer, allocate_uniform: F) {
+        match task {
+            PipelineTask::UpdateUniform(shader, data) => {
+                self.update_uniform(*shader, data);
+            }
+            PipelineTask::UpdateTexture(shader, index, view, sampler) => {
+                self.update_texture(*shader, *index, *view, *sampler);
+            }
+            PipelineTask::UpdateTextures(shader, textures) => {
+                // The descriptor set is only written once before the next draw
+                for (index, view, sampler) in textures.iter() {
+                    self.update_texture(*shader, *index, *view, *sampler);
+                }
+            }
+            PipelineTask::UpdateStorageImage(shader, index, view) => {
+                self.update_storage_image(*shader, *index, *view);
+            }
+            PipelineTask::SetDynamicUniformBuffer(shader, buffer, range) => {
+                self.set_dynamic_uniform_buffer(*shader, *buffer, *range);
+            }
+            PipelineTask::Draw(task) => {
+                self.draw(task, cmd, allocate_uniform);
+            }
+            PipelineTask::DrawIndirect(task) => {
+                self.draw_indirect(task, cmd, allocate_uniform);
+            }
+            PipelineTask::ClearAttachments(attachments, rects) => {
+                let device = self.parent.emulator.get_device();
+                unsafe {
+                    device.vk().cmd_clear_attachments(cmd, attachments, rects);
+                }
+            }
+        }
+    }
+
+    fn update_uniform(&mut self, shader: ShaderId, data: &McUniformData) {
+        if !self.shader_uniforms.contains_key(&shader) {
+            let uniforms = self.parent.pipelines.lock().unwrap().get(&shader).unwrap().used_uniforms;
+            self.shader_uniforms.insert(shader, UniformStateTracker::new(uniforms, self.placeholder_texture, self.placeholder_sampler));
+        }
+        let tracker = self.shader_uniforms.get_mut(&shader).unwrap();
+        tracker.update_uniform(data);
+    }
 
     fn update_texture(&mut self, shader: ShaderId, index: u32, view: vk::ImageView, sampler: vk::Sampler) {
         if !self.shader_uniforms.contains_key(&shader) {
@@ -1300,35 +1995,142 @@ impl DebugPipelinePass {
         tracker.update_texture(index, view, sampler);
     }
 
-    fn draw(&mut self, task: &DrawTask, obj: &mut PooledObjectProvider) {
+    fn update_storage_image(&mut self, shader: ShaderId, index: u32, view: vk::ImageView) {
+        if !self.shader_uniforms.contains_key(&shader) {
+            let uniforms = self.parent.pipelines.lock().unwrap().get(&shader).unwrap().used_uniforms;
+            self.shader_uniforms.insert(shader, UniformStateTracker::new(uniforms, self.placeholder_texture, self.placeholder_sampler));
+        }
+        let tracker = self.shader_uniforms.get_mut(&shader).unwrap();
+        tracker.update_storage_image(index, view);
+    }
+
+    fn set_dynamic_uniform_buffer(&mut self, shader: ShaderId, buffer: vk::Buffer, range: vk::DeviceSize) {
+        let device = self.parent.emulator.get_device();
+
+        let set = self.parent.dynamic_uniform_allocator.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned dynamic uniform allocator mutex in DrawRecorder::set_dynamic_uniform_buffer!");
+            panic!()
+        }).allocate(device).unwrap_or_else(|err| {
+            log::error!("Failed to allocate dynamic uniform descriptor set {:?}", err);
+            panic!()
+        });
+        self.allocated_sets.push(set);
+
+        let buffer_info = vk::DescriptorBufferInfo {
+            buffer,
+            offset: 0,
+            range
+        };
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .buffer_info(std::slice::from_ref(&buffer_info));
+
+        unsafe {
+            device.vk().update_descriptor_sets(std::slice::from_ref(&write), &[]);
+        }
+
+        self.dynamic_uniform_sets.insert(shader, set);
+    }
+
+    fn draw<F: FnOnce(&[u8]) -> (vk::Buffer, vk::DeviceSize)>(&mut self, task: &DrawTask, cmd: vk::CommandBuffer, allocate_uniform: F) {
+        self.prepare_draw(cmd, task.shader, task.primitive_topology, task.depth_write_enable, task.conservative, task.vertex_buffer, task.index_buffer, task.index_type, allocate_uniform);
+        self.bind_dynamic_uniform(cmd, task.shader, task.dynamic_uniform_offset);
+        let device = self.parent.emulator.get_device();
+
+        unsafe {
+            device.vk().cmd_draw_indexed(cmd, task.index_count, 1, task.first_index, task.vertex_offset, 0);
+        }
+    }
+
+    fn draw_indirect<F: FnOnce(&[u8]) -> (vk::Buffer, vk::DeviceSize)>(&mut self, task: &DrawIndirectTask, cmd: vk::CommandBuffer, allocate_uniform: F) {
+        self.prepare_draw(cmd, task.shader, task.primitive_topology, task.depth_write_enable, task.conservative, task.vertex_buffer, task.index_buffer, task.index_type, allocate_uniform);
+        self.bind_dynamic_uniform(cmd, task.shader, 0);
+        let device = self.parent.emulator.get_device();
+
+        unsafe {
+            device.vk().cmd_draw_indexed_indirect(cmd, task.draw_buffer, task.draw_offset, task.draw_count, task.stride);
+        }
+    }
+
+    /// Binds the dynamic uniform buffer of a shader at the specified offset if one is set.
+    fn bind_dynamic_uniform(&mut self, cmd: vk::CommandBuffer, shader: ShaderId, offset: u32) {
+        let set = match self.dynamic_uniform_sets.get(&shader) {
+            Some(set) => *set,
+            None => {
+                if offset != 0 && cfg!(debug_assertions) {
+                    log::warn!("Draw with dynamic uniform offset {:?} but shader {:?} has no dynamic uniform buffer", offset, shader);
+                }
+                return;
+            }
+        };
+
+        if self.current_dynamic_uniform != Some((set, offset)) {
+            self.current_dynamic_uniform = Some((set, offset));
+
+            let device = self.parent.emulator.get_device();
+            unsafe {
+                device.vk().cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.parent.draw_pipeline.pipeline_layout,
+                    1,
+                    std::slice::from_ref(&set),
+                    std::slice::from_ref(&offset)
+                );
+            }
+        }
+    }
+
+    /// Binds the pipeline, uniforms and buffers needed for a draw.
+    fn prepare_draw<F: FnOnce(&[u8]) -> (vk::Buffer, vk::DeviceSize)>(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        shader: ShaderId,
+        primitive_topology: vk::PrimitiveTopology,
+        depth_write_enable: bool,
+        conservative: bool,
+        vertex_buffer: vk::Buffer,
+        index_buffer: vk::Buffer,
+        index_type: vk::IndexType,
+        allocate_uniform: F
+    ) {
         let device = self.parent.emulator.get_device();
-        let cmd = *self.command_buffer.as_ref().unwrap();
+
+        let has_depth = self.parent.has_depth;
+        if !has_depth && depth_write_enable && cfg!(debug_assertions) && !self.depth_write_warned {
+            log::warn!("Draw with shader {:?} requested depth writes in a pass without depth attachment. Ignoring depth_write_enable for all draws of this pass", shader);
+            self.depth_write_warned = true;
+        }
 
         let pipeline_config = PipelineConfig {
-            primitive_topology: task.primitive_topology,
-            depth_test_enable: true,
-            depth_write_enable: task.depth_write_enable
+            primitive_topology,
+            depth_test_enable: has_depth,
+            depth_write_enable: has_depth && depth_write_enable,
+            conservative_rasterization: conservative && device.supports_conservative_rasterization(),
         };
 
-        if self.current_pipeline != Some((task.shader, pipeline_config)) {
-            self.current_pipeline = Some((task.shader, pipeline_config));
+        if self.current_pipeline != Some((shader, pipeline_config)) {
+            self.current_pipeline = Some((shader, pipeline_config));
 
-            let new_pipeline = self.parent.get_pipeline(task.shader, &pipeline_config);
+            let new_pipeline = self.parent.get_pipeline(shader, &pipeline_config);
             unsafe {
                 device.vk().cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, new_pipeline);
             }
         }
 
-        if !self.shader_uniforms.contains_key(&task.shader) {
+        if !self.shader_uniforms.contains_key(&shader) {
             log::warn!("Called draw without any shader uniforms. Using default values!");
-            let uniforms = self.parent.pipelines.lock().unwrap().get(&task.shader).unwrap().used_uniforms;
-            self.shader_uniforms.insert(task.shader, UniformStateTracker::new(uniforms, self.placeholder_texture, self.placeholder_sampler));
+            let uniforms = self.parent.pipelines.lock().unwrap().get(&shader).unwrap().used_uniforms;
+            self.shader_uniforms.insert(shader, UniformStateTracker::new(uniforms, self.placeholder_texture, self.placeholder_sampler));
         }
-        if let Some(tracker) = self.shader_uniforms.get_mut(&task.shader) {
+        if let Some(tracker) = self.shader_uniforms.get_mut(&shader) {
             if let Some(push_constants) = tracker.validate_push_constants() {
                 unsafe {
                     device.vk().cmd_push_constants(
-                        self.command_buffer.unwrap(),
+                        cmd,
                         self.parent.draw_pipeline.pipeline_layout,
                         vk::ShaderStageFlags::ALL_GRAPHICS,
                         0,
@@ -1338,7 +2140,7 @@ impl DebugPipelinePass {
             }
 
             if let Some(static_uniforms) = tracker.validate_static_uniforms() {
-                let (buffer, offset) = obj.allocate_uniform(bytes_of(static_uniforms));
+                let (buffer, offset) = allocate_uniform(bytes_of(static_uniforms));
                 let buffer_info = vk::DescriptorBufferInfo {
                     buffer,
                     offset,
@@ -1352,7 +2154,7 @@ impl DebugPipelinePass {
 
                 unsafe {
                     device.push_descriptor_khr().cmd_push_descriptor_set(
-                        self.command_buffer.unwrap(),
+                        cmd,
                         vk::PipelineBindPoint::GRAPHICS,
                         self.parent.draw_pipeline.pipeline_layout,
                         0,
@@ -1400,7 +2202,7 @@ impl DebugPipelinePass {
 
                 unsafe {
                     device.push_descriptor_khr().cmd_push_descriptor_set(
-                        self.command_buffer.unwrap(),
+                        cmd,
                         vk::PipelineBindPoint::GRAPHICS,
                         self.parent.draw_pipeline.pipeline_layout,
                         0,
@@ -1408,110 +2210,165 @@ impl DebugPipelinePass {
                     );
                 }
             }
+
+            if let Some(storage_images) = tracker.validate_storage_images() {
+                // Unbound storage images are never accessed by the shader so they are not written
+                let image_infos: Vec<_> = storage_images.iter().map(|view| view.map(|view| vk::DescriptorImageInfo {
+                    sampler: vk::Sampler::null(),
+                    image_view: view,
+                    image_layout: vk::ImageLayout::GENERAL
+                })).collect();
+                let writes: Vec<_> = image_infos.iter().enumerate().filter_map(|(index, info)| info.as_ref().map(|info| {
+                    vk::WriteDescriptorSet::builder()
+                        .dst_binding(2)
+                        .dst_array_element(index as u32)
+                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                        .image_info(std::slice::from_ref(info))
+                        .build()
+                })).collect();
+
+                if !writes.is_empty() {
+                    unsafe {
+                        device.push_descriptor_khr().cmd_push_descriptor_set(
+                            cmd,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            self.parent.draw_pipeline.pipeline_layout,
+                            0,
+                            &writes
+                        );
+                    }
+                }
+            }
         }
 
-        if self.current_vertex_buffer != Some(task.vertex_buffer) {
+        if self.current_vertex_buffer != Some(vertex_buffer) {
             unsafe {
                 device.vk().cmd_bind_vertex_buffers(
                     cmd,
                     0,
-                    std::slice::from_ref(&task.vertex_buffer),
+                    std::slice::from_ref(&vertex_buffer),
                     std::slice::from_ref(&0)
                 );
             }
-            self.current_vertex_buffer = Some(task.vertex_buffer);
+            self.current_vertex_buffer = Some(vertex_buffer);
         }
 
-        if self.current_index_buffer != Some(task.index_buffer) {
+        // Meshes packed into the same buffer may use different index types
+        if self.current_index_buffer != Some((index_buffer, index_type)) {
             unsafe {
-                device.vk().cmd_bind_index_buffer(cmd, task.index_buffer, 0, task.index_type);
+                device.vk().cmd_bind_index_buffer(cmd, index_buffer, 0, index_type);
             }
-            self.current_index_buffer = Some(task.index_buffer);
+            self.current_index_buffer = Some((index_buffer, index_type));
         }
+    }
+}
 
-        unsafe {
-            device.vk().cmd_draw_indexed(cmd, task.index_count, 1, task.first_index, task.vertex_offset, 0);
+impl Drop for DrawRecorder {
+    fn drop(&mut self) {
+        if !self.allocated_sets.is_empty() {
+            let mut guard = self.parent.dynamic_uniform_allocator.lock().unwrap_or_else(|_| {
+                log::error!("Poisoned dynamic uniform allocator mutex in DrawRecorder::drop!");
+                panic!()
+            });
+            for set in self.allocated_sets.drain(..) {
+                guard.free(set);
+            }
         }
     }
 }
 
 impl EmulatorPipelinePass for DebugPipelinePass {
     fn init(&mut self, _: &Queue, obj: &mut PooledObjectProvider, placeholder_texture: vk::ImageView, placeholder_sampler: vk::Sampler) {
-        self.placeholder_texture = placeholder_texture;
-        self.placeholder_sampler = placeholder_sampler;
+        self.draws.placeholder_texture = placeholder_texture;
+        self.draws.placeholder_sampler = placeholder_sampler;
 
         let cmd = obj.get_begin_command_buffer().unwrap();
         self.command_buffer = Some(cmd);
 
         let device = self.parent.emulator.get_device();
 
-        let clear_values = [
-            vk::ClearValue {
-                depth_stencil: vk::ClearDepthStencilValue {
-                    depth: 1.0,
-                    stencil: 0
-                }
-            },
-            vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0f32, 0f32, 0f32, 0f32],
-                }
-            },
-            vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0f32, 0f32, 0f32, 0f32],
-                }
+        let depth_clear = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: self.parent.get_depth_clear_value(),
+                stencil: 0
             }
-        ];
+        };
+        let color_clear = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0f32, 0f32, 0f32, 0f32],
+            }
+        };
+
+        // Only the depth and pass color attachments may be cleared. Clear values of attachments
+        // which are not cleared are ignored.
+        let mut clear_values = Vec::with_capacity(2);
+        if self.parent.has_depth {
+            clear_values.push(depth_clear);
+        }
+        clear_values.push(color_clear);
         let info = vk::RenderPassBeginInfo::builder()
-            .render_pass(self.parent.render_pass)
+            .render_pass(self.render_pass)
             .framebuffer(self.parent.pass_objects[self.index].framebuffer)
             .render_area(make_full_rect(self.parent.framebuffer_size))
             .clear_values(&clear_values);
 
+        let contents = if self.uses_secondaries {
+            vk::SubpassContents::SECONDARY_COMMAND_BUFFERS
+        } else {
+            vk::SubpassContents::INLINE
+        };
         unsafe {
-            device.vk().cmd_begin_render_pass(cmd, &info, vk::SubpassContents::INLINE);
+            device.vk().cmd_begin_render_pass(cmd, &info, contents);
         }
     }
 
     fn process_task(&mut self, task: &PipelineTask, obj: &mut PooledObjectProvider) {
-        match task {
-            PipelineTask::UpdateUniform(shader, data) => {
-                self.update_uniform(*shader, data);
-            }
-            PipelineTask::UpdateTexture(shader, index, view, sampler) => {
-                self.update_texture(*shader, *index, *view, *sampler);
-            }
-            PipelineTask::Draw(task) => {
-                self.draw(task, obj);
-            }
+        let cmd = self.get_draw_command_buffer(obj);
+        self.draws.process_task(task, cmd, |data| obj.allocate_uniform(data));
+    }
+
+    fn execute_secondary(&mut self, cmd: vk::CommandBuffer, _: &mut PooledObjectProvider) {
+        if !self.uses_secondaries {
+            log::error!("Called DebugPipelinePass::execute_secondary on a pass which records draws inline");
+            panic!()
         }
+        self.end_draw_command_buffer();
+        self.secondary_command_buffers.push(cmd);
     }
 
     fn record<'a>(&mut self, _: &mut PooledObjectProvider, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump) {
         let device = self.parent.emulator.get_device();
         let cmd = self.command_buffer.take().unwrap();
 
+        self.end_draw_command_buffer();
+
         let bg_descriptor_sets = [self.parent.pass_objects[self.index].bg_descriptor_set];
 
         unsafe {
+            if !self.secondary_command_buffers.is_empty() {
+                device.vk().cmd_execute_commands(cmd, &self.secondary_command_buffers);
+            }
             device.vk().cmd_next_subpass(cmd, vk::SubpassContents::INLINE);
             device.vk().cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.parent.background_pipeline.pipeline);
             device.vk().cmd_bind_descriptor_sets(cmd, vk::PipelineBindPoint::GRAPHICS, self.parent.background_pipeline.pipeline_layout, 0, &bg_descriptor_sets, &[]);
             device.vk().cmd_draw(cmd, 4, 1, 0, 0);
         }
 
-        let image_barrier = [
-            vk::ImageMemoryBarrier2::builder()
-                .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-                .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
+        self.recorded = true;
+
+        let mut image_barrier = Vec::with_capacity(2);
+        if self.parent.has_depth && self.parent.sample_count == vk::SampleCountFlags::TYPE_1 {
+            // The multisampled depth image is transient and never leaves the render pass
+            image_barrier.push(vk::ImageMemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS)
+                .src_access_mask(vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE)
                 .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
                 .dst_access_mask(vk::AccessFlags2::MEMORY_READ)
                 .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
                 .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
                 .src_queue_family_index(0)
                 .dst_queue_family_index(0)
-                .image(self.parent.pass_objects[self.index].depth_image)
+                .image(objects.depth_image)
                 .subresource_range(vk::ImageSubresourceRange {
                     aspect_mask: vk::ImageAspectFlags::DEPTH,
                     base_mip_level: 0,
@@ -1519,26 +2376,28 @@ impl EmulatorPipelinePass for DebugPipelinePass {
                     base_array_layer: 0,
                     layer_count: 1
                 })
-                .build(),
-            vk::ImageMemoryBarrier2::builder()
-                .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-                .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
-                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-                .dst_access_mask(vk::AccessFlags2::MEMORY_READ)
-                .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                .src_queue_family_index(0)
-                .dst_queue_family_index(0)
-                .image(self.parent.pass_objects[self.index].output_image)
-                .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    base_mip_level: 0,
-                    level_count: 1,
-                    base_array_layer: 0,
-                    layer_count: 1
-                })
-                .build(),
-        ];
+                .build()
+            );
+        }
+        image_barrier.push(vk::ImageMemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .dst_access_mask(vk::AccessFlags2::MEMORY_READ)
+            .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_queue_family_index(0)
+            .dst_queue_family_index(0)
+            .image(objects.output_image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1
+            })
+            .build()
+        );
 
         let info = vk::DependencyInfo::builder()
             .image_memory_barriers(&image_barrier);
@@ -1546,7 +2405,7 @@ impl EmulatorPipelinePass for DebugPipelinePass {
         unsafe {
             device.vk().cmd_end_render_pass(cmd);
 
-            device.synchronization_2_khr().cmd_pipeline_barrier2(cmd, &info);
+            device.synchronization_2().cmd_pipeline_barrier2(cmd, &info);
 
             device.vk().end_command_buffer(cmd).unwrap();
         }
@@ -1571,7 +2430,61 @@ impl EmulatorPipelinePass for DebugPipelinePass {
 
 impl Drop for DebugPipelinePass {
     fn drop(&mut self) {
-        self.parent.pass_objects[self.index].ready.store(true, Ordering::SeqCst);
+        // The pass is only dropped once it has completed execution
+        let objects = &self.parent.pass_objects[self.index];
+        if self.recorded {
+            objects.initialized.store(true, Ordering::SeqCst);
+        }
+        objects.ready.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A [`DebugPipeline`] using custom attachment ops. Created by
+/// [`DebugPipeline::with_attachment_ops`].
+struct DebugPipelineWithOps {
+    parent: Arc<DebugPipeline>,
+    ops: PassAttachmentOps,
+}
+
+impl EmulatorPipeline for DebugPipelineWithOps {
+    fn start_pass(&self) -> Box<dyn EmulatorPipelinePass + Send> {
+        self.parent.start_pass_with_ops(&self.ops, false)
+    }
+
+    fn start_pass_with_secondaries(&self) -> Option<Box<dyn EmulatorPipelinePass + Send>> {
+        Some(self.parent.start_pass_with_ops(&self.ops, true))
+    }
+
+    fn create_secondary(&self, placeholder_image: vk::ImageView, placeholder_sampler: vk::Sampler) -> Option<Box<dyn EmulatorPipelineSecondary + Send>> {
+        self.parent.create_secondary(placeholder_image, placeholder_sampler)
+    }
+
+    fn get_output(&self) -> (Vec2u32, &[vk::ImageView]) {
+        self.parent.get_output()
+    }
+
+    fn get_color_output(&self) -> Option<(vk::Format, &[vk::Image])> {
+        self.parent.get_color_output()
+    }
+
+    fn get_depth_output(&self) -> Option<(vk::Format, &[vk::Image])> {
+        self.parent.get_depth_output()
+    }
+
+    fn get_color_attachment_format(&self, index: u32) -> Option<vk::Format> {
+        self.parent.get_color_attachment_format(index)
+    }
+
+    fn get_depth_attachment_format(&self) -> Option<vk::Format> {
+        self.parent.get_depth_attachment_format()
+    }
+
+    fn inc_shader_used(&self, shader: ShaderId) {
+        self.parent.inc_shader_used(shader)
+    }
+
+    fn dec_shader_used(&self, shader: ShaderId) {
+        self.parent.dec_shader_used(shader)
     }
 }
 
@@ -1580,9 +2493,11 @@ struct UniformStateTracker {
     push_constants_dirty: bool,
     static_uniforms_dirty: bool,
     textures_dirty: bool,
+    storage_images_dirty: bool,
     push_constant_cache: PushConstants,
     static_uniform_cache: StaticUniforms,
     textures: [(vk::ImageView, vk::Sampler); 3],
+    storage_images: [Option<vk::ImageView>; STORAGE_IMAGE_COUNT],
 }
 
 impl UniformStateTracker {
@@ -1592,6 +2507,7 @@ impl UniformStateTracker {
             push_constants_dirty: true,
             static_uniforms_dirty: true,
             textures_dirty: true,
+            storage_images_dirty: false,
             push_constant_cache: PushConstants {
                 model_view_matrix: Mat4f32::identity(),
                 chunk_offset: Vec3f32::zeros(),
@@ -1608,6 +2524,7 @@ impl UniformStateTracker {
                 _padding2: Default::default(),
             },
             textures: [(initial_texture, initial_sampler); 3],
+            storage_images: [None; STORAGE_IMAGE_COUNT],
         }
     }
 
@@ -1694,6 +2611,23 @@ impl UniformStateTracker {
         }
     }
 
+    fn update_storage_image(&mut self, index: u32, view: vk::ImageView) {
+        if let Some(storage_image) = self.storage_images.get_mut(index as usize) {
+            *storage_image = Some(view);
+            self.storage_images_dirty = true;
+        } else {
+            log::warn!("Called update storage image on index {:?} which is out of bounds", index);
+        }
+    }
+
+    /// Marks all state as dirty so that it is bound again before the next draw.
+    fn invalidate(&mut self) {
+        self.push_constants_dirty = true;
+        self.static_uniforms_dirty = true;
+        self.textures_dirty = true;
+        self.storage_images_dirty = self.storage_images.iter().any(Option::is_some);
+    }
+
     fn validate_push_constants(&mut self) -> Option<&PushConstants> {
         if self.push_constants_dirty {
             self.push_constants_dirty = false;
@@ -1720,6 +2654,15 @@ impl UniformStateTracker {
             None
         }
     }
+
+    fn validate_storage_images(&mut self) -> Option<&[Option<vk::ImageView>; STORAGE_IMAGE_COUNT]> {
+        if self.storage_images_dirty {
+            self.storage_images_dirty = false;
+            Some(&self.storage_images)
+        } else {
+            None
+        }
+    }
 }
 
 #[repr(C)]
@@ -1769,6 +2712,77 @@ const_assert_eq!(std::mem::size_of::<StaticUniforms>() % 16, 0);
 unsafe impl Zeroable for StaticUniforms {}
 unsafe impl Pod for StaticUniforms {}
 
+/// Validates that only load and store ops supported by the pipeline are used and that loaded
+/// attachments are stored. Attachments can only be loaded if they are single sampled and there is
+/// exactly 1 set of attachments.
+fn validate_attachment_ops(ops: &PassAttachmentOps, multisampled: bool, concurrent_passes: u32) -> Result<(), ObjectCreateError> {
+    let valid_load = |op: vk::AttachmentLoadOp| op == vk::AttachmentLoadOp::CLEAR || op == vk::AttachmentLoadOp::LOAD || op == vk::AttachmentLoadOp::DONT_CARE;
+    let valid_store = |op: vk::AttachmentStoreOp| op == vk::AttachmentStoreOp::STORE || op == vk::AttachmentStoreOp::DONT_CARE;
+
+    if !valid_load(ops.color_load) || !valid_load(ops.depth_load) || !valid_store(ops.color_store) || !valid_store(ops.depth_store) {
+        log::error!("Unsupported attachment ops {:?}", ops);
+        return Err(ObjectCreateError::UnsupportedConfiguration);
+    }
+    if multisampled && ops.has_load() {
+        log::error!("Multisampled attachments are transient and cannot be loaded (ops: {:?})", ops);
+        return Err(ObjectCreateError::UnsupportedConfiguration);
+    }
+    if (ops.color_load == vk::AttachmentLoadOp::LOAD && ops.color_store != vk::AttachmentStoreOp::STORE)
+        || (ops.depth_load == vk::AttachmentLoadOp::LOAD && ops.depth_store != vk::AttachmentStoreOp::STORE) {
+        log::error!("Loaded attachments must be stored (ops: {:?})", ops);
+        return Err(ObjectCreateError::UnsupportedConfiguration);
+    }
+    if ops.has_load() && concurrent_passes != 1 {
+        log::error!("Attachments can only be loaded with 1 concurrent pass but the pipeline uses {:?} (ops: {:?})", concurrent_passes, ops);
+        return Err(ObjectCreateError::UnsupportedConfiguration);
+    }
+    Ok(())
+}
+
+/// Returns true if points of the specified size in pixels can be drawn.
+/// Merges the specialization constants of a shader with constants used internally by a pipeline
+/// into a single [`vk::SpecializationInfo`]. Internal constants replace shader constants with the
+/// same id.
+fn merge_specialization<'a>(shader: &SpecializationData, internal: &[(u32, [u8; 4])], alloc: &'a Bump) -> &'a vk::SpecializationInfo {
+    let mut entries = Vec::with_capacity(shader.get_entries().len() + internal.len());
+    let mut data = Vec::with_capacity(shader.get_data().len() + internal.len() * 4);
+    for entry in shader.get_entries() {
+        if internal.iter().any(|(id, _)| *id == entry.constant_id) {
+            continue;
+        }
+        let start = entry.offset as usize;
+        entries.push(vk::SpecializationMapEntry {
+            constant_id: entry.constant_id,
+            offset: data.len() as u32,
+            size: entry.size
+        });
+        data.extend_from_slice(&shader.get_data()[start..(start + entry.size)]);
+    }
+    for (id, value) in internal {
+        entries.push(vk::SpecializationMapEntry {
+            constant_id: *id,
+            offset: data.len() as u32,
+            size: 4
+        });
+        data.extend_from_slice(value);
+    }
+
+    let entries = alloc.alloc_slice_copy(&entries);
+    let data = alloc.alloc_slice_copy(&data);
+    alloc.alloc(vk::SpecializationInfo::builder()
+        .map_entries(entries)
+        .data(data)
+        .build()
+    )
+}
+
+fn is_point_size_supported(point_size: u32, large_points: bool, point_size_range: [f32; 2]) -> bool {
+    if point_size == 1 {
+        return true;
+    }
+    point_size != 0 && large_points && (point_size as f32) >= point_size_range[0] && (point_size as f32) <= point_size_range[1]
+}
+
 fn try_create_shader_module(device: &DeviceContext, data: &[u8], name: &str) -> Result<vk::ShaderModule, vk::Result> {
     unsafe {
         create_shader_from_bytes(device.get_functions(), data)
@@ -1778,6 +2792,52 @@ fn try_create_shader_module(device: &DeviceContext, data: &[u8], name: &str) ->
     })
 }
 
+/// Creates a shader module used by a [`DrawPipeline`]. If the `spirv-reflect` feature is enabled
+/// the module is validated against the draw pipeline layout first.
+fn try_create_draw_shader_module(device: &DeviceContext, data: &[u8], name: &str) -> Result<vk::ShaderModule, ObjectCreateError> {
+    #[cfg(feature = "spirv-reflect")]
+    {
+        validate_interface(data, &DRAW_SHADER_INTERFACE).map_err(|err| {
+            log::error!("Shader module {:?} does not match the draw pipeline layout: {:?}", name, err);
+            match err {
+                InterfaceError::InvalidSpirv => ObjectCreateError::InvalidShaderCode,
+                InterfaceError::Mismatch(mismatches) => ObjectCreateError::InvalidShaderInterface(mismatches),
+            }
+        })?;
+    }
+
+    Ok(try_create_shader_module(device, data, name)?)
+}
+
+/// The number of storage images which can be bound to a draw shader at set 0 binding 2.
+const STORAGE_IMAGE_COUNT: usize = 2;
+
+/// The descriptor bindings of the layout created by [`DrawPipeline::new`].
+#[cfg(feature = "spirv-reflect")]
+const DRAW_SHADER_BINDINGS: [ExpectedBinding; 4] = [
+    expected_binding(0, &DrawPipeline::SET0_BINDINGS[0]),
+    expected_binding(0, &DrawPipeline::SET0_BINDINGS[1]),
+    expected_binding(0, &DrawPipeline::SET0_BINDINGS[2]),
+    expected_binding(1, &DrawPipeline::SET1_BINDING),
+];
+
+/// The resource interface provided to draw shaders by the layout created by [`DrawPipeline::new`].
+#[cfg(feature = "spirv-reflect")]
+pub(crate) const DRAW_SHADER_INTERFACE: ExpectedInterface<'static> = ExpectedInterface {
+    bindings: &DRAW_SHADER_BINDINGS,
+    push_constant_size: std::mem::size_of::<PushConstants>() as u32,
+};
+
+#[cfg(feature = "spirv-reflect")]
+const fn expected_binding(set: u32, binding: &vk::DescriptorSetLayoutBinding) -> ExpectedBinding {
+    ExpectedBinding {
+        set,
+        binding: binding.binding,
+        descriptor_type: binding.descriptor_type,
+        descriptor_count: binding.descriptor_count,
+    }
+}
+
 const SHADER_ENTRY: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") }; // GOD I LOVE RUSTS FFI API IT IS SO NICE AND DEFINITELY NOT STUPID WITH WHICH FUNCTIONS ARE CONST AND WHICH AREN'T
 static DEBUG_POSITION_VERTEX_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/debug_position_vert.spv"));
 static DEBUG_COLOR_VERTEX_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/debug_color_vert.spv"));
@@ -1787,4 +2847,78 @@ static DEBUG_FRAGMENT_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env
 static TEXTURED_FRAGMENT_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/textured_frag.spv"));
 
 static BACKGROUND_VERTEX_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/background_vert.spv"));
-static BACKGROUND_FRAGMENT_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/background_frag.spv"));
\ No newline at end of file
+static BACKGROUND_FRAGMENT_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/background_frag.spv"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn point_size_support() {
+        assert!(is_point_size_supported(1, false, [1.0, 1.0]));
+        assert!(!is_point_size_supported(0, true, [0.0, 64.0]));
+        assert!(!is_point_size_supported(4, false, [1.0, 64.0]));
+        assert!(is_point_size_supported(4, true, [1.0, 64.0]));
+        assert!(!is_point_size_supported(128, true, [1.0, 64.0]));
+    }
+
+    #[test]
+    fn specialization_merging() {
+        use crate::renderer::emulator::mc_shaders::SpecConstantValue;
+
+        let mut constants = HashMap::new();
+        constants.insert(3, SpecConstantValue::U32(7));
+        constants.insert(16, SpecConstantValue::F32(8.0f32));
+        let shader = SpecializationData::new(&constants);
+
+        let alloc = Bump::new();
+        let info = merge_specialization(&shader, &[(16, 2.0f32.to_ne_bytes())], &alloc);
+        let entries = unsafe { std::slice::from_raw_parts(info.p_map_entries, info.map_entry_count as usize) };
+        let data = unsafe { std::slice::from_raw_parts(info.p_data as *const u8, info.data_size) };
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].constant_id, 3);
+        assert_eq!(&data[entries[0].offset as usize..][..4], &7u32.to_ne_bytes());
+        assert_eq!(entries[1].constant_id, 16);
+        assert_eq!(&data[entries[1].offset as usize..][..4], &2.0f32.to_ne_bytes());
+
+        let info = merge_specialization(&SpecializationData::new(&HashMap::new()), &[], &alloc);
+        assert_eq!(info.map_entry_count, 0);
+        assert_eq!(info.data_size, 0);
+    }
+
+    #[test]
+    fn attachment_ops_validation() {
+        let load = PassAttachmentOps {
+            depth_load: vk::AttachmentLoadOp::LOAD,
+            ..Default::default()
+        };
+        assert_eq!(validate_attachment_ops(&PassAttachmentOps::default(), false, 2), Ok(()));
+        assert_eq!(validate_attachment_ops(&PassAttachmentOps::default(), true, 2), Ok(()));
+        assert_eq!(validate_attachment_ops(&load, false, 1), Ok(()));
+        assert_eq!(validate_attachment_ops(&load, true, 1), Err(ObjectCreateError::UnsupportedConfiguration));
+        assert_eq!(validate_attachment_ops(&load, false, 2), Err(ObjectCreateError::UnsupportedConfiguration));
+
+        // The default color store op is DONT_CARE
+        let color_load = PassAttachmentOps {
+            color_load: vk::AttachmentLoadOp::LOAD,
+            ..Default::default()
+        };
+        assert_eq!(validate_attachment_ops(&color_load, false, 1), Err(ObjectCreateError::UnsupportedConfiguration));
+        let color_load = PassAttachmentOps {
+            color_store: vk::AttachmentStoreOp::STORE,
+            ..color_load
+        };
+        assert_eq!(validate_attachment_ops(&color_load, false, 1), Ok(()));
+
+        // VK_ATTACHMENT_STORE_OP_NONE
+        let none = PassAttachmentOps {
+            color_store: vk::AttachmentStoreOp::from_raw(1000301000),
+            ..Default::default()
+        };
+        assert_eq!(validate_attachment_ops(&none, false, 1), Err(ObjectCreateError::UnsupportedConfiguration));
+
+        assert_eq!(load.without_load(), PassAttachmentOps::default());
+        assert!(!load.without_load().has_load());
+    }
+}
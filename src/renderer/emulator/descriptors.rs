@@ -4,6 +4,7 @@ use std::sync::Arc;
 use ash::vk;
 
 use crate::vk::objects::allocator::{Allocation, AllocationStrategy};
+use crate::util::alloc::align_up;
 
 use crate::prelude::*;
 
@@ -78,8 +79,7 @@ impl UniformBufferPool {
         }
 
         // We align to 256bytes because that was the highest in the gpuinfo database (Yes this is entire module is very much a TODO)
-        let add = 256 - (self.current_offset % 256);
-        let mut base_offset = self.current_offset + add;
+        let mut base_offset = align_up(self.current_offset as vk::DeviceSize, 256) as usize;
 
         if base_offset + src.len() > self.buffer_size {
             base_offset = 0;
@@ -105,4 +105,155 @@ impl UniformBufferPool {
 }
 
 unsafe impl Send for UniformBufferPool {
+}
+
+/// Statistics of a [`DescriptorSetAllocator`] useful for diagnostics.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct DescriptorPoolStats {
+    /// The number of descriptor pools created so far.
+    pub pool_count: u32,
+    /// The number of descriptor sets allocated from the pools including recycled sets.
+    pub allocated_sets: u32,
+    /// The number of sets which have been freed and are available for reuse.
+    pub free_sets: u32,
+}
+
+/// Allocates descriptor sets of a single layout.
+///
+/// New descriptor pools are created on demand once the existing pools are exhausted so allocation
+/// only fails if the device runs out of memory. Freed sets are recycled by later allocations.
+/// Recycled sets keep their previous contents and must be fully rewritten before use.
+pub(super) struct DescriptorSetAllocator {
+    layout: vk::DescriptorSetLayout,
+    /// The descriptors needed by a single set.
+    set_sizes: Box<[vk::DescriptorPoolSize]>,
+    pools: Vec<vk::DescriptorPool>,
+    /// The number of sets that can be allocated from the newest pool.
+    current_pool_capacity: u32,
+    free_sets: Vec<vk::DescriptorSet>,
+    allocated_sets: u32,
+}
+
+impl DescriptorSetAllocator {
+    const INITIAL_POOL_CAPACITY: u32 = 4;
+    const MAX_POOL_CAPACITY: u32 = 256;
+
+    /// Creates a new allocator for sets of some layout. `set_sizes` lists the number of
+    /// descriptors of each type a single set of the layout needs. No pool is created until the
+    /// first allocation.
+    pub(super) fn new(layout: vk::DescriptorSetLayout, set_sizes: &[vk::DescriptorPoolSize]) -> Self {
+        Self {
+            layout,
+            set_sizes: set_sizes.into(),
+            pools: Vec::new(),
+            current_pool_capacity: 0,
+            free_sets: Vec::new(),
+            allocated_sets: 0,
+        }
+    }
+
+    pub(super) fn allocate(&mut self, device: &DeviceContext) -> Result<vk::DescriptorSet, vk::Result> {
+        if let Some(set) = self.free_sets.pop() {
+            return Ok(set);
+        }
+
+        if let Some(pool) = self.pools.last() {
+            match self.allocate_from(device, *pool) {
+                Ok(set) => return Ok(set),
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) => {},
+                Err(err) => {
+                    log::error!("vkAllocateDescriptorSets returned {:?} in DescriptorSetAllocator::allocate", err);
+                    return Err(err);
+                }
+            }
+        }
+
+        let capacity = next_pool_capacity(self.current_pool_capacity, Self::INITIAL_POOL_CAPACITY, Self::MAX_POOL_CAPACITY);
+        let pool = Self::create_pool(device, &self.set_sizes, capacity)?;
+        self.pools.push(pool);
+        self.current_pool_capacity = capacity;
+
+        self.allocate_from(device, pool).map_err(|err| {
+            log::error!("vkAllocateDescriptorSets returned {:?} in DescriptorSetAllocator::allocate for a new pool", err);
+            err
+        })
+    }
+
+    /// Returns a set to the allocator. The set must not be in use by any pending command buffer.
+    pub(super) fn free(&mut self, set: vk::DescriptorSet) {
+        self.free_sets.push(set);
+    }
+
+    pub(super) fn get_stats(&self) -> DescriptorPoolStats {
+        DescriptorPoolStats {
+            pool_count: self.pools.len() as u32,
+            allocated_sets: self.allocated_sets,
+            free_sets: self.free_sets.len() as u32,
+        }
+    }
+
+    /// Destroys all pools. All sets allocated from this allocator become invalid.
+    pub(super) fn destroy(&mut self, device: &DeviceContext) {
+        for pool in self.pools.drain(..) {
+            unsafe {
+                device.vk().destroy_descriptor_pool(pool, None);
+            }
+        }
+        self.free_sets.clear();
+        self.allocated_sets = 0;
+    }
+
+    fn allocate_from(&mut self, device: &DeviceContext, pool: vk::DescriptorPool) -> Result<vk::DescriptorSet, vk::Result> {
+        let info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(std::slice::from_ref(&self.layout));
+
+        let set = unsafe {
+            device.vk().allocate_descriptor_sets(&info)
+        }?[0];
+        self.allocated_sets += 1;
+
+        Ok(set)
+    }
+
+    fn create_pool(device: &DeviceContext, set_sizes: &[vk::DescriptorPoolSize], capacity: u32) -> Result<vk::DescriptorPool, vk::Result> {
+        let sizes: Box<[_]> = set_sizes.iter().map(|size| vk::DescriptorPoolSize {
+            ty: size.ty,
+            descriptor_count: size.descriptor_count * capacity,
+        }).collect();
+
+        let info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(capacity)
+            .pool_sizes(&sizes);
+
+        unsafe {
+            device.vk().create_descriptor_pool(&info, None)
+        }.map_err(|err| {
+            log::error!("vkCreateDescriptorPool returned {:?} in DescriptorSetAllocator::create_pool", err);
+            err
+        })
+    }
+}
+
+/// Returns the capacity of the next pool created by a [`DescriptorSetAllocator`]. Pools double in
+/// size up to some maximum.
+fn next_pool_capacity(current: u32, initial: u32, max: u32) -> u32 {
+    if current == 0 {
+        initial
+    } else {
+        current.saturating_mul(2).min(max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::next_pool_capacity;
+
+    #[test]
+    fn pool_capacity_growth() {
+        assert_eq!(next_pool_capacity(0, 4, 256), 4);
+        assert_eq!(next_pool_capacity(4, 4, 256), 8);
+        assert_eq!(next_pool_capacity(128, 4, 256), 256);
+        assert_eq!(next_pool_capacity(256, 4, 256), 256);
+    }
 }
\ No newline at end of file
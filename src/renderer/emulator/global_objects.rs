@@ -3,18 +3,23 @@ use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::sync::{Arc, Mutex, Weak};
 use std::sync::atomic::AtomicU64;
+use std::sync::mpsc::sync_channel;
 
 use ash::vk;
 use crate::define_uuid_type;
 
 use crate::renderer::emulator::{MeshData, PassId};
 use crate::vk::objects::allocator::{Allocation, AllocationStrategy};
+use crate::vk::objects::ImageSize;
 
 use crate::prelude::*;
+use crate::device::device::TrackedObject;
 use crate::renderer::emulator::share::Share;
-use crate::renderer::emulator::worker::{GlobalImageClear, GlobalImageWrite, GlobalMeshWrite, WorkerTask};
-use crate::util::alloc::next_aligned;
-use crate::util::format::Format;
+use crate::renderer::emulator::worker::{GlobalImageClear, GlobalImageReadBack, GlobalImageWrite, GlobalMeshWrite, WorkerTask};
+use crate::util::alloc::align_up;
+use crate::util::format::{ColorEncoding, convert_linear_to_srgb, convert_srgb_to_linear, Format, pad_rgb_to_rgba};
+#[cfg(feature = "transcode")]
+use crate::util::transcode;
 
 define_uuid_type!(pub, GlobalMeshId);
 
@@ -22,6 +27,10 @@ define_uuid_type!(pub, GlobalMeshId);
 pub enum GlobalObjectCreateError {
     Vulkan(vk::Result),
     Allocation,
+    /// The requested format is not supported by the device and no fallback is available.
+    UnsupportedFormat,
+    /// The requested image size is empty, exceeds the device limits or is invalid for a cube image.
+    InvalidSize,
 }
 
 impl From<vk::Result> for GlobalObjectCreateError {
@@ -30,65 +39,117 @@ impl From<vk::Result> for GlobalObjectCreateError {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GenerateMipmapsError {
+    /// Compressed formats cannot be blitted.
+    CompressedFormat,
+    /// The format supports neither linear filtered blits nor generation by a compute shader.
+    UnsupportedFormat,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ImageUpdateError {
+    /// The size of the data of a region does not match its extent and row stride.
+    InvalidDataSize,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ReadBackError {
+    /// Memory for the read back could not be allocated.
+    Allocation,
+    /// The renderer was shut down before the read back was executed.
+    Shutdown,
+    /// The worker is paused. Waiting for the read back could deadlock since it may be queued
+    /// behind a pass which is only processed once the worker is resumed.
+    Paused,
+}
+
 pub struct GlobalMesh {
-    share: Arc<Share>,
     id: GlobalMeshId,
 
     last_used_pass: AtomicU64,
 
-    buffer: vk::Buffer,
-    allocation: Option<Allocation>,
-    buffer_size: vk::DeviceSize,
+    storage: Arc<GlobalMeshBuffer>,
+
+    /// The byte offset and size of the vertex data in the buffer.
+    vertex_range: (vk::DeviceSize, vk::DeviceSize),
+    /// The byte offset and size of the index data in the buffer.
+    index_range: (vk::DeviceSize, vk::DeviceSize),
 
     draw_info: GlobalMeshDrawInfo,
 }
 
 impl GlobalMesh {
     pub(super) fn new(share: Arc<Share>, data: &MeshData) -> Result<Arc<Self>, GlobalObjectCreateError> {
-        let index_offset = next_aligned(data.vertex_data.len() as vk::DeviceSize, data.get_index_size() as vk::DeviceSize);
-        let required_size = index_offset + (data.index_data.len() as vk::DeviceSize);
+        let mut meshes = Self::new_packed(share, std::slice::from_ref(data))?;
+        Ok(meshes.pop().unwrap())
+    }
+
+    /// Creates multiple meshes sharing a single buffer. The vertex data of all meshes is stored
+    /// first followed by the index data, so that draws of any of the meshes can use the same vertex
+    /// and index buffer binding.
+    pub(super) fn new_packed(share: Arc<Share>, meshes: &[MeshData]) -> Result<Vec<Arc<Self>>, GlobalObjectCreateError> {
+        if meshes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (layouts, required_size) = compute_packed_layout(meshes);
 
         let (buffer, allocation) = Self::create_buffer(share.get_device(), required_size)?;
+        let storage = Arc::new(GlobalMeshBuffer {
+            share: share.clone(),
+            buffer,
+            allocation: Some(allocation),
+        });
 
         let (staging, staging_allocation) = share.get_staging_pool().lock().unwrap_or_else(|_| {
-            log::error!("Poisoned staging memory mutex in GlobalMesh::new");
+            log::error!("Poisoned staging memory mutex in GlobalMesh::new_packed");
             panic!()
         }).allocate(required_size, 1);
 
         unsafe {
             let dst = std::slice::from_raw_parts_mut(staging.mapped.as_ptr(), required_size as usize);
 
-            dst[0..data.vertex_data.len()].copy_from_slice(data.vertex_data);
-            dst[(index_offset as usize)..].copy_from_slice(data.index_data);
+            for (data, layout) in meshes.iter().zip(layouts.iter()) {
+                let vertex_start = layout.vertex_byte_offset as usize;
+                dst[vertex_start..(vertex_start + data.vertex_data.len())].copy_from_slice(data.vertex_data);
+
+                let index_start = layout.index_byte_offset as usize;
+                dst[index_start..(index_start + data.index_data.len())].copy_from_slice(data.index_data);
+            }
         }
 
-        let draw_info = GlobalMeshDrawInfo {
-            buffer,
-            first_index: (index_offset / (data.get_index_size() as vk::DeviceSize)) as u32,
-            index_type: data.index_type,
-            index_count: data.index_count,
-            primitive_topology: data.primitive_topology
-        };
+        let result: Vec<_> = meshes.iter().zip(layouts.iter()).map(|(data, layout)| {
+            Arc::new(GlobalMesh {
+                id: GlobalMeshId::new(),
 
-        let mesh = Arc::new(GlobalMesh {
-            share,
-            id: GlobalMeshId::new(),
+                last_used_pass: AtomicU64::new(0),
 
-            last_used_pass: AtomicU64::new(0),
+                storage: storage.clone(),
 
-            buffer,
-            allocation: Some(allocation),
-            buffer_size: required_size,
+                vertex_range: (layout.vertex_byte_offset, data.vertex_data.len() as vk::DeviceSize),
+                index_range: (layout.index_byte_offset, data.index_data.len() as vk::DeviceSize),
 
-            draw_info
-        });
+                draw_info: GlobalMeshDrawInfo {
+                    buffer,
+                    vertex_offset: layout.vertex_offset,
+                    first_index: layout.first_index,
+                    index_type: data.index_type,
+                    index_count: data.index_count,
+                    vertex_stride: data.vertex_stride,
+                    primitive_topology: data.primitive_topology
+                }
+            })
+        }).collect();
 
-        mesh.share.push_task(WorkerTask::WriteGlobalMesh(GlobalMeshWrite {
+        // The write covers the whole buffer so the state tracking of the first mesh is enough to
+        // make the data of all meshes available.
+        share.push_task(WorkerTask::WriteGlobalMesh(GlobalMeshWrite {
             after_pass: PassId::from_raw(0),
             staging_allocation,
             staging_range: (staging.offset, required_size),
             staging_buffer: staging.buffer,
-            dst_mesh: mesh.clone(),
+            dst_mesh: result[0].clone(),
             regions: Box::new([vk::BufferCopy {
                 src_offset: staging.offset,
                 dst_offset: 0,
@@ -96,7 +157,62 @@ impl GlobalMesh {
             }])
         }, true));
 
-        Ok(mesh)
+        Ok(result)
+    }
+
+    /// Overwrites part of the vertex data of the mesh. The offset is in bytes relative to the start
+    /// of the vertex data of the mesh. Only the written range is staged and copied.
+    ///
+    /// The write is ordered after all passes the mesh has been used in so far. If the mesh is used
+    /// by a pass that has not been ended yet the write is only executed after that pass, which means
+    /// all draws of that pass will use the old data, including draws recorded after this call.
+    /// Callers wanting to use the new data must do so in a later pass.
+    pub fn update_vertices(self: &Arc<Self>, offset: vk::DeviceSize, data: &[u8]) {
+        self.update_range(self.vertex_range, offset, data, "GlobalMesh::update_vertices");
+    }
+
+    /// Overwrites part of the index data of the mesh. The offset is in bytes relative to the start
+    /// of the index data of the mesh. Only the written range is staged and copied.
+    ///
+    /// The indices are relative to the first vertex of the mesh. The same ordering rules as for
+    /// [`GlobalMesh::update_vertices`] apply.
+    pub fn update_indices(self: &Arc<Self>, offset: vk::DeviceSize, data: &[u8]) {
+        self.update_range(self.index_range, offset, data, "GlobalMesh::update_indices");
+    }
+
+    fn update_range(self: &Arc<Self>, range: (vk::DeviceSize, vk::DeviceSize), offset: vk::DeviceSize, data: &[u8], fn_name: &str) {
+        if data.is_empty() {
+            return;
+        }
+
+        let size = data.len() as vk::DeviceSize;
+        let dst_offset = get_update_offset(range, offset, size).unwrap_or_else(|| {
+            log::error!("Write (offset: {}, size: {}) is out of bounds for data of size {} in {}", offset, size, range.1, fn_name);
+            panic!()
+        });
+
+        let share = &self.storage.share;
+        let (staging, staging_allocation) = share.get_staging_pool().lock().unwrap_or_else(|_| {
+            log::error!("Poisoned staging memory mutex in {}", fn_name);
+            panic!()
+        }).allocate(size, 1);
+
+        unsafe {
+            std::slice::from_raw_parts_mut(staging.mapped.as_ptr(), data.len()).copy_from_slice(data);
+        }
+
+        share.push_task(WorkerTask::WriteGlobalMesh(GlobalMeshWrite {
+            after_pass: PassId::from_raw(self.last_used_pass.load(std::sync::atomic::Ordering::Acquire)),
+            staging_allocation,
+            staging_range: (staging.offset, size),
+            staging_buffer: staging.buffer,
+            dst_mesh: self.clone(),
+            regions: Box::new([vk::BufferCopy {
+                src_offset: staging.offset,
+                dst_offset,
+                size
+            }])
+        }, false));
     }
 
     pub(super) fn update_used_in(&self, pass: PassId) {
@@ -113,7 +229,7 @@ impl GlobalMesh {
     }
 
     pub(super) fn get_buffer_handle(&self) -> vk::Buffer {
-        self.buffer
+        self.storage.buffer
     }
 
     pub(super) fn get_draw_info(&self) -> &GlobalMeshDrawInfo {
@@ -179,7 +295,15 @@ impl Hash for GlobalMesh {
     }
 }
 
-impl Drop for GlobalMesh {
+/// The buffer storing the data of one or more [`GlobalMesh`]es. Destroyed once all meshes using it
+/// have been dropped.
+struct GlobalMeshBuffer {
+    share: Arc<Share>,
+    buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+}
+
+impl Drop for GlobalMeshBuffer {
     fn drop(&mut self) {
         let allocation = self.allocation.take().unwrap();
         let device = self.share.get_device();
@@ -193,12 +317,54 @@ impl Drop for GlobalMesh {
 
 pub(super) struct GlobalMeshDrawInfo {
     pub(super) buffer: vk::Buffer,
+    /// The index of the first vertex of the mesh in the buffer. Added to every index.
+    pub(super) vertex_offset: i32,
     pub(super) first_index: u32,
     pub(super) index_count: u32,
     pub(super) index_type: vk::IndexType,
+    pub(super) vertex_stride: u32,
     pub(super) primitive_topology: vk::PrimitiveTopology,
 }
 
+/// The location of a mesh inside a buffer created by [`GlobalMesh::new_packed`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct PackedMeshLayout {
+    vertex_byte_offset: vk::DeviceSize,
+    vertex_offset: i32,
+    index_byte_offset: vk::DeviceSize,
+    first_index: u32,
+}
+
+/// Computes the location of each mesh in a packed buffer and the required buffer size.
+///
+/// The vertex data of each mesh is aligned to its vertex stride and the index data to its index
+/// size so that the locations can be expressed as vertex offset and first index.
+fn compute_packed_layout(meshes: &[MeshData]) -> (Vec<PackedMeshLayout>, vk::DeviceSize) {
+    let mut current: vk::DeviceSize = 0;
+    let mut vertex_offsets = Vec::with_capacity(meshes.len());
+    for data in meshes {
+        let stride = data.vertex_stride as vk::DeviceSize;
+        current = align_up(current, stride);
+        vertex_offsets.push(current);
+        current += data.vertex_data.len() as vk::DeviceSize;
+    }
+
+    let mut layouts = Vec::with_capacity(meshes.len());
+    for (data, vertex_byte_offset) in meshes.iter().zip(vertex_offsets) {
+        let index_size = data.get_index_size() as vk::DeviceSize;
+        current = align_up(current, index_size);
+        layouts.push(PackedMeshLayout {
+            vertex_byte_offset,
+            vertex_offset: (vertex_byte_offset / (data.vertex_stride as vk::DeviceSize)) as i32,
+            index_byte_offset: current,
+            first_index: (current / index_size) as u32,
+        });
+        current += data.index_data.len() as vk::DeviceSize;
+    }
+
+    (layouts, current)
+}
+
 pub struct ImageData<'a> {
     /// The image data
     pub data: &'a [u8],
@@ -211,6 +377,18 @@ pub struct ImageData<'a> {
 
     /// The size of the upload region in the image.
     pub extent: Vec2u32,
+
+    /// The array layer or for 3D images the depth slice written by the region. For cube images
+    /// the layers are the faces in the order +X, -X, +Y, -Y, +Z, -Z.
+    pub layer: u32,
+
+    /// The mip level written by the region.
+    pub mip_level: u32,
+
+    /// The color encoding of the data. If it differs from the encoding of the image format the
+    /// data is converted during the upload. Only supported for 8-bit UNORM and sRGB formats. If
+    /// [`None`] the data is assumed to use the encoding of the image format.
+    pub encoding: Option<ColorEncoding>,
 }
 
 impl<'a> ImageData<'a> {
@@ -220,6 +398,9 @@ impl<'a> ImageData<'a> {
             row_stride: 0,
             offset: Vec2u32::new(0, 0),
             extent: size,
+            layer: 0,
+            mip_level: 0,
+            encoding: None,
         }
     }
 
@@ -229,6 +410,9 @@ impl<'a> ImageData<'a> {
             row_stride,
             offset: Vec2u32::new(0, 0),
             extent: size,
+            layer: 0,
+            mip_level: 0,
+            encoding: None,
         }
     }
 
@@ -237,7 +421,10 @@ impl<'a> ImageData<'a> {
             data,
             row_stride: 0,
             offset,
-            extent
+            extent,
+            layer: 0,
+            mip_level: 0,
+            encoding: None,
         }
     }
 
@@ -246,9 +433,30 @@ impl<'a> ImageData<'a> {
             data,
             row_stride,
             offset,
-            extent
+            extent,
+            layer: 0,
+            mip_level: 0,
+            encoding: None,
         }
     }
+
+    /// Sets the array layer or depth slice written by the region.
+    pub fn with_layer(mut self, layer: u32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Sets the mip level written by the region.
+    pub fn with_mip_level(mut self, mip_level: u32) -> Self {
+        self.mip_level = mip_level;
+        self
+    }
+
+    /// Sets the color encoding of the data.
+    pub fn with_encoding(mut self, encoding: ColorEncoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
 }
 
 define_uuid_type!(pub, GlobalImageId);
@@ -263,15 +471,70 @@ pub struct GlobalImage {
     image: vk::Image,
     sampler_view: vk::ImageView,
     allocation: Option<Allocation>,
-    size: Vec2u32,
-    mip_levels: u32,
-
-    sampler_database: Mutex<HashMap<SamplerInfo, vk::Sampler>>,
+    size: ImageSize,
+    view_type: vk::ImageViewType,
+    format: &'static Format,
+    aspect_mask: vk::ImageAspectFlags,
+    conversion: UploadConversion,
+    storage: bool,
+    mipmap_method: Option<MipmapMethod>,
+    /// Views of each individual mip level used to generate mipmaps with [`MipmapMethod::Compute`].
+    /// Empty for all other methods.
+    mip_views: Box<[vk::ImageView]>,
+
+    sampler_database: Mutex<HashMap<SamplerInfo, CachedSampler>>,
 }
 
 impl GlobalImage {
-    pub(super) fn new(share: Arc<Share>, size: Vec2u32, mip_levels: u32, format: &'static Format) -> Result<Arc<Self>, GlobalObjectCreateError> {
-        let (image, allocation, sampler_view) = Self::create_image(share.get_device(), format.into(), size, mip_levels)?;
+    /// Creates a new global image. If `cube` is true the image is sampled as a cube map and must be
+    /// a square 2D image with exactly 6 array layers.
+    ///
+    /// If `storage` is true the image can additionally be bound as a storage image. In this case
+    /// the format must support [`vk::FormatFeatureFlags::STORAGE_IMAGE`] as no fallback format can
+    /// be used.
+    pub(super) fn new(share: Arc<Share>, size: ImageSize, cube: bool, storage: bool, format: &'static Format) -> Result<Arc<Self>, GlobalObjectCreateError> {
+        let view_type = Self::select_view_type(share.get_device(), &size, cube)?;
+        let mip_levels = size.get_mip_levels();
+
+        let aspect_mask = if let Some(depth_aspect) = format.depth_aspect() {
+            // Depth images can only be sampled as a single aspect and are never blitted
+            if format.has_stencil() || mip_levels > 1 || view_type != vk::ImageViewType::TYPE_2D {
+                log::error!("Depth format {:?} with size {:?} is not supported for global images", format, size);
+                return Err(GlobalObjectCreateError::UnsupportedFormat);
+            }
+            depth_aspect
+        } else {
+            vk::ImageAspectFlags::COLOR
+        };
+
+        let (format, conversion) = Self::select_format(share.get_device(), format, mip_levels)?;
+        if storage {
+            Self::check_storage_format(share.get_device(), format, aspect_mask, conversion)?;
+        }
+        let mipmap_method = if mip_levels > 1 {
+            Self::select_mipmap_method(share.get_device(), format, view_type)
+        } else {
+            None
+        };
+        let compute_mipmaps = mipmap_method == Some(MipmapMethod::Compute);
+
+        let (image, allocation, sampler_view) = Self::create_image(share.get_device(), format.into(), aspect_mask, &size, view_type, storage || compute_mipmaps)?;
+        let mip_views = if compute_mipmaps {
+            match Self::create_mip_views(share.get_device(), image, format.into(), &size) {
+                Ok(views) => views,
+                Err(err) => {
+                    let device = share.get_device();
+                    unsafe {
+                        device.vk().destroy_image_view(sampler_view, None);
+                        device.vk().destroy_image(image, None);
+                    }
+                    device.get_allocator().free(allocation);
+                    return Err(err);
+                }
+            }
+        } else {
+            Box::new([])
+        };
 
         let image = Arc::new_cyclic(|weak| GlobalImage {
             weak: weak.clone(),
@@ -284,14 +547,20 @@ impl GlobalImage {
             sampler_view,
             allocation: Some(allocation),
             size,
-            mip_levels,
+            view_type,
+            format,
+            aspect_mask,
+            conversion,
+            storage,
+            mipmap_method,
+            mip_views,
 
             sampler_database: Mutex::new(HashMap::new())
         });
 
         image.share.push_task(WorkerTask::ClearGlobalImage(GlobalImageClear {
             after_pass: PassId::from_raw(0),
-            clear_value: format.get_clear_color_type().unwrap().make_zero_clear(),
+            clear_value: format.get_clear_color_type().map(|clear_type| clear_type.make_zero_clear()),
             dst_image: image.clone()
         }, true));
 
@@ -315,33 +584,149 @@ impl GlobalImage {
         self.id
     }
 
+    /// Returns the width and height of the first mip level.
     pub fn get_size(&self) -> Vec2u32 {
+        Vec2u32::new(self.size.get_width(), self.size.get_height())
+    }
+
+    pub fn get_image_size(&self) -> ImageSize {
         self.size
     }
 
-    pub fn update_regions(&self, regions: &[ImageData]) {
+    /// Returns the type of the view returned to pipelines by [`PassRecorder::update_texture`].
+    ///
+    /// [`PassRecorder::update_texture`]: crate::renderer::emulator::PassRecorder::update_texture
+    pub fn get_view_type(&self) -> vk::ImageViewType {
+        self.view_type
+    }
+
+    /// Returns the format actually used by the image.
+    ///
+    /// This may differ from the format requested at creation if the requested format is a 24-bit
+    /// format unsupported by the device or, with the `transcode` feature enabled, a compressed format
+    /// unsupported by the device. Uploads always use the requested format and are padded or
+    /// transcoded automatically.
+    pub fn get_format(&self) -> &'static Format {
+        self.format
+    }
+
+    /// Returns true if the image was created as a storage image and can be bound using
+    /// [`PassRecorder::update_storage_image`].
+    ///
+    /// [`PassRecorder::update_storage_image`]: crate::renderer::emulator::PassRecorder::update_storage_image
+    pub fn is_storage_image(&self) -> bool {
+        self.storage
+    }
+
+    /// Updates a region of the image without modifying the rest of the image.
+    ///
+    /// For 3D images the z components of `offset` and `extent` select the depth slices to write
+    /// and `layer` must be 0. For all other images the z component of `offset` must be 0 and of
+    /// `extent` must be 1. The data of each slice must be tightly packed and stored consecutively.
+    pub fn update_region(&self, offset: Vec3u32, extent: Vec3u32, mip_level: u32, layer: u32, data: &[u8]) -> Result<(), ImageUpdateError> {
+        let is_3d = self.size.get_vulkan_type() == vk::ImageType::TYPE_3D;
+        if (is_3d && layer != 0) || (!is_3d && (offset[2] != 0 || extent[2] != 1)) {
+            log::error!("Invalid region offset {:?} extent {:?} layer {} for image with size {:?} in GlobalImage::update_region", offset, extent, layer, self.size);
+            panic!()
+        }
+        if extent[2] == 0 {
+            return Ok(());
+        }
+        if data.len() % (extent[2] as usize) != 0 {
+            log::error!("Data size {} is not a multiple of the slice count {} in GlobalImage::update_region", data.len(), extent[2]);
+            panic!()
+        }
+
+        let slice_size = data.len() / (extent[2] as usize);
+        let regions: Vec<_> = data.chunks_exact(slice_size.max(1)).enumerate().map(|(index, slice)| {
+            ImageData {
+                data: slice,
+                row_stride: 0,
+                offset: Vec2u32::new(offset[0], offset[1]),
+                extent: Vec2u32::new(extent[0], extent[1]),
+                layer: if is_3d { offset[2] + (index as u32) } else { layer },
+                mip_level,
+                encoding: None,
+            }
+        }).collect();
+
+        self.update_regions(&regions)
+    }
+
+    /// Updates regions of the image without modifying the rest of the image.
+    ///
+    /// If the image uses a padded 32-bit format for a requested 24-bit format (see
+    /// [`GlobalImage::get_format`]) the data of every region must contain whole 24-bit texels
+    /// covering the region. Otherwise [`ImageUpdateError::InvalidDataSize`] is returned and
+    /// nothing is uploaded.
+    pub fn update_regions(&self, regions: &[ImageData]) -> Result<(), ImageUpdateError> {
         if regions.is_empty() {
-            return;
+            return Ok(());
         }
 
-        let required_memory = regions.iter().map(|r| r.data.len()).sum::<usize>() as u64;
+        let is_3d = self.size.get_vulkan_type() == vk::ImageType::TYPE_3D;
+        for region in regions {
+            if region.mip_level >= self.size.get_mip_levels() {
+                log::error!("Region mip level {} is out of bounds for image with size {:?} in GlobalImage::update_regions", region.mip_level, self.size);
+                panic!()
+            }
 
-        let (staging, allocation) = self.share.get_staging_pool().lock().unwrap().allocate(required_memory as u64, 1);
+            let mip_extent = self.size.get_mip_extent_3d(region.mip_level);
+            let layers = if is_3d { mip_extent.depth } else { self.size.get_array_layers() };
+            if region.layer >= layers || region.offset[0] + region.extent[0] > mip_extent.width || region.offset[1] + region.extent[1] > mip_extent.height {
+                log::error!("Region (offset: {:?}, extent: {:?}, layer: {}) is out of bounds for mip level {} of image with size {:?} in GlobalImage::update_regions",
+                    region.offset, region.extent, region.layer, region.mip_level, self.size);
+                panic!()
+            }
+
+            if self.get_encoding_conversion(region).is_some() && !self.format.is_8bit_unorm_or_srgb() {
+                log::error!("Color encoding conversion is not supported for format {:?} in GlobalImage::update_regions", self.format);
+                panic!()
+            }
+
+            if let UploadConversion::PadRgba(_) = self.conversion {
+                if !is_valid_rgb_data_size(region.data.len(), region.extent, region.row_stride) {
+                    log::error!("Region data size {} does not contain whole 24-bit texels covering extent {:?} with row stride {} in GlobalImage::update_regions",
+                        region.data.len(), region.extent, region.row_stride);
+                    return Err(ImageUpdateError::InvalidDataSize);
+                }
+            }
+        }
+
+        let staging_size = |region: &ImageData| {
+            match self.conversion {
+                UploadConversion::None => region.data.len(),
+                UploadConversion::PadRgba(_) => (region.data.len() / 3) * 4,
+                #[cfg(feature = "transcode")]
+                UploadConversion::Transcode(_) => transcode::get_transcoded_size(region.extent),
+            }
+        };
+
+        let required_memory = regions.iter().map(|r| staging_size(r)).sum::<usize>() as u64;
+
+        let (staging, allocation) = self.share.get_staging_pool().lock().unwrap().allocate(required_memory as u64, 4);
 
         let mut copies = Vec::with_capacity(regions.len());
         let mut current_offset = 0;
         for region in regions {
+            // Transcoded data is always tightly packed
+            let row_stride = match self.conversion {
+                #[cfg(feature = "transcode")]
+                UploadConversion::Transcode(_) => 0,
+                _ => region.row_stride,
+            };
+
             copies.push(vk::BufferImageCopy {
                 buffer_offset: staging.offset + current_offset,
-                buffer_row_length: region.row_stride,
+                buffer_row_length: row_stride,
                 buffer_image_height: 0,
                 image_subresource: vk::ImageSubresourceLayers {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    mip_level: 0,
-                    base_array_layer: 0,
+                    aspect_mask: self.aspect_mask,
+                    mip_level: region.mip_level,
+                    base_array_layer: if is_3d { 0 } else { region.layer },
                     layer_count: 1
                 },
-                image_offset: vk::Offset3D { x: region.offset[0] as i32, y: region.offset[1] as i32, z: 0 },
+                image_offset: vk::Offset3D { x: region.offset[0] as i32, y: region.offset[1] as i32, z: if is_3d { region.layer as i32 } else { 0 } },
                 image_extent: vk::Extent3D {
                     width: region.extent[0],
                     height: region.extent[1],
@@ -349,12 +734,29 @@ impl GlobalImage {
                 }
             });
 
+            let size = staging_size(region);
             unsafe {
-                let mapped = std::slice::from_raw_parts_mut(staging.mapped.as_ptr().offset(current_offset as isize), region.data.len());
-                mapped.copy_from_slice(region.data);
+                let mapped = std::slice::from_raw_parts_mut(staging.mapped.as_ptr().offset(current_offset as isize), size);
+                match self.conversion {
+                    UploadConversion::None => mapped.copy_from_slice(region.data),
+                    UploadConversion::PadRgba(alpha) => pad_rgb_to_rgba(region.data, mapped, alpha),
+                    #[cfg(feature = "transcode")]
+                    UploadConversion::Transcode(src_format) => {
+                        transcode::transcode_to_bgra8(src_format, region.data, region.row_stride, region.extent, mapped).unwrap_or_else(|err| {
+                            log::error!("Failed to transcode {:?} data in GlobalImage::update_regions: {:?}", src_format, err);
+                            panic!()
+                        });
+                    }
+                }
+
+                match self.get_encoding_conversion(region) {
+                    Some(ColorEncoding::Linear) => convert_srgb_to_linear(mapped, self.format.get_channel_count()),
+                    Some(ColorEncoding::Srgb) => convert_linear_to_srgb(mapped, self.format.get_channel_count()),
+                    None => {}
+                }
             }
 
-            current_offset += region.data.len() as u64;
+            current_offset += size as u64;
         }
 
         self.share.push_task(WorkerTask::WriteGlobalImage(GlobalImageWrite {
@@ -365,6 +767,166 @@ impl GlobalImage {
             dst_image: self.weak.upgrade().unwrap(),
             regions: copies.into_boxed_slice()
         }));
+
+        Ok(())
+    }
+
+    /// Returns the encoding the data of the region must be converted to or [`None`] if the data
+    /// already uses the encoding of the image format.
+    fn get_encoding_conversion(&self, region: &ImageData) -> Option<ColorEncoding> {
+        let dst_encoding = ColorEncoding::of(self.format);
+        match region.encoding {
+            Some(encoding) if encoding != dst_encoding => Some(dst_encoding),
+            _ => None,
+        }
+    }
+
+    /// Generates all mip levels except the first by repeatedly downsampling the previous level.
+    ///
+    /// If the format supports linear filtered blits the levels are blitted. Otherwise if possible
+    /// a compute shader averaging 2x2 blocks of texels (box filter) is used, which has a lower
+    /// quality for odd sizes and truncates integer values (see [`MipmapUtils`]). 3D images are
+    /// only supported if blits are supported.
+    ///
+    /// The generation is ordered after any previous call to [`GlobalImage::update_regions`] so
+    /// the first mip level should be uploaded before calling this function. Does nothing if the
+    /// image only has a single mip level.
+    ///
+    /// [`MipmapUtils`]: crate::device::device_utils::MipmapUtils
+    pub fn generate_mipmaps(&self) -> Result<(), GenerateMipmapsError> {
+        if self.size.get_mip_levels() <= 1 {
+            return Ok(());
+        }
+
+        if self.format.is_compressed() {
+            log::error!("Cannot generate mipmaps for compressed format {:?}", self.format);
+            return Err(GenerateMipmapsError::CompressedFormat);
+        }
+
+        if self.mipmap_method.is_none() {
+            log::error!("Format {:?} supports neither linear blits nor compute mipmap generation for {:?} images. Cannot generate mipmaps", self.format, self.view_type);
+            return Err(GenerateMipmapsError::UnsupportedFormat);
+        }
+
+        self.share.push_task(WorkerTask::GenerateGlobalImageMipmaps(
+            self.weak.upgrade().unwrap(),
+            PassId::from_raw(self.last_used_pass.load(std::sync::atomic::Ordering::Acquire))
+        ));
+
+        Ok(())
+    }
+
+    /// Downloads the contents of all mip levels and array layers of the image.
+    ///
+    /// The returned data contains the mip levels in order. Each mip level contains all array layers
+    /// (or depth slices for 3D images) in order, each made of tightly packed rows of texel blocks.
+    /// Compressed formats are returned as raw compressed blocks. The data uses the format returned
+    /// alongside it which is the format actually used by the image (see [`GlobalImage::get_format`]).
+    ///
+    /// The read back is ordered after all previous updates of the image and all passes started
+    /// before this call. This function blocks until the read back has completed. It must not be
+    /// called on a thread which is currently recording a pass as that pass could never be ended.
+    ///
+    /// Returns [`ReadBackError::Paused`] if the worker is paused.
+    pub fn read_back(&self) -> Result<(Vec<u8>, &'static Format, ImageSize), ReadBackError> {
+        if self.share.is_paused() {
+            return Err(ReadBackError::Paused);
+        }
+
+        let block_size = self.format.get_texel_block_size() as vk::DeviceSize;
+        // Buffer offsets of image copies must be a multiple of 4 and of the texel block size
+        let alignment = block_size * 4;
+
+        let mut regions = Vec::with_capacity(self.size.get_mip_levels() as usize);
+        let mut buffer_size = 0;
+        let mut data_size = 0;
+        for mip_level in 0..self.size.get_mip_levels() {
+            let extent = self.size.get_mip_extent_3d(mip_level);
+
+            // Formats with 3 component texel blocks do not have a power of 2 block size
+            buffer_size = align_up(buffer_size, alignment);
+            regions.push(vk::BufferImageCopy {
+                buffer_offset: buffer_size,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: self.aspect_mask,
+                    mip_level,
+                    base_array_layer: 0,
+                    layer_count: self.size.get_array_layers()
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: extent
+            });
+
+            let size = get_read_back_size(self.format, extent, self.size.get_array_layers());
+            buffer_size += size;
+            data_size += size;
+        }
+
+        let device = self.share.get_device();
+        let (buffer, allocation) = Self::create_read_back_buffer(device, buffer_size)?;
+
+        let (signal_send, signal_recv) = sync_channel(1);
+        self.share.push_task(WorkerTask::ReadBackGlobalImage(GlobalImageReadBack {
+            src_image: self.weak.upgrade().unwrap(),
+            dst_buffer: buffer,
+            regions: regions.clone().into_boxed_slice(),
+            signal: signal_send,
+        }));
+
+        // The sender is dropped without sending if the read back is never executed
+        let result = signal_recv.recv().map_err(|_| ReadBackError::Shutdown).map(|_| {
+            let mapped = allocation.mapped_ptr().unwrap().as_ptr() as *const u8;
+
+            let mut data = Vec::with_capacity(data_size as usize);
+            for region in &regions {
+                let size = get_read_back_size(self.format, region.image_extent, region.image_subresource.layer_count);
+                data.extend_from_slice(unsafe {
+                    std::slice::from_raw_parts(mapped.offset(region.buffer_offset as isize), size as usize)
+                });
+            }
+
+            (data, self.format, self.size)
+        });
+
+        unsafe {
+            device.vk().destroy_buffer(buffer, None);
+        }
+        device.get_allocator().free(allocation);
+
+        result
+    }
+
+    fn create_read_back_buffer(device: &DeviceContext, size: vk::DeviceSize) -> Result<(vk::Buffer, Allocation), ReadBackError> {
+        let info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let buffer = unsafe {
+            device.vk().create_buffer(&info, None)
+        }.map_err(|err| {
+            log::error!("vkCreateBuffer returned {:?} in GlobalImage::create_read_back_buffer", err);
+            ReadBackError::Allocation
+        })?;
+
+        let alloc = device.get_allocator().allocate_buffer_memory(buffer, &AllocationStrategy::AutoGpuToCpu).map_err(|_| {
+            log::error!("Failed to allocate buffer memory in GlobalImage::create_read_back_buffer");
+            unsafe { device.vk().destroy_buffer(buffer, None) };
+            ReadBackError::Allocation
+        })?;
+
+        if let Err(err) = unsafe {
+            device.vk().bind_buffer_memory(buffer, alloc.memory(), alloc.offset())
+        } {
+            log::error!("vkBindBufferMemory returned {:?} in GlobalImage::create_read_back_buffer", err);
+            unsafe { device.vk().destroy_buffer(buffer, None) };
+            device.get_allocator().free(alloc);
+            return Err(ReadBackError::Allocation);
+        }
+
+        Ok((buffer, alloc))
     }
 
     pub(super) fn get_image_handle(&self) -> vk::Image {
@@ -372,59 +934,264 @@ impl GlobalImage {
     }
 
     pub(super) fn get_mip_levels(&self) -> u32 {
-        self.mip_levels
+        self.size.get_mip_levels()
+    }
+
+    /// Returns [`vk::ImageAspectFlags::DEPTH`] for depth images and
+    /// [`vk::ImageAspectFlags::COLOR`] otherwise.
+    pub(super) fn get_aspect_mask(&self) -> vk::ImageAspectFlags {
+        self.aspect_mask
     }
 
     pub(super) fn get_sampler_view(&self) -> vk::ImageView {
         self.sampler_view
     }
 
+    /// Returns 2D array views of each mip level if mipmaps are generated by a compute shader or an
+    /// empty slice if they are blitted.
+    pub(super) fn get_mip_views(&self) -> &[vk::ImageView] {
+        &self.mip_views
+    }
+
     pub(super) fn get_sampler(&self, sampler_info: &SamplerInfo) -> vk::Sampler {
+        // Samplers requested outside of a pass are never evicted until they are used in a pass
+        let pass = self.share.get_current_pass_id().unwrap_or(u64::MAX);
+
         let mut guard = self.sampler_database.lock().unwrap();
-        if let Some(sampler) = guard.get(sampler_info) {
-            *sampler
+        if let Some(cached) = guard.get_mut(sampler_info) {
+            cached.last_used_pass = pass;
+            cached.sampler
         } else {
+            if self.format.is_integer() && sampler_info.uses_linear_filtering() {
+                log::error!("Linear filtering requested for integer format {:?} in GlobalImage::get_sampler", self.format);
+                panic!()
+            }
+            if sampler_info.compare.is_some() && !self.format.has_depth() {
+                log::error!("Comparison sampler requested for non depth format {:?} in GlobalImage::get_sampler", self.format);
+                panic!()
+            }
+
+            let device = self.share.get_device();
+
+            // The cache is keyed by the requested info so the fallback is only resolved once
+            let mut create_info = *sampler_info;
+            if create_info.uses_linear_filtering() && !self.format.is_filterable(device) {
+                log::warn!("Linear filtering requested for format {:?} which does not support it on this device. Falling back to nearest filtering", self.format);
+                create_info = create_info.with_nearest_filtering();
+            }
+
+            let limits = device.sampler_limits();
+            let max_anisotropy = sampler_info.max_anisotropy.and_then(|max_anisotropy| {
+                let clamped = limits.clamp_anisotropy(max_anisotropy);
+                if clamped.is_none() {
+                    log::warn!("Anisotropic filtering requested but the samplerAnisotropy feature is not supported. Disabling anisotropic filtering");
+                }
+                clamped
+            });
+            let mip_lod_bias = limits.clamp_lod_bias(sampler_info.mip_lod_bias);
+
             let info = vk::SamplerCreateInfo::builder()
-                .mag_filter(sampler_info.mag_filter)
-                .min_filter(sampler_info.min_filter)
-                .mipmap_mode(sampler_info.mipmap_mode)
+                .mag_filter(create_info.mag_filter)
+                .min_filter(create_info.min_filter)
+                .mipmap_mode(create_info.mipmap_mode)
                 .address_mode_u(sampler_info.address_mode_u)
                 .address_mode_v(sampler_info.address_mode_v)
                 .address_mode_w(vk::SamplerAddressMode::REPEAT)
-                .mip_lod_bias(0f32)
-                .anisotropy_enable(sampler_info.anisotropy_enable)
-                .max_anisotropy(0f32)
-                .compare_enable(false)
+                .mip_lod_bias(mip_lod_bias)
+                .anisotropy_enable(max_anisotropy.is_some())
+                .max_anisotropy(max_anisotropy.unwrap_or(1f32))
+                .compare_enable(sampler_info.compare.is_some())
+                .compare_op(sampler_info.compare.unwrap_or(vk::CompareOp::NEVER))
                 .min_lod(0f32)
                 .max_lod(vk::LOD_CLAMP_NONE)
                 .unnormalized_coordinates(false);
 
             let sampler = unsafe {
-                self.share.get_device().vk().create_sampler(&info, None)
+                device.vk().create_sampler(&info, None)
             }.unwrap_or_else(|err| {
                 log::error!("vkCreateSampler returned {:?} in GlobalImage::get_sampler", err);
                 panic!()
             });
+            device.on_object_created(TrackedObject::Sampler);
 
-            guard.insert(*sampler_info, sampler);
+            guard.insert(*sampler_info, CachedSampler { sampler, last_used_pass: pass });
             sampler
         }
     }
 
-    fn create_image(device: &DeviceContext, format: vk::Format, size: Vec2u32, mip_levels: u32) -> Result<(vk::Image, Allocation, vk::ImageView), GlobalObjectCreateError> {
+    /// Returns the number of samplers currently cached for this image.
+    pub fn get_sampler_cache_size(&self) -> usize {
+        self.sampler_database.lock().unwrap().len()
+    }
+
+    /// Destroys the least recently used samplers of this image until at most `max_entries` remain.
+    ///
+    /// Samplers used by a pass which has not yet completed execution are never destroyed, so the
+    /// cache may still contain more than `max_entries` samplers afterwards. Returns the number of
+    /// destroyed samplers.
+    pub fn trim_sampler_cache(&self, max_entries: usize) -> usize {
+        let completed_pass = self.share.get_completed_pass_id();
+
+        let mut guard = self.sampler_database.lock().unwrap();
+        if guard.len() <= max_entries {
+            return 0;
+        }
+
+        let mut candidates: Vec<_> = guard.iter()
+            .filter(|(_, cached)| cached.last_used_pass <= completed_pass)
+            .map(|(info, cached)| (cached.last_used_pass, *info))
+            .collect();
+        candidates.sort_by_key(|(last_used_pass, _)| *last_used_pass);
+
+        let evict_count = std::cmp::min(guard.len() - max_entries, candidates.len());
+        let device = self.share.get_device();
+        for (_, info) in &candidates[0..evict_count] {
+            let cached = guard.remove(info).unwrap();
+            unsafe {
+                device.vk().destroy_sampler(cached.sampler, None);
+            }
+            device.on_object_destroyed(TrackedObject::Sampler);
+        }
+
+        evict_count
+    }
+
+    /// Selects the format used to create the image. If the requested format does not support the
+    /// required features a fallback format is selected together with the conversion that has to
+    /// be applied to uploads.
+    ///
+    /// 24-bit formats are padded to the matching 32-bit format. If the `transcode` feature is
+    /// enabled compressed formats are decoded into a 32-bit BGRA format.
+    fn select_format(device: &DeviceContext, format: &'static Format, mip_levels: u32) -> Result<(&'static Format, UploadConversion), GlobalObjectCreateError> {
+        let mut required_features = vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_SRC | vk::FormatFeatureFlags::TRANSFER_DST;
+        if mip_levels > 1 {
+            required_features |= vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        }
+
+        if device.get_format_properties(format).optimal_tiling_features.contains(required_features) {
+            return Ok((format, UploadConversion::None));
+        }
+
+        if let Some((padded_format, alpha)) = format.get_rgba_padding() {
+            log::debug!("Format {:?} is not supported for global images. Padding to {:?}", format, padded_format);
+            return Ok((padded_format, UploadConversion::PadRgba(alpha)));
+        }
+
+        if format.is_compressed() {
+            #[cfg(feature = "transcode")]
+            if let Some(target) = transcode::get_transcode_target(format) {
+                log::debug!("Format {:?} is not supported for global images. Transcoding to {:?}", format, target);
+                return Ok((target, UploadConversion::Transcode(format)));
+            }
+
+            log::error!("Compressed format {:?} is not supported for global images", format);
+            return Err(GlobalObjectCreateError::UnsupportedFormat);
+        }
+
+        Ok((format, UploadConversion::None))
+    }
+
+    /// Selects how mipmaps are generated for a format. Returns [`None`] if they cannot be
+    /// generated.
+    fn select_mipmap_method(device: &DeviceContext, format: &'static Format, view_type: vk::ImageViewType) -> Option<MipmapMethod> {
+        if format.is_compressed() || !format.is_color() {
+            return None;
+        }
+
+        let features = device.get_format_properties(format).optimal_tiling_features;
+        if features.contains(vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR) {
+            return Some(MipmapMethod::Blit);
+        }
+
+        let is_2d = view_type == vk::ImageViewType::TYPE_2D || view_type == vk::ImageViewType::TYPE_2D_ARRAY || view_type == vk::ImageViewType::CUBE;
+        if is_2d && features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE) && device.get_utils().mipmap_utils().is_supported() {
+            log::debug!("Format {:?} does not support linear blits. Using compute shader to generate mipmaps", format);
+            return Some(MipmapMethod::Compute);
+        }
+
+        None
+    }
+
+    /// Validates that images of a format can be used as storage images. Storage images are written
+    /// by shaders so the format cannot differ from the requested format.
+    fn check_storage_format(device: &DeviceContext, format: &'static Format, aspect_mask: vk::ImageAspectFlags, conversion: UploadConversion) -> Result<(), GlobalObjectCreateError> {
+        if aspect_mask != vk::ImageAspectFlags::COLOR || conversion != UploadConversion::None {
+            log::error!("Format {:?} is not supported for storage images", format);
+            return Err(GlobalObjectCreateError::UnsupportedFormat);
+        }
+        if !device.get_format_properties(format).optimal_tiling_features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE) {
+            log::error!("Format {:?} does not support vk::FormatFeatureFlags::STORAGE_IMAGE", format);
+            return Err(GlobalObjectCreateError::UnsupportedFormat);
+        }
+        Ok(())
+    }
+
+    /// Validates the image size against the device limits and returns the view type used to sample
+    /// the image.
+    fn select_view_type(device: &DeviceContext, size: &ImageSize, cube: bool) -> Result<vk::ImageViewType, GlobalObjectCreateError> {
+        let limits = device.get_limits();
+
+        let extent = size.as_extent_3d();
+        if extent.width == 0 || extent.height == 0 || extent.depth == 0 || size.get_mip_levels() == 0 || size.get_array_layers() == 0 {
+            log::error!("Global image size {:?} must not be empty", size);
+            return Err(GlobalObjectCreateError::InvalidSize);
+        }
+        if size.get_array_layers() > limits.max_image_array_layers {
+            log::error!("Global image size {:?} exceeds the maximum array layer count {}", size, limits.max_image_array_layers);
+            return Err(GlobalObjectCreateError::InvalidSize);
+        }
+
+        let (view_type, max_dimension) = match size {
+            ImageSize::Type2D { width, height, array_layers, .. } if cube => {
+                if *array_layers != 6 || width != height {
+                    log::error!("Cube global images must be square and have exactly 6 array layers (got {:?})", size);
+                    return Err(GlobalObjectCreateError::InvalidSize);
+                }
+                (vk::ImageViewType::CUBE, limits.max_image_dimension_cube)
+            }
+            _ if cube => {
+                log::error!("Cube global images must be 2D images (got {:?})", size);
+                return Err(GlobalObjectCreateError::InvalidSize);
+            }
+            ImageSize::Type1D { array_layers, .. } => {
+                (if *array_layers > 1 { vk::ImageViewType::TYPE_1D_ARRAY } else { vk::ImageViewType::TYPE_1D }, limits.max_image_dimension1_d)
+            }
+            ImageSize::Type2D { array_layers, .. } => {
+                (if *array_layers > 1 { vk::ImageViewType::TYPE_2D_ARRAY } else { vk::ImageViewType::TYPE_2D }, limits.max_image_dimension2_d)
+            }
+            ImageSize::Type3D { .. } => (vk::ImageViewType::TYPE_3D, limits.max_image_dimension3_d),
+        };
+
+        if extent.width.max(extent.height).max(extent.depth) > max_dimension {
+            log::error!("Global image size {:?} exceeds the maximum dimension {} for {:?} images", size, max_dimension, view_type);
+            return Err(GlobalObjectCreateError::InvalidSize);
+        }
+
+        Ok(view_type)
+    }
+
+    fn create_image(device: &DeviceContext, format: vk::Format, aspect_mask: vk::ImageAspectFlags, size: &ImageSize, view_type: vk::ImageViewType, storage: bool) -> Result<(vk::Image, Allocation, vk::ImageView), GlobalObjectCreateError> {
+        let flags = if view_type == vk::ImageViewType::CUBE {
+            vk::ImageCreateFlags::CUBE_COMPATIBLE
+        } else {
+            vk::ImageCreateFlags::empty()
+        };
+
+        let mut usage = vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+        if storage {
+            usage |= vk::ImageUsageFlags::STORAGE;
+        }
+
         let info = vk::ImageCreateInfo::builder()
-            .image_type(vk::ImageType::TYPE_2D)
+            .flags(flags)
+            .image_type(size.get_vulkan_type())
             .format(format)
-            .extent(vk::Extent3D {
-                width: size[0],
-                height: size[1],
-                depth: 1
-            })
-            .mip_levels(mip_levels)
-            .array_layers(1)
+            .extent(size.as_extent_3d())
+            .mip_levels(size.get_mip_levels())
+            .array_layers(size.get_array_layers())
             .samples(vk::SampleCountFlags::TYPE_1)
             .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+            .usage(usage)
             .sharing_mode(vk::SharingMode::EXCLUSIVE)
             .initial_layout(vk::ImageLayout::UNDEFINED);
 
@@ -435,7 +1202,7 @@ impl GlobalImage {
             err
         })?;
 
-        let allocation = device.get_allocator().allocate_image_memory(image, &AllocationStrategy::AutoGpuOnly).map_err(|_| {
+        let allocation = device.get_allocator().allocate_image_memory(image, vk::ImageTiling::OPTIMAL, &AllocationStrategy::AutoGpuOnly).map_err(|_| {
             log::error!("Failed to allocate image memory in GlobalImage::create_image");
             unsafe { device.vk().destroy_image(image, None) };
             GlobalObjectCreateError::Allocation
@@ -452,7 +1219,7 @@ impl GlobalImage {
 
         let info = vk::ImageViewCreateInfo::builder()
             .image(image)
-            .view_type(vk::ImageViewType::TYPE_2D)
+            .view_type(view_type)
             .format(format)
             .components(vk::ComponentMapping {
                 r: vk::ComponentSwizzle::IDENTITY,
@@ -461,11 +1228,11 @@ impl GlobalImage {
                 a: vk::ComponentSwizzle::IDENTITY
             })
             .subresource_range(vk::ImageSubresourceRange {
-                aspect_mask: vk::ImageAspectFlags::COLOR,
+                aspect_mask,
                 base_mip_level: 0,
-                level_count: mip_levels,
+                level_count: size.get_mip_levels(),
                 base_array_layer: 0,
-                layer_count: 1
+                layer_count: size.get_array_layers()
             });
 
         let sampler_view = match unsafe {
@@ -482,6 +1249,39 @@ impl GlobalImage {
 
         Ok((image, allocation, sampler_view))
     }
+
+    /// Creates a 2D array view for each mip level of a image.
+    fn create_mip_views(device: &DeviceContext, image: vk::Image, format: vk::Format, size: &ImageSize) -> Result<Box<[vk::ImageView]>, GlobalObjectCreateError> {
+        let mut views = Vec::with_capacity(size.get_mip_levels() as usize);
+        for level in 0..size.get_mip_levels() {
+            let info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                .format(format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: level,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: size.get_array_layers()
+                });
+
+            match unsafe {
+                device.vk().create_image_view(&info, None)
+            } {
+                Ok(view) => views.push(view),
+                Err(err) => {
+                    log::error!("vkCreateImageView returned {:?} in GlobalImage::create_mip_views", err);
+                    for view in views {
+                        unsafe { device.vk().destroy_image_view(view, None) };
+                    }
+                    return Err(GlobalObjectCreateError::Vulkan(err));
+                }
+            }
+        }
+
+        Ok(views.into_boxed_slice())
+    }
 }
 
 impl PartialEq for GlobalImage {
@@ -515,8 +1315,12 @@ impl Drop for GlobalImage {
     fn drop(&mut self) {
         let device = self.share.get_device();
         unsafe {
-            for (_, sampler) in self.sampler_database.get_mut().unwrap() {
-                device.vk().destroy_sampler(*sampler, None);
+            for (_, cached) in self.sampler_database.get_mut().unwrap() {
+                device.vk().destroy_sampler(cached.sampler, None);
+                device.on_object_destroyed(TrackedObject::Sampler);
+            }
+            for view in self.mip_views.iter() {
+                device.vk().destroy_image_view(*view, None);
             }
             device.vk().destroy_image_view(self.sampler_view, None);
             device.vk().destroy_image(self.image, None);
@@ -525,12 +1329,268 @@ impl Drop for GlobalImage {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+struct CachedSampler {
+    sampler: vk::Sampler,
+    /// The id of the last pass which requested this sampler.
+    last_used_pass: u64,
+}
+
+/// How the mip levels of a [`GlobalImage`] are generated by [`GlobalImage::generate_mipmaps`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum MipmapMethod {
+    /// Linear filtered blits.
+    Blit,
+    /// A box filter compute shader. Used for formats which support storage but not blits.
+    Compute,
+}
+
+/// Returns true if `size` bytes are whole 24-bit texels covering a region with the specified
+/// extent and row stride in texels (0 if tightly packed).
+fn is_valid_rgb_data_size(size: usize, extent: Vec2u32, row_stride: u32) -> bool {
+    if size % 3 != 0 {
+        return false;
+    }
+    if extent[0] == 0 || extent[1] == 0 {
+        return true;
+    }
+
+    let row_length = (if row_stride == 0 { extent[0] } else { row_stride }) as usize;
+    let required = (row_length * (extent[1] as usize - 1)) + (extent[0] as usize);
+    size / 3 >= required
+}
+
+/// Conversion applied to data uploaded to a [`GlobalImage`] if the image was created using a
+/// fallback format.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum UploadConversion {
+    None,
+
+    /// The requested 24-bit format is unsupported and the matching 32-bit format is used instead.
+    /// Contains the alpha value that is inserted during uploads.
+    PadRgba(u8),
+
+    /// The requested compressed format is unsupported. Contains the requested format which
+    /// uploads are decoded from.
+    #[cfg(feature = "transcode")]
+    Transcode(&'static Format),
+}
+
+#[derive(Copy, Clone, Debug)]
 pub struct SamplerInfo {
     pub mag_filter: vk::Filter,
     pub min_filter: vk::Filter,
     pub mipmap_mode: vk::SamplerMipmapMode,
     pub address_mode_u: vk::SamplerAddressMode,
     pub address_mode_v: vk::SamplerAddressMode,
-    pub anisotropy_enable: bool,
-}
\ No newline at end of file
+
+    /// The max anisotropy used for anisotropic filtering or [`None`] to disable anisotropic
+    /// filtering. The value is clamped to the `maxSamplerAnisotropy` limit. Anisotropic filtering
+    /// is disabled if the device does not support the `samplerAnisotropy` feature. See
+    /// [`DeviceContext::sampler_limits`].
+    pub max_anisotropy: Option<f32>,
+
+    /// Bias added to the calculated mip level. Clamped to the `maxSamplerLodBias` limit.
+    pub mip_lod_bias: f32,
+
+    /// The compare op used for depth comparison (for example shadow mapping) or [`None`] to
+    /// disable comparison. Can only be used with images of a depth format.
+    pub compare: Option<vk::CompareOp>,
+}
+
+impl SamplerInfo {
+    /// Returns true if any of the filters or the mipmap mode is linear.
+    pub fn uses_linear_filtering(&self) -> bool {
+        self.mag_filter == vk::Filter::LINEAR || self.min_filter == vk::Filter::LINEAR || self.mipmap_mode == vk::SamplerMipmapMode::LINEAR
+    }
+
+    /// Returns a copy of this info with all filters and the mipmap mode set to nearest.
+    pub fn with_nearest_filtering(&self) -> Self {
+        Self {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            ..*self
+        }
+    }
+}
+
+impl SamplerInfo {
+    /// Returns every parameter affecting the created sampler in a form which can be compared and
+    /// hashed. Floats are represented by their bit patterns.
+    ///
+    /// The struct is destructured so that adding a field without including it in the cache key
+    /// fails to compile.
+    fn as_key(&self) -> (vk::Filter, vk::Filter, vk::SamplerMipmapMode, vk::SamplerAddressMode, vk::SamplerAddressMode, Option<u32>, u32, Option<vk::CompareOp>) {
+        let SamplerInfo {
+            mag_filter,
+            min_filter,
+            mipmap_mode,
+            address_mode_u,
+            address_mode_v,
+            max_anisotropy,
+            mip_lod_bias,
+            compare,
+        } = *self;
+
+        (mag_filter, min_filter, mipmap_mode, address_mode_u, address_mode_v, max_anisotropy.map(f32::to_bits), mip_lod_bias.to_bits(), compare)
+    }
+}
+
+// Used as key in the sampler cache of global images
+impl PartialEq for SamplerInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_key() == other.as_key()
+    }
+}
+
+impl Eq for SamplerInfo {
+}
+
+impl Hash for SamplerInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_key().hash(state)
+    }
+}
+
+/// Returns the number of bytes needed to store `layers` tightly packed slices of the specified
+/// extent in some format.
+fn get_read_back_size(format: &Format, extent: vk::Extent3D, layers: u32) -> vk::DeviceSize {
+    let [block_width, block_height, _] = format.get_block_extent();
+    let blocks_x = ((extent.width + block_width - 1) / block_width) as vk::DeviceSize;
+    let blocks_y = ((extent.height + block_height - 1) / block_height) as vk::DeviceSize;
+
+    blocks_x * blocks_y * (extent.depth as vk::DeviceSize) * (layers as vk::DeviceSize) * (format.get_texel_block_size() as vk::DeviceSize)
+}
+
+/// Returns the buffer offset of a write of `size` bytes at `offset` relative to the start of a
+/// `(offset, size)` range of a mesh buffer or [`None`] if the write is out of bounds.
+fn get_update_offset(range: (vk::DeviceSize, vk::DeviceSize), offset: vk::DeviceSize, size: vk::DeviceSize) -> Option<vk::DeviceSize> {
+    match offset.checked_add(size) {
+        Some(end) if end <= range.1 => Some(range.0 + offset),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb_data_size() {
+        let extent = Vec2u32::new(4, 2);
+        assert!(is_valid_rgb_data_size(4 * 2 * 3, extent, 0));
+        assert!(!is_valid_rgb_data_size(4 * 2 * 3 - 1, extent, 0));
+        assert!(!is_valid_rgb_data_size(4 * 2 * 3 + 2, extent, 0));
+        assert!(!is_valid_rgb_data_size(4 * 3, extent, 0));
+
+        // The last row does not need to be padded to the row stride
+        assert!(is_valid_rgb_data_size((8 + 4) * 3, extent, 8));
+        assert!(!is_valid_rgb_data_size((8 + 3) * 3, extent, 8));
+    }
+
+    #[test]
+    fn read_back_size() {
+        let extent = vk::Extent3D { width: 5, height: 3, depth: 1 };
+        assert_eq!(get_read_back_size(&Format::R8G8B8A8_UNORM, extent, 2), 5 * 3 * 4 * 2);
+        assert_eq!(get_read_back_size(&Format::BC1_RGB_UNORM_BLOCK, extent, 1), 2 * 8);
+        assert_eq!(get_read_back_size(&Format::R16_UNORM, vk::Extent3D { width: 4, height: 4, depth: 3 }, 1), 4 * 4 * 3 * 2);
+    }
+
+    #[test]
+    fn packed_mesh_layout() {
+        let vertices = [0u8; 60];
+        let indices = [0u8; 12];
+        let meshes = [
+            MeshData {
+                vertex_data: &vertices[0..36],
+                index_data: &indices[0..6],
+                vertex_stride: 12,
+                index_count: 3,
+                index_type: vk::IndexType::UINT16,
+                primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            },
+            MeshData {
+                vertex_data: &vertices[0..60],
+                index_data: &indices[0..12],
+                vertex_stride: 20,
+                index_count: 3,
+                index_type: vk::IndexType::UINT32,
+                primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            },
+            MeshData {
+                vertex_data: &vertices[0..36],
+                index_data: &indices[0..6],
+                vertex_stride: 12,
+                index_count: 3,
+                index_type: vk::IndexType::UINT16,
+                primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            },
+        ];
+
+        let (layouts, size) = compute_packed_layout(&meshes);
+        assert_eq!(layouts, vec![
+            PackedMeshLayout { vertex_byte_offset: 0, vertex_offset: 0, index_byte_offset: 144, first_index: 72 },
+            PackedMeshLayout { vertex_byte_offset: 40, vertex_offset: 2, index_byte_offset: 152, first_index: 38 },
+            PackedMeshLayout { vertex_byte_offset: 108, vertex_offset: 9, index_byte_offset: 164, first_index: 82 },
+        ]);
+        assert_eq!(size, 170);
+
+        for (data, layout) in meshes.iter().zip(layouts.iter()) {
+            assert_eq!(layout.vertex_byte_offset % (data.vertex_stride as vk::DeviceSize), 0);
+            assert_eq!(layout.index_byte_offset, (layout.first_index * data.get_index_size()) as vk::DeviceSize);
+        }
+    }
+
+    #[test]
+    fn partial_update_offsets() {
+        // The vertex and index ranges of the second mesh of packed_mesh_layout
+        let vertex_range = (40, 60);
+        let index_range = (152, 12);
+
+        assert_eq!(get_update_offset(vertex_range, 0, 20), Some(40));
+        assert_eq!(get_update_offset(vertex_range, 40, 20), Some(80));
+        assert_eq!(get_update_offset(vertex_range, 41, 20), None);
+        assert_eq!(get_update_offset(vertex_range, 60, 1), None);
+        assert_eq!(get_update_offset(index_range, 4, 8), Some(156));
+        assert_eq!(get_update_offset(index_range, 8, 8), None);
+        assert_eq!(get_update_offset(index_range, vk::DeviceSize::MAX, 2), None);
+    }
+
+    #[test]
+    fn sampler_info_cache_keys() {
+        let repeat = SamplerInfo {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            max_anisotropy: None,
+            mip_lod_bias: 0f32,
+            compare: None,
+        };
+        let clamp = SamplerInfo {
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..repeat
+        };
+        let biased = SamplerInfo {
+            mip_lod_bias: 0.5f32,
+            ..repeat
+        };
+        let compare = SamplerInfo {
+            compare: Some(vk::CompareOp::LESS),
+            ..repeat
+        };
+
+        let mut cache = HashMap::new();
+        cache.insert(repeat, 0u32);
+        cache.insert(clamp, 1u32);
+        cache.insert(biased, 2u32);
+        cache.insert(compare, 4u32);
+        cache.insert(repeat, 3u32);
+
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.get(&repeat), Some(&3u32));
+        assert_eq!(cache.get(&clamp), Some(&1u32));
+        assert_ne!(repeat, clamp);
+    }
+}
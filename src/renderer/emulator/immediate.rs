@@ -6,20 +6,53 @@ use std::sync::{Arc, Condvar, Mutex};
 use ash::vk;
 
 use crate::vk::objects::allocator::{Allocation, AllocationStrategy};
-use crate::util::alloc::next_aligned;
+use crate::util::alloc::align_up;
 
 use crate::prelude::*;
 
+/// Configures the pool of immediate buffers used to store immediate meshes and uniforms of a pass.
+///
+/// Every pass holds one immediate buffer from when it is started until its gpu work has completed.
+/// The buffer count therefore limits the number of passes which can be recorded or in flight at the
+/// same time. Starting a pass blocks until a buffer is available.
+///
+/// Each buffer initially allocates `buffer_size` bytes of device local memory. If that memory is
+/// not host visible an additional staging buffer of the same size is allocated in host memory. The
+/// upfront memory cost is thus up to `2 * buffer_count * buffer_size` bytes. If a pass needs more
+/// memory the buffer grows and keeps the grown size for later passes.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ImmediatePoolConfig {
+    /// The number of immediate buffers. Must be at least 1.
+    pub buffer_count: u32,
+
+    /// The initial size of each buffer in bytes. Must not be 0.
+    pub buffer_size: vk::DeviceSize,
+}
+
+impl Default for ImmediatePoolConfig {
+    fn default() -> Self {
+        Self {
+            buffer_count: 2,
+            buffer_size: 2u64.pow(24), // 16MB
+        }
+    }
+}
+
 pub(super) struct ImmediatePool {
     buffer_queue: Mutex<VecDeque<Box<ImmediateBuffer>>>,
     ready_condvar: Condvar,
 }
 
 impl ImmediatePool {
-    pub(super) fn new(device: Arc<DeviceContext>) -> Self {
-        let mut buffer_queue = VecDeque::with_capacity(2);
-        for _ in 0..2 {
-            buffer_queue.push_back(Box::new(ImmediateBuffer::new(device.clone())));
+    pub(super) fn new(device: Arc<DeviceContext>, config: &ImmediatePoolConfig) -> Self {
+        if config.buffer_count == 0 || config.buffer_size == 0 {
+            log::error!("Invalid immediate pool config {:?}", config);
+            panic!()
+        }
+
+        let mut buffer_queue = VecDeque::with_capacity(config.buffer_count as usize);
+        for _ in 0..config.buffer_count {
+            buffer_queue.push_back(Box::new(ImmediateBuffer::new(device.clone(), config.buffer_size)));
         }
 
         Self {
@@ -50,6 +83,15 @@ impl ImmediatePool {
         }
     }
 
+    /// Returns the next available buffer or [`None`] if all buffers are currently in use.
+    pub(super) fn try_get_next_buffer(&self) -> Option<Box<ImmediateBuffer>> {
+        let mut guard = self.buffer_queue.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned queue mutex in ImmediatePool::try_get_next_buffer");
+            panic!()
+        });
+        guard.pop_front()
+    }
+
     pub(super) fn return_buffer(&self, mut buffer: Box<ImmediateBuffer>) {
         buffer.reset();
 
@@ -69,19 +111,20 @@ pub(super) struct ImmediateBuffer {
     device: Arc<DeviceContext>,
     current_buffer: Buffer,
     old_buffers: Vec<Buffer>,
+    min_buffer_size: vk::DeviceSize,
 }
 
 impl ImmediateBuffer {
-    const MIN_BUFFER_SIZE: vk::DeviceSize = 2u64.pow(24); // 16MB
     const OVER_ALLOCATION: u8 = 77; // 30%
 
-    fn new(device: Arc<DeviceContext>) -> Self {
-        let current_buffer = Buffer::new(device.clone(), Self::MIN_BUFFER_SIZE);
+    fn new(device: Arc<DeviceContext>, min_buffer_size: vk::DeviceSize) -> Self {
+        let current_buffer = Buffer::new(device.clone(), min_buffer_size);
 
         Self {
             device,
             current_buffer,
             old_buffers: Vec::new(),
+            min_buffer_size,
         }
     }
 
@@ -104,7 +147,7 @@ impl ImmediateBuffer {
             let usage = self.get_current_usage();
             let alloc_size = usage + (usage * (Self::OVER_ALLOCATION as u64) / (u8::MAX as u64));
             let alloc_size = std::cmp::max(alloc_size, data.len() as u64);
-            let alloc_size = std::cmp::max(alloc_size, Self::MIN_BUFFER_SIZE);
+            let alloc_size = std::cmp::max(alloc_size, self.min_buffer_size);
 
             let new_buffer = Buffer::new(self.device.clone(), alloc_size);
             self.old_buffers.push(std::mem::replace(&mut self.current_buffer, new_buffer));
@@ -184,7 +227,9 @@ impl Buffer {
     }
 
     fn allocate(&mut self, bytes: &[u8], alignment: vk::DeviceSize) -> Option<(vk::Buffer, vk::DeviceSize)> {
-        let aligned = next_aligned(self.current_offset, alignment);
+        // Index data is aligned to the index size while vertex data is aligned to the vertex
+        // stride which may not be a power of 2
+        let aligned = align_up(self.current_offset, alignment);
         if aligned + (bytes.len() as vk::DeviceSize) > self.size {
             return None;
         }
@@ -2,10 +2,12 @@
 
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
 use std::sync::{Arc, Mutex, Weak};
 use ash::vk;
 use crate::define_uuid_type;
+use crate::util::format::{ClearColorType, Format};
 
 use crate::prelude::*;
 
@@ -15,21 +17,47 @@ pub trait ShaderDropListener {
     fn on_shader_drop(&self, id: ShaderId);
 }
 
+/// A shader registered with the emulator renderer.
+///
+/// A shader may specify specialization constants which pipelines should apply to all shader
+/// modules used for the shader. Since pipelines are cached per shader id any cache using a
+/// different key must include the specialization constants returned by
+/// [`Shader::get_specialization`].
 pub struct Shader {
     id: ShaderId,
     vertex_format: VertexFormat,
     used_uniforms: McUniform,
+    specialization: HashMap<u32, SpecConstantValue>,
+    specialization_data: SpecializationData,
+    texture_types: HashMap<u32, TextureBindingType>,
     weak: Weak<Self>,
     listeners: Mutex<HashMap<UUID, Weak<dyn ShaderDropListener + Send + Sync>>>,
 }
 
 impl Shader {
     pub fn new(vertex_format: VertexFormat, used_uniforms: McUniform) -> Arc<Self> {
+        Self::new_specialized(vertex_format, used_uniforms, HashMap::new())
+    }
+
+    /// Creates a new shader with a set of specialization constants mapping constant ids to values.
+    pub fn new_specialized(vertex_format: VertexFormat, used_uniforms: McUniform, specialization: HashMap<u32, SpecConstantValue>) -> Arc<Self> {
+        Self::new_with_textures(vertex_format, used_uniforms, specialization, HashMap::new())
+    }
+
+    /// Creates a new shader which additionally declares how it samples its texture bindings.
+    ///
+    /// The declared types are used to validate images bound to the shader in debug builds.
+    /// Bindings without a declared type are not validated.
+    pub fn new_with_textures(vertex_format: VertexFormat, used_uniforms: McUniform, specialization: HashMap<u32, SpecConstantValue>, texture_types: HashMap<u32, TextureBindingType>) -> Arc<Self> {
+        let specialization_data = SpecializationData::new(&specialization);
         Arc::new_cyclic(|weak| {
             Self {
                 id: ShaderId::new(),
                 vertex_format,
                 used_uniforms,
+                specialization,
+                specialization_data,
+                texture_types,
                 weak: weak.clone(),
                 listeners: Mutex::new(HashMap::new()),
             }
@@ -48,6 +76,21 @@ impl Shader {
         self.used_uniforms
     }
 
+    pub fn get_specialization(&self) -> &HashMap<u32, SpecConstantValue> {
+        &self.specialization
+    }
+
+    /// Returns the specialization constants of this shader packed for use in a
+    /// [`vk::PipelineShaderStageCreateInfo`].
+    pub fn get_specialization_data(&self) -> &SpecializationData {
+        &self.specialization_data
+    }
+
+    /// Returns the declared type of a texture binding or [`None`] if no type has been declared.
+    pub fn get_texture_type(&self, index: u32) -> Option<&TextureBindingType> {
+        self.texture_types.get(&index)
+    }
+
     /// Registers a drop listener to this shader. If this shader is dropped the listener will be called.
     ///
     /// The returned [`ShaderListener`] is used keep track of the liveliness of the listener. If it is
@@ -84,6 +127,113 @@ impl Drop for ShaderListener {
     }
 }
 
+/// Describes how a shader samples a texture binding.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TextureBindingType {
+    /// The type of the sampled values.
+    pub numeric_type: ClearColorType,
+    /// The number of components the shader reads.
+    pub component_count: u32,
+}
+
+impl TextureBindingType {
+    /// Returns true if images of the specified format can be bound to a binding of this type.
+    ///
+    /// The format must have the same numeric type and at least as many channels as the shader
+    /// reads.
+    pub fn is_compatible_with(&self, format: &Format) -> bool {
+        format.get_numeric_type() == self.numeric_type && format.get_channel_count() >= self.component_count
+    }
+}
+
+/// The value of a specialization constant. All values are 4 bytes large.
+#[derive(Copy, Clone, Debug)]
+pub enum SpecConstantValue {
+    Bool(bool),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+}
+
+impl SpecConstantValue {
+    /// Returns the raw bytes of the value as expected by vulkan.
+    pub fn to_bytes(&self) -> [u8; 4] {
+        match self {
+            SpecConstantValue::Bool(value) => (if *value { vk::TRUE } else { vk::FALSE }).to_ne_bytes(),
+            SpecConstantValue::U32(value) => value.to_ne_bytes(),
+            SpecConstantValue::I32(value) => value.to_ne_bytes(),
+            SpecConstantValue::F32(value) => value.to_ne_bytes(),
+        }
+    }
+}
+
+// Floats are compared by their bit pattern so the value can be used as part of a cache key
+impl PartialEq for SpecConstantValue {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other) && self.to_bytes() == other.to_bytes()
+    }
+}
+
+impl Eq for SpecConstantValue {
+}
+
+impl Hash for SpecConstantValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        self.to_bytes().hash(state);
+    }
+}
+
+/// Specialization constants packed into map entries and a data blob. Entries are sorted by
+/// constant id so equal constants always produce equal data.
+#[derive(Clone, Debug)]
+pub struct SpecializationData {
+    entries: Box<[vk::SpecializationMapEntry]>,
+    data: Box<[u8]>,
+}
+
+impl SpecializationData {
+    pub fn new(constants: &HashMap<u32, SpecConstantValue>) -> Self {
+        let mut sorted: Vec<_> = constants.iter().collect();
+        sorted.sort_by_key(|(id, _)| **id);
+
+        let mut entries = Vec::with_capacity(sorted.len());
+        let mut data = Vec::with_capacity(sorted.len() * 4);
+        for (id, value) in sorted {
+            entries.push(vk::SpecializationMapEntry {
+                constant_id: *id,
+                offset: data.len() as u32,
+                size: 4
+            });
+            data.extend_from_slice(&value.to_bytes());
+        }
+
+        Self {
+            entries: entries.into_boxed_slice(),
+            data: data.into_boxed_slice(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get_entries(&self) -> &[vk::SpecializationMapEntry] {
+        &self.entries
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns a [`vk::SpecializationInfo`] referencing the data of this struct.
+    pub fn get_info(&self) -> vk::SpecializationInfoBuilder {
+        vk::SpecializationInfo::builder()
+            .map_entries(&self.entries)
+            .data(&self.data)
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct McUniform(u64);
 
@@ -243,4 +393,45 @@ pub struct VertexFormat {
     pub uv0: Option<VertexFormatEntry>,
     pub uv1: Option<VertexFormatEntry>,
     pub uv2: Option<VertexFormatEntry>,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specialization_data_sorted() {
+        let mut constants = HashMap::new();
+        constants.insert(7, SpecConstantValue::F32(1.5f32));
+        constants.insert(2, SpecConstantValue::Bool(true));
+        constants.insert(4, SpecConstantValue::I32(-3));
+
+        let data = SpecializationData::new(&constants);
+        let ids: Vec<_> = data.get_entries().iter().map(|entry| (entry.constant_id, entry.offset)).collect();
+        assert_eq!(ids, vec![(2, 0), (4, 4), (7, 8)]);
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&vk::TRUE.to_ne_bytes());
+        expected.extend_from_slice(&(-3i32).to_ne_bytes());
+        expected.extend_from_slice(&1.5f32.to_ne_bytes());
+        assert_eq!(data.get_data(), expected.as_slice());
+    }
+
+    #[test]
+    fn spec_constant_equality() {
+        assert_eq!(SpecConstantValue::F32(0.5f32), SpecConstantValue::F32(0.5f32));
+        assert_ne!(SpecConstantValue::U32(1), SpecConstantValue::I32(1));
+        assert_ne!(SpecConstantValue::F32(0f32), SpecConstantValue::F32(-0f32));
+    }
+
+    #[test]
+    fn texture_binding_compatibility() {
+        let rgba = TextureBindingType { numeric_type: ClearColorType::Float, component_count: 4 };
+        assert!(rgba.is_compatible_with(&Format::R8G8B8A8_UNORM));
+        assert!(!rgba.is_compatible_with(&Format::R8_UNORM));
+        assert!(!rgba.is_compatible_with(&Format::R8G8B8A8_UINT));
+
+        let depth = TextureBindingType { numeric_type: ClearColorType::Float, component_count: 1 };
+        assert!(depth.is_compatible_with(&Format::D32_SFLOAT));
+    }
+}
@@ -24,63 +24,100 @@ mod descriptors;
 mod share;
 mod staging;
 
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
+use std::future::Future;
 use std::panic::RefUnwindSafe;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use ash::vk;
 use bytemuck::cast_slice;
 
 use crate::renderer::emulator::worker::run_worker;
-use crate::renderer::emulator::pipeline::EmulatorPipeline;
+use crate::renderer::emulator::pipeline::{EmulatorPipeline, PassTimingHook, PresentHook};
 
 use crate::prelude::*;
 
-pub use global_objects::{GlobalMesh, GlobalImage, ImageData, SamplerInfo};
+pub use global_objects::{GlobalMesh, GlobalImage, GlobalObjectCreateError, GenerateMipmapsError, ImageData, ImageUpdateError, ReadBackError, SamplerInfo};
 
 pub use pass::PassId;
 pub use pass::PassRecorder;
+pub use pass::SecondaryRecorder;
 pub use pass::ImmediateMeshId;
+pub use pass::{ClearAttachment, ClearColor};
+pub use share::TaskChannelFull;
+pub use descriptors::DescriptorPoolStats;
+pub use immediate::ImmediatePoolConfig;
+pub use worker::{TaskInfo, TaskObserver, WorkerConfig};
 
 use share::Share;
-use crate::renderer::emulator::mc_shaders::{McUniform, Shader, ShaderId, VertexFormat};
+use crate::renderer::emulator::mc_shaders::{McUniform, Shader, ShaderId, SpecConstantValue, TextureBindingType, VertexFormat};
 use crate::util::format::Format;
+use crate::vk::objects::ImageSize;
 
 pub struct EmulatorRenderer {
     share: Arc<Share>,
     placeholder_image: Arc<GlobalImage>,
-    placeholder_sampler: SamplerInfo,
-    worker: std::thread::JoinHandle<()>,
+    worker: Mutex<Option<std::thread::JoinHandle<()>>>,
 }
 
 impl EmulatorRenderer {
-    pub(crate) fn new(device: Arc<DeviceContext>) -> Self {
-        let share = Arc::new(Share::new(device.clone()));
+    /// Creates a new renderer using the specified immediate buffer pool and worker thread
+    /// configuration. See [`ImmediatePoolConfig`] for the memory cost and [`WorkerConfig`] for the
+    /// threading model.
+    pub(crate) fn new(device: Arc<DeviceContext>, immediate_pool: &ImmediatePoolConfig, worker: &WorkerConfig) -> Self {
+        let share = Arc::new(Share::new(device.clone(), immediate_pool));
+
+        let mut builder = std::thread::Builder::new().name(worker.thread_name.clone());
+        if let Some(stack_size) = worker.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
 
         let share2 = share.clone();
-        let worker = std::thread::spawn(move || {
+        let worker = builder.spawn(move || {
             std::panic::catch_unwind(|| {
                 run_worker(device,share2);
             }).unwrap_or_else(|_| {
                 log::error!("Emulator worker panicked!");
                 std::process::exit(1);
             })
+        }).unwrap_or_else(|err| {
+            log::error!("Failed to spawn emulator worker thread {:?}", err);
+            panic!()
         });
 
         let placeholder_image = Self::create_placeholder_image(share.clone());
-        let placeholder_sampler = SamplerInfo {
-            mag_filter: vk::Filter::LINEAR,
-            min_filter: vk::Filter::LINEAR,
-            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
-            address_mode_u: vk::SamplerAddressMode::REPEAT,
-            address_mode_v: vk::SamplerAddressMode::REPEAT,
-            anisotropy_enable: false
-        };
 
         Self {
             share,
             placeholder_image,
-            placeholder_sampler,
-            worker,
+            worker: Mutex::new(Some(worker)),
+        }
+    }
+
+    /// Shuts down the renderer. Does nothing if the renderer has already been shut down.
+    ///
+    /// Teardown happens in a fixed order. First the renderer stops accepting new work. Then the
+    /// worker processes all remaining tasks and waits for all submitted gpu work to complete.
+    /// Finally all pooled resources of the worker are freed. This function blocks until all of
+    /// this has happened. Any work submitted after shutdown (for example by a [`PassRecorder`]
+    /// which is still alive) is discarded.
+    ///
+    /// Dropping the renderer calls this function if it has not been called yet.
+    pub fn shutdown(&self) {
+        let worker = self.worker.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned worker mutex in EmulatorRenderer::shutdown!");
+            panic!()
+        }).take();
+
+        if let Some(worker) = worker {
+            self.share.shutdown_channel();
+
+            // The renderer may be dropped by the worker itself if it owns the last reference
+            if worker.thread().id() != std::thread::current().id() {
+                if worker.join().is_err() {
+                    log::error!("Emulator worker panicked during shutdown!");
+                }
+            }
         }
     }
 
@@ -88,20 +125,171 @@ impl EmulatorRenderer {
         self.share.get_device()
     }
 
+    /// Returns true if uploads are performed on a separate transfer queue concurrently to
+    /// rendering.
+    ///
+    /// Only the initial write of a newly created global mesh is executed on the transfer queue.
+    /// Updates of existing meshes as well as all global image uploads, clears, mipmap generation
+    /// and read backs are always executed on the main queue.
+    pub fn uses_async_transfer(&self) -> bool {
+        worker::get_async_transfer_queue(self.share.get_device()).is_some()
+    }
+
+    /// Sets a hook which is invoked by the worker after every present. Passing [`None`] removes
+    /// the current hook.
+    ///
+    /// The hook is called on the worker thread and should return quickly.
+    pub fn set_present_hook(&self, hook: Option<Arc<PresentHook>>) {
+        self.share.set_present_hook(hook);
+    }
+
+    /// Sets a observer which is invoked with every task submitted to the worker. Passing [`None`]
+    /// removes the current observer. No overhead is added while no observer is set.
+    ///
+    /// The observer is called on the submitting thread in the order the worker receives the tasks.
+    /// It must return quickly and must not call into the renderer.
+    pub fn set_task_observer(&self, observer: Option<Box<TaskObserver>>) {
+        self.share.set_task_observer(observer);
+    }
+
+    /// Returns true if the device supports measuring the gpu execution time of passes.
+    pub fn supports_pass_timing(&self) -> bool {
+        self.share.supports_pass_timing()
+    }
+
+    /// Sets a hook which receives the gpu execution time of every pass started while the hook is
+    /// set. Passing [`None`] removes the current hook and disables timing. Aborted passes are not
+    /// timed.
+    ///
+    /// The hook is ignored if [`EmulatorRenderer::supports_pass_timing`] returns false. It is
+    /// called on the worker thread and should return quickly.
+    pub fn set_pass_timing_hook(&self, hook: Option<Arc<PassTimingHook>>) {
+        self.share.set_pass_timing_hook(hook);
+    }
+
+    /// Sets the maximum number of tasks which may be queued for the worker. Defaults to 16384.
+    ///
+    /// Once the limit is reached all functions submitting work to the worker (for example
+    /// [`PassRecorder::draw_immediate`]) block until the worker has processed enough tasks. This
+    /// bounds the memory used if work is recorded faster than the gpu can process it.
+    /// [`PassRecorder::try_draw_immediate`] can be used to avoid blocking.
+    pub fn set_task_channel_capacity(&self, capacity: usize) {
+        self.share.set_task_channel_capacity(capacity);
+    }
+
+    /// Pauses the worker thread. Any active pass is still submitted but no new pass is started
+    /// until [`EmulatorRenderer::resume`] is called. In flight passes complete and release their
+    /// frame slots as usual.
+    ///
+    /// Passes can still be started and recorded while paused. Their tasks are queued without
+    /// blocking and processed after the worker is resumed. However queued passes keep their frame
+    /// slot so if all frame slots are in use [`EmulatorRenderer::start_pass`] waits until the
+    /// worker is resumed from another thread and [`EmulatorRenderer::try_start_pass`] returns
+    /// [`None`]. [`GlobalImage::read_back`] returns [`ReadBackError::Paused`] while paused.
+    pub fn pause(&self) {
+        self.share.pause();
+    }
+
+    /// Resumes a worker paused by [`EmulatorRenderer::pause`].
+    pub fn resume(&self) {
+        self.share.resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.share.is_paused()
+    }
+
+    /// Sets the sampler used for the placeholder image which is bound to every texture slot of a
+    /// shader that has not been updated in a pass. Only affects passes started afterwards.
+    ///
+    /// Defaults to linear filtering with linear mipmap interpolation and no anisotropic filtering.
+    pub fn set_default_sampler(&self, sampler: SamplerInfo) {
+        self.share.set_default_sampler(sampler);
+    }
+
+    pub fn get_default_sampler(&self) -> SamplerInfo {
+        self.share.get_default_sampler()
+    }
+
+    /// Sets the maximum number of passes which may be in flight at the same time. Starting a new
+    /// pass blocks until the resources of an older pass have been recycled. Defaults to 2.
+    pub fn set_max_frames_in_flight(&self, max: u32) {
+        self.share.set_max_frames_in_flight(max);
+    }
+
+    /// Returns the number of started passes whose resources have not been recycled yet.
+    pub fn get_frames_in_flight(&self) -> u32 {
+        self.share.get_frames_in_flight()
+    }
+
     pub fn create_global_mesh(&self, data: &MeshData) -> Arc<GlobalMesh> {
         GlobalMesh::new(self.share.clone(), data).unwrap()
     }
 
+    /// Creates multiple global meshes stored in a single shared buffer. Consecutive draws of these
+    /// meshes do not need to rebind the vertex or index buffer. The buffer is freed once all
+    /// returned meshes have been dropped.
+    pub fn create_global_meshes_packed(&self, data: &[MeshData]) -> Vec<Arc<GlobalMesh>> {
+        GlobalMesh::new_packed(self.share.clone(), data).unwrap()
+    }
+
+    /// Creates a new global image.
+    ///
+    /// Depth only formats are supported and can be used as the target of a [`DepthCopyOutput`] to
+    /// sample the depth buffer of a pass in later passes. Their contents are undefined until written.
+    ///
+    /// [`DepthCopyOutput`]: pipeline::DepthCopyOutput
     pub fn create_global_image(&self, size: Vec2u32, format: &'static Format) -> Arc<GlobalImage> {
-        GlobalImage::new(self.share.clone(), size, 1, format).unwrap()
+        GlobalImage::new(self.share.clone(), ImageSize::make_2d(size[0], size[1]), false, false, format).unwrap()
     }
 
     pub fn create_global_image_mips(&self, size: Vec2u32, mip_levels: u32, format: &'static Format) -> Arc<GlobalImage> {
-        GlobalImage::new(self.share.clone(), size, mip_levels, format).unwrap()
+        GlobalImage::new(self.share.clone(), ImageSize::make_2d_mip(size[0], size[1], mip_levels), false, false, format).unwrap()
+    }
+
+    /// Creates a new 1D, 2D or 3D global image which may have multiple array layers. Images with
+    /// multiple array layers are sampled as array images.
+    pub fn create_global_image_with_size(&self, size: ImageSize, format: &'static Format) -> Result<Arc<GlobalImage>, GlobalObjectCreateError> {
+        GlobalImage::new(self.share.clone(), size, false, false, format)
+    }
+
+    /// Creates a new cube global image with 6 faces of `size` x `size` texels.
+    pub fn create_global_cube_image(&self, size: u32, mip_levels: u32, format: &'static Format) -> Result<Arc<GlobalImage>, GlobalObjectCreateError> {
+        GlobalImage::new(self.share.clone(), ImageSize::make_2d_array_mip(size, size, 6, mip_levels), true, false, format)
+    }
+
+    /// Creates a new global image which can additionally be bound as a storage image using
+    /// [`PassRecorder::update_storage_image`].
+    ///
+    /// Returns [`GlobalObjectCreateError::UnsupportedFormat`] if the format does not support
+    /// [`vk::FormatFeatureFlags::STORAGE_IMAGE`] or is not a color format.
+    pub fn create_global_storage_image(&self, size: ImageSize, format: &'static Format) -> Result<Arc<GlobalImage>, GlobalObjectCreateError> {
+        GlobalImage::new(self.share.clone(), size, false, true, format)
     }
 
     pub fn create_shader(&self, vertex_format: &VertexFormat, used_uniforms: McUniform) -> ShaderId {
-        self.share.create_shader(vertex_format, used_uniforms)
+        self.share.create_shader(vertex_format, used_uniforms, HashMap::new(), HashMap::new())
+    }
+
+    /// Creates a shader with a set of specialization constants mapping constant ids to values.
+    ///
+    /// The constants are applied by the pipeline to all stages when creating vulkan pipelines for the
+    /// shader. This allows a single shader module to be used for multiple variants. Pipelines may
+    /// reserve constant ids for internal use in which case their values take precedence. The
+    /// [`DebugPipeline`] reserves ids 0 and 16.
+    ///
+    /// [`DebugPipeline`]: debug_pipeline::DebugPipeline
+    pub fn create_specialized_shader(&self, vertex_format: &VertexFormat, used_uniforms: McUniform, specialization: HashMap<u32, SpecConstantValue>) -> ShaderId {
+        self.share.create_shader(vertex_format, used_uniforms, specialization, HashMap::new())
+    }
+
+    /// Creates a shader which additionally declares how it samples its texture bindings mapping
+    /// binding indices to types.
+    ///
+    /// In debug builds images bound through [`PassRecorder::update_texture`] are validated against
+    /// the declared types and a warning is logged on mismatch.
+    pub fn create_shader_with_textures(&self, vertex_format: &VertexFormat, used_uniforms: McUniform, specialization: HashMap<u32, SpecConstantValue>, texture_types: HashMap<u32, TextureBindingType>) -> ShaderId {
+        self.share.create_shader(vertex_format, used_uniforms, specialization, texture_types)
     }
 
     pub fn drop_shader(&self, id: ShaderId) {
@@ -112,8 +300,39 @@ impl EmulatorRenderer {
         self.share.get_shader(id)
     }
 
+    /// Starts a new pass. Blocks until a frame slot is available.
+    ///
+    /// If all frame slots are in use while the worker is paused this waits until the worker is
+    /// resumed from another thread (see [`EmulatorRenderer::pause`]).
     pub fn start_pass(&self, pipeline: Arc<dyn EmulatorPipeline>) -> PassRecorder {
-        PassRecorder::new(self.share.clone(), pipeline, self.placeholder_image.clone(), &self.placeholder_sampler)
+        PassRecorder::new(self.share.clone(), pipeline, self.placeholder_image.clone(), &self.share.get_default_sampler())
+    }
+
+    /// Starts a new pass which may execute draws recorded by [`PassRecorder::create_secondary`].
+    /// Returns [`None`] if the pipeline does not support secondary command buffers.
+    ///
+    /// Passes started by [`EmulatorRenderer::start_pass`] record their draws inline which avoids
+    /// the overhead of secondary command buffers. Blocks in the same way as
+    /// [`EmulatorRenderer::start_pass`].
+    pub fn start_pass_with_secondaries(&self, pipeline: Arc<dyn EmulatorPipeline>) -> Option<PassRecorder> {
+        PassRecorder::new_with_secondaries(self.share.clone(), pipeline, self.placeholder_image.clone(), &self.share.get_default_sampler())
+    }
+
+    /// Returns a future which resolves once the pass has completed execution on the gpu. If the
+    /// pass has already completed the future resolves immediately.
+    ///
+    /// The future does not depend on any specific async runtime. It is woken by the worker thread
+    /// once it observes the completion, so no thread is blocked while waiting. If the renderer is
+    /// shut down all pending futures resolve, even if their pass was discarded.
+    pub fn pass_completion(&self, pass: PassId) -> impl Future<Output = ()> + Send + 'static {
+        self.share.pass_completion(pass)
+    }
+
+    /// Non blocking version of [`EmulatorRenderer::start_pass`]. Returns [`None`] if the renderer
+    /// is starved, that is the maximum number of frames are in flight or no immediate buffer is
+    /// available, so that the caller can skip the frame instead of stalling.
+    pub fn try_start_pass(&self, pipeline: Arc<dyn EmulatorPipeline>) -> Option<PassRecorder> {
+        PassRecorder::try_new(self.share.clone(), pipeline, self.placeholder_image.clone(), &self.share.get_default_sampler())
     }
 
     fn create_placeholder_image(share: Arc<Share>) -> Arc<GlobalImage> {
@@ -134,11 +353,17 @@ impl EmulatorRenderer {
             data: bytes,
             row_stride: 0,
             offset: Vec2u32::new(0, 0),
-            extent: size
+            extent: size,
+            layer: 0,
+            mip_level: 0,
+            encoding: None,
         };
 
-        let image = GlobalImage::new(share, size, 1, &Format::R8G8B8A8_SRGB).unwrap();
-        image.update_regions(std::slice::from_ref(&info));
+        let image = GlobalImage::new(share, ImageSize::make_2d(size[0], size[1]), false, false, &Format::R8G8B8A8_SRGB).unwrap();
+        image.update_regions(std::slice::from_ref(&info)).unwrap_or_else(|err| {
+            log::error!("Failed to upload placeholder image data {:?}", err);
+            panic!()
+        });
         image
     }
 }
@@ -152,6 +377,12 @@ impl PartialEq for EmulatorRenderer {
 impl Eq for EmulatorRenderer {
 }
 
+impl Drop for EmulatorRenderer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
 impl RefUnwindSafe for EmulatorRenderer { // Join handle is making issues
 }
 
@@ -164,6 +395,24 @@ pub struct MeshData<'a> {
     pub primitive_topology: vk::PrimitiveTopology,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MeshDataError {
+    /// The vertex stride is 0 or the size of the vertex data is not a multiple of the stride.
+    InvalidVertexStride,
+
+    /// The index type is not one of UINT8, UINT16 or UINT32.
+    UnsupportedIndexType,
+
+    /// The size of the index data is not a multiple of the index size.
+    InvalidIndexDataSize,
+
+    /// The index count does not match the number of indices in the index data.
+    IndexCountMismatch,
+
+    /// The index count cannot form a whole number of primitives of the primitive topology.
+    IncompatibleTopology,
+}
+
 impl<'a> MeshData<'a> {
     pub fn get_index_size(&self) -> u32 {
         match self.index_type {
@@ -176,6 +425,41 @@ impl<'a> MeshData<'a> {
             }
         }
     }
+
+    /// Validates that the sizes, counts and topology of the mesh are consistent with each other.
+    pub fn validate(&self) -> Result<(), MeshDataError> {
+        if self.vertex_stride == 0 || self.vertex_data.len() % (self.vertex_stride as usize) != 0 {
+            return Err(MeshDataError::InvalidVertexStride);
+        }
+
+        let index_size = match self.index_type {
+            vk::IndexType::UINT8_EXT => 1usize,
+            vk::IndexType::UINT16 => 2usize,
+            vk::IndexType::UINT32 => 4usize,
+            _ => return Err(MeshDataError::UnsupportedIndexType),
+        };
+        if self.index_data.len() % index_size != 0 {
+            return Err(MeshDataError::InvalidIndexDataSize);
+        }
+        if self.index_data.len() / index_size != self.index_count as usize {
+            return Err(MeshDataError::IndexCountMismatch);
+        }
+
+        let count = self.index_count;
+        let compatible = match self.primitive_topology {
+            vk::PrimitiveTopology::LINE_LIST => count % 2 == 0,
+            vk::PrimitiveTopology::TRIANGLE_LIST => count % 3 == 0,
+            vk::PrimitiveTopology::LINE_STRIP => count == 0 || count >= 2,
+            vk::PrimitiveTopology::TRIANGLE_STRIP |
+            vk::PrimitiveTopology::TRIANGLE_FAN => count == 0 || count >= 3,
+            _ => true,
+        };
+        if !compatible {
+            return Err(MeshDataError::IncompatibleTopology);
+        }
+
+        Ok(())
+    }
 }
 
 impl<'a> Debug for MeshData<'a> {
@@ -190,3 +474,33 @@ impl<'a> Debug for MeshData<'a> {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_mesh<'a>(vertex_data: &'a [u8], index_data: &'a [u8], index_count: u32, primitive_topology: vk::PrimitiveTopology) -> MeshData<'a> {
+        MeshData {
+            vertex_data,
+            index_data,
+            vertex_stride: 8,
+            index_count,
+            index_type: vk::IndexType::UINT16,
+            primitive_topology,
+        }
+    }
+
+    #[test]
+    fn mesh_data_validation() {
+        let vertices = [0u8; 32];
+        let indices = [0u8; 12];
+
+        assert_eq!(make_mesh(&vertices, &indices, 6, vk::PrimitiveTopology::TRIANGLE_LIST).validate(), Ok(()));
+        assert_eq!(make_mesh(&vertices[0..30], &indices, 6, vk::PrimitiveTopology::TRIANGLE_LIST).validate(), Err(MeshDataError::InvalidVertexStride));
+        assert_eq!(make_mesh(&vertices, &indices[0..11], 6, vk::PrimitiveTopology::TRIANGLE_LIST).validate(), Err(MeshDataError::InvalidIndexDataSize));
+        assert_eq!(make_mesh(&vertices, &indices, 5, vk::PrimitiveTopology::TRIANGLE_LIST).validate(), Err(MeshDataError::IndexCountMismatch));
+        assert_eq!(make_mesh(&vertices, &indices[0..8], 4, vk::PrimitiveTopology::TRIANGLE_LIST).validate(), Err(MeshDataError::IncompatibleTopology));
+        assert_eq!(make_mesh(&vertices, &indices[0..8], 4, vk::PrimitiveTopology::TRIANGLE_STRIP).validate(), Ok(()));
+        assert_eq!(make_mesh(&vertices, &indices[0..10], 5, vk::PrimitiveTopology::POINT_LIST).validate(), Ok(()));
+    }
+}
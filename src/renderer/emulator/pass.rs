@@ -1,4 +1,5 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
 use std::sync::Arc;
 
 use ash::vk;
@@ -6,11 +7,13 @@ use ash::vk;
 use crate::renderer::emulator::immediate::ImmediateBuffer;
 use crate::renderer::emulator::{GlobalImage, GlobalMesh, MeshData};
 use crate::renderer::emulator::global_objects::{GlobalImageId, SamplerInfo};
-use crate::renderer::emulator::worker::WorkerTask;
+use crate::renderer::emulator::worker::{SecondaryCommands, WorkerTask};
 
 use crate::renderer::emulator::mc_shaders::{McUniformData, ShaderId};
-use crate::renderer::emulator::pipeline::{DrawTask, EmulatorOutput, EmulatorPipeline, PipelineTask};
-use crate::renderer::emulator::share::Share;
+use crate::renderer::emulator::pipeline::{DrawIndirectTask, DrawTask, EmulatorOutput, EmulatorPipeline, EmulatorPipelinePass, EmulatorPipelineSecondary, PipelineTask};
+use crate::renderer::emulator::share::{Share, TaskChannelFull};
+use crate::util::format::{ClearColorType, Format};
+use crate::util::vk::DEFAULT_LABEL_COLOR;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 pub struct PassId(u64);
@@ -43,42 +46,167 @@ pub struct PassRecorder {
     share: Arc<Share>,
 
     used_shaders: HashSet<ShaderId>,
+    /// Vertex strides of the shaders used by this pass. Avoids locking the shader database for
+    /// every draw.
+    shader_vertex_strides: HashMap<ShaderId, Option<u32>>,
     used_global_image: HashSet<GlobalImageId>,
+    used_storage_images: HashSet<GlobalImageId>,
+    /// Textures bound by executed secondary recorders. These cannot be used as storage images.
+    secondary_textures: HashSet<GlobalImageId>,
+    used_indirect_buffers: HashSet<vk::Buffer>,
+    dynamic_uniform_shaders: HashSet<ShaderId>,
     immediate_meshes: Vec<ImmediateMeshInfo>,
 
     immediate_buffer: Option<Box<ImmediateBuffer>>,
 
-    #[allow(unused)] // We just need to keep the pipeline alive
+    placeholder_view: vk::ImageView,
+    placeholder_sampler: vk::Sampler,
+
+    log_draws: bool,
+    last_draw: Option<DrawTask>,
+    conservative_rasterization: bool,
+    has_label: bool,
+    /// True if the pass was started using [`EmulatorPipeline::start_pass_with_secondaries`].
+    uses_secondaries: bool,
+
     pipeline: Arc<dyn EmulatorPipeline>,
 }
 
 impl PassRecorder {
     pub(super) fn new(share: Arc<Share>, pipeline: Arc<dyn EmulatorPipeline>, placeholder_image: Arc<GlobalImage>, placeholder_sampler: &SamplerInfo) -> Self {
+        // Released by the worker once the pass has completed execution
+        share.acquire_frame_slot();
+
+        let immediate_buffer = share.get_next_immediate_buffer();
+        let pipeline_pass = pipeline.start_pass();
+
+        Self::start(share, pipeline, pipeline_pass, false, immediate_buffer, placeholder_image, placeholder_sampler)
+    }
+
+    /// Same as [`PassRecorder::new`] but starts the pass using
+    /// [`EmulatorPipeline::start_pass_with_secondaries`]. Returns [`None`] if the pipeline does
+    /// not support secondary command buffers.
+    pub(super) fn new_with_secondaries(share: Arc<Share>, pipeline: Arc<dyn EmulatorPipeline>, placeholder_image: Arc<GlobalImage>, placeholder_sampler: &SamplerInfo) -> Option<Self> {
+        share.acquire_frame_slot();
+
+        let pipeline_pass = match pipeline.start_pass_with_secondaries() {
+            Some(pass) => pass,
+            None => {
+                share.release_frame_slot();
+                return None;
+            }
+        };
+        let immediate_buffer = share.get_next_immediate_buffer();
+
+        Some(Self::start(share, pipeline, pipeline_pass, true, immediate_buffer, placeholder_image, placeholder_sampler))
+    }
+
+    /// Non blocking version of [`PassRecorder::new`]. Returns [`None`] if the maximum number of
+    /// frames are in flight or no immediate buffer is available.
+    pub(super) fn try_new(share: Arc<Share>, pipeline: Arc<dyn EmulatorPipeline>, placeholder_image: Arc<GlobalImage>, placeholder_sampler: &SamplerInfo) -> Option<Self> {
+        if !share.try_acquire_frame_slot() {
+            return None;
+        }
+
+        let immediate_buffer = match share.try_get_next_immediate_buffer() {
+            Some(buffer) => buffer,
+            None => {
+                share.release_frame_slot();
+                return None;
+            }
+        };
+
+        let pipeline_pass = pipeline.start_pass();
+
+        Some(Self::start(share, pipeline, pipeline_pass, false, immediate_buffer, placeholder_image, placeholder_sampler))
+    }
+
+    fn start(share: Arc<Share>, pipeline: Arc<dyn EmulatorPipeline>, pipeline_pass: Box<dyn EmulatorPipelinePass + Send>, uses_secondaries: bool, immediate_buffer: Box<ImmediateBuffer>, placeholder_image: Arc<GlobalImage>, placeholder_sampler: &SamplerInfo) -> Self {
         let id = share.try_start_pass_id().unwrap_or_else(|| {
             log::error!("Attempted to start pass with an already running pass!");
             panic!();
         });
         let id = PassId::from_raw(id);
 
-        let immediate_buffer = Some(share.get_next_immediate_buffer());
-
+        let placeholder_view = placeholder_image.get_sampler_view();
         let placeholder_sampler = placeholder_image.get_sampler(placeholder_sampler);
-        share.push_task(WorkerTask::StartPass(id, pipeline.clone(), pipeline.start_pass(), placeholder_image, placeholder_sampler));
+        share.push_task(WorkerTask::StartPass(id, pipeline.clone(), pipeline_pass, placeholder_image, placeholder_sampler));
 
         Self {
             id,
             share,
 
             used_shaders: HashSet::new(),
+            shader_vertex_strides: HashMap::new(),
             used_global_image: HashSet::new(),
+            used_storage_images: HashSet::new(),
+            secondary_textures: HashSet::new(),
+            used_indirect_buffers: HashSet::new(),
+            dynamic_uniform_shaders: HashSet::new(),
             immediate_meshes: Vec::with_capacity(128),
 
-            immediate_buffer,
+            immediate_buffer: Some(immediate_buffer),
+
+            placeholder_view,
+            placeholder_sampler,
+
+            log_draws: false,
+            last_draw: None,
+            conservative_rasterization: false,
+            has_label: false,
+            uses_secondaries,
 
             pipeline,
         }
     }
 
+    /// If enabled the parameters of every draw made through [`PassRecorder::draw_immediate`],
+    /// [`PassRecorder::try_draw_immediate`] and [`PassRecorder::draw_global`] are logged at debug
+    /// level. Disabled by default.
+    pub fn set_draw_logging(&mut self, enabled: bool) {
+        self.log_draws = enabled;
+    }
+
+    /// Returns the parameters of the last draw made through [`PassRecorder::draw_immediate`],
+    /// [`PassRecorder::try_draw_immediate`] or [`PassRecorder::draw_global`].
+    pub fn last_draw(&self) -> Option<&DrawTask> {
+        self.last_draw.as_ref()
+    }
+
+    /// Enables or disables conservative rasterization for all following draws made through
+    /// [`PassRecorder::draw_immediate`], [`PassRecorder::try_draw_immediate`] and
+    /// [`PassRecorder::draw_global`]. Disabled by default.
+    ///
+    /// If the device does not support VK_EXT_conservative_rasterization a warning is logged and
+    /// draws use regular rasterization.
+    pub fn set_conservative_rasterization(&mut self, enabled: bool) {
+        self.conservative_rasterization = check_conservative_rasterization(&self.share, enabled);
+    }
+
+    /// Labels all commands of this pass in capture tools such as RenderDoc. The color is used to
+    /// tint the label region and defaults to a neutral gray ([`DEFAULT_LABEL_COLOR`]) if [`None`].
+    /// Using different colors for different categories of passes (UI, world, shadows) makes them
+    /// easy to tell apart.
+    ///
+    /// Can only be called once per pass. Does nothing if `VK_EXT_debug_utils` is not enabled.
+    pub fn set_label(&mut self, name: &str, color: Option<[f32; 4]>) {
+        if self.has_label {
+            log::error!("Called PassRecorder::set_label on a pass which already has a label");
+            panic!()
+        }
+
+        let name = match CString::new(name) {
+            Ok(name) => name,
+            Err(_) => {
+                log::warn!("Pass label {:?} contains a nul byte in PassRecorder::set_label", name);
+                return;
+            }
+        };
+
+        self.has_label = true;
+        self.share.push_task(WorkerTask::SetPassLabel(name, color.unwrap_or(DEFAULT_LABEL_COLOR)));
+    }
+
     pub fn use_output(&mut self, output: Box<dyn EmulatorOutput + Send>) {
         self.share.push_task(WorkerTask::UseOutput(output));
     }
@@ -88,7 +216,16 @@ impl PassRecorder {
         self.share.push_task(WorkerTask::PipelineTask(PipelineTask::UpdateUniform(shader, *data)))
     }
 
+    /// Binds a global image to a texture slot of a shader.
+    ///
+    /// The pipeline must support the view type of the image (see [`GlobalImage::get_view_type`]).
+    /// The [`DebugPipeline`] only supports 2D images.
+    ///
+    /// [`DebugPipeline`]: crate::renderer::emulator::debug_pipeline::DebugPipeline
     pub fn update_texture(&mut self, index: u32, image: &Arc<GlobalImage>, sampler_info: &SamplerInfo, shader: ShaderId) {
+        check_texture_type(&self.share, shader, index, image);
+        check_not_storage(&self.used_storage_images, image);
+
         self.use_shader(shader);
         let view = image.get_sampler_view();
         let sampler = image.get_sampler(sampler_info);
@@ -100,7 +237,102 @@ impl PassRecorder {
         self.share.push_task(WorkerTask::PipelineTask(PipelineTask::UpdateTexture(shader, index, view, sampler)));
     }
 
+    /// Binds multiple global images to texture slots of a shader using a single task.
+    ///
+    /// Each entry contains the texture index, image and sampler info in the same way as
+    /// [`PassRecorder::update_texture`].
+    pub fn update_textures(&mut self, shader: ShaderId, bindings: &[(u32, &Arc<GlobalImage>, &SamplerInfo)]) {
+        if bindings.is_empty() {
+            return;
+        }
+
+        self.use_shader(shader);
+
+        let mut textures = Vec::with_capacity(bindings.len());
+        for (index, image, sampler_info) in bindings {
+            check_texture_type(&self.share, shader, *index, image);
+            check_not_storage(&self.used_storage_images, image);
+
+            if self.used_global_image.insert(image.get_id()) {
+                self.share.push_task(WorkerTask::UseGlobalImage((*image).clone()));
+            }
+
+            textures.push((*index, image.get_sampler_view(), image.get_sampler(sampler_info)));
+        }
+
+        self.share.push_task(WorkerTask::PipelineTask(PipelineTask::UpdateTextures(shader, textures.into_boxed_slice())));
+    }
+
+    /// Binds a global image to a storage image slot of a shader allowing unfiltered reads and
+    /// writes.
+    ///
+    /// The image must have been created using [`EmulatorRenderer::create_global_storage_image`].
+    /// It is kept in [`vk::ImageLayout::GENERAL`] for the whole pass and therefore cannot be bound
+    /// as a texture in the same pass. Shader writes are visible to all following passes and global
+    /// object operations.
+    ///
+    /// The [`DebugPipeline`] binds storage images at set 0 binding 2 and supports 2 storage images
+    /// per shader.
+    ///
+    /// [`DebugPipeline`]: crate::renderer::emulator::debug_pipeline::DebugPipeline
+    /// [`EmulatorRenderer::create_global_storage_image`]: crate::renderer::emulator::EmulatorRenderer::create_global_storage_image
+    pub fn update_storage_image(&mut self, index: u32, image: &Arc<GlobalImage>, shader: ShaderId) {
+        if !image.is_storage_image() {
+            log::error!("Global image {:?} with format {:?} is not a storage image", image.get_id(), image.get_format());
+            panic!()
+        }
+        if (self.used_global_image.contains(&image.get_id()) && !self.used_storage_images.contains(&image.get_id())) || self.secondary_textures.contains(&image.get_id()) {
+            log::error!("Global image {:?} is bound as a texture and cannot be used as a storage image in the same pass", image.get_id());
+            panic!()
+        }
+
+        self.use_shader(shader);
+
+        if self.used_storage_images.insert(image.get_id()) {
+            self.used_global_image.insert(image.get_id());
+            self.share.push_task(WorkerTask::UseStorageImage(image.clone()));
+        }
+
+        self.share.push_task(WorkerTask::PipelineTask(PipelineTask::UpdateStorageImage(shader, index, image.get_sampler_view())));
+    }
+
+    /// Binds a range of a uniform buffer as the dynamic uniform buffer of a shader for all
+    /// following draws of this pass. Shaders access the range at set 1 binding 0.
+    ///
+    /// The start of the range is selected per draw by the offset passed to
+    /// [`PassRecorder::draw_immediate_with_uniform_offset`] or
+    /// [`PassRecorder::draw_global_with_uniform_offset`]. All other draws use an offset of 0.
+    /// This allows per object data to be stored in a single large buffer instead of updating
+    /// uniforms before every draw.
+    ///
+    /// # Safety
+    /// The buffer must have been created with [`vk::BufferUsageFlags::UNIFORM_BUFFER`], must be
+    /// owned by the main queue family and must not be destroyed or modified until the pass has
+    /// completed execution. The range must lie within the buffer at every offset used by a draw.
+    pub unsafe fn set_dynamic_uniform_buffer(&mut self, shader: ShaderId, buffer: vk::Buffer, range: vk::DeviceSize) {
+        check_dynamic_uniform_range(&self.share, range);
+
+        self.use_shader(shader);
+        self.dynamic_uniform_shaders.insert(shader);
+        self.share.push_task(WorkerTask::PipelineTask(PipelineTask::SetDynamicUniformBuffer(shader, buffer, range)));
+    }
+
+    /// Uploads a mesh which can be drawn during this pass.
+    ///
+    /// The vertex stride of the mesh must match the stride of the vertex format of every shader the
+    /// mesh is drawn with.
     pub fn upload_immediate(&mut self, data: &MeshData) -> ImmediateMeshId {
+        if cfg!(debug_assertions) {
+            if let Err(err) = data.validate() {
+                log::error!("Invalid immediate mesh data {:?}: {:?}", data, err);
+                panic!()
+            }
+        }
+        if data.vertex_stride == 0 || data.vertex_data.len() % (data.vertex_stride as usize) != 0 {
+            log::error!("Immediate mesh vertex data size {:?} is not a multiple of the vertex stride {:?}", data.vertex_data.len(), data.vertex_stride);
+            panic!()
+        }
+
         let index_size = data.get_index_size();
 
         let immediate = self.immediate_buffer.as_mut().unwrap();
@@ -115,6 +347,7 @@ impl PassRecorder {
             first_index: (index_offset / (index_size as vk::DeviceSize)) as u32,
             index_type: data.index_type,
             index_count: data.index_count,
+            vertex_stride: data.vertex_stride,
             primitive_topology: data.primitive_topology
         });
 
@@ -122,11 +355,39 @@ impl PassRecorder {
     }
 
     pub fn draw_immediate(&mut self, id: ImmediateMeshId, shader: ShaderId, depth_write_enable: bool) {
+        self.draw_immediate_with_uniform_offset(id, shader, depth_write_enable, 0);
+    }
+
+    /// Same as [`PassRecorder::draw_immediate`] but reads the dynamic uniform buffer of the shader
+    /// at the specified offset. See [`PassRecorder::set_dynamic_uniform_buffer`].
+    ///
+    /// The offset must be a multiple of `minUniformBufferOffsetAlignment`.
+    pub fn draw_immediate_with_uniform_offset(&mut self, id: ImmediateMeshId, shader: ShaderId, depth_write_enable: bool, dynamic_uniform_offset: u32) {
+        check_dynamic_uniform_offset(&self.share, &self.dynamic_uniform_shaders, shader, dynamic_uniform_offset);
+
+        let draw_task = self.make_immediate_draw_task(id, shader, depth_write_enable, dynamic_uniform_offset);
         self.use_shader(shader);
+        self.on_draw(&draw_task);
+        self.share.push_task(WorkerTask::PipelineTask(PipelineTask::Draw(draw_task)));
+    }
+
+    /// Same as [`PassRecorder::draw_immediate`] but does not block if the task channel of the
+    /// worker is full. In that case the draw is dropped and [`TaskChannelFull`] is returned.
+    pub fn try_draw_immediate(&mut self, id: ImmediateMeshId, shader: ShaderId, depth_write_enable: bool) -> Result<(), TaskChannelFull> {
+        let draw_task = self.make_immediate_draw_task(id, shader, depth_write_enable, 0);
+        self.try_use_shader(shader)?;
+        self.share.try_push_task(WorkerTask::PipelineTask(PipelineTask::Draw(draw_task)))?;
+        self.on_draw(&draw_task);
+        Ok(())
+    }
+
+    fn make_immediate_draw_task(&mut self, id: ImmediateMeshId, shader: ShaderId, depth_write_enable: bool, dynamic_uniform_offset: u32) -> DrawTask {
+        let vertex_stride = self.immediate_meshes.get(id.get_raw() as usize).unwrap().vertex_stride;
+        self.check_vertex_stride(shader, vertex_stride);
 
         let mesh_data = self.immediate_meshes.get(id.get_raw() as usize).unwrap();
 
-        let draw_task = DrawTask {
+        DrawTask {
             vertex_buffer: mesh_data.vertex_buffer,
             index_buffer: mesh_data.index_buffer,
             vertex_offset: mesh_data.vertex_offset,
@@ -136,48 +397,563 @@ impl PassRecorder {
             shader,
             primitive_topology: mesh_data.primitive_topology,
             depth_write_enable,
-        };
-        self.share.push_task(WorkerTask::PipelineTask(PipelineTask::Draw(draw_task)));
+            conservative: self.conservative_rasterization,
+            dynamic_uniform_offset,
+        }
     }
 
     pub fn draw_global(&mut self, mesh: Arc<GlobalMesh>, shader: ShaderId, depth_write_enable: bool) {
-        mesh.update_used_in(self.id);
+        self.draw_global_with_uniform_offset(mesh, shader, depth_write_enable, 0);
+    }
 
-        self.use_shader(shader);
+    /// Same as [`PassRecorder::draw_global`] but reads the dynamic uniform buffer of the shader at
+    /// the specified offset. See [`PassRecorder::set_dynamic_uniform_buffer`].
+    ///
+    /// The offset must be a multiple of `minUniformBufferOffsetAlignment`.
+    pub fn draw_global_with_uniform_offset(&mut self, mesh: Arc<GlobalMesh>, shader: ShaderId, depth_write_enable: bool, dynamic_uniform_offset: u32) {
+        check_dynamic_uniform_offset(&self.share, &self.dynamic_uniform_shaders, shader, dynamic_uniform_offset);
 
         let draw_info = mesh.get_draw_info();
+        self.check_vertex_stride(shader, draw_info.vertex_stride);
+
+        mesh.update_used_in(self.id);
+
+        self.use_shader(shader);
 
         let draw_task = DrawTask {
             vertex_buffer: draw_info.buffer,
             index_buffer: draw_info.buffer,
-            vertex_offset: 0,
+            vertex_offset: draw_info.vertex_offset,
             first_index: draw_info.first_index,
             index_type: draw_info.index_type,
             index_count: draw_info.index_count,
             shader,
             primitive_topology: draw_info.primitive_topology,
             depth_write_enable,
+            conservative: self.conservative_rasterization,
+            dynamic_uniform_offset,
         };
+        self.on_draw(&draw_task);
 
         self.share.push_task(WorkerTask::UseGlobalMesh(mesh));
         self.share.push_task(WorkerTask::PipelineTask(PipelineTask::Draw(draw_task)));
     }
 
+    /// Draws a global mesh using draw parameters read from a buffer of
+    /// [`vk::DrawIndexedIndirectCommand`]s. The index and vertex offsets of the commands are
+    /// relative to the start of the mesh buffer. For meshes created by
+    /// [`EmulatorRenderer::create_global_meshes_packed`] this is the buffer shared by all packed
+    /// meshes.
+    ///
+    /// [`EmulatorRenderer::create_global_meshes_packed`]: crate::renderer::emulator::EmulatorRenderer::create_global_meshes_packed
+    ///
+    /// Writes made to the buffer by compute shaders submitted before the pass are made visible
+    /// to the draw.
+    ///
+    /// # Safety
+    /// The buffer must have been created with [`vk::BufferUsageFlags::INDIRECT_BUFFER`], must be
+    /// owned by the main queue family and must not be destroyed or modified until the pass has
+    /// completed execution.
+    pub unsafe fn draw_indirect(&mut self, mesh: Arc<GlobalMesh>, buffer: vk::Buffer, offset: vk::DeviceSize, draw_count: u32, stride: u32, shader: ShaderId, depth_write_enable: bool) {
+        let device = self.share.get_device();
+        if offset % 4 != 0 {
+            log::error!("Indirect draw offset {:?} is not a multiple of 4", offset);
+            panic!()
+        }
+        if draw_count > 1 {
+            if device.get_features().multi_draw_indirect != vk::TRUE {
+                log::error!("Indirect draw with draw count {:?} requires the multiDrawIndirect feature", draw_count);
+                panic!()
+            }
+            if stride % 4 != 0 || (stride as usize) < std::mem::size_of::<vk::DrawIndexedIndirectCommand>() {
+                log::error!("Invalid indirect draw stride {:?}", stride);
+                panic!()
+            }
+        }
+        if draw_count > device.get_limits().max_draw_indirect_count {
+            log::error!("Indirect draw count {:?} exceeds max_draw_indirect_count {:?}", draw_count, device.get_limits().max_draw_indirect_count);
+            panic!()
+        }
+
+        let draw_info = mesh.get_draw_info();
+        self.check_vertex_stride(shader, draw_info.vertex_stride);
+
+        mesh.update_used_in(self.id);
+
+        self.use_shader(shader);
+
+        if self.used_indirect_buffers.insert(buffer) {
+            self.share.push_task(WorkerTask::UseIndirectBuffer(buffer));
+        }
+
+        let draw_task = DrawIndirectTask {
+            vertex_buffer: draw_info.buffer,
+            index_buffer: draw_info.buffer,
+            index_type: draw_info.index_type,
+            draw_buffer: buffer,
+            draw_offset: offset,
+            draw_count,
+            stride,
+            shader,
+            primitive_topology: draw_info.primitive_topology,
+            depth_write_enable,
+            conservative: self.conservative_rasterization,
+        };
+
+        self.share.push_task(WorkerTask::UseGlobalMesh(mesh));
+        self.share.push_task(WorkerTask::PipelineTask(PipelineTask::DrawIndirect(draw_task)));
+    }
+
+    /// Clears regions of the attachments draws of this pass render into. Later draws are recorded
+    /// on top of the cleared regions (for example clearing depth before drawing a viewmodel over
+    /// the world).
+    ///
+    /// Each clear must target an attachment which exists in the pipeline of the pass and color
+    /// clear values must match the numeric type of the attachment format. The rects must have a
+    /// non zero extent, lie within the output size of the pipeline and cover exactly layer 0.
+    ///
+    /// Depth clears must use the clear value of the pipeline. For a [`DebugPipeline`] using a
+    /// reversed depth range this is [`DebugPipeline::get_depth_clear_value`] and not `1.0`.
+    ///
+    /// [`DebugPipeline`]: super::debug_pipeline::DebugPipeline
+    /// [`DebugPipeline::get_depth_clear_value`]: super::debug_pipeline::DebugPipeline::get_depth_clear_value
+    pub fn clear_region(&mut self, clears: &[ClearAttachment], rects: &[vk::ClearRect]) {
+        if clears.is_empty() || rects.is_empty() {
+            return;
+        }
+        check_clear_attachments(self.pipeline.as_ref(), clears, rects);
+
+        let attachments = clears.iter().map(ClearAttachment::to_vk).collect();
+        self.share.push_task(WorkerTask::PipelineTask(PipelineTask::ClearAttachments(attachments, rects.into())));
+    }
+
+    /// Creates a recorder which records draws of this pass into a secondary command buffer.
+    /// Returns [`None`] if the pass was not started using
+    /// [`EmulatorRenderer::start_pass_with_secondaries`] or the pipeline of the pass does not
+    /// support secondary command buffers.
+    ///
+    /// See [`SecondaryRecorder`] for details.
+    ///
+    /// [`EmulatorRenderer::start_pass_with_secondaries`]: crate::renderer::emulator::EmulatorRenderer::start_pass_with_secondaries
+    pub fn create_secondary(&self) -> Option<SecondaryRecorder> {
+        if !self.uses_secondaries {
+            return None;
+        }
+        let recorder = self.pipeline.create_secondary(self.placeholder_view, self.placeholder_sampler)?;
+
+        Some(SecondaryRecorder {
+            id: self.id,
+            share: self.share.clone(),
+            pipeline: self.pipeline.clone(),
+            recorder: Some(recorder),
+            conservative_rasterization: false,
+
+            used_shaders: HashSet::new(),
+            shader_vertex_strides: HashMap::new(),
+            used_global_image: HashSet::new(),
+            pass_storage_images: self.used_storage_images.clone(),
+            dynamic_uniform_shaders: HashSet::new(),
+            global_meshes: Vec::new(),
+            global_images: Vec::new(),
+        })
+    }
+
+    /// Executes all draws recorded by a secondary recorder at the current position of the pass.
+    ///
+    /// The recorder must have been created by this pass.
+    pub fn execute_secondary(&mut self, mut secondary: SecondaryRecorder) {
+        if secondary.id != self.id {
+            log::error!("Attempted to execute secondary recorder of pass {:?} in pass {:?}", secondary.id, self.id);
+            panic!()
+        }
+
+        // Storage images may have been bound by the pass after the recorder was created
+        for image in &secondary.global_images {
+            check_not_storage(&self.used_storage_images, image);
+        }
+        self.secondary_textures.extend(secondary.used_global_image.iter().copied());
+
+        let mut recorder = secondary.recorder.take().unwrap();
+        let command_buffer = recorder.finish();
+
+        // The shaders are released by the worker once the pass has completed execution
+        let shaders = std::mem::take(&mut secondary.used_shaders).into_iter().collect();
+
+        self.share.push_task(WorkerTask::ExecuteSecondary(SecondaryCommands {
+            recorder,
+            command_buffer,
+            shaders,
+            global_meshes: std::mem::take(&mut secondary.global_meshes),
+            global_images: std::mem::take(&mut secondary.global_images),
+        }));
+    }
+
+    /// Aborts the pass discarding all recorded draws.
+    ///
+    /// Pending global object updates are still executed. Outputs used by this pass will not receive
+    /// the output of the pass. Swapchain outputs present a cleared image.
+    pub fn abort(mut self) {
+        // Taking the buffer prevents drop from ending the pass normally
+        let immediate_buffer = self.immediate_buffer.take().unwrap();
+        self.share.push_task(WorkerTask::AbortPass(immediate_buffer));
+        self.share.end_pass_id();
+    }
+
+    fn on_draw(&mut self, task: &DrawTask) {
+        if self.log_draws {
+            log::debug!("Pass {:?} draw: {:?}", self.id, task);
+        }
+        self.last_draw = Some(*task);
+    }
+
+    fn check_vertex_stride(&mut self, shader: ShaderId, vertex_stride: u32) {
+        check_vertex_stride(&self.share, &mut self.shader_vertex_strides, shader, vertex_stride);
+    }
+
     fn use_shader(&mut self, shader: ShaderId) {
         if self.used_shaders.insert(shader) {
             self.pipeline.inc_shader_used(shader);
             self.share.push_task(WorkerTask::UseShader(shader));
         }
     }
+
+    /// Same as [`PassRecorder::use_shader`] but does not block if the task channel is full. The
+    /// shader is only marked as used if the task could be pushed.
+    fn try_use_shader(&mut self, shader: ShaderId) -> Result<(), TaskChannelFull> {
+        if !self.used_shaders.contains(&shader) {
+            self.pipeline.inc_shader_used(shader);
+            if let Err(err) = self.share.try_push_task(WorkerTask::UseShader(shader)) {
+                self.pipeline.dec_shader_used(shader);
+                return Err(err);
+            }
+            self.used_shaders.insert(shader);
+        }
+        Ok(())
+    }
 }
 
 impl Drop for PassRecorder {
     fn drop(&mut self) {
-        self.share.push_task(WorkerTask::EndPass(self.immediate_buffer.take().unwrap()));
-        self.share.end_pass_id();
+        if let Some(immediate_buffer) = self.immediate_buffer.take() {
+            self.share.push_task(WorkerTask::EndPass(immediate_buffer));
+            self.share.end_pass_id();
+        }
     }
 }
 
+/// Records draws of a pass into a secondary command buffer.
+///
+/// Created by [`PassRecorder::create_secondary`]. The recorder can be moved to a different thread
+/// and multiple recorders of the same pass can record in parallel. The recorded draws are executed
+/// at the position in the pass at which the recorder is passed to
+/// [`PassRecorder::execute_secondary`]. If the recorder is dropped instead all recorded draws are
+/// discarded.
+///
+/// Secondary command buffers do not inherit any state of the pass. Uniforms and textures updated
+/// through the [`PassRecorder`] are not visible to the recorder and updates made through the
+/// recorder are not visible to the pass. Immediate meshes cannot be drawn by a secondary recorder
+/// since they are uploaded into the immediate buffer of the pass.
+///
+/// Storage images of the pass cannot be bound as textures. Images bound as storage images after
+/// the recorder has been created are validated once the recorder is executed.
+pub struct SecondaryRecorder {
+    id: PassId,
+    share: Arc<Share>,
+    pipeline: Arc<dyn EmulatorPipeline>,
+    recorder: Option<Box<dyn EmulatorPipelineSecondary + Send>>,
+    conservative_rasterization: bool,
+
+    used_shaders: HashSet<ShaderId>,
+    shader_vertex_strides: HashMap<ShaderId, Option<u32>>,
+    used_global_image: HashSet<GlobalImageId>,
+    /// The storage images used by the pass when this recorder was created.
+    pass_storage_images: HashSet<GlobalImageId>,
+    dynamic_uniform_shaders: HashSet<ShaderId>,
+    global_meshes: Vec<Arc<GlobalMesh>>,
+    global_images: Vec<Arc<GlobalImage>>,
+}
+
+impl SecondaryRecorder {
+    pub fn get_pass_id(&self) -> PassId {
+        self.id
+    }
+
+    /// Same as [`PassRecorder::set_conservative_rasterization`].
+    pub fn set_conservative_rasterization(&mut self, enabled: bool) {
+        self.conservative_rasterization = check_conservative_rasterization(&self.share, enabled);
+    }
+
+    pub fn update_uniform(&mut self, data: &McUniformData, shader: ShaderId) {
+        self.use_shader(shader);
+        self.recorder.as_mut().unwrap().process_task(&PipelineTask::UpdateUniform(shader, *data));
+    }
+
+    /// Binds a global image to a texture slot of a shader in the same way as
+    /// [`PassRecorder::update_texture`].
+    pub fn update_texture(&mut self, index: u32, image: &Arc<GlobalImage>, sampler_info: &SamplerInfo, shader: ShaderId) {
+        check_texture_type(&self.share, shader, index, image);
+        check_not_storage(&self.pass_storage_images, image);
+
+        self.use_shader(shader);
+        let view = image.get_sampler_view();
+        let sampler = image.get_sampler(sampler_info);
+
+        if self.used_global_image.insert(image.get_id()) {
+            self.global_images.push(image.clone());
+        }
+
+        self.recorder.as_mut().unwrap().process_task(&PipelineTask::UpdateTexture(shader, index, view, sampler));
+    }
+
+    /// Same as [`PassRecorder::set_dynamic_uniform_buffer`].
+    ///
+    /// # Safety
+    /// See [`PassRecorder::set_dynamic_uniform_buffer`].
+    pub unsafe fn set_dynamic_uniform_buffer(&mut self, shader: ShaderId, buffer: vk::Buffer, range: vk::DeviceSize) {
+        check_dynamic_uniform_range(&self.share, range);
+
+        self.use_shader(shader);
+        self.dynamic_uniform_shaders.insert(shader);
+        self.recorder.as_mut().unwrap().process_task(&PipelineTask::SetDynamicUniformBuffer(shader, buffer, range));
+    }
+
+    pub fn draw_global(&mut self, mesh: Arc<GlobalMesh>, shader: ShaderId, depth_write_enable: bool) {
+        self.draw_global_with_uniform_offset(mesh, shader, depth_write_enable, 0);
+    }
+
+    /// Same as [`PassRecorder::draw_global_with_uniform_offset`].
+    pub fn draw_global_with_uniform_offset(&mut self, mesh: Arc<GlobalMesh>, shader: ShaderId, depth_write_enable: bool, dynamic_uniform_offset: u32) {
+        check_dynamic_uniform_offset(&self.share, &self.dynamic_uniform_shaders, shader, dynamic_uniform_offset);
+
+        let draw_info = mesh.get_draw_info();
+        check_vertex_stride(&self.share, &mut self.shader_vertex_strides, shader, draw_info.vertex_stride);
+
+        mesh.update_used_in(self.id);
+
+        self.use_shader(shader);
+
+        let draw_task = DrawTask {
+            vertex_buffer: draw_info.buffer,
+            index_buffer: draw_info.buffer,
+            vertex_offset: draw_info.vertex_offset,
+            first_index: draw_info.first_index,
+            index_type: draw_info.index_type,
+            index_count: draw_info.index_count,
+            shader,
+            primitive_topology: draw_info.primitive_topology,
+            depth_write_enable,
+            conservative: self.conservative_rasterization,
+            dynamic_uniform_offset,
+        };
+
+        self.global_meshes.push(mesh);
+        self.recorder.as_mut().unwrap().process_task(&PipelineTask::Draw(draw_task));
+    }
+
+    fn use_shader(&mut self, shader: ShaderId) {
+        if self.used_shaders.insert(shader) {
+            self.pipeline.inc_shader_used(shader);
+        }
+    }
+}
+
+impl Drop for SecondaryRecorder {
+    fn drop(&mut self) {
+        // The command buffer is never executed so the shaders can be released immediately. If the
+        // recorder has been executed the shaders have been moved to the worker.
+        drop(self.recorder.take());
+        for shader in &self.used_shaders {
+            self.pipeline.dec_shader_used(*shader);
+        }
+    }
+}
+
+/// A clear value for a color attachment. The variant must match the numeric type of the
+/// attachment format as reported by [`Format::get_clear_color_type`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ClearColor {
+    Float([f32; 4]),
+    Int32([i32; 4]),
+    Uint32([u32; 4]),
+}
+
+impl ClearColor {
+    pub const fn get_type(&self) -> ClearColorType {
+        match self {
+            Self::Float(_) => ClearColorType::Float,
+            Self::Int32(_) => ClearColorType::Int32,
+            Self::Uint32(_) => ClearColorType::Uint32,
+        }
+    }
+
+    pub const fn to_vk(&self) -> vk::ClearColorValue {
+        match self {
+            Self::Float(value) => vk::ClearColorValue { float32: *value },
+            Self::Int32(value) => vk::ClearColorValue { int32: *value },
+            Self::Uint32(value) => vk::ClearColorValue { uint32: *value },
+        }
+    }
+}
+
+/// An attachment clear recorded by [`PassRecorder::clear_region`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ClearAttachment {
+    /// Clears the color attachment with the specified index.
+    Color(u32, ClearColor),
+    Depth(f32),
+    Stencil(u32),
+    DepthStencil(f32, u32),
+}
+
+impl ClearAttachment {
+    pub fn to_vk(&self) -> vk::ClearAttachment {
+        match self {
+            Self::Color(index, color) => vk::ClearAttachment {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                color_attachment: *index,
+                clear_value: vk::ClearValue { color: color.to_vk() },
+            },
+            Self::Depth(depth) => Self::make_depth_stencil(vk::ImageAspectFlags::DEPTH, *depth, 0),
+            Self::Stencil(stencil) => Self::make_depth_stencil(vk::ImageAspectFlags::STENCIL, 0f32, *stencil),
+            Self::DepthStencil(depth, stencil) => Self::make_depth_stencil(vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL, *depth, *stencil),
+        }
+    }
+
+    fn make_depth_stencil(aspect_mask: vk::ImageAspectFlags, depth: f32, stencil: u32) -> vk::ClearAttachment {
+        vk::ClearAttachment {
+            aspect_mask,
+            color_attachment: 0,
+            clear_value: vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue { depth, stencil }
+            },
+        }
+    }
+}
+
+/// Validates the attachments and clear values of a [`PassRecorder::clear_region`] call against
+/// the attachment formats of the pipeline. A mismatching clear value type would be reinterpreted
+/// by the driver and write garbage.
+fn check_clear_attachments(pipeline: &dyn EmulatorPipeline, clears: &[ClearAttachment], rects: &[vk::ClearRect]) {
+    for clear in clears {
+        match clear {
+            ClearAttachment::Color(index, color) => {
+                let format = pipeline.get_color_attachment_format(*index).unwrap_or_else(|| {
+                    log::error!("Attempted to clear color attachment {:?} but the pipeline has no such attachment", index);
+                    panic!()
+                });
+                let expected = Format::format_for(format).get_clear_color_type();
+                if expected != Some(color.get_type()) {
+                    log::error!("Attempted to clear color attachment {:?} with format {:?} using clear value {:?}", index, format, color);
+                    panic!()
+                }
+            }
+            ClearAttachment::Depth(_) | ClearAttachment::Stencil(_) | ClearAttachment::DepthStencil(_, _) => {
+                let aspect_mask = clear.to_vk().aspect_mask;
+                let format = pipeline.get_depth_attachment_format().unwrap_or_else(|| {
+                    log::error!("Attempted to clear {:?} but the pipeline has no depth stencil attachment", aspect_mask);
+                    panic!()
+                });
+                if !Format::format_for(format).aspect_mask().contains(aspect_mask) {
+                    log::error!("Attempted to clear {:?} of depth stencil attachment with format {:?}", aspect_mask, format);
+                    panic!()
+                }
+            }
+        }
+    }
+
+    let (size, _) = pipeline.get_output();
+    for rect in rects {
+        let end_x = (rect.rect.offset.x as i64) + (rect.rect.extent.width as i64);
+        let end_y = (rect.rect.offset.y as i64) + (rect.rect.extent.height as i64);
+        let end_layer = (rect.base_array_layer as u64) + (rect.layer_count as u64);
+        if rect.rect.extent.width == 0 || rect.rect.extent.height == 0 || rect.layer_count == 0 || end_layer > 1 ||
+            rect.rect.offset.x < 0 || rect.rect.offset.y < 0 || end_x > (size[0] as i64) || end_y > (size[1] as i64) {
+            log::error!("Invalid clear rect {:?} for pipeline output size {:?}", rect, size);
+            panic!()
+        }
+    }
+}
+
+/// Validates that a mesh with the specified vertex stride can be drawn using a shader. Each
+/// shader has its own vertex format which is used by the pipeline to build the vertex input
+/// state, so a mismatching stride would silently read garbage vertices.
+///
+/// The stride of each shader is cached in `strides` so the shader database is only accessed once
+/// per shader.
+fn check_vertex_stride(share: &Share, strides: &mut HashMap<ShaderId, Option<u32>>, shader: ShaderId, vertex_stride: u32) {
+    let expected = *strides.entry(shader).or_insert_with(|| {
+        share.get_shader(shader).map(|shader_obj| shader_obj.get_vertex_format().stride)
+    });
+    if let Some(expected) = expected {
+        if expected != vertex_stride {
+            log::error!("Mesh vertex stride {:?} does not match the vertex stride {:?} of shader {:?}", vertex_stride, expected, shader);
+            panic!()
+        }
+    }
+}
+
+/// Validates in debug builds that the format of an image matches the declared type of a texture
+/// binding of a shader. Binding an image with fewer channels or a different numeric type than the
+/// shader samples silently produces wrong results so a warning is logged on mismatch.
+fn check_texture_type(share: &Share, shader: ShaderId, index: u32, image: &GlobalImage) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    if let Some(shader_obj) = share.get_shader(shader) {
+        if let Some(texture_type) = shader_obj.get_texture_type(index) {
+            let format = image.get_format();
+            if !texture_type.is_compatible_with(format) {
+                log::warn!("Image format {:?} with {:?} channels of type {:?} bound to texture {:?} of shader {:?} which samples {:?}",
+                    format, format.get_channel_count(), format.get_numeric_type(), index, shader, texture_type);
+            }
+        }
+    }
+}
+
+/// Validates that a image bound as a texture is not used as a storage image in the same pass.
+fn check_not_storage(used_storage_images: &HashSet<GlobalImageId>, image: &GlobalImage) {
+    if used_storage_images.contains(&image.get_id()) {
+        log::error!("Global image {:?} is bound as a storage image and cannot be used as a texture in the same pass", image.get_id());
+        panic!()
+    }
+}
+
+/// Validates the size of a dynamic uniform buffer range against the `maxUniformBufferRange` limit.
+fn check_dynamic_uniform_range(share: &Share, range: vk::DeviceSize) {
+    let max_range = share.get_device().get_limits().max_uniform_buffer_range;
+    if range == 0 || range > (max_range as vk::DeviceSize) {
+        log::error!("Dynamic uniform buffer range {:?} must be non zero and at most max_uniform_buffer_range {:?}", range, max_range);
+        panic!()
+    }
+}
+
+/// Validates that a dynamic uniform offset is aligned to `minUniformBufferOffsetAlignment` and that
+/// a dynamic uniform buffer has been set for the shader if the offset is not 0.
+fn check_dynamic_uniform_offset(share: &Share, dynamic_uniform_shaders: &HashSet<ShaderId>, shader: ShaderId, offset: u32) {
+    if offset == 0 {
+        return;
+    }
+    if !dynamic_uniform_shaders.contains(&shader) {
+        log::error!("Draw with dynamic uniform offset {:?} but no dynamic uniform buffer has been set for shader {:?}", offset, shader);
+        panic!()
+    }
+    let alignment = share.get_device().get_limits().min_uniform_buffer_offset_alignment;
+    if (offset as vk::DeviceSize) % alignment != 0 {
+        log::error!("Dynamic uniform offset {:?} is not a multiple of min_uniform_buffer_offset_alignment {:?}", offset, alignment);
+        panic!()
+    }
+}
+
+/// Returns if conservative rasterization can be enabled logging a warning if it is requested but
+/// not supported.
+fn check_conservative_rasterization(share: &Share, enabled: bool) -> bool {
+    if enabled && !share.get_device().supports_conservative_rasterization() {
+        log::warn!("Conservative rasterization requested but VK_EXT_conservative_rasterization is not supported. Using regular rasterization");
+        return false;
+    }
+    enabled
+}
+
 struct ImmediateMeshInfo {
     vertex_buffer: vk::Buffer,
     index_buffer: vk::Buffer,
@@ -185,5 +961,119 @@ struct ImmediateMeshInfo {
     first_index: u32,
     index_type: vk::IndexType,
     index_count: u32,
+    vertex_stride: u32,
     primitive_topology: vk::PrimitiveTopology,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+
+    use crate::device::device::Queue;
+    use crate::prelude::Vec2u32;
+    use crate::renderer::emulator::pipeline::{PooledObjectProvider, SubmitRecorder};
+
+    use super::*;
+
+    /// A pass which does not record anything.
+    struct EmptyPass;
+
+    impl EmulatorPipelinePass for EmptyPass {
+        fn init(&mut self, _: &Queue, _: &mut PooledObjectProvider, _: vk::ImageView, _: vk::Sampler) {
+        }
+
+        fn process_task(&mut self, _: &PipelineTask, _: &mut PooledObjectProvider) {
+        }
+
+        fn record<'a>(&mut self, _: &mut PooledObjectProvider, _: &mut SubmitRecorder<'a>, _: &'a Bump) {
+        }
+
+        fn get_output_index(&self) -> usize {
+            0
+        }
+
+        fn get_internal_fences(&self, _: &mut Vec<vk::Fence>) {
+        }
+    }
+
+    /// A pipeline with a single R8G8B8A8_SRGB color attachment and a D32_SFLOAT depth attachment.
+    struct ClearTestPipeline;
+
+    impl EmulatorPipeline for ClearTestPipeline {
+        fn start_pass(&self) -> Box<dyn EmulatorPipelinePass + Send> {
+            Box::new(EmptyPass)
+        }
+
+        fn get_output(&self) -> (Vec2u32, &[vk::ImageView]) {
+            (Vec2u32::new(64, 32), &[])
+        }
+
+        fn get_color_attachment_format(&self, index: u32) -> Option<vk::Format> {
+            if index == 0 {
+                Some(vk::Format::R8G8B8A8_SRGB)
+            } else {
+                None
+            }
+        }
+
+        fn get_depth_attachment_format(&self) -> Option<vk::Format> {
+            Some(vk::Format::D32_SFLOAT)
+        }
+
+        fn inc_shader_used(&self, _: ShaderId) {
+        }
+
+        fn dec_shader_used(&self, _: ShaderId) {
+        }
+    }
+
+    fn make_rect(x: i32, y: i32, width: u32, height: u32, base_array_layer: u32, layer_count: u32) -> vk::ClearRect {
+        vk::ClearRect {
+            rect: vk::Rect2D {
+                offset: vk::Offset2D { x, y },
+                extent: vk::Extent2D { width, height }
+            },
+            base_array_layer,
+            layer_count
+        }
+    }
+
+    #[test]
+    fn valid_clears() {
+        let clears = [
+            ClearAttachment::Color(0, ClearColor::Float([0.0, 0.0, 0.0, 1.0])),
+            ClearAttachment::Depth(1.0),
+        ];
+        check_clear_attachments(&ClearTestPipeline, &clears, &[make_rect(0, 0, 64, 32, 0, 1), make_rect(10, 5, 4, 4, 0, 1)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn clear_color_type_mismatch() {
+        check_clear_attachments(&ClearTestPipeline, &[ClearAttachment::Color(0, ClearColor::Uint32([0; 4]))], &[make_rect(0, 0, 64, 32, 0, 1)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn clear_missing_color_attachment() {
+        check_clear_attachments(&ClearTestPipeline, &[ClearAttachment::Color(1, ClearColor::Float([0.0; 4]))], &[make_rect(0, 0, 64, 32, 0, 1)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn clear_missing_stencil_aspect() {
+        check_clear_attachments(&ClearTestPipeline, &[ClearAttachment::Stencil(0)], &[make_rect(0, 0, 64, 32, 0, 1)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn clear_rect_out_of_bounds() {
+        check_clear_attachments(&ClearTestPipeline, &[ClearAttachment::Depth(1.0)], &[make_rect(32, 0, 64, 32, 0, 1)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn clear_rect_invalid_layer() {
+        check_clear_attachments(&ClearTestPipeline, &[ClearAttachment::Depth(1.0)], &[make_rect(0, 0, 64, 32, 1, 1)]);
+    }
+}
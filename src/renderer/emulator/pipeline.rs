@@ -1,16 +1,23 @@
 use std::hash::Hash;
 use std::panic::{RefUnwindSafe, UnwindSafe};
-use std::sync::{Arc, Weak};
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use ash::prelude::VkResult;
 
 use ash::vk;
 use bumpalo::Bump;
 use crate::device::device::Queue;
-use crate::device::device_utils::BlitPass;
+use crate::device::device_utils::{BlitPass, ColorAttachmentPass, ColorAttachmentTarget};
+use crate::device::transfer::resource_state::ImageStateTracker;
 use crate::device::surface::{AcquiredImageInfo, SurfaceSwapchain};
+use crate::objects::sync::SemaphoreOp;
+use crate::vk::objects::image::Image;
+use crate::util::format::Format;
 
 use crate::prelude::*;
 use crate::renderer::emulator::mc_shaders::{McUniformData, ShaderId};
+use crate::renderer::emulator::{GlobalImage, PassId};
 
 pub use super::worker::SubmitRecorder;
 pub use super::worker::PooledObjectProvider;
@@ -30,12 +37,69 @@ pub trait EmulatorPipeline: Send + Sync + UnwindSafe + RefUnwindSafe {
     /// if the user submits tasks faster than the gpu can process them.
     fn start_pass(&self) -> Box<dyn EmulatorPipelinePass + Send>;
 
+    /// Same as [`EmulatorPipeline::start_pass`] but the returned pass must support
+    /// [`EmulatorPipelinePass::execute_secondary`]. Returns [`None`] if the pipeline does not
+    /// support secondary command buffers.
+    ///
+    /// Passes created by [`EmulatorPipeline::start_pass`] never execute secondary command buffers
+    /// and may record all tasks inline.
+    fn start_pass_with_secondaries(&self) -> Option<Box<dyn EmulatorPipelinePass + Send>> {
+        None
+    }
+
+    /// Creates a recorder which records tasks into a secondary command buffer on the calling
+    /// thread or returns [`None`] if the pipeline does not support secondary command buffers.
+    ///
+    /// The recorded command buffer is passed to [`EmulatorPipelinePass::execute_secondary`] of a
+    /// pass created by [`EmulatorPipeline::start_pass_with_secondaries`] of this pipeline. The placeholder image and sampler are the same as the ones
+    /// passed to [`EmulatorPipelinePass::init`].
+    fn create_secondary(&self, _placeholder_image: vk::ImageView, _placeholder_sampler: vk::Sampler) -> Option<Box<dyn EmulatorPipelineSecondary + Send>> {
+        None
+    }
+
     /// Returns the size and a list of image views which can be used as source images for samplers
     /// for the output of the pipeline.
     ///
     /// **This is a temporary api and needs a rework to improve flexibility and elegance**
     fn get_output(&self) -> (Vec2u32, &[vk::ImageView]);
 
+    /// Returns the format and a list of images containing the color output of a pass indexed the
+    /// same way as the views returned by [`EmulatorPipeline::get_output`] or [`None`] if the
+    /// pipeline does not expose its color images.
+    ///
+    /// After a pass the color image must be in [`vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL`] and
+    /// have been created with [`vk::ImageUsageFlags::TRANSFER_SRC`]. The size of the images is the
+    /// size returned by [`EmulatorPipeline::get_output`].
+    fn get_color_output(&self) -> Option<(vk::Format, &[vk::Image])> {
+        None
+    }
+
+    /// Returns the format and a list of images containing the depth buffer of a pass indexed the
+    /// same way as the views returned by [`EmulatorPipeline::get_output`] or [`None`] if the
+    /// pipeline does not expose its depth buffer.
+    ///
+    /// After a pass the depth image must be in [`vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL`] and
+    /// have been created with [`vk::ImageUsageFlags::TRANSFER_SRC`]. The size of the images is the
+    /// size returned by [`EmulatorPipeline::get_output`]. The images must be single sampled,
+    /// pipelines using a multisampled depth buffer must return [`None`].
+    fn get_depth_output(&self) -> Option<(vk::Format, &[vk::Image])> {
+        None
+    }
+
+    /// Returns the format of the color attachment with the specified index of the subpass draws
+    /// are recorded in or [`None`] if no such attachment exists. Used to validate
+    /// [`PipelineTask::ClearAttachments`].
+    fn get_color_attachment_format(&self, _index: u32) -> Option<vk::Format> {
+        None
+    }
+
+    /// Returns the format of the depth stencil attachment of the subpass draws are recorded in or
+    /// [`None`] if the subpass has no depth stencil attachment. Used to validate
+    /// [`PipelineTask::ClearAttachments`].
+    fn get_depth_attachment_format(&self) -> Option<vk::Format> {
+        None
+    }
+
     /// Called internally by the emulator renderer when pass uses a shader for the first time.
     /// A corresponding call to [`dec_shader_used`] will be performed after the corresponding pass
     /// has been dropped.
@@ -81,6 +145,17 @@ pub trait EmulatorPipelinePass {
     /// Must only be called while the pass is in the recording state.
     fn process_task(&mut self, task: &PipelineTask, obj: &mut PooledObjectProvider);
 
+    /// Called to execute a secondary command buffer recorded by a [`EmulatorPipelineSecondary`] of
+    /// the parent pipeline. The commands must be executed after all previously processed tasks and
+    /// before any task processed afterwards.
+    ///
+    /// Must only be called while the pass is in the recording state. Is only called on passes
+    /// created by [`EmulatorPipeline::start_pass_with_secondaries`].
+    fn execute_secondary(&mut self, _cmd: vk::CommandBuffer, _obj: &mut PooledObjectProvider) {
+        log::error!("Called EmulatorPipelinePass::execute_secondary on a pass which does not support secondary command buffers");
+        panic!()
+    }
+
     /// Called to record any necessary command buffer submissions for the execution of the pass.
     /// The recorded submits will be submitted by the calling code.
     ///
@@ -106,11 +181,45 @@ pub trait EmulatorPipelinePass {
     fn get_internal_fences(&self, fences: &mut Vec<vk::Fence>);
 }
 
-#[derive(Copy, Clone, Debug)]
+/// Records tasks of a pass into a secondary command buffer. Created by
+/// [`EmulatorPipeline::create_secondary`].
+///
+/// The secondary command buffer does not inherit any state of the pass. Uniforms and textures must
+/// be updated through [`EmulatorPipelineSecondary::process_task`] before they are used by a draw.
+///
+/// Any instance of this struct will not be dropped until the recorded command buffer has finished
+/// execution or it is known that the command buffer will never be submitted.
+pub trait EmulatorPipelineSecondary {
+
+    /// Called to process a task. Must not be called after [`EmulatorPipelineSecondary::finish`].
+    fn process_task(&mut self, task: &PipelineTask);
+
+    /// Ends recording and returns the recorded secondary command buffer.
+    fn finish(&mut self) -> vk::CommandBuffer;
+}
+
+#[derive(Clone, Debug)]
 pub enum PipelineTask {
     UpdateUniform(ShaderId, McUniformData),
     UpdateTexture(ShaderId, u32, vk::ImageView, vk::Sampler),
+    /// Updates multiple textures of a shader at once. Each entry contains the texture index, view
+    /// and sampler in the same way as [`PipelineTask::UpdateTexture`].
+    UpdateTextures(ShaderId, Box<[(u32, vk::ImageView, vk::Sampler)]>),
+    /// Binds a image view as a storage image of a shader. Contains the storage image index and
+    /// view. The image is in [`vk::ImageLayout::GENERAL`] for the whole pass and any shader writes
+    /// are made visible to later passes.
+    UpdateStorageImage(ShaderId, u32, vk::ImageView),
+    /// Binds a range of a uniform buffer as the dynamic uniform buffer of a shader. Contains the
+    /// buffer and the size of the range. The offset of the range is selected per draw by
+    /// [`DrawTask::dynamic_uniform_offset`].
+    SetDynamicUniformBuffer(ShaderId, vk::Buffer, vk::DeviceSize),
     Draw(DrawTask),
+    DrawIndirect(DrawIndirectTask),
+    /// Clears regions of attachments of the subpass draws are recorded in using
+    /// `vkCmdClearAttachments`. The attachments and clear values have been validated against
+    /// [`EmulatorPipeline::get_color_attachment_format`] and
+    /// [`EmulatorPipeline::get_depth_attachment_format`].
+    ClearAttachments(Box<[vk::ClearAttachment]>, Box<[vk::ClearRect]>),
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
@@ -124,6 +233,33 @@ pub struct DrawTask {
     pub shader: ShaderId,
     pub primitive_topology: vk::PrimitiveTopology,
     pub depth_write_enable: bool,
+    /// If true the draw uses overestimating conservative rasterization. Ignored if
+    /// VK_EXT_conservative_rasterization is not supported.
+    pub conservative: bool,
+    /// The offset into the dynamic uniform buffer of the shader. Must be a multiple of
+    /// `minUniformBufferOffsetAlignment`. Ignored if no dynamic uniform buffer is set for the
+    /// shader.
+    pub dynamic_uniform_offset: u32,
+}
+
+/// A indexed draw reading its parameters from a buffer of [`vk::DrawIndexedIndirectCommand`]s.
+///
+/// Any required synchronization of the draw buffer is performed before the pass starts.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct DrawIndirectTask {
+    pub vertex_buffer: vk::Buffer,
+    pub index_buffer: vk::Buffer,
+    pub index_type: vk::IndexType,
+    pub draw_buffer: vk::Buffer,
+    pub draw_offset: vk::DeviceSize,
+    pub draw_count: u32,
+    pub stride: u32,
+    pub shader: ShaderId,
+    pub primitive_topology: vk::PrimitiveTopology,
+    pub depth_write_enable: bool,
+    /// If true the draw uses overestimating conservative rasterization. Ignored if
+    /// VK_EXT_conservative_rasterization is not supported.
+    pub conservative: bool,
 }
 
 /// Used to process the output of a [`EmulatorPipelinePass`].
@@ -141,11 +277,43 @@ pub trait EmulatorOutput {
     /// The recorded submits will be submitted by the calling code.
     fn record<'a>(&mut self, obj: &mut PooledObjectProvider, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump);
 
+    /// Called instead of [`EmulatorOutput::record`] if the pass has been aborted.
+    ///
+    /// The output of the pass must not be accessed. Any necessary submissions to release resources
+    /// acquired by the output (for example semaphores which must be waited on or signaled) must be
+    /// recorded. [`EmulatorOutput::on_post_submit`] is still called afterwards.
+    fn record_abort<'a>(&mut self, obj: &mut PooledObjectProvider, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump);
+
     /// Called after the submits recorded by [`EmulatorOutput::record`] have been submitted for
     /// execution. This is particularly useful to perform any queue present operations.
-    fn on_post_submit(&mut self, queue: &Queue);
+    ///
+    /// The frame index is the raw id of the pass. If the output presented an image it must return
+    /// the swapchain it presented to so that any registered [`PresentHook`] can be invoked.
+    fn on_post_submit(&mut self, queue: &Queue, frame_index: u64) -> Option<&SurfaceSwapchain>;
 }
 
+/// Timing information passed to a [`PresentHook`] after a image has been presented.
+pub struct PresentTiming<'a> {
+    /// The raw id of the pass which rendered the presented image.
+    pub frame_index: u64,
+
+    /// The cpu time directly after the present was queued.
+    pub cpu_time: Instant,
+
+    /// Timings of previous presents reported by VK_GOOGLE_display_timing since the last present
+    /// to the same swapchain. The present id of each entry is the lower 32 bits of the frame index.
+    /// Empty if the extension is not supported.
+    pub past_presents: &'a [vk::PastPresentationTimingGOOGLE],
+}
+
+/// A callback invoked by the emulator worker after each present. Can be used to implement frame
+/// pacing.
+pub type PresentHook = dyn Fn(&PresentTiming) + Send + Sync;
+
+/// A callback invoked by the emulator worker with the gpu execution time of a pass once the pass
+/// has completed. The duration covers the pipeline pass and all of its outputs.
+pub type PassTimingHook = dyn Fn(PassId, Duration) + Send + Sync;
+
 /// A utility struct providing a [`BlitPass`] for the output of a [`EmulatorPipeline`].
 pub struct OutputUtil {
     #[allow(unused)] // We just need to keep the pipeline alive
@@ -157,9 +325,15 @@ pub struct OutputUtil {
 
 impl OutputUtil {
     pub fn new(device: &DeviceContext, pipeline: Arc<dyn EmulatorPipeline>, format: vk::Format, final_layout: vk::ImageLayout) -> Self {
+        Self::new_with_layouts(device, pipeline, format, vk::ImageLayout::UNDEFINED, final_layout)
+    }
+
+    /// Creates a new output util where the output image is expected to be in `initial_layout` when
+    /// the blit pass begins.
+    pub fn new_with_layouts(device: &DeviceContext, pipeline: Arc<dyn EmulatorPipeline>, format: vk::Format, initial_layout: vk::ImageLayout, final_layout: vk::ImageLayout) -> Self {
         let (_, sampler_views) = pipeline.get_output();
 
-        let blit_pass = device.get_utils().blit_utils().create_blit_pass(format, vk::AttachmentLoadOp::DONT_CARE, vk::ImageLayout::UNDEFINED, final_layout);
+        let blit_pass = device.get_utils().blit_utils().create_blit_pass(format, vk::AttachmentLoadOp::DONT_CARE, initial_layout, final_layout);
 
         let descriptor_pool = Self::create_descriptor_pool(device, sampler_views.len());
         let descriptor_sets = blit_pass.create_descriptor_sets(descriptor_pool, sampler_views).unwrap().into_boxed_slice();
@@ -172,23 +346,22 @@ impl OutputUtil {
         }
     }
 
-    /// Creates a framebuffer which can be used as a draw target for the blit pass.
+    /// Creates a target which can be used as a draw target for the blit pass.
     ///
-    /// The returned framebuffer is fully owned by the calling code and must be destroyed before
-    /// this struct is dropped.
-    pub fn create_framebuffer(&self, image_view: vk::ImageView, size: Vec2u32) -> VkResult<vk::Framebuffer> {
-        self.blit_pass.create_framebuffer(image_view, size)
+    /// The returned target is fully owned by the calling code and must be destroyed before this
+    /// struct is dropped.
+    pub fn create_target(&self, image: vk::Image, image_view: vk::ImageView, size: Vec2u32) -> VkResult<ColorAttachmentTarget> {
+        self.blit_pass.create_target(image, image_view, size)
     }
 
     /// Records one execution of the blit pass.
     ///
     /// The pipeline index is the index returned by [`EmulatorPipelinePass::get_output_index`].
-    pub fn record(&self, command_buffer: vk::CommandBuffer, output_framebuffer: vk::Framebuffer, output_size: Vec2u32, pipeline_index: usize) {
+    pub fn record(&self, command_buffer: vk::CommandBuffer, output_target: &ColorAttachmentTarget, pipeline_index: usize) {
         self.blit_pass.record_blit(
             command_buffer,
             self.descriptor_sets[pipeline_index],
-            output_framebuffer,
-            output_size,
+            output_target,
             None
         )
     }
@@ -219,49 +392,268 @@ impl Drop for OutputUtil {
     }
 }
 
+/// Checks that images of the specified formats can be used with [`record_output_blit`].
+///
+/// If a format is not supported it is returned as the error.
+pub fn validate_output_blit(device: &DeviceContext, src_format: vk::Format, dst_format: vk::Format) -> Result<(), vk::Format> {
+    let src_features = vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+    if !device.get_format_properties(Format::format_for(src_format)).optimal_tiling_features.contains(src_features) {
+        return Err(src_format);
+    }
+    if !device.get_format_properties(Format::format_for(dst_format)).optimal_tiling_features.contains(vk::FormatFeatureFlags::BLIT_DST) {
+        return Err(dst_format);
+    }
+    Ok(())
+}
+
+/// Records a blit scaling the full `src_image` to the full `dst_image` using linear filtering.
+///
+/// The source image must be in [`vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL`] with all writes
+/// being visible and is returned to that layout afterwards. The previous content of the
+/// destination image is discarded and it is transitioned into `dst_final_layout`. If this is
+/// [`vk::ImageLayout::PRESENT_SRC_KHR`] the transition is recorded as a present transition (see
+/// [`ImageStateTracker::release_for_present`]). Both images must be single sampled 2D color images
+/// and their formats must pass [`validate_output_blit`].
+pub fn record_output_blit(device: &DeviceContext, cmd: vk::CommandBuffer, src_image: Image, src_format: vk::Format, src_size: Vec2u32, dst_image: Image, dst_format: vk::Format, dst_size: Vec2u32, dst_final_layout: vk::ImageLayout) {
+    let mut tracker = ImageStateTracker::new();
+    tracker.register_with_format(src_image, Format::format_for(src_format), vk::ImageAspectFlags::COLOR, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL).unwrap();
+    tracker.register_with_format(dst_image, Format::format_for(dst_format), vk::ImageAspectFlags::COLOR, vk::ImageLayout::UNDEFINED).unwrap();
+
+    let mut barriers = Vec::with_capacity(2);
+    let src_handle = tracker.update_state_read(src_image.get_id(), &mut barriers).unwrap();
+    let dst_handle = tracker.update_state_write(dst_image.get_id(), &mut barriers).unwrap();
+
+    let subresource = vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1
+    };
+    let region = vk::ImageBlit {
+        src_subresource: subresource,
+        src_offsets: [vk::Offset3D { x: 0, y: 0, z: 0 }, vk::Offset3D { x: src_size[0] as i32, y: src_size[1] as i32, z: 1 }],
+        dst_subresource: subresource,
+        dst_offsets: [vk::Offset3D { x: 0, y: 0, z: 0 }, vk::Offset3D { x: dst_size[0] as i32, y: dst_size[1] as i32, z: 1 }],
+    };
+
+    unsafe {
+        device.synchronization_2().cmd_pipeline_barrier2(cmd, &vk::DependencyInfo::builder().image_memory_barriers(&barriers));
+        device.vk().cmd_blit_image(cmd, src_handle, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, dst_handle, vk::ImageLayout::TRANSFER_DST_OPTIMAL, std::slice::from_ref(&region), vk::Filter::LINEAR);
+    }
+
+    barriers.clear();
+    tracker.release_to(src_image.get_id(), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, &mut barriers).unwrap();
+    if dst_final_layout == vk::ImageLayout::PRESENT_SRC_KHR {
+        tracker.release_for_present(dst_image.get_id(), &mut barriers).unwrap();
+    } else {
+        tracker.release_to(dst_image.get_id(), dst_final_layout, &mut barriers).unwrap();
+    }
+
+    unsafe {
+        device.synchronization_2().cmd_pipeline_barrier2(cmd, &vk::DependencyInfo::builder().image_memory_barriers(&barriers));
+    }
+}
+
+/// Errors reported by [`SwapchainOutput::new_scaled`] and [`ImageOutput::new`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ScaledOutputError {
+    /// The pipeline does not expose its color output through [`EmulatorPipeline::get_color_output`].
+    NoColorOutput,
+
+    /// The swapchain images have not been created with [`vk::ImageUsageFlags::TRANSFER_DST`].
+    MissingTransferDst,
+
+    /// The format does not support linear filtered blits.
+    UnsupportedFormat(vk::Format),
+}
+
+/// Errors reported by [`SwapchainOutput::next_image`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PresentError {
+    /// The device has been lost. The device and all objects created from it must be recreated.
+    DeviceLost,
+
+    /// The surface has been lost. The surface and swapchain must be recreated.
+    SurfaceLost,
+
+    /// The swapchain no longer matches the surface and must be recreated.
+    OutOfDate,
+
+    /// A previous present succeeded but the swapchain no longer matches the surface exactly. The
+    /// swapchain should be recreated.
+    Suboptimal,
+
+    /// No image could be acquired within the acquire timeout.
+    Timeout,
+
+    /// Any other error returned by vulkan.
+    Vulkan(vk::Result),
+}
+
+impl From<vk::Result> for PresentError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_DEVICE_LOST => PresentError::DeviceLost,
+            vk::Result::ERROR_SURFACE_LOST_KHR => PresentError::SurfaceLost,
+            vk::Result::ERROR_OUT_OF_DATE_KHR => PresentError::OutOfDate,
+            vk::Result::SUBOPTIMAL_KHR => PresentError::Suboptimal,
+            vk::Result::TIMEOUT | vk::Result::NOT_READY => PresentError::Timeout,
+            err => PresentError::Vulkan(err),
+        }
+    }
+}
+
 /// A [`EmulatorOutput`] implementation which copes the output image to a swapchain image and
 /// presents it.
 pub struct SwapchainOutput {
     weak: Weak<Self>,
+    device: Arc<DeviceContext>,
     swapchain: Arc<SurfaceSwapchain>,
+    /// The queue used to present. May be different from the queue used for rendering in which
+    /// case the present waits on the present semaphore signaled by the render submission.
+    present_queue: Arc<Queue>,
     util: OutputUtil,
-    framebuffers: Box<[vk::Framebuffer]>,
+    targets: Box<[ColorAttachmentTarget]>,
+    /// Pass compatible with the blit pass used to clear the image if a pass is aborted.
+    clear_pass: ColorAttachmentPass,
+    /// Timeout in nanoseconds used when acquiring images.
+    acquire_timeout: AtomicU64,
+    /// The result of the last failed or suboptimal present. Reported by the next call to
+    /// [`SwapchainOutput::next_image`].
+    present_error: Mutex<Option<PresentError>>,
+    /// If true the pipeline output is copied using [`record_output_blit`] instead of the blit pass.
+    scaled: bool,
 }
 
 impl SwapchainOutput {
-    pub fn new(device: &DeviceContext, pipeline: Arc<dyn EmulatorPipeline>, swapchain: Arc<SurfaceSwapchain>) -> Arc<Self> {
+    pub fn new(device: &Arc<DeviceContext>, pipeline: Arc<dyn EmulatorPipeline>, swapchain: Arc<SurfaceSwapchain>) -> Arc<Self> {
+        Self::new_internal(device, pipeline, swapchain, false)
+    }
+
+    /// Creates a swapchain output which scales the pipeline output to the swapchain image using
+    /// [`record_output_blit`]. This allows the pipeline to render at a resolution independent of
+    /// the window size.
+    ///
+    /// The pipeline must expose its color output through [`EmulatorPipeline::get_color_output`]
+    /// and the swapchain images must have been created with
+    /// [`vk::ImageUsageFlags::TRANSFER_DST`].
+    pub fn new_scaled(device: &Arc<DeviceContext>, pipeline: Arc<dyn EmulatorPipeline>, swapchain: Arc<SurfaceSwapchain>) -> Result<Arc<Self>, ScaledOutputError> {
+        let (src_format, _) = pipeline.get_color_output().ok_or(ScaledOutputError::NoColorOutput)?;
+        if !swapchain.get_image_usage().contains(vk::ImageUsageFlags::TRANSFER_DST) {
+            return Err(ScaledOutputError::MissingTransferDst);
+        }
+        validate_output_blit(device, src_format, swapchain.get_image_format().format).map_err(ScaledOutputError::UnsupportedFormat)?;
+
+        Ok(Self::new_internal(device, pipeline, swapchain, true))
+    }
+
+    fn new_internal(device: &Arc<DeviceContext>, pipeline: Arc<dyn EmulatorPipeline>, swapchain: Arc<SurfaceSwapchain>, scaled: bool) -> Arc<Self> {
         let util = OutputUtil::new(device, pipeline, swapchain.get_image_format().format, vk::ImageLayout::PRESENT_SRC_KHR);
 
-        let framebuffers = swapchain.get_images().iter().map(|image| {
-            util.create_framebuffer(image.get_framebuffer_view(), swapchain.get_image_size()).unwrap()
+        let targets = swapchain.get_images().iter().map(|image| {
+            util.create_target(image.get_image().get_handle(), image.get_framebuffer_view(), swapchain.get_image_size()).unwrap()
         }).collect();
 
+        let clear_pass = ColorAttachmentPass::new(device.get_functions().clone(), swapchain.get_image_format().format, vk::AttachmentLoadOp::CLEAR, vk::ImageLayout::UNDEFINED, vk::ImageLayout::PRESENT_SRC_KHR);
+
         Arc::new_cyclic(|weak| Self {
             weak: weak.clone(),
+            device: device.clone(),
             swapchain,
+            present_queue: device.get_present_queue().clone(),
             util,
-            framebuffers
+            targets,
+            clear_pass,
+            acquire_timeout: AtomicU64::new(Self::DEFAULT_ACQUIRE_TIMEOUT.as_nanos() as u64),
+            present_error: Mutex::new(None),
+            scaled
         })
     }
 
-    /// Attempts to acquire a new image from the swapchain blocking until it does.
+    pub const DEFAULT_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Sets the maximum time [`SwapchainOutput::next_image`] waits for a image to become available.
+    pub fn set_acquire_timeout(&self, timeout: Duration) {
+        self.acquire_timeout.store(timeout.as_nanos().min(u64::MAX as u128) as u64, Ordering::Release);
+    }
+
+    pub fn get_acquire_timeout(&self) -> Duration {
+        Duration::from_nanos(self.acquire_timeout.load(Ordering::Acquire))
+    }
+
+    /// Records the result of a present. Errors are kept until they are reported by
+    /// [`SwapchainOutput::next_image`] and are never replaced by [`PresentError::Suboptimal`].
+    fn set_present_error(&self, error: PresentError) {
+        let mut guard = self.present_error.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned present error mutex in SwapchainOutput::set_present_error!");
+            panic!()
+        });
+        if guard.is_none() || *guard == Some(PresentError::Suboptimal) {
+            *guard = Some(error);
+        }
+    }
+
+    /// Attempts to acquire a new image from the swapchain blocking until it does or the acquire
+    /// timeout runs out.
     ///
-    /// Returns [`None`] if the swapchain is out of date.
+    /// If a previous present failed or was suboptimal that error is returned first and no image is
+    /// acquired. If the error is [`PresentError::DeviceLost`] the whole device must be recreated.
     ///
     /// If it successfully acquires a image returns a [`EmulatorOutput`] instance for the image as
     /// well as a boolean flag set to true if the swapchain is suboptimal.
-    pub fn next_image(&self) -> Option<(Box<dyn EmulatorOutput + Send>, bool)> {
-        loop {
-            let arc = self.weak.upgrade().unwrap();
-            match self.swapchain.acquire_next_image(1000000000, None) {
-                Ok((info, suboptimal)) =>
-                    return Some((Box::new(SwapchainOutputInstance::new(arc, info)), suboptimal)),
-                Err(vk::Result::TIMEOUT) =>
-                    log::warn!("1s timeout reached while waiting for next swapchain image in SwapchainOutput::next_image"),
-                Err(err) => {
-                    log::error!("vkAcquireNextImageKHR returned {:?} in SwapchainOutput::next_image", err);
-                    panic!()
-                }
+    pub fn next_image(&self) -> Result<(Box<dyn EmulatorOutput + Send>, bool), PresentError> {
+        self.next_image_with_damage(&[])
+    }
+
+    /// Same as [`SwapchainOutput::next_image`] but additionally specifies the regions of the image
+    /// which changed since the last present.
+    ///
+    /// The regions are passed to the presentation engine using VK_KHR_incremental_present and are
+    /// only a hint which the compositor is free to ignore, so content outside of the regions must
+    /// still be valid. If the extension is not enabled or `present_regions` is empty the whole
+    /// image is presented.
+    pub fn next_image_with_damage(&self, present_regions: &[vk::Rect2D]) -> Result<(Box<dyn EmulatorOutput + Send>, bool), PresentError> {
+        let present_error = self.present_error.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned present error mutex in SwapchainOutput::next_image_with_damage!");
+            panic!()
+        }).take();
+        if let Some(err) = present_error {
+            return Err(err);
+        }
+
+        let timeout = self.acquire_timeout.load(Ordering::Acquire);
+        match self.swapchain.acquire_next_image(timeout, None) {
+            Ok((info, suboptimal)) => {
+                let arc = self.weak.upgrade().unwrap();
+                let present_regions = if self.swapchain.get_device().incremental_present_khr {
+                    // Regions must lie within the swapchain image
+                    let size = self.swapchain.get_image_size();
+                    present_regions.iter().filter_map(|rect| {
+                        let x = rect.offset.x.max(0) as u32;
+                        let y = rect.offset.y.max(0) as u32;
+                        let end_x = ((rect.offset.x as i64) + (rect.extent.width as i64)).clamp(0, size[0] as i64) as u32;
+                        let end_y = ((rect.offset.y as i64) + (rect.extent.height as i64)).clamp(0, size[1] as i64) as u32;
+                        if end_x <= x || end_y <= y {
+                            return None;
+                        }
+                        Some(vk::RectLayerKHR {
+                            offset: vk::Offset2D { x: x as i32, y: y as i32 },
+                            extent: vk::Extent2D { width: end_x - x, height: end_y - y },
+                            layer: 0
+                        })
+                    }).collect()
+                } else {
+                    Box::new([])
+                };
+                Ok((Box::new(SwapchainOutputInstance::new(arc, info, present_regions)), suboptimal))
+            },
+            Err(vk::Result::TIMEOUT) => {
+                log::warn!("{:?} timeout reached while waiting for next swapchain image in SwapchainOutput::next_image_with_damage", self.get_acquire_timeout());
+                Err(PresentError::Timeout)
+            },
+            Err(err) => {
+                log::error!("vkAcquireNextImageKHR returned {:?} in SwapchainOutput::next_image_with_damage", err);
+                Err(PresentError::from(err))
             }
         }
     }
@@ -270,10 +662,8 @@ impl SwapchainOutput {
 impl Drop for SwapchainOutput {
     fn drop(&mut self) {
         let device = self.swapchain.get_device();
-        unsafe {
-            for framebuffer in self.framebuffers.iter() {
-                device.vk.destroy_framebuffer(*framebuffer, None);
-            }
+        for target in self.targets.iter_mut() {
+            target.destroy(device);
         }
     }
 }
@@ -282,32 +672,21 @@ struct SwapchainOutputInstance {
     output: Arc<SwapchainOutput>,
     image_info: AcquiredImageInfo,
     pipeline_index: Option<usize>,
+    /// Damage regions passed to VK_KHR_incremental_present. Empty if the whole image is presented.
+    present_regions: Box<[vk::RectLayerKHR]>,
 }
 
 impl SwapchainOutputInstance {
-    fn new(output: Arc<SwapchainOutput>, image_info: AcquiredImageInfo) -> Self {
+    fn new(output: Arc<SwapchainOutput>, image_info: AcquiredImageInfo, present_regions: Box<[vk::RectLayerKHR]>) -> Self {
         Self {
             output,
             image_info,
             pipeline_index: None,
+            present_regions,
         }
     }
-}
-
-impl EmulatorOutput for SwapchainOutputInstance {
-    fn init(&mut self, pass: &dyn EmulatorPipelinePass, _: &mut PooledObjectProvider) {
-        self.pipeline_index = Some(pass.get_output_index());
-    }
-
-    fn record<'a>(&mut self, obj: &mut PooledObjectProvider, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump) {
-        let cmd = obj.get_begin_command_buffer().unwrap();
-
-        self.output.util.record(cmd, self.output.framebuffers[self.image_info.image_index as usize], self.output.swapchain.get_image_size(), self.pipeline_index.unwrap());
-
-        unsafe {
-            self.output.swapchain.get_device().vk.end_command_buffer(cmd)
-        }.unwrap();
 
+    fn push_submit<'a>(&self, cmd: vk::CommandBuffer, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump) {
         let waits = alloc.alloc([
             vk::SemaphoreSubmitInfo::builder()
                 .semaphore(self.image_info.acquire_semaphore.semaphore.get_handle())
@@ -337,8 +716,58 @@ impl EmulatorOutput for SwapchainOutputInstance {
             .signal_semaphore_infos(signals)
         );
     }
+}
+
+impl EmulatorOutput for SwapchainOutputInstance {
+    fn init(&mut self, pass: &dyn EmulatorPipelinePass, _: &mut PooledObjectProvider) {
+        self.pipeline_index = Some(pass.get_output_index());
+    }
+
+    fn record<'a>(&mut self, obj: &mut PooledObjectProvider, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump) {
+        let cmd = obj.get_begin_command_buffer().unwrap();
+
+        if self.output.scaled {
+            let (src_size, _) = self.output.util.pipeline.get_output();
+            let (src_format, src_images) = self.output.util.pipeline.get_color_output().unwrap();
+            let src_image = Image::new(src_images[self.pipeline_index.unwrap()]);
+            let dst_image = self.output.swapchain.get_images()[self.image_info.image_index as usize].get_image();
+            let dst_format = self.output.swapchain.get_image_format().format;
+
+            record_output_blit(&self.output.device, cmd, src_image, src_format, src_size, dst_image, dst_format, self.output.swapchain.get_image_size(), vk::ImageLayout::PRESENT_SRC_KHR);
+        } else {
+            self.output.util.record(cmd, &self.output.targets[self.image_info.image_index as usize], self.pipeline_index.unwrap());
+        }
+
+        unsafe {
+            self.output.swapchain.get_device().vk.end_command_buffer(cmd)
+        }.unwrap();
+
+        self.push_submit(cmd, submits, alloc);
+    }
+
+    fn record_abort<'a>(&mut self, obj: &mut PooledObjectProvider, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump) {
+        // The acquired image must still be presented so we clear it to avoid presenting garbage
+        let cmd = obj.get_begin_command_buffer().unwrap();
+        let device = self.output.swapchain.get_device();
+
+        let clear_value = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0f32, 0f32, 0f32, 1f32]
+            }
+        };
+
+        let target = &self.output.targets[self.image_info.image_index as usize];
+        self.output.clear_pass.begin(cmd, target, Some(&clear_value));
+        self.output.clear_pass.end(cmd, target);
 
-    fn on_post_submit(&mut self, queue: &Queue) {
+        unsafe {
+            device.vk.end_command_buffer(cmd)
+        }.unwrap();
+
+        self.push_submit(cmd, submits, alloc);
+    }
+
+    fn on_post_submit(&mut self, _: &Queue, frame_index: u64) -> Option<&SurfaceSwapchain> {
         let present_semaphore = self.output.swapchain.get_images()[self.image_info.image_index as usize].get_present_semaphore().get_handle();
 
         let guard = self.output.swapchain.get_swapchain().lock().unwrap();
@@ -348,8 +777,388 @@ impl EmulatorOutput for SwapchainOutputInstance {
             .swapchains(std::slice::from_ref(&*guard))
             .image_indices(std::slice::from_ref(&self.image_info.image_index));
 
+        let present_time = vk::PresentTimeGOOGLE {
+            present_id: frame_index as u32,
+            desired_present_time: 0
+        };
+        let mut present_times = vk::PresentTimesInfoGOOGLE::builder()
+            .times(std::slice::from_ref(&present_time));
+
+        let present_info = if self.output.swapchain.get_device().display_timing_google.is_some() {
+            present_info.push_next(&mut present_times)
+        } else {
+            present_info
+        };
+
+        let present_region = vk::PresentRegionKHR::builder()
+            .rectangles(&self.present_regions)
+            .build();
+        let mut present_regions = vk::PresentRegionsKHR::builder()
+            .regions(std::slice::from_ref(&present_region));
+
+        let present_info = if !self.present_regions.is_empty() {
+            present_info.push_next(&mut present_regions)
+        } else {
+            present_info
+        };
+
+        let result = unsafe {
+            self.output.present_queue.present(&present_info)
+        };
+
+        match result {
+            Ok(false) => Some(&self.output.swapchain),
+            Ok(true) => {
+                self.output.set_present_error(PresentError::Suboptimal);
+                Some(&self.output.swapchain)
+            }
+            Err(err) => {
+                log::error!("vkQueuePresentKHR returned {:?} in SwapchainOutputInstance::on_post_submit", err);
+                self.output.set_present_error(PresentError::from(err));
+                None
+            }
+        }
+    }
+}
+
+/// A [`EmulatorOutput`] implementation which copies the output image into a externally provided
+/// image. It can be used with any pipeline which exposes its color output through
+/// [`EmulatorPipeline::get_color_output`] by passing an instance to
+/// [`PassRecorder::use_output`](super::PassRecorder::use_output).
+///
+/// The pipeline output is scaled to the image using [`record_output_blit`] which discards the
+/// previous content of the image. The image is transitioned into `final_layout` after the pass. If
+/// a pass is aborted the image is only transitioned from `current_layout` into `final_layout`
+/// without modifying its contents. Synchronization with external users of the image on other
+/// queues must be done using the semaphores passed to [`ImageOutput::create_instance`].
+pub struct ImageOutput {
+    weak: Weak<Self>,
+    device: Arc<DeviceContext>,
+    pipeline: Arc<dyn EmulatorPipeline>,
+    image: Image,
+    format: vk::Format,
+    size: Vec2u32,
+    current_layout: vk::ImageLayout,
+    final_layout: vk::ImageLayout,
+}
+
+impl ImageOutput {
+    /// Creates a new image output for a single sampled 2D color image with the specified format
+    /// and size.
+    ///
+    /// The image must have been created with [`vk::ImageUsageFlags::TRANSFER_DST`].
+    pub fn new(
+        device: Arc<DeviceContext>,
+        pipeline: Arc<dyn EmulatorPipeline>,
+        image: Image,
+        format: vk::Format,
+        size: Vec2u32,
+        current_layout: vk::ImageLayout,
+        final_layout: vk::ImageLayout
+    ) -> Result<Arc<Self>, ScaledOutputError> {
+        let (src_format, _) = pipeline.get_color_output().ok_or(ScaledOutputError::NoColorOutput)?;
+        validate_output_blit(&device, src_format, format).map_err(ScaledOutputError::UnsupportedFormat)?;
+
+        Ok(Arc::new_cyclic(|weak| Self {
+            weak: weak.clone(),
+            device,
+            pipeline,
+            image,
+            format,
+            size,
+            current_layout,
+            final_layout,
+        }))
+    }
+
+    pub fn get_image(&self) -> Image {
+        self.image
+    }
+
+    /// Creates a [`EmulatorOutput`] instance which can be used by a single pass.
+    ///
+    /// The wait semaphore op will be waited on before the image is accessed and the signal
+    /// semaphore op will be signaled after the image is in the final layout.
+    pub fn create_instance(&self, wait: Option<SemaphoreOp>, signal: Option<SemaphoreOp>) -> Box<dyn EmulatorOutput + Send> {
+        Box::new(ImageOutputInstance {
+            output: self.weak.upgrade().unwrap(),
+            wait,
+            signal,
+            pipeline_index: None,
+        })
+    }
+}
+
+struct ImageOutputInstance {
+    output: Arc<ImageOutput>,
+    wait: Option<SemaphoreOp>,
+    signal: Option<SemaphoreOp>,
+    pipeline_index: Option<usize>,
+}
+
+impl ImageOutputInstance {
+    fn push_submit<'a>(&self, cmd: vk::CommandBuffer, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump) {
+        let waits: &[vk::SemaphoreSubmitInfo] = match &self.wait {
+            Some(wait) => alloc.alloc([
+                vk::SemaphoreSubmitInfo::builder()
+                    .semaphore(wait.semaphore.get_handle())
+                    .value(wait.value.unwrap_or(0))
+                    .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                    .build()
+            ]),
+            None => &[],
+        };
+
+        let signals: &[vk::SemaphoreSubmitInfo] = match &self.signal {
+            Some(signal) => alloc.alloc([
+                vk::SemaphoreSubmitInfo::builder()
+                    .semaphore(signal.semaphore.get_handle())
+                    .value(signal.value.unwrap_or(0))
+                    .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                    .build()
+            ]),
+            None => &[],
+        };
+
+        let commands = alloc.alloc([
+            vk::CommandBufferSubmitInfo::builder()
+                .command_buffer(cmd)
+                .build()
+        ]);
+
+        submits.push(vk::SubmitInfo2::builder()
+            .wait_semaphore_infos(waits)
+            .command_buffer_infos(commands)
+            .signal_semaphore_infos(signals)
+        );
+    }
+}
+
+impl EmulatorOutput for ImageOutputInstance {
+    fn init(&mut self, pass: &dyn EmulatorPipelinePass, _: &mut PooledObjectProvider) {
+        self.pipeline_index = Some(pass.get_output_index());
+    }
+
+    fn record<'a>(&mut self, obj: &mut PooledObjectProvider, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump) {
+        let output = &self.output;
+        let cmd = obj.get_begin_command_buffer().unwrap();
+
+        let (src_size, _) = output.pipeline.get_output();
+        let (src_format, src_images) = output.pipeline.get_color_output().unwrap();
+        let src_image = Image::new(src_images[self.pipeline_index.unwrap()]);
+
+        record_output_blit(&output.device, cmd, src_image, src_format, src_size, output.image, output.format, output.size, output.final_layout);
+
+        unsafe {
+            output.device.vk().end_command_buffer(cmd)
+        }.unwrap();
+
+        self.push_submit(cmd, submits, alloc);
+    }
+
+    fn record_abort<'a>(&mut self, obj: &mut PooledObjectProvider, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump) {
+        // Only transition the image into the final layout without modifying its contents
+        let output = &self.output;
+        let cmd = obj.get_begin_command_buffer().unwrap();
+
+        let mut tracker = ImageStateTracker::new();
+        tracker.register_with_format(output.image, Format::format_for(output.format), vk::ImageAspectFlags::COLOR, output.current_layout).unwrap();
+
+        let mut barriers = Vec::with_capacity(1);
+        tracker.release_to(output.image.get_id(), output.final_layout, &mut barriers).unwrap();
+
         unsafe {
-            queue.present(&present_info)
+            output.device.synchronization_2().cmd_pipeline_barrier2(cmd, &vk::DependencyInfo::builder().image_memory_barriers(&barriers));
+            output.device.vk().end_command_buffer(cmd)
         }.unwrap();
+
+        self.push_submit(cmd, submits, alloc);
     }
-}
\ No newline at end of file
+
+    fn on_post_submit(&mut self, _: &Queue, _: u64) -> Option<&SurfaceSwapchain> {
+        None
+    }
+}
+
+/// A [`EmulatorOutput`] implementation which copies the depth buffer of a pass into a depth
+/// [`GlobalImage`] so that it can be sampled by later passes. For example to implement shadow maps.
+///
+/// The global image must use the depth format returned by [`EmulatorPipeline::get_depth_output`]
+/// and have the same size as the pipeline output. It is transitioned back into the read only layout
+/// used by all global images after the copy and can be bound with a comparison [`SamplerInfo`].
+/// The image must not be written to by [`GlobalImage::update_regions`] while the pass is running.
+///
+/// Only single sampled depth buffers can be copied. Multisampled pipelines do not expose their
+/// depth buffer and are rejected by [`DepthCopyOutput::new`].
+///
+/// [`SamplerInfo`]: crate::renderer::emulator::SamplerInfo
+pub struct DepthCopyOutput {
+    device: Arc<DeviceContext>,
+    pipeline: Arc<dyn EmulatorPipeline>,
+    image: Arc<GlobalImage>,
+    pipeline_index: Option<usize>,
+}
+
+impl DepthCopyOutput {
+    /// Creates a new depth copy output which can be used by a single pass.
+    ///
+    /// Panics if the pipeline does not expose its depth buffer (for example because it is
+    /// multisampled) or the image is incompatible with it.
+    pub fn new(device: Arc<DeviceContext>, pipeline: Arc<dyn EmulatorPipeline>, image: Arc<GlobalImage>) -> Box<dyn EmulatorOutput + Send> {
+        let (depth_format, _) = pipeline.get_depth_output().unwrap_or_else(|| {
+            log::error!("Pipeline passed to DepthCopyOutput::new does not expose a single sampled depth buffer");
+            panic!()
+        });
+        if image.get_format().get_format() != depth_format {
+            log::error!("Global image format {:?} does not match the pipeline depth format {:?} in DepthCopyOutput::new", image.get_format(), depth_format);
+            panic!()
+        }
+        let (size, _) = pipeline.get_output();
+        if image.get_size() != size {
+            log::error!("Global image size {:?} does not match the pipeline output size {:?} in DepthCopyOutput::new", image.get_size(), size);
+            panic!()
+        }
+
+        Box::new(Self {
+            device,
+            pipeline,
+            image,
+            pipeline_index: None,
+        })
+    }
+}
+
+impl EmulatorOutput for DepthCopyOutput {
+    fn init(&mut self, pass: &dyn EmulatorPipelinePass, _: &mut PooledObjectProvider) {
+        self.pipeline_index = Some(pass.get_output_index());
+    }
+
+    fn record<'a>(&mut self, obj: &mut PooledObjectProvider, submits: &mut SubmitRecorder<'a>, alloc: &'a Bump) {
+        let device = &self.device;
+        let cmd = obj.get_begin_command_buffer().unwrap();
+
+        let (_, depth_images) = self.pipeline.get_depth_output().unwrap();
+        let src_image = depth_images[self.pipeline_index.unwrap()];
+        let dst_image = self.image.get_image_handle();
+        let size = self.image.get_size();
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1
+        };
+
+        let pre_barriers = [
+            vk::ImageMemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)
+                .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .image(src_image)
+                .subresource_range(subresource_range)
+                .build(),
+            vk::ImageMemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::FRAGMENT_SHADER)
+                .src_access_mask(vk::AccessFlags2::NONE)
+                .dst_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .image(dst_image)
+                .subresource_range(subresource_range)
+                .build()
+        ];
+
+        let info = vk::DependencyInfo::builder()
+            .image_memory_barriers(&pre_barriers);
+
+        let subresource_layers = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1
+        };
+        let copy = vk::ImageCopy {
+            src_subresource: subresource_layers,
+            src_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            dst_subresource: subresource_layers,
+            dst_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            extent: vk::Extent3D {
+                width: size[0],
+                height: size[1],
+                depth: 1
+            }
+        };
+
+        unsafe {
+            device.synchronization_2().cmd_pipeline_barrier2(cmd, &info);
+            device.vk().cmd_copy_image(cmd, src_image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, dst_image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, std::slice::from_ref(&copy));
+        }
+
+        let post_barriers = [
+            vk::ImageMemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .src_access_mask(vk::AccessFlags2::NONE)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::NONE)
+                .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image(src_image)
+                .subresource_range(subresource_range)
+                .build(),
+            vk::ImageMemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags2::SHADER_SAMPLED_READ)
+                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image(dst_image)
+                .subresource_range(subresource_range)
+                .build()
+        ];
+
+        let info = vk::DependencyInfo::builder()
+            .image_memory_barriers(&post_barriers);
+
+        unsafe {
+            device.synchronization_2().cmd_pipeline_barrier2(cmd, &info);
+            device.vk().end_command_buffer(cmd)
+        }.unwrap();
+
+        let commands = alloc.alloc([
+            vk::CommandBufferSubmitInfo::builder()
+                .command_buffer(cmd)
+                .build()
+        ]);
+
+        submits.push(vk::SubmitInfo2::builder()
+            .command_buffer_infos(commands)
+        );
+    }
+
+    fn record_abort<'a>(&mut self, _: &mut PooledObjectProvider, _: &mut SubmitRecorder<'a>, _: &'a Bump) {
+        // The depth image keeps its previous contents
+    }
+
+    fn on_post_submit(&mut self, _: &Queue, _: u64) -> Option<&SurfaceSwapchain> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn present_error_from_result() {
+        assert_eq!(PresentError::from(vk::Result::ERROR_DEVICE_LOST), PresentError::DeviceLost);
+        assert_eq!(PresentError::from(vk::Result::ERROR_OUT_OF_DATE_KHR), PresentError::OutOfDate);
+        assert_eq!(PresentError::from(vk::Result::TIMEOUT), PresentError::Timeout);
+        assert_eq!(PresentError::from(vk::Result::ERROR_OUT_OF_HOST_MEMORY), PresentError::Vulkan(vk::Result::ERROR_OUT_OF_HOST_MEMORY));
+    }
+}
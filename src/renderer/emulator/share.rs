@@ -1,4 +1,7 @@
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
 use std::time::{Duration, Instant};
 use std::panic::RefUnwindSafe;
 use std::collections::{HashMap, VecDeque};
@@ -6,17 +9,25 @@ use std::sync::atomic::AtomicU64;
 use ash::vk;
 
 use crate::renderer::emulator::descriptors::DescriptorPool;
-use crate::renderer::emulator::worker::WorkerTask;
-use crate::renderer::emulator::mc_shaders::{McUniform, Shader, ShaderId, VertexFormat};
+use crate::renderer::emulator::worker::{TaskObserver, WorkerTask};
+use crate::renderer::emulator::mc_shaders::{McUniform, Shader, ShaderId, SpecConstantValue, TextureBindingType, VertexFormat};
 
 use crate::prelude::*;
-use crate::renderer::emulator::immediate::{ImmediateBuffer, ImmediatePool};
+use crate::renderer::emulator::immediate::{ImmediateBuffer, ImmediatePool, ImmediatePoolConfig};
 use crate::renderer::emulator::staging::StagingMemoryPool;
+use crate::renderer::emulator::pipeline::{PassTimingHook, PresentHook, PresentTiming};
+use crate::renderer::emulator::{PassId, SamplerInfo};
+use crate::device::device::TrackedObject;
+use crate::device::surface::SurfaceSwapchain;
 
 pub(super) struct Share {
     id: UUID,
     device: Arc<DeviceContext>,
     current_pass: AtomicU64,
+    /// All passes with an id less or equal to this value have completed execution on the gpu.
+    completed_pass: AtomicU64,
+    /// Wakers of [`PassCompletion`] futures and the pass they are waiting for.
+    pass_wakers: Mutex<Vec<(u64, Waker)>>,
 
     staging_memory: Mutex<StagingMemoryPool>,
     immediate_buffers: ImmediatePool,
@@ -24,29 +35,59 @@ pub(super) struct Share {
     descriptors: Mutex<DescriptorPool>,
     channel: Mutex<Channel>,
     signal: Condvar,
+    /// Signaled whenever the worker removes a task from the channel.
+    space_signal: Condvar,
+    present_hook: Mutex<Option<Arc<PresentHook>>>,
+    pass_timing_hook: Mutex<Option<Arc<PassTimingHook>>>,
+    frames_in_flight: Mutex<FramesInFlight>,
+    frames_in_flight_signal: Condvar,
+    default_sampler: Mutex<SamplerInfo>,
 }
 
 impl Share {
     const PASS_ID_ACTIVE_BIT: u64 = 1u64 << 63;
-
-    pub(super) fn new(device: Arc<DeviceContext>) -> Self {
+    const DEFAULT_MAX_FRAMES_IN_FLIGHT: u32 = 2;
+    pub(super) const DEFAULT_TASK_CHANNEL_CAPACITY: usize = 16384;
+    const DEFAULT_SAMPLER: SamplerInfo = SamplerInfo {
+        mag_filter: vk::Filter::LINEAR,
+        min_filter: vk::Filter::LINEAR,
+        mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+        address_mode_u: vk::SamplerAddressMode::REPEAT,
+        address_mode_v: vk::SamplerAddressMode::REPEAT,
+        max_anisotropy: None,
+        mip_lod_bias: 0f32,
+        compare: None,
+    };
+
+    pub(super) fn new(device: Arc<DeviceContext>, immediate_pool: &ImmediatePoolConfig) -> Self {
         let queue = device.get_main_queue();
 
         let staging_memory = StagingMemoryPool::new(device.clone());
-        let immediate_buffers = ImmediatePool::new(device.clone());
+        let immediate_buffers = ImmediatePool::new(device.clone(), immediate_pool);
         let descriptors = Mutex::new(DescriptorPool::new(device.clone()));
 
         Self {
             id: UUID::new(),
             device,
             current_pass: AtomicU64::new(0),
+            completed_pass: AtomicU64::new(0),
+            pass_wakers: Mutex::new(Vec::new()),
 
             staging_memory: Mutex::new(staging_memory),
             immediate_buffers,
             shader_database: Mutex::new(HashMap::new()),
             descriptors,
-            channel: Mutex::new(Channel::new()),
+            channel: Mutex::new(Channel::new(Self::DEFAULT_TASK_CHANNEL_CAPACITY)),
             signal: Condvar::new(),
+            space_signal: Condvar::new(),
+            present_hook: Mutex::new(None),
+            pass_timing_hook: Mutex::new(None),
+            frames_in_flight: Mutex::new(FramesInFlight {
+                current: 0,
+                max: Self::DEFAULT_MAX_FRAMES_IN_FLIGHT,
+            }),
+            frames_in_flight_signal: Condvar::new(),
+            default_sampler: Mutex::new(Self::DEFAULT_SAMPLER),
         }
     }
 
@@ -58,19 +99,22 @@ impl Share {
         &self.staging_memory
     }
 
-    pub(super) fn create_shader(&self, vertex_format: &VertexFormat, used_uniforms: McUniform) -> ShaderId {
-        let shader = Shader::new(*vertex_format, used_uniforms);
+    pub(super) fn create_shader(&self, vertex_format: &VertexFormat, used_uniforms: McUniform, specialization: HashMap<u32, SpecConstantValue>, texture_types: HashMap<u32, TextureBindingType>) -> ShaderId {
+        let shader = Shader::new_with_textures(*vertex_format, used_uniforms, specialization, texture_types);
         let id = shader.get_id();
 
         let mut guard = self.shader_database.lock().unwrap();
         guard.insert(id, shader);
+        self.device.on_object_created(TrackedObject::Shader);
 
         id
     }
 
     pub(super) fn drop_shader(&self, id: ShaderId) {
         let mut guard = self.shader_database.lock().unwrap();
-        guard.remove(&id);
+        if guard.remove(&id).is_some() {
+            self.device.on_object_destroyed(TrackedObject::Shader);
+        }
     }
 
     pub(super) fn get_shader(&self, id: ShaderId) -> Option<Arc<Shader>> {
@@ -120,10 +164,138 @@ impl Share {
         });
     }
 
+    /// Returns the id of the newest pass for which it is guaranteed that it and all passes before
+    /// it have completed execution.
+    pub(super) fn get_completed_pass_id(&self) -> u64 {
+        self.completed_pass.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    pub(super) fn set_completed_pass_id(&self, id: u64) {
+        let old = self.completed_pass.fetch_max(id, std::sync::atomic::Ordering::AcqRel);
+        if old < id {
+            self.wake_completed_passes(id);
+        }
+    }
+
+    /// Returns a future which resolves once the pass has completed execution on the gpu.
+    pub(super) fn pass_completion(self: &Arc<Self>, pass: PassId) -> PassCompletion {
+        PassCompletion {
+            share: self.clone(),
+            pass: pass.get_raw(),
+        }
+    }
+
+    fn wake_completed_passes(&self, completed: u64) {
+        let mut woken = Vec::new();
+        let mut guard = self.pass_wakers.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned pass wakers mutex in Share::wake_completed_passes!");
+            panic!()
+        });
+        guard.retain(|(pass, waker)| {
+            if *pass <= completed {
+                woken.push(waker.clone());
+                false
+            } else {
+                true
+            }
+        });
+        drop(guard);
+
+        // Wakers may run arbitrary code so they must not be called while holding the lock
+        for waker in woken {
+            waker.wake();
+        }
+    }
+
+    /// Blocks until the number of frames in flight is below the maximum and then reserves a slot
+    /// for a new frame. The slot is released by calling [`Share::release_frame_slot`] once all
+    /// resources of the frame have been recycled.
+    ///
+    /// If the worker is paused queued passes keep their slot until the worker is resumed so this
+    /// function waits until another thread resumes the worker.
+    pub(super) fn acquire_frame_slot(&self) {
+        let mut guard = self.frames_in_flight.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned frames in flight mutex in Share::acquire_frame_slot!");
+            panic!()
+        });
+        while guard.current >= guard.max {
+            guard = self.frames_in_flight_signal.wait(guard).unwrap_or_else(|_| {
+                log::error!("Poisoned frames in flight mutex in Share::acquire_frame_slot!");
+                panic!()
+            });
+        }
+        guard.current += 1;
+    }
+
+    /// Non blocking version of [`Share::acquire_frame_slot`]. Returns false if the maximum number
+    /// of frames are currently in flight.
+    pub(super) fn try_acquire_frame_slot(&self) -> bool {
+        let mut guard = self.frames_in_flight.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned frames in flight mutex in Share::try_acquire_frame_slot!");
+            panic!()
+        });
+        if guard.current >= guard.max {
+            return false;
+        }
+        guard.current += 1;
+        true
+    }
+
+    pub(super) fn release_frame_slot(&self) {
+        let mut guard = self.frames_in_flight.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned frames in flight mutex in Share::release_frame_slot!");
+            panic!()
+        });
+        if guard.current == 0 {
+            log::error!("Called Share::release_frame_slot with no frames in flight!");
+            panic!()
+        }
+        guard.current -= 1;
+        self.frames_in_flight_signal.notify_all();
+    }
+
+    pub(super) fn get_frames_in_flight(&self) -> u32 {
+        self.frames_in_flight.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned frames in flight mutex in Share::get_frames_in_flight!");
+            panic!()
+        }).current
+    }
+
+    pub(super) fn set_max_frames_in_flight(&self, max: u32) {
+        if max == 0 {
+            log::error!("Max frames in flight must be at least 1");
+            panic!()
+        }
+        self.frames_in_flight.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned frames in flight mutex in Share::set_max_frames_in_flight!");
+            panic!()
+        }).max = max;
+        self.frames_in_flight_signal.notify_all();
+    }
+
+    /// Sets the sampler used for the placeholder image of passes started afterwards.
+    pub(super) fn set_default_sampler(&self, sampler: SamplerInfo) {
+        *self.default_sampler.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned default sampler mutex in Share::set_default_sampler!");
+            panic!()
+        }) = sampler;
+    }
+
+    pub(super) fn get_default_sampler(&self) -> SamplerInfo {
+        *self.default_sampler.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned default sampler mutex in Share::get_default_sampler!");
+            panic!()
+        })
+    }
+
     pub(super) fn get_next_immediate_buffer(&self) -> Box<ImmediateBuffer> {
         self.immediate_buffers.get_next_buffer()
     }
 
+    pub(super) fn try_get_next_immediate_buffer(&self) -> Option<Box<ImmediateBuffer>> {
+        self.immediate_buffers.try_get_next_buffer()
+    }
+
     pub(super) fn return_immediate_buffer(&self, buffer: Box<ImmediateBuffer>) {
         self.immediate_buffers.return_buffer(buffer);
     }
@@ -132,9 +304,197 @@ impl Share {
         self.descriptors.lock().unwrap().allocate_uniform(data)
     }
 
+    pub(super) fn set_present_hook(&self, hook: Option<Arc<PresentHook>>) {
+        *self.present_hook.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned present hook mutex in Share::set_present_hook!");
+            panic!()
+        }) = hook;
+    }
+
+    /// Invokes the present hook if one is set. Called by the worker after each present.
+    pub(super) fn on_present(&self, frame_index: u64, swapchain: &SurfaceSwapchain) {
+        let hook = self.present_hook.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned present hook mutex in Share::on_present!");
+            panic!()
+        }).clone();
+
+        if let Some(hook) = hook {
+            let cpu_time = Instant::now();
+            let past_presents = match swapchain.get_past_presentation_timing() {
+                Some(Ok(timings)) => timings,
+                Some(Err(err)) => {
+                    log::warn!("vkGetPastPresentationTimingGOOGLE returned {:?} in Share::on_present", err);
+                    Vec::new()
+                }
+                None => Vec::new(),
+            };
+
+            hook(&PresentTiming {
+                frame_index,
+                cpu_time,
+                past_presents: &past_presents
+            });
+        }
+    }
+
+    /// Returns true if the device supports timestamp queries on the main queue.
+    pub(super) fn supports_pass_timing(&self) -> bool {
+        let limits = self.device.get_limits();
+        limits.timestamp_compute_and_graphics == vk::TRUE && limits.timestamp_period > 0f32
+    }
+
+    pub(super) fn set_pass_timing_hook(&self, hook: Option<Arc<PassTimingHook>>) {
+        if hook.is_some() && !self.supports_pass_timing() {
+            log::warn!("Pass timing requested but timestamp queries are not supported by the device. Ignoring hook");
+            return;
+        }
+
+        *self.pass_timing_hook.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned pass timing hook mutex in Share::set_pass_timing_hook!");
+            panic!()
+        }) = hook;
+    }
+
+    /// Returns true if passes should record timestamps.
+    pub(super) fn is_pass_timing_enabled(&self) -> bool {
+        self.pass_timing_hook.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned pass timing hook mutex in Share::is_pass_timing_enabled!");
+            panic!()
+        }).is_some()
+    }
+
+    /// Invokes the pass timing hook if one is set. Called by the worker once a timed pass completed.
+    pub(super) fn on_pass_timing(&self, pass: PassId, duration: Duration) {
+        let hook = self.pass_timing_hook.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned pass timing hook mutex in Share::on_pass_timing!");
+            panic!()
+        }).clone();
+
+        if let Some(hook) = hook {
+            hook(pass, duration);
+        }
+    }
+
+    /// Pushes a task to the worker blocking while the task channel is full.
+    ///
+    /// If the channel has been shut down the task is dropped.
     pub(super) fn push_task(&self, task: WorkerTask) {
-        self.channel.lock().unwrap().queue.push_back(task);
+        let mut guard = self.channel.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned channel mutex in Share::push_task!");
+            panic!()
+        });
+
+        // While paused the worker may not process tasks so blocking here could deadlock the caller
+        while guard.queue.len() >= guard.capacity && !guard.shutdown && !guard.paused {
+            guard = self.space_signal.wait(guard).unwrap_or_else(|_| {
+                log::error!("Poisoned channel mutex in Share::push_task!");
+                panic!()
+            });
+        }
+
+        if guard.shutdown {
+            log::warn!("Task pushed after the emulator worker has been shut down. Dropping task");
+            return;
+        }
+
+        if let Some(observer) = &guard.observer {
+            observer(&task.get_info());
+        }
+        guard.queue.push_back(task);
+        drop(guard);
+
+        self.signal.notify_one();
+    }
+
+    /// Pushes a task to the worker if the task channel is not full. Otherwise the task is dropped.
+    ///
+    /// If the channel has been shut down the task is dropped.
+    pub(super) fn try_push_task(&self, task: WorkerTask) -> Result<(), TaskChannelFull> {
+        let mut guard = self.channel.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned channel mutex in Share::try_push_task!");
+            panic!()
+        });
+
+        if guard.shutdown {
+            log::warn!("Task pushed after the emulator worker has been shut down. Dropping task");
+            return Ok(());
+        }
+
+        if guard.queue.len() >= guard.capacity {
+            return Err(TaskChannelFull);
+        }
+
+        if let Some(observer) = &guard.observer {
+            observer(&task.get_info());
+        }
+        guard.queue.push_back(task);
+        drop(guard);
+
         self.signal.notify_one();
+        Ok(())
+    }
+
+    /// Sets a observer which is called with every task pushed afterwards. Passing [`None`] removes
+    /// the current observer.
+    ///
+    /// The observer is called while the task channel is locked so that tasks are observed in the
+    /// order the worker receives them. It must not push tasks itself.
+    pub(super) fn set_task_observer(&self, observer: Option<Box<TaskObserver>>) {
+        self.channel.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned channel mutex in Share::set_task_observer!");
+            panic!()
+        }).observer = observer;
+    }
+
+    pub(super) fn set_task_channel_capacity(&self, capacity: usize) {
+        self.channel.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned channel mutex in Share::set_task_channel_capacity!");
+            panic!()
+        }).capacity = capacity.max(1);
+
+        self.space_signal.notify_all();
+    }
+
+    /// Pauses the worker. Once the currently active pass (if any) has been submitted the worker
+    /// stops processing tasks until [`Share::resume`] is called. Tasks which are not part of a
+    /// pass and were pushed before the next pass is started are still processed.
+    ///
+    /// While paused the task channel capacity is ignored so pushing tasks never blocks.
+    pub(super) fn pause(&self) {
+        self.channel.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned channel mutex in Share::pause!");
+            panic!()
+        }).paused = true;
+
+        self.space_signal.notify_all();
+    }
+
+    pub(super) fn resume(&self) {
+        self.channel.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned channel mutex in Share::resume!");
+            panic!()
+        }).paused = false;
+
+        self.signal.notify_all();
+    }
+
+    pub(super) fn is_paused(&self) -> bool {
+        self.channel.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned channel mutex in Share::is_paused!");
+            panic!()
+        }).paused
+    }
+
+    /// Stops accepting new tasks. Tasks already in the channel are still handed to the worker after
+    /// which [`NextTaskResult::Shutdown`] is returned.
+    pub(super) fn shutdown_channel(&self) {
+        self.channel.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned channel mutex in Share::shutdown_channel!");
+            panic!()
+        }).shutdown = true;
+
+        self.signal.notify_all();
+        self.space_signal.notify_all();
     }
 
     pub(super) fn try_get_next_task_timeout(&self, timeout: Duration) -> NextTaskResult {
@@ -146,8 +506,17 @@ impl Share {
         });
 
         loop {
-            if let Some(task) = guard.queue.pop_front() {
-                return NextTaskResult::Ok(task);
+            // A pause only takes effect between passes. Shutdown overrides any pause.
+            let blocked = guard.paused && !guard.shutdown && matches!(guard.queue.front(), Some(WorkerTask::StartPass(..)));
+            if !blocked {
+                if let Some(task) = guard.queue.pop_front() {
+                    self.space_signal.notify_one();
+                    return NextTaskResult::Ok(task);
+                }
+            }
+
+            if guard.shutdown {
+                return NextTaskResult::Shutdown;
             }
 
             let diff = (start + timeout).saturating_duration_since(Instant::now());
@@ -177,6 +546,15 @@ impl PartialEq for Share {
 impl Eq for Share {
 }
 
+impl Drop for Share {
+    fn drop(&mut self) {
+        let shaders = self.shader_database.get_mut().map(|shaders| shaders.len()).unwrap_or(0);
+        for _ in 0..shaders {
+            self.device.on_object_destroyed(TrackedObject::Shader);
+        }
+    }
+}
+
 // Condvar issues
 impl RefUnwindSafe for Share {
 }
@@ -184,16 +562,67 @@ impl RefUnwindSafe for Share {
 pub(in crate::renderer::emulator) enum NextTaskResult {
     Ok(WorkerTask),
     Timeout,
+    /// The channel has been shut down and all remaining tasks have been handed out.
+    Shutdown,
+}
+
+/// Future returned by [`Share::pass_completion`].
+pub(super) struct PassCompletion {
+    share: Arc<Share>,
+    pass: u64,
+}
+
+impl Future for PassCompletion {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.share.get_completed_pass_id() >= self.pass {
+            return Poll::Ready(());
+        }
+
+        let mut guard = self.share.pass_wakers.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned pass wakers mutex in PassCompletion::poll!");
+            panic!()
+        });
+
+        // The pass may have completed before we acquired the lock in which case nobody will wake us
+        if self.share.get_completed_pass_id() >= self.pass {
+            return Poll::Ready(());
+        }
+
+        if !guard.iter().any(|(pass, waker)| *pass == self.pass && waker.will_wake(cx.waker())) {
+            guard.push((self.pass, cx.waker().clone()));
+        }
+        Poll::Pending
+    }
 }
 
+/// Returned by non blocking task submissions if the task channel of the worker is full.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TaskChannelFull;
+
 struct Channel {
     queue: VecDeque<WorkerTask>,
+    capacity: usize,
+    shutdown: bool,
+    paused: bool,
+    observer: Option<Box<TaskObserver>>,
+}
+
+struct FramesInFlight {
+    /// The number of passes started whose resources have not been recycled yet.
+    current: u32,
+    max: u32,
 }
 
 impl Channel {
-    fn new() -> Self {
+    fn new(capacity: usize) -> Self {
         Self {
-            queue: VecDeque::new()
+            queue: VecDeque::new(),
+            capacity,
+            shutdown: false,
+            paused: false,
+            observer: None,
         }
     }
 }
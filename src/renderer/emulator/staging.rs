@@ -4,6 +4,7 @@ use std::sync::Arc;
 use ash::vk;
 
 use crate::prelude::DeviceContext;
+use crate::renderer::emulator::worker::get_upload_sharing_mode;
 use crate::util::alloc::RingAllocator;
 use crate::vk::objects::allocator::{Allocation, AllocationStrategy};
 
@@ -46,6 +47,7 @@ impl StagingMemoryPool {
         }
     }
 
+    /// Allocates a range of the staging memory. The alignment must be a power of 2.
     pub(super) fn allocate(&mut self, size: vk::DeviceSize, alignment: vk::DeviceSize) -> (StagingAllocation, StagingAllocationId) {
         if let Some((alloc, slot_id)) = self.current_buffer.try_allocate(size, alignment) {
             (alloc, StagingAllocationId{ buffer_id: self.current_buffer_id, slot_id })
@@ -125,10 +127,14 @@ struct StagingBuffer {
 
 impl StagingBuffer {
     fn new(device: Arc<DeviceContext>, size: vk::DeviceSize) -> Self {
+        // Staging memory is read by the async transfer queue as well as the main queue and no
+        // ownership transfers are recorded for it
+        let sharing_mode = get_upload_sharing_mode(&device);
         let info = vk::BufferCreateInfo::builder()
             .size(size)
             .usage(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            .sharing_mode(sharing_mode.get_vk_sharing_mode())
+            .queue_family_indices(sharing_mode.get_queue_families());
 
         let buffer = unsafe {
             device.vk().create_buffer(&info, None)
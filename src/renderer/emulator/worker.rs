@@ -1,8 +1,10 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, CString};
 use std::marker::PhantomData;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::sync::mpsc::SyncSender;
 use std::time::Duration;
 
 use ash::prelude::VkResult;
@@ -10,29 +12,104 @@ use ash::vk;
 use bumpalo::Bump;
 
 use crate::device::device::Queue;
+use crate::device::transfer::resource_state::PassDependencyTracker;
 
 use crate::renderer::emulator::pass::PassId;
 use crate::renderer::emulator::immediate::ImmediateBuffer;
-use crate::renderer::emulator::pipeline::{EmulatorOutput, EmulatorPipeline, EmulatorPipelinePass, PipelineTask};
+use crate::renderer::emulator::pipeline::{EmulatorOutput, EmulatorPipeline, EmulatorPipelinePass, EmulatorPipelineSecondary, PipelineTask};
 
 use crate::prelude::*;
 use crate::renderer::emulator::global_objects::{GlobalImage, GlobalMesh};
 use crate::renderer::emulator::mc_shaders::ShaderId;
+use crate::util::vk::{begin_label, end_label};
+use crate::vk::objects::types::SharingMode;
 use crate::renderer::emulator::share::{NextTaskResult, Share};
 use crate::renderer::emulator::staging::StagingAllocationId;
 
 pub(super) enum WorkerTask {
     StartPass(PassId, Arc<dyn EmulatorPipeline>, Box<dyn EmulatorPipelinePass + Send>, Arc<GlobalImage>, vk::Sampler),
     EndPass(Box<ImmediateBuffer>),
+    AbortPass(Box<ImmediateBuffer>),
     UseGlobalMesh(Arc<GlobalMesh>),
     UseGlobalImage(Arc<GlobalImage>),
+    /// Transitions a global image into [`vk::ImageLayout::GENERAL`] for the duration of the pass.
+    UseStorageImage(Arc<GlobalImage>),
     UseShader(ShaderId),
+    UseIndirectBuffer(vk::Buffer),
     UseOutput(Box<dyn EmulatorOutput + Send>),
+    /// Wraps all commands of the pass in a debug label with the given name and color.
+    SetPassLabel(CString, [f32; 4]),
     PipelineTask(PipelineTask),
+    ExecuteSecondary(SecondaryCommands),
     WriteGlobalMesh(GlobalMeshWrite, bool),
     ClearGlobalImage(GlobalImageClear, bool),
     WriteGlobalImage(GlobalImageWrite),
     GenerateGlobalImageMipmaps(Arc<GlobalImage>, PassId),
+    ReadBackGlobalImage(GlobalImageReadBack),
+}
+
+impl WorkerTask {
+    /// Returns a read only view of this task which can be passed to a [`TaskObserver`].
+    pub(super) fn get_info(&self) -> TaskInfo {
+        match self {
+            WorkerTask::StartPass(id, ..) => TaskInfo::StartPass(*id),
+            WorkerTask::EndPass(_) => TaskInfo::EndPass,
+            WorkerTask::AbortPass(_) => TaskInfo::AbortPass,
+            WorkerTask::UseGlobalMesh(mesh) => TaskInfo::UseGlobalMesh(mesh),
+            WorkerTask::UseGlobalImage(image) => TaskInfo::UseGlobalImage(image),
+            WorkerTask::UseStorageImage(image) => TaskInfo::UseStorageImage(image),
+            WorkerTask::UseShader(shader) => TaskInfo::UseShader(*shader),
+            WorkerTask::UseIndirectBuffer(buffer) => TaskInfo::UseIndirectBuffer(*buffer),
+            WorkerTask::UseOutput(_) => TaskInfo::UseOutput,
+            WorkerTask::SetPassLabel(name, color) => TaskInfo::SetPassLabel(name, *color),
+            WorkerTask::PipelineTask(task) => TaskInfo::PipelineTask(task),
+            WorkerTask::ExecuteSecondary(_) => TaskInfo::ExecuteSecondary,
+            WorkerTask::WriteGlobalMesh(write, _) => TaskInfo::WriteGlobalMesh(&write.dst_mesh, write.after_pass),
+            WorkerTask::ClearGlobalImage(clear, _) => TaskInfo::ClearGlobalImage(&clear.dst_image, clear.after_pass),
+            WorkerTask::WriteGlobalImage(write) => TaskInfo::WriteGlobalImage(&write.dst_image, write.after_pass),
+            WorkerTask::GenerateGlobalImageMipmaps(image, after_pass) => TaskInfo::GenerateGlobalImageMipmaps(image, *after_pass),
+            WorkerTask::ReadBackGlobalImage(read_back) => TaskInfo::ReadBackGlobalImage(&read_back.src_image),
+        }
+    }
+}
+
+/// A read only view of a task submitted to the emulator worker. Passed to a [`TaskObserver`].
+///
+/// Global object writes contain the pass after which they are executed.
+#[derive(Copy, Clone)]
+pub enum TaskInfo<'a> {
+    StartPass(PassId),
+    EndPass,
+    AbortPass,
+    UseGlobalMesh(&'a Arc<GlobalMesh>),
+    UseGlobalImage(&'a Arc<GlobalImage>),
+    UseStorageImage(&'a Arc<GlobalImage>),
+    UseShader(ShaderId),
+    UseIndirectBuffer(vk::Buffer),
+    UseOutput,
+    SetPassLabel(&'a CStr, [f32; 4]),
+    PipelineTask(&'a PipelineTask),
+    ExecuteSecondary,
+    WriteGlobalMesh(&'a Arc<GlobalMesh>, PassId),
+    ClearGlobalImage(&'a Arc<GlobalImage>, PassId),
+    WriteGlobalImage(&'a Arc<GlobalImage>, PassId),
+    GenerateGlobalImageMipmaps(&'a Arc<GlobalImage>, PassId),
+    ReadBackGlobalImage(&'a Arc<GlobalImage>),
+}
+
+/// A callback invoked with every task submitted to the emulator worker in submission order. Can be
+/// used to record a trace of the command stream for debugging or testing.
+pub type TaskObserver = dyn Fn(&TaskInfo) + Send + Sync;
+
+/// A secondary command buffer recorded by a [`SecondaryRecorder`] together with all objects it uses.
+///
+/// [`SecondaryRecorder`]: crate::renderer::emulator::SecondaryRecorder
+pub(super) struct SecondaryCommands {
+    pub(super) recorder: Box<dyn EmulatorPipelineSecondary + Send>,
+    pub(super) command_buffer: vk::CommandBuffer,
+    pub(super) shaders: Vec<ShaderId>,
+    pub(super) global_meshes: Vec<Arc<GlobalMesh>>,
+    pub(super) global_images: Vec<Arc<GlobalImage>>,
 }
 
 pub(super) struct GlobalMeshWrite {
@@ -53,18 +130,99 @@ pub(super) struct GlobalImageWrite {
     pub(super) regions: Box<[vk::BufferImageCopy]>,
 }
 
+pub(super) struct GlobalImageReadBack {
+    pub(super) src_image: Arc<GlobalImage>,
+    pub(super) dst_buffer: vk::Buffer,
+    pub(super) regions: Box<[vk::BufferImageCopy]>,
+    /// Sent to once the read back has completed execution. Dropped without sending if the read
+    /// back is never submitted.
+    pub(super) signal: SyncSender<()>,
+}
+
 pub(super) struct GlobalImageClear {
     pub(super) after_pass: PassId,
-    pub(super) clear_value: vk::ClearColorValue,
+    /// If [`None`] the image is only transitioned and its contents are left undefined. Depth images
+    /// cannot be cleared by a transfer queue and must be written before they are sampled.
+    pub(super) clear_value: Option<vk::ClearColorValue>,
     pub(super) dst_image: Arc<GlobalImage>,
 }
 
+/// Returns the queue used to upload global objects concurrently to rendering or [`None`] if all
+/// work is submitted to the main queue.
+///
+/// A separate queue is only used if the device has a async transfer queue from a different queue
+/// family than the main queue.
+///
+/// Only writes to uninitialized global meshes are executed on this queue. All other global object
+/// operations stay on the main queue:
+/// - Updates of initialized meshes and all image writes must be ordered after passes executed on
+///   the main queue. Moving them to the transfer queue would require releasing ownership on the
+///   main queue, waiting for that on the transfer queue and acquiring it back, which serializes
+///   both queues and gains no overlap.
+/// - Global images are initialized by a clear or transition on creation. Clears cannot be
+///   executed on a transfer queue so every image write targets an initialized image.
+/// - Mipmap generation uses blits or compute shaders and read backs must be ordered after the
+///   current pass.
+pub(super) fn get_async_transfer_queue(device: &DeviceContext) -> Option<&Arc<Queue>> {
+    device.get_async_transfer_queue().filter(|queue| {
+        queue.get_queue_family_index() != device.get_main_queue().get_queue_family_index()
+    })
+}
+
+/// Returns the sharing mode used for buffers which are accessed by both the main queue and the
+/// async transfer queue without a queue family ownership transfer (for example staging memory which
+/// is written by the host and may be read on either queue).
+pub(super) fn get_upload_sharing_mode(device: &DeviceContext) -> SharingMode {
+    match get_async_transfer_queue(device) {
+        Some(transfer_queue) => SharingMode::concurrent(&[
+            device.get_main_queue().get_queue_family_index(),
+            transfer_queue.get_queue_family_index()
+        ]),
+        None => SharingMode::Exclusive,
+    }
+}
+
+/// Configures the thread running the emulator worker.
+///
+/// The emulator uses a single worker thread which owns the main queue. It processes all tasks in
+/// submission order and is the only thread submitting work of the emulator. Secondary command
+/// buffers are not recorded by the worker. They are recorded by [`SecondaryRecorder`]s on any
+/// number of caller threads and only handed to the worker for execution, so cpu side recording
+/// scales with the number of threads the caller uses without additional worker threads.
+///
+/// [`SecondaryRecorder`]: crate::renderer::emulator::SecondaryRecorder
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WorkerConfig {
+    /// The name of the worker thread. Shows up in debuggers and profilers.
+    pub thread_name: String,
+
+    /// The stack size of the worker thread in bytes. If [`None`] the default of the standard
+    /// library is used.
+    pub stack_size: Option<usize>,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self {
+            thread_name: String::from("b4d-emulator-worker"),
+            stack_size: None,
+        }
+    }
+}
+
 pub(super) fn run_worker(device: Arc<DeviceContext>, share: Arc<Share>) {
     let queue = device.get_main_queue();
 
     let pool = Rc::new(RefCell::new(WorkerObjectPool::new(device.clone(), queue.get_queue_family_index())));
+    let async_transfer = get_async_transfer_queue(&device).map(|transfer_queue| {
+        log::debug!("Emulator worker using async transfer queue family {:?}", transfer_queue.get_queue_family_index());
+        Rc::new(RefCell::new(AsyncTransfer::new(device.clone(), transfer_queue.clone())))
+    });
     let mut current_pass: Option<PassState> = None;
+    let mut newest_pass = 0u64;
     let mut old_frames = Vec::new();
+    // Global objects recorders submitted outside of a pass
+    let mut old_recorders = Vec::new();
 
     // A global objects recorder submitted before the current frame.
     // If no active pass exits this **must** be [`None`].
@@ -79,10 +237,19 @@ pub(super) fn run_worker(device: Arc<DeviceContext>, share: Arc<Share>) {
         old_frames.retain(|old: &PassState| {
             !old.is_complete()
         });
+        old_recorders.retain(|old: &GlobalObjectsRecorder| {
+            !old.is_complete()
+        });
+
+        let oldest_pending = old_frames.iter().map(|old| old.pass_id.get_raw())
+            .chain(current_pass.as_ref().map(|pass| pass.pass_id.get_raw()))
+            .min();
+        share.set_completed_pass_id(oldest_pending.map(|id| id - 1).unwrap_or(newest_pass));
 
         let task = match share.try_get_next_task_timeout(Duration::from_micros(500)) {
             NextTaskResult::Ok(task) => task,
             NextTaskResult::Timeout => continue,
+            NextTaskResult::Shutdown => break,
         };
 
         match task {
@@ -91,6 +258,7 @@ pub(super) fn run_worker(device: Arc<DeviceContext>, share: Arc<Share>) {
                     log::error!("Worker received WorkerTask::StartPass when a pass is already running");
                     panic!()
                 }
+                newest_pass = id.get_raw();
                 let state = PassState::new(id, pipeline, pass, device.clone(), &queue, share.clone(), pool.clone(), placeholder_image, placeholder_sampler);
                 current_pass = Some(state);
                 current_global_recorder = next_global_recorder.take();
@@ -101,12 +269,24 @@ pub(super) fn run_worker(device: Arc<DeviceContext>, share: Arc<Share>) {
                     pass.use_immediate_buffer(immediate_buffer);
                     pass.submit(&queue, current_global_recorder.take());
                     old_frames.push(pass);
+                    flush_read_backs(&mut next_global_recorder, &mut old_recorders, &queue);
                 } else {
                     log::error!("Worker received WorkerTask::EndPass when no active pass exists");
                     panic!()
                 }
             }
 
+            WorkerTask::AbortPass(immediate_buffer) => {
+                if let Some(mut pass) = current_pass.take() {
+                    pass.abort(&queue, immediate_buffer, current_global_recorder.take());
+                    old_frames.push(pass);
+                    flush_read_backs(&mut next_global_recorder, &mut old_recorders, &queue);
+                } else {
+                    log::error!("Worker received WorkerTask::AbortPass when no active pass exists");
+                    panic!()
+                }
+            }
+
             WorkerTask::UseGlobalMesh(mesh) => {
                 if let Some(pass) = &mut current_pass {
                     pass.global_meshes.push(mesh)
@@ -125,6 +305,15 @@ pub(super) fn run_worker(device: Arc<DeviceContext>, share: Arc<Share>) {
                 }
             }
 
+            WorkerTask::UseStorageImage(image) => {
+                if let Some(pass) = &mut current_pass {
+                    pass.use_storage_image(image);
+                } else {
+                    log::error!("Worker received WorkerTask::UseStorageImage when no active pass exists");
+                    panic!()
+                }
+            }
+
             WorkerTask::UseShader(shader) => {
                 if let Some(pass) = &mut current_pass {
                     pass.shaders.push(shader);
@@ -134,6 +323,15 @@ pub(super) fn run_worker(device: Arc<DeviceContext>, share: Arc<Share>) {
                 }
             }
 
+            WorkerTask::UseIndirectBuffer(buffer) => {
+                if let Some(pass) = &mut current_pass {
+                    pass.use_indirect_buffer(buffer);
+                } else {
+                    log::error!("Worker received WorkerTask::UseIndirectBuffer when no active pass exists");
+                    panic!()
+                }
+            }
+
             WorkerTask::UseOutput(output) => {
                 if let Some(pass) = &mut current_pass {
                     pass.use_output(output);
@@ -143,6 +341,15 @@ pub(super) fn run_worker(device: Arc<DeviceContext>, share: Arc<Share>) {
                 }
             }
 
+            WorkerTask::SetPassLabel(name, color) => {
+                if let Some(pass) = &mut current_pass {
+                    pass.set_label(&name, color);
+                } else {
+                    log::error!("Worker received WorkerTask::SetPassLabel when no active pass exists");
+                    panic!()
+                }
+            }
+
             WorkerTask::PipelineTask(task) => {
                 if let Some(pass) = &mut current_pass {
                     pass.process_task(&task)
@@ -152,62 +359,131 @@ pub(super) fn run_worker(device: Arc<DeviceContext>, share: Arc<Share>) {
                 }
             }
 
+            WorkerTask::ExecuteSecondary(commands) => {
+                if let Some(pass) = &mut current_pass {
+                    pass.execute_secondary(commands)
+                } else {
+                    log::error!("Worker received WorkerTask::ExecuteSecondary when no active pass exists");
+                    panic!()
+                }
+            }
+
             WorkerTask::WriteGlobalMesh(write, uninit) => {
                 if let Some(current_pass) = &current_pass {
                     if current_pass.pass_id > write.after_pass {
-                        get_or_create_recorder(&mut current_global_recorder, &share, &pool).record_global_buffer_write(write, uninit);
+                        get_or_create_recorder(&mut current_global_recorder, &share, &pool, &async_transfer).record_global_buffer_write(write, uninit);
                     } else {
-                        get_or_create_recorder(&mut next_global_recorder, &share, &pool).record_global_buffer_write(write, uninit);
+                        get_or_create_recorder(&mut next_global_recorder, &share, &pool, &async_transfer).record_global_buffer_write(write, uninit);
                     }
                 } else {
-                    get_or_create_recorder(&mut next_global_recorder, &share, &pool).record_global_buffer_write(write, uninit);
+                    get_or_create_recorder(&mut next_global_recorder, &share, &pool, &async_transfer).record_global_buffer_write(write, uninit);
                 }
             }
 
             WorkerTask::ClearGlobalImage(clear, uninit) => {
                 if let Some(current_pass) = &current_pass {
                     if current_pass.pass_id > clear.after_pass {
-                        get_or_create_recorder(&mut current_global_recorder, &share, &pool).record_global_image_clear(clear, uninit);
+                        get_or_create_recorder(&mut current_global_recorder, &share, &pool, &async_transfer).record_global_image_clear(clear, uninit);
                     } else {
-                        get_or_create_recorder(&mut next_global_recorder, &share, &pool).record_global_image_clear(clear, uninit);
+                        get_or_create_recorder(&mut next_global_recorder, &share, &pool, &async_transfer).record_global_image_clear(clear, uninit);
                     }
                 } else {
-                    get_or_create_recorder(&mut next_global_recorder, &share, &pool).record_global_image_clear(clear, uninit);
+                    get_or_create_recorder(&mut next_global_recorder, &share, &pool, &async_transfer).record_global_image_clear(clear, uninit);
                 }
             }
 
             WorkerTask::WriteGlobalImage(write) => {
                 if let Some(current_pass) = &current_pass {
                     if current_pass.pass_id > write.after_pass {
-                        get_or_create_recorder(&mut current_global_recorder, &share, &pool).record_global_image_write(write, false);
+                        get_or_create_recorder(&mut current_global_recorder, &share, &pool, &async_transfer).record_global_image_write(write, false);
                     } else {
-                        get_or_create_recorder(&mut next_global_recorder, &share, &pool).record_global_image_write(write, false);
+                        get_or_create_recorder(&mut next_global_recorder, &share, &pool, &async_transfer).record_global_image_write(write, false);
                     }
                 } else {
-                    get_or_create_recorder(&mut next_global_recorder, &share, &pool).record_global_image_write(write, false);
+                    get_or_create_recorder(&mut next_global_recorder, &share, &pool, &async_transfer).record_global_image_write(write, false);
                 }
             }
 
             WorkerTask::GenerateGlobalImageMipmaps(image, after_pass) => {
                 if let Some(current_pass) = &current_pass {
                     if current_pass.pass_id > after_pass {
-                        get_or_create_recorder(&mut current_global_recorder, &share, &pool).record_global_image_generate_mipmaps(image);
+                        get_or_create_recorder(&mut current_global_recorder, &share, &pool, &async_transfer).record_global_image_generate_mipmaps(image);
                     } else {
-                        get_or_create_recorder(&mut next_global_recorder, &share, &pool).record_global_image_generate_mipmaps(image);
+                        get_or_create_recorder(&mut next_global_recorder, &share, &pool, &async_transfer).record_global_image_generate_mipmaps(image);
                     }
                 } else {
-                    get_or_create_recorder(&mut next_global_recorder, &share, &pool).record_global_image_generate_mipmaps(image);
+                    get_or_create_recorder(&mut next_global_recorder, &share, &pool, &async_transfer).record_global_image_generate_mipmaps(image);
+                }
+            }
+
+            WorkerTask::ReadBackGlobalImage(read_back) => {
+                // Read backs must be ordered after the current pass since it may write the image
+                get_or_create_recorder(&mut next_global_recorder, &share, &pool, &async_transfer).record_global_image_read_back(read_back);
+                if current_pass.is_none() {
+                    flush_read_backs(&mut next_global_recorder, &mut old_recorders, &queue);
                 }
             }
         }
     }
+
+    // The channel has been drained so a pass which is still active will never be ended
+    if let Some(pass) = current_pass.take() {
+        log::warn!("Emulator worker shut down while pass {:?} is active. Discarding pass", pass.pass_id);
+        drop(pass);
+    }
+
+    // Updates which have not been submitted yet are never needed
+    drop(current_global_recorder);
+    drop(next_global_recorder);
+
+    // All submitted work must complete before any of the used resources are freed
+    wait_queue_idle(queue);
+    if device.has_separate_present_queue() {
+        wait_queue_idle(device.get_present_queue());
+    }
+    if let Some(async_transfer) = &async_transfer {
+        wait_queue_idle(&async_transfer.borrow().queue);
+    }
+
+    // The pools are destroyed once the last pass releases its pooled objects
+    drop(old_frames);
+    drop(old_recorders);
+    drop(async_transfer);
+    drop(pool);
+
+    // No pass will execute after this point so any pending completion futures must be resolved
+    share.set_completed_pass_id(u64::MAX);
 }
 
-fn get_or_create_recorder<'a>(recorder: &'a mut Option<GlobalObjectsRecorder>, share: &Arc<Share>, object_pool: &Rc<RefCell<WorkerObjectPool>>) -> &'a mut GlobalObjectsRecorder {
+/// Submits the recorder outside of a pass if it contains any read backs. Read backs block the
+/// thread requesting them so they cannot wait for the next pass to be submitted.
+fn flush_read_backs(recorder: &mut Option<GlobalObjectsRecorder>, old_recorders: &mut Vec<GlobalObjectsRecorder>, queue: &Queue) {
+    if recorder.as_ref().map(|recorder| recorder.has_read_backs()).unwrap_or(false) {
+        let mut recorder = recorder.take().unwrap();
+        recorder.submit_standalone(queue);
+        old_recorders.push(recorder);
+    }
+}
+
+fn wait_queue_idle(queue: &Queue) {
+    unsafe {
+        queue.wait_idle()
+    }.unwrap_or_else(|err| {
+        log::error!("vkQueueWaitIdle returned {:?} in run_worker", err);
+        panic!()
+    });
+}
+
+fn get_or_create_recorder<'a>(
+    recorder: &'a mut Option<GlobalObjectsRecorder>,
+    share: &Arc<Share>,
+    object_pool: &Rc<RefCell<WorkerObjectPool>>,
+    async_transfer: &Option<Rc<RefCell<AsyncTransfer>>>
+) -> &'a mut GlobalObjectsRecorder {
     if let Some(recorder) = recorder {
         recorder
     } else {
-        *recorder = Some(GlobalObjectsRecorder::new(share.clone(), object_pool.clone()));
+        *recorder = Some(GlobalObjectsRecorder::new(share.clone(), object_pool.clone(), async_transfer.clone()));
         recorder.as_mut().unwrap()
     }
 }
@@ -216,7 +492,9 @@ struct WorkerObjectPool {
     device: Arc<DeviceContext>,
     command_pool: vk::CommandPool,
     command_buffers: Vec<vk::CommandBuffer>,
+    secondary_command_buffers: Vec<vk::CommandBuffer>,
     fences: Vec<vk::Fence>,
+    timestamp_pools: Vec<vk::QueryPool>,
 }
 
 impl WorkerObjectPool {
@@ -233,7 +511,9 @@ impl WorkerObjectPool {
             device,
             command_pool,
             command_buffers: Vec::new(),
+            secondary_command_buffers: Vec::new(),
             fences: Vec::new(),
+            timestamp_pools: Vec::new(),
         }
     }
 
@@ -262,6 +542,27 @@ impl WorkerObjectPool {
         self.command_buffers.extend_from_slice(buffers);
     }
 
+    fn get_secondary_buffer(&mut self) -> vk::CommandBuffer {
+        if self.secondary_command_buffers.is_empty() {
+            let info = vk::CommandBufferAllocateInfo::builder()
+                .command_pool(self.command_pool)
+                .level(vk::CommandBufferLevel::SECONDARY)
+                .command_buffer_count(8);
+
+            let buffers = unsafe {
+                self.device.vk().allocate_command_buffers(&info)
+            }.unwrap();
+
+            self.secondary_command_buffers.extend(buffers);
+        }
+
+        self.secondary_command_buffers.pop().unwrap()
+    }
+
+    fn return_secondary_buffers(&mut self, buffers: &[vk::CommandBuffer]) {
+        self.secondary_command_buffers.extend_from_slice(buffers);
+    }
+
     fn get_fence(&mut self) -> vk::Fence {
         if self.fences.is_empty() {
             let info = vk::FenceCreateInfo::builder();
@@ -279,13 +580,233 @@ impl WorkerObjectPool {
     fn return_fence(&mut self, fence: vk::Fence) {
         self.fences.push(fence);
     }
+
+    /// Returns a query pool with 2 timestamp queries.
+    fn get_timestamp_pool(&mut self) -> vk::QueryPool {
+        if let Some(pool) = self.timestamp_pools.pop() {
+            return pool;
+        }
+
+        let info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(2);
+
+        unsafe {
+            self.device.vk().create_query_pool(&info, None)
+        }.unwrap_or_else(|err| {
+            log::error!("vkCreateQueryPool returned {:?} in WorkerObjectPool::get_timestamp_pool", err);
+            panic!()
+        })
+    }
+
+    fn return_timestamp_pools(&mut self, pools: &[vk::QueryPool]) {
+        self.timestamp_pools.extend_from_slice(pools);
+    }
+}
+
+impl Drop for WorkerObjectPool {
+    fn drop(&mut self) {
+        unsafe {
+            // Also frees all command buffers allocated from the pool
+            self.device.vk().destroy_command_pool(self.command_pool, None);
+            for fence in &self.fences {
+                self.device.vk().destroy_fence(*fence, None);
+            }
+            for pool in &self.timestamp_pools {
+                self.device.vk().destroy_query_pool(*pool, None);
+            }
+        }
+    }
+}
+
+/// Objects needed to submit work to the async transfer queue.
+struct AsyncTransfer {
+    device: Arc<DeviceContext>,
+    queue: Arc<Queue>,
+    pool: Rc<RefCell<WorkerObjectPool>>,
+
+    /// Timeline semaphore signaled by transfer submissions.
+    semaphore: vk::Semaphore,
+    last_value: u64,
+}
+
+impl AsyncTransfer {
+    fn new(device: Arc<DeviceContext>, queue: Arc<Queue>) -> Self {
+        let pool = Rc::new(RefCell::new(WorkerObjectPool::new(device.clone(), queue.get_queue_family_index())));
+
+        let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+
+        let info = vk::SemaphoreCreateInfo::builder()
+            .push_next(&mut type_info);
+
+        let semaphore = unsafe {
+            device.vk().create_semaphore(&info, None)
+        }.unwrap_or_else(|err| {
+            log::error!("vkCreateSemaphore returned {:?} in AsyncTransfer::new", err);
+            panic!()
+        });
+
+        Self {
+            device,
+            queue,
+            pool,
+            semaphore,
+            last_value: 0,
+        }
+    }
+
+    fn get_queue_family_index(&self) -> u32 {
+        self.queue.get_queue_family_index()
+    }
+
+    /// Submits a command buffer to the transfer queue and returns the value the timeline
+    /// semaphore will be signaled with once execution completes.
+    fn submit(&mut self, cmd: vk::CommandBuffer) -> u64 {
+        self.last_value += 1;
+
+        let cmd_info = vk::CommandBufferSubmitInfo::builder()
+            .command_buffer(cmd);
+
+        let signal_info = vk::SemaphoreSubmitInfo::builder()
+            .semaphore(self.semaphore)
+            .value(self.last_value)
+            .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS);
+
+        let submit_info = vk::SubmitInfo2::builder()
+            .command_buffer_infos(std::slice::from_ref(&cmd_info))
+            .signal_semaphore_infos(std::slice::from_ref(&signal_info));
+
+        unsafe {
+            self.queue.submit_2(std::slice::from_ref(&submit_info), None)
+        }.unwrap_or_else(|err| {
+            log::error!("vkQueueSubmit2 returned {:?} in AsyncTransfer::submit", err);
+            panic!()
+        });
+
+        self.last_value
+    }
+}
+
+impl Drop for AsyncTransfer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.vk().destroy_semaphore(self.semaphore, None);
+        }
+    }
+}
+
+/// Global object writes recorded for the async transfer queue.
+///
+/// Resources written on the transfer queue are released to the main queue family at the end of
+/// the transfer command buffer and acquired by the main queue before any other use.
+struct TransferRecording {
+    async_transfer: Rc<RefCell<AsyncTransfer>>,
+    object_pool: PooledObjectProvider,
+    cmd: Option<vk::CommandBuffer>,
+
+    release_barriers: Vec<vk::BufferMemoryBarrier2>,
+    acquire_barriers: Vec<vk::BufferMemoryBarrier2>,
+
+    /// Meshes written on the transfer queue. Just here to keep them alive until execution completes.
+    used_global_meshes: Vec<Arc<GlobalMesh>>,
+}
+
+impl TransferRecording {
+    fn new(share: Arc<Share>, async_transfer: Rc<RefCell<AsyncTransfer>>) -> Self {
+        let object_pool = PooledObjectProvider::new(share, async_transfer.borrow().pool.clone());
+
+        Self {
+            async_transfer,
+            object_pool,
+            cmd: None,
+            release_barriers: Vec::new(),
+            acquire_barriers: Vec::new(),
+            used_global_meshes: Vec::new(),
+        }
+    }
+
+    fn get_cmd(&mut self) -> vk::CommandBuffer {
+        if let Some(cmd) = self.cmd {
+            cmd
+        } else {
+            let cmd = self.object_pool.get_begin_command_buffer().unwrap_or_else(|err| {
+                log::error!("Failed to begin transfer command buffer {:?}", err);
+                panic!();
+            });
+            self.cmd = Some(cmd);
+            cmd
+        }
+    }
+
+    /// Records a write to a uninitialized mesh.
+    fn record_mesh_write(&mut self, device: &DeviceContext, write: &GlobalMeshWrite, main_family: u32) {
+        let cmd = self.get_cmd();
+        let dst_buffer = write.dst_mesh.get_buffer_handle();
+        let transfer_family = self.async_transfer.borrow().get_queue_family_index();
+
+        unsafe {
+            device.vk().cmd_copy_buffer(cmd, write.staging_buffer, dst_buffer, write.regions.as_ref());
+        }
+
+        self.release_barriers.push(vk::BufferMemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .src_queue_family_index(transfer_family)
+            .dst_queue_family_index(main_family)
+            .buffer(dst_buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build()
+        );
+
+        self.acquire_barriers.push(vk::BufferMemoryBarrier2::builder()
+            .src_queue_family_index(transfer_family)
+            .dst_queue_family_index(main_family)
+            .dst_stage_mask(vk::PipelineStageFlags2::VERTEX_INPUT)
+            .dst_access_mask(vk::AccessFlags2::VERTEX_ATTRIBUTE_READ | vk::AccessFlags2::INDEX_READ)
+            .buffer(dst_buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build()
+        );
+
+        self.used_global_meshes.push(write.dst_mesh.clone());
+    }
+
+    /// Submits the recorded commands to the transfer queue if any were recorded.
+    ///
+    /// Returns the timeline semaphore and value which must be waited on before any of the acquire
+    /// barriers are executed.
+    fn submit(&mut self, device: &DeviceContext) -> Option<(vk::Semaphore, u64)> {
+        let cmd = self.cmd?;
+
+        let info = vk::DependencyInfo::builder()
+            .buffer_memory_barriers(self.release_barriers.as_slice());
+
+        unsafe {
+            device.synchronization_2().cmd_pipeline_barrier2(cmd, &info);
+            device.vk().end_command_buffer(cmd)
+        }.unwrap_or_else(|err| {
+            log::error!("Failed to end transfer command buffer recording {:?}", err);
+            panic!()
+        });
+
+        let mut async_transfer = self.async_transfer.borrow_mut();
+        let value = async_transfer.submit(cmd);
+
+        Some((async_transfer.semaphore, value))
+    }
 }
 
 pub struct PooledObjectProvider {
     share: Arc<Share>,
     pool: Rc<RefCell<WorkerObjectPool>>,
     used_buffers: Vec<vk::CommandBuffer>,
+    used_secondary_buffers: Vec<vk::CommandBuffer>,
     used_fences: Vec<vk::Fence>,
+    used_timestamp_pools: Vec<vk::QueryPool>,
 }
 
 impl PooledObjectProvider {
@@ -294,7 +815,9 @@ impl PooledObjectProvider {
             share,
             pool,
             used_buffers: Vec::with_capacity(8),
+            used_secondary_buffers: Vec::new(),
             used_fences: Vec::with_capacity(4),
+            used_timestamp_pools: Vec::new(),
         }
     }
 
@@ -318,6 +841,28 @@ impl PooledObjectProvider {
         Ok(cmd)
     }
 
+    /// Returns a secondary command buffer which has been begun using the provided inheritance info.
+    /// If the inheritance info contains a render pass the buffer is begun with
+    /// [`vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE`].
+    pub fn get_begin_secondary_command_buffer(&mut self, inheritance: &vk::CommandBufferInheritanceInfo) -> VkResult<vk::CommandBuffer> {
+        let cmd = self.pool.borrow_mut().get_secondary_buffer();
+        self.used_secondary_buffers.push(cmd);
+
+        let mut flags = vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT;
+        if inheritance.render_pass != vk::RenderPass::null() {
+            flags |= vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE;
+        }
+        let info = vk::CommandBufferBeginInfo::builder()
+            .flags(flags)
+            .inheritance_info(inheritance);
+
+        unsafe {
+            self.pool.borrow().device.vk().begin_command_buffer(cmd, &info)
+        }?;
+
+        Ok(cmd)
+    }
+
     pub fn get_fence(&mut self) -> vk::Fence {
         let fence = self.pool.borrow_mut().get_fence();
         self.used_fences.push(fence);
@@ -328,11 +873,34 @@ impl PooledObjectProvider {
     pub fn allocate_uniform(&mut self, data: &[u8]) -> (vk::Buffer, vk::DeviceSize) {
         self.share.allocate_uniform(data)
     }
+
+    fn get_timestamp_pool(&mut self) -> vk::QueryPool {
+        let pool = self.pool.borrow_mut().get_timestamp_pool();
+        self.used_timestamp_pools.push(pool);
+
+        pool
+    }
+
+    /// Resets all command buffers handed out so far, discarding any recorded commands.
+    fn reset_command_buffers(&mut self) {
+        let pool = self.pool.borrow();
+        for buffer in self.used_buffers.iter().chain(self.used_secondary_buffers.iter()) {
+            unsafe {
+                pool.device.vk().reset_command_buffer(*buffer, vk::CommandBufferResetFlags::empty())
+            }.unwrap_or_else(|err| {
+                log::error!("vkResetCommandBuffer returned {:?} in PooledObjectProvider::reset_command_buffers", err);
+                panic!()
+            });
+        }
+    }
 }
 
 impl Drop for PooledObjectProvider {
     fn drop(&mut self) {
-        self.pool.borrow_mut().return_buffers(self.used_buffers.as_slice());
+        let mut pool = self.pool.borrow_mut();
+        pool.return_buffers(self.used_buffers.as_slice());
+        pool.return_secondary_buffers(self.used_secondary_buffers.as_slice());
+        pool.return_timestamp_pools(self.used_timestamp_pools.as_slice());
     }
 }
 
@@ -372,11 +940,26 @@ struct PassState {
     immediate_buffer: Option<Box<ImmediateBuffer>>,
     global_meshes: Vec<Arc<GlobalMesh>>,
     global_images: Vec<Arc<GlobalImage>>,
+    /// Tracks the global images written by this pass. Global meshes are never written by a pass
+    /// so only images need to be tracked.
+    image_dependencies: PassDependencyTracker,
     shaders: Vec<ShaderId>,
+    /// Kept alive until the pass has completed execution since they own the secondary command
+    /// buffers executed by the pass.
+    secondaries: Vec<Box<dyn EmulatorPipelineSecondary>>,
 
     pre_cmd: vk::CommandBuffer,
     post_cmd: vk::CommandBuffer,
 
+    /// True if a debug label region has been opened in the pre command buffer. It is closed at the
+    /// end of the post command buffer.
+    has_label: bool,
+
+    /// Query pool used to measure the gpu execution time if pass timing is enabled.
+    timestamp_pool: Option<vk::QueryPool>,
+    /// True if both timestamps have been submitted.
+    timestamps_submitted: bool,
+
     end_fence: Option<vk::Fence>,
 
     gob: Option<GlobalObjectsRecorder>,
@@ -399,8 +982,22 @@ impl PassState {
         let pre_cmd = object_pool.get_begin_command_buffer().unwrap();
         let post_cmd = object_pool.get_begin_command_buffer().unwrap();
 
+        let timestamp_pool = if share.is_pass_timing_enabled() {
+            let pool = object_pool.get_timestamp_pool();
+            unsafe {
+                device.vk().cmd_reset_query_pool(pre_cmd, pool, 0, 2);
+                device.synchronization_2().cmd_write_timestamp2(pre_cmd, vk::PipelineStageFlags2::TOP_OF_PIPE, pool, 0);
+            }
+            Some(pool)
+        } else {
+            None
+        };
+
         pass.init(queue, &mut object_pool, placeholder_image.get_sampler_view(), placeholder_sampler);
 
+        let mut image_dependencies = PassDependencyTracker::new();
+        image_dependencies.begin_pass();
+
         Self {
             share,
             device,
@@ -415,11 +1012,18 @@ impl PassState {
             immediate_buffer: None,
             global_meshes: Vec::new(),
             global_images: vec![placeholder_image],
+            image_dependencies,
             shaders: Vec::new(),
+            secondaries: Vec::new(),
 
             pre_cmd,
             post_cmd,
 
+            has_label: false,
+
+            timestamp_pool,
+            timestamps_submitted: false,
+
             end_fence: None,
             gob: None
         }
@@ -435,6 +1039,70 @@ impl PassState {
         self.immediate_buffer = Some(immediate_buffer);
     }
 
+    /// Makes any prior compute shader writes to the buffer visible to indirect draw commands.
+    fn use_indirect_buffer(&mut self, buffer: vk::Buffer) {
+        let barrier = vk::BufferMemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .src_access_mask(vk::AccessFlags2::SHADER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::DRAW_INDIRECT)
+            .dst_access_mask(vk::AccessFlags2::INDIRECT_COMMAND_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE);
+
+        let info = vk::DependencyInfo::builder()
+            .buffer_memory_barriers(std::slice::from_ref(&barrier));
+
+        unsafe {
+            self.device.synchronization_2().cmd_pipeline_barrier2(self.pre_cmd, &info);
+        }
+    }
+
+    /// Transitions the image into the storage layout before the pass. The image is released back
+    /// into the ready state after all outputs of the pass by [`PassState::release_images`].
+    fn use_storage_image(&mut self, image: Arc<GlobalImage>) {
+        let mut barriers = Vec::with_capacity(1);
+        gob::declare_storage_image_write(&mut self.image_dependencies, &image, &mut barriers);
+
+        if !barriers.is_empty() {
+            let info = vk::DependencyInfo::builder()
+                .image_memory_barriers(&barriers);
+            unsafe {
+                self.device.synchronization_2().cmd_pipeline_barrier2(self.pre_cmd, &info);
+            }
+        }
+
+        self.global_images.push(image);
+    }
+
+    /// Records the barriers returning all images written by the pass into the ready state.
+    fn release_images(&mut self) {
+        let mut barriers = Vec::new();
+        for image in &self.global_images {
+            gob::release_pass_image(&mut self.image_dependencies, image, &mut barriers);
+        }
+
+        if !barriers.is_empty() {
+            let info = vk::DependencyInfo::builder()
+                .image_memory_barriers(&barriers);
+            unsafe {
+                self.device.synchronization_2().cmd_pipeline_barrier2(self.post_cmd, &info);
+            }
+        }
+    }
+
+    fn set_label(&mut self, name: &CStr, color: [f32; 4]) {
+        if self.has_label {
+            log::error!("Called PassState::set_label when the pass already has a label");
+            panic!()
+        }
+
+        begin_label(self.device.get_functions(), self.pre_cmd, name, color);
+        self.has_label = true;
+    }
+
     fn use_output(&mut self, mut output: Box<dyn EmulatorOutput>) {
         output.init(self.pass.as_ref(), &mut self.object_pool);
         self.outputs.push(output);
@@ -444,6 +1112,15 @@ impl PassState {
         self.pass.process_task(task, &mut self.object_pool);
     }
 
+    fn execute_secondary(&mut self, commands: SecondaryCommands) {
+        self.shaders.extend(commands.shaders);
+        self.global_meshes.extend(commands.global_meshes);
+        self.global_images.extend(commands.global_images);
+
+        self.pass.execute_secondary(commands.command_buffer, &mut self.object_pool);
+        self.secondaries.push(commands.recorder);
+    }
+
     fn submit(&mut self, queue: &Queue, gob: Option<GlobalObjectsRecorder>) {
         assert!(self.end_fence.is_none());
         let end_fence = self.object_pool.get_fence();
@@ -453,6 +1130,18 @@ impl PassState {
             self.device.vk().end_command_buffer(self.pre_cmd)
         }.unwrap();
 
+        if let Some(pool) = self.timestamp_pool {
+            unsafe {
+                self.device.synchronization_2().cmd_write_timestamp2(self.post_cmd, vk::PipelineStageFlags2::BOTTOM_OF_PIPE, pool, 1);
+            }
+        }
+
+        self.release_images();
+
+        if self.has_label {
+            end_label(self.device.get_functions(), self.post_cmd);
+        }
+
         unsafe {
             self.device.vk().end_command_buffer(self.post_cmd)
         }.unwrap();
@@ -476,8 +1165,52 @@ impl PassState {
             queue.submit_2(submit_recorder.as_slice(), Some(end_fence))
         }.unwrap();
 
+        self.post_submit(queue);
+    }
+
+    /// Discards all work recorded by the pipeline pass. Global object updates are still submitted
+    /// and outputs are given the chance to release any acquired resources.
+    fn abort(&mut self, queue: &Queue, immediate_buffer: Box<ImmediateBuffer>, gob: Option<GlobalObjectsRecorder>) {
+        assert!(self.end_fence.is_none());
+        if self.immediate_buffer.is_some() {
+            log::error!("Called PassState::abort when a immediate buffer already exists");
+            panic!()
+        }
+        // No copy commands are needed. The buffer is returned once the pass is dropped.
+        self.immediate_buffer = Some(immediate_buffer);
+
+        // This includes the pre and post command buffers as well as anything used by the pipeline pass
+        self.object_pool.reset_command_buffers();
+
+        let end_fence = self.object_pool.get_fence();
+        self.end_fence = Some(end_fence);
+
+        let submit_alloc = Bump::new();
+        let mut submit_recorder = SubmitRecorder::new(8);
+
+        if let Some(mut gob) = gob {
+            gob.record(&mut submit_recorder, &submit_alloc);
+            self.gob = Some(gob);
+        }
+
+        for output in &mut self.outputs {
+            output.record_abort(&mut self.object_pool, &mut submit_recorder, &submit_alloc);
+        }
+
+        // Submitted even if empty so the fence signals once all previous work is done
+        unsafe {
+            queue.submit_2(submit_recorder.as_slice(), Some(end_fence))
+        }.unwrap();
+
+        self.post_submit(queue);
+    }
+
+    fn post_submit(&mut self, queue: &Queue) {
+        let frame_index = self.pass_id.get_raw();
         for output in &mut self.outputs {
-            output.on_post_submit(&queue);
+            if let Some(swapchain) = output.on_post_submit(queue, frame_index) {
+                self.share.on_present(frame_index, swapchain);
+            }
         }
     }
 
@@ -504,46 +1237,102 @@ impl PassState {
         recorder.push(submit_info);
     }
 
-    fn record_post_submits<'a>(&self, _: &mut SubmitRecorder<'a>, _: &'a Bump) {
+    /// Submits the post command buffer. It must be the last submission of the pass since it
+    /// contains the end timestamp of the pass and the barriers after all outputs.
+    fn record_post_submits<'a>(&mut self, recorder: &mut SubmitRecorder<'a>, alloc: &'a Bump) {
+        let cmd_infos = alloc.alloc([
+            vk::CommandBufferSubmitInfo::builder()
+                .command_buffer(self.post_cmd)
+                .build()
+        ]);
+
+        let submit_info = vk::SubmitInfo2::builder()
+            .command_buffer_infos(cmd_infos);
+
+        recorder.push(submit_info);
+
+        // Both timestamps are only available once the post command buffer has been submitted
+        self.timestamps_submitted = self.timestamp_pool.is_some();
     }
 }
 
 impl Drop for PassState {
     fn drop(&mut self) {
+        if let (Some(pool), true) = (self.timestamp_pool, self.timestamps_submitted) {
+            let mut timestamps = [0u64; 2];
+            match unsafe {
+                self.device.vk().get_query_pool_results(pool, 0, 2, &mut timestamps, vk::QueryResultFlags::TYPE_64)
+            } {
+                Ok(()) => {
+                    let period = self.device.get_limits().timestamp_period;
+                    self.share.on_pass_timing(self.pass_id, timestamp_duration(timestamps[0], timestamps[1], period));
+                }
+                Err(err) => log::warn!("vkGetQueryPoolResults returned {:?} in PassState::drop", err),
+            }
+        }
         if let Some(immediate_buffer) = self.immediate_buffer.take() {
             self.share.return_immediate_buffer(immediate_buffer);
         }
         for shader in &self.shaders {
             self.pipeline.dec_shader_used(*shader);
         }
+        self.share.release_frame_slot();
     }
 }
 
+/// Converts 2 timestamps into a duration using the timestamp period in nanoseconds per tick.
+fn timestamp_duration(start: u64, end: u64, period: f32) -> Duration {
+    let ticks = end.wrapping_sub(start);
+    Duration::from_nanos(((ticks as f64) * (period as f64)) as u64)
+}
+
 struct GlobalObjectsRecorder {
     share: Arc<Share>,
-    _object_pool: PooledObjectProvider,
+    object_pool: PooledObjectProvider,
 
     cmd: vk::CommandBuffer,
 
+    /// Writes recorded for the async transfer queue if one is used.
+    transfer: Option<TransferRecording>,
+
     staging_allocations: Vec<StagingAllocationId>,
 
+    /// Barriers executed once after all recorded commands.
     staging_barriers: Vec<vk::BufferMemoryBarrier2>,
 
-    used_global_meshes: HashMap<Arc<GlobalMesh>, gob::MeshState>,
-    used_global_images: HashMap<Arc<GlobalImage>, gob::ImageState>,
-
-    /// A [`vk::ImageMemoryBarrier2`] Vec which can be used locally inside functions to avoid new
-    /// allocations. It should always be cleared before use.
-    tmp_image_barriers: Vec<vk::ImageMemoryBarrier2>,
-
-    /// A [`vk::BufferMemoryBarrier2`] Vec which can be used locally inside functions to avoid new
-    /// allocations. It should always be cleared before use.
-    tmp_buffer_barriers: Vec<vk::BufferMemoryBarrier2>,
+    /// Transition barriers which have not been recorded yet. They are flushed together in a
+    /// single barrier command before the deferred commands depending on them.
+    pending_buffer_barriers: Vec<vk::BufferMemoryBarrier2>,
+    pending_image_barriers: Vec<vk::ImageMemoryBarrier2>,
+    /// Commands waiting for the pending barriers to be recorded.
+    pending_commands: Vec<DeferredCommand>,
+    /// Resources transitioned since the last flush. Transitioning any of them again requires a
+    /// flush first since the new barrier must be ordered after the pending commands.
+    pending_buffers: HashSet<vk::Buffer>,
+    pending_images: HashSet<vk::Image>,
+
+    /// Meshes used by the recorded commands. Kept alive until the commands have completed.
+    used_global_meshes: Vec<Arc<GlobalMesh>>,
+    /// The state of the buffers of all used meshes. Meshes created by [`GlobalMesh::new_packed`]
+    /// share a buffer so the state is tracked per buffer.
+    mesh_buffer_states: HashMap<vk::Buffer, gob::MeshState>,
+    /// The state of all used images. If a range is set only those subresources are in the state
+    /// while the rest of the image is still ready.
+    used_global_images: HashMap<Arc<GlobalImage>, (gob::ImageState, Option<gob::ImageRange>)>,
+
+    /// Signals of recorded read backs. Sent to once execution has completed.
+    read_back_signals: Vec<SyncSender<()>>,
+    /// True once the recorded commands have been submitted. Recorders are only dropped after
+    /// their submission has completed execution.
+    submitted: bool,
+    /// The fence signaled by a submission outside of a pass.
+    end_fence: Option<vk::Fence>,
 }
 
 impl GlobalObjectsRecorder {
-    fn new(share: Arc<Share>, object_pool: Rc<RefCell<WorkerObjectPool>>) -> Self {
+    fn new(share: Arc<Share>, object_pool: Rc<RefCell<WorkerObjectPool>>, async_transfer: Option<Rc<RefCell<AsyncTransfer>>>) -> Self {
         let mut object_pool = PooledObjectProvider::new(share.clone(), object_pool);
+        let transfer = async_transfer.map(|async_transfer| TransferRecording::new(share.clone(), async_transfer));
 
         let cmd = object_pool.get_begin_command_buffer().unwrap_or_else(|err| {
             log::error!("Failed to begin global object command buffer {:?}", err);
@@ -552,35 +1341,53 @@ impl GlobalObjectsRecorder {
 
         Self {
             share,
-            _object_pool: object_pool,
+            object_pool,
 
             cmd,
 
+            transfer,
+
             staging_allocations: Vec::new(),
             staging_barriers: Vec::new(),
 
-            used_global_meshes: HashMap::new(),
+            pending_buffer_barriers: Vec::new(),
+            pending_image_barriers: Vec::new(),
+            pending_commands: Vec::new(),
+            pending_buffers: HashSet::new(),
+            pending_images: HashSet::new(),
+
+            used_global_meshes: Vec::new(),
+            mesh_buffer_states: HashMap::new(),
             used_global_images: HashMap::new(),
 
-            tmp_image_barriers: Vec::new(),
-            tmp_buffer_barriers: Vec::new(),
+            read_back_signals: Vec::new(),
+            submitted: false,
+            end_fence: None,
         }
     }
 
     fn record_global_buffer_write(&mut self, write: GlobalMeshWrite, is_uninit: bool) {
+        // Writes to uninitialized meshes have no dependency on previous work so they can be
+        // executed on the transfer queue.
+        if is_uninit && !write.regions.is_empty() {
+            if let Some(transfer) = &mut self.transfer {
+                let main_family = self.share.get_device().get_main_queue().get_queue_family_index();
+                transfer.record_mesh_write(self.share.get_device(), &write, main_family);
+                self.staging_allocations.push(write.staging_allocation);
+                return;
+            }
+        }
+
         let dst_buffer = write.dst_mesh.get_buffer_handle();
 
         if !write.regions.is_empty() {
             self.transition_mesh(write.dst_mesh, gob::MeshState::TransferWrite, is_uninit);
 
-            unsafe {
-                self.share.get_device().vk().cmd_copy_buffer(
-                    self.cmd,
-                    write.staging_buffer,
-                    dst_buffer,
-                    write.regions.as_ref()
-                );
-            }
+            self.pending_commands.push(DeferredCommand::CopyBuffer {
+                src_buffer: write.staging_buffer,
+                dst_buffer,
+                regions: write.regions,
+            });
         }
 
         self.push_staging(write.staging_allocation, write.staging_buffer, write.staging_range.0, write.staging_range.1);
@@ -591,38 +1398,29 @@ impl GlobalObjectsRecorder {
 
         self.transition_image(clear.dst_image, gob::ImageState::TransferWrite, is_uninit);
 
-        unsafe {
-            self.share.get_device().vk().cmd_clear_color_image(
-                self.cmd,
+        if let Some(clear_value) = clear.clear_value {
+            self.pending_commands.push(DeferredCommand::ClearColorImage {
                 dst_image,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                &clear.clear_value,
-                std::slice::from_ref(&vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    base_mip_level: 0,
-                    level_count: vk::REMAINING_MIP_LEVELS,
-                    base_array_layer: 0,
-                    layer_count: vk::REMAINING_ARRAY_LAYERS
-                })
-            )
+                clear_value,
+            });
         }
     }
 
     fn record_global_image_write(&mut self, write: GlobalImageWrite, is_uninit: bool) {
         let dst_image = write.dst_image.get_image_handle();
 
-        self.transition_image(write.dst_image, gob::ImageState::TransferWrite, is_uninit);
+        if let Some(range) = gob::ImageRange::from_regions(&write.regions) {
+            self.transition_image_write_range(write.dst_image, range, is_uninit);
+        } else {
+            self.transition_image(write.dst_image, gob::ImageState::TransferWrite, is_uninit);
+        }
 
         if !write.regions.is_empty() {
-            unsafe {
-                self.share.get_device().vk().cmd_copy_buffer_to_image(
-                    self.cmd,
-                    write.staging_buffer,
-                    dst_image,
-                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                    write.regions.as_ref()
-                );
-            }
+            self.pending_commands.push(DeferredCommand::CopyBufferToImage {
+                src_buffer: write.staging_buffer,
+                dst_image,
+                regions: write.regions,
+            });
         }
 
         self.push_staging(write.staging_allocation, write.staging_buffer, write.staging_range.0, write.staging_range.1);
@@ -630,12 +1428,15 @@ impl GlobalObjectsRecorder {
 
     fn record_global_image_generate_mipmaps(&mut self, image: Arc<GlobalImage>) {
         let mip_levels = image.get_mip_levels();
-        if mip_levels > 1 {
+        if mip_levels > 1 && !image.get_mip_views().is_empty() {
+            self.record_global_image_compute_mipmaps(image);
+        } else if mip_levels > 1 {
             let handle = image.get_image_handle();
-            let src_size = image.get_size();
-            let mut src_size = Vec2i32::new(src_size[0] as i32, src_size[1] as i32);
+            let size = image.get_image_size();
+            let array_layers = size.get_array_layers();
 
             self.transition_image(image, gob::ImageState::GenerateMipmaps, false);
+            self.flush_pending();
 
             let device = self.share.get_device();
             for level in 1..mip_levels {
@@ -653,36 +1454,34 @@ impl GlobalObjectsRecorder {
                             base_mip_level: level - 1,
                             level_count: 1,
                             base_array_layer: 0,
-                            layer_count: 0
+                            layer_count: array_layers
                         });
 
                     let info = vk::DependencyInfo::builder()
                         .image_memory_barriers(std::slice::from_ref(&barrier));
 
                     unsafe {
-                        device.synchronization_2_khr().cmd_pipeline_barrier2(self.cmd, &info);
+                        device.synchronization_2().cmd_pipeline_barrier2(self.cmd, &info);
                     }
                 }
 
-                let dst_size = Vec2i32::new(
-                    if src_size[0] > 1 { src_size[0] / 2 } else { 1 },
-                    if src_size[1] > 1 { src_size[1] / 2 } else { 1 }
-                );
+                let src_size = size.get_mip_extent_3d(level - 1);
+                let dst_size = size.get_mip_extent_3d(level);
                 let blit = vk::ImageBlit::builder()
                     .src_subresource(vk::ImageSubresourceLayers {
                         aspect_mask: vk::ImageAspectFlags::COLOR,
                         mip_level: level - 1,
                         base_array_layer: 0,
-                        layer_count: 1
+                        layer_count: array_layers
                     })
-                    .src_offsets([vk::Offset3D { x: 0, y: 0, z: 0 }, vk::Offset3D { x: src_size[0], y: src_size[1], z: 1 }])
+                    .src_offsets([vk::Offset3D { x: 0, y: 0, z: 0 }, vk::Offset3D { x: src_size.width as i32, y: src_size.height as i32, z: src_size.depth as i32 }])
                     .dst_subresource(vk::ImageSubresourceLayers {
                         aspect_mask: vk::ImageAspectFlags::COLOR,
                         mip_level: level,
                         base_array_layer: 0,
-                        layer_count: 1
+                        layer_count: array_layers
                     })
-                    .dst_offsets([vk::Offset3D { x: 0, y: 0, z: 0 }, vk::Offset3D { x: dst_size[0], y: dst_size[1], z: 1 }]);
+                    .dst_offsets([vk::Offset3D { x: 0, y: 0, z: 0 }, vk::Offset3D { x: dst_size.width as i32, y: dst_size.height as i32, z: dst_size.depth as i32 }]);
 
                 unsafe {
                     device.vk().cmd_blit_image(
@@ -695,25 +1494,151 @@ impl GlobalObjectsRecorder {
                         vk::Filter::LINEAR
                     );
                 }
+            }
+        }
+    }
+
+    /// Generates the mipmaps of a image whose format does not support blits using a compute shader.
+    /// All mip levels stay in the general layout and each level is read as a sampled image after it
+    /// has been written.
+    fn record_global_image_compute_mipmaps(&mut self, image: Arc<GlobalImage>) {
+        let handle = image.get_image_handle();
+        let size = image.get_image_size();
+        let array_layers = size.get_array_layers();
+        let numeric_type = image.get_format().get_numeric_type();
+
+        self.transition_image(image.clone(), gob::ImageState::ComputeMipmaps, false);
+        self.flush_pending();
+
+        let device = self.share.get_device();
+        let mip_views = image.get_mip_views();
+        for level in 1..image.get_mip_levels() {
+            if level > 1 {
+                let barrier = vk::ImageMemoryBarrier2::builder()
+                    .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                    .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                    .dst_access_mask(vk::AccessFlags2::SHADER_SAMPLED_READ)
+                    .old_layout(vk::ImageLayout::GENERAL)
+                    .new_layout(vk::ImageLayout::GENERAL)
+                    .image(handle)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level: level - 1,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: array_layers
+                    });
+
+                let info = vk::DependencyInfo::builder()
+                    .image_memory_barriers(std::slice::from_ref(&barrier));
 
-                src_size = dst_size;
+                unsafe {
+                    device.synchronization_2().cmd_pipeline_barrier2(self.cmd, &info);
+                }
             }
+
+            let dst_size = size.get_mip_extent_3d(level);
+            device.get_utils().mipmap_utils().record_generate_level(
+                self.cmd,
+                numeric_type,
+                mip_views[(level - 1) as usize],
+                mip_views[level as usize],
+                Vec2u32::new(dst_size.width, dst_size.height),
+                array_layers
+            );
+        }
+    }
+
+    fn record_global_image_read_back(&mut self, read_back: GlobalImageReadBack) {
+        let src_image = read_back.src_image.get_image_handle();
+
+        self.transition_image(read_back.src_image, gob::ImageState::TransferRead, false);
+        self.flush_pending();
+
+        unsafe {
+            self.share.get_device().vk().cmd_copy_image_to_buffer(
+                self.cmd,
+                src_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                read_back.dst_buffer,
+                read_back.regions.as_ref()
+            );
+        }
+
+        self.staging_barriers.push(vk::BufferMemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+            .dst_access_mask(vk::AccessFlags2::HOST_READ)
+            .buffer(read_back.dst_buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build()
+        );
+
+        self.read_back_signals.push(read_back.signal);
+    }
+
+    fn has_read_backs(&self) -> bool {
+        !self.read_back_signals.is_empty()
+    }
+
+    /// Submits the recorded commands on their own. Must only be called if no pass is active or
+    /// after the active pass has been submitted.
+    fn submit_standalone(&mut self, queue: &Queue) {
+        let end_fence = self.object_pool.get_fence();
+        self.end_fence = Some(end_fence);
+
+        let submit_alloc = Bump::new();
+        let mut submit_recorder = SubmitRecorder::new(2);
+        self.record(&mut submit_recorder, &submit_alloc);
+
+        unsafe {
+            queue.submit_2(submit_recorder.as_slice(), Some(end_fence))
+        }.unwrap_or_else(|err| {
+            log::error!("vkQueueSubmit2 returned {:?} in GlobalObjectsRecorder::submit_standalone", err);
+            panic!()
+        });
+    }
+
+    /// Returns true if a submission made by [`GlobalObjectsRecorder::submit_standalone`] has
+    /// completed execution.
+    fn is_complete(&self) -> bool {
+        if let Some(fence) = self.end_fence {
+            unsafe {
+                self.share.get_device().vk().get_fence_status(fence)
+            }.unwrap()
+        } else {
+            panic!("Illegal state");
         }
     }
 
     fn record<'a>(&mut self, recorder: &mut SubmitRecorder<'a>, bump: &'a Bump) {
-        let buffer_post_barriers = self.generate_buffer_post_barriers();
+        self.submitted = true;
+
+        self.flush_pending();
+
+        let mut buffer_post_barriers = self.generate_buffer_post_barriers();
         let image_post_barriers = self.generate_image_post_barriers();
 
         let device = self.share.get_device();
 
+        let mut transfer_wait = None;
+        if let Some(transfer) = &mut self.transfer {
+            transfer_wait = transfer.submit(device);
+            if transfer_wait.is_some() {
+                buffer_post_barriers.extend_from_slice(transfer.acquire_barriers.as_slice());
+            }
+        }
+
         if !buffer_post_barriers.is_empty() || !image_post_barriers.is_empty() {
             let info = vk::DependencyInfo::builder()
                 .buffer_memory_barriers(buffer_post_barriers.as_slice())
                 .image_memory_barriers(image_post_barriers.as_slice());
 
             unsafe {
-                device.synchronization_2_khr().cmd_pipeline_barrier2(self.cmd, &info);
+                device.synchronization_2().cmd_pipeline_barrier2(self.cmd, &info);
             }
         }
 
@@ -729,18 +1654,30 @@ impl GlobalObjectsRecorder {
             .build()
         );
 
-        recorder.push(vk::SubmitInfo2::builder()
-            .command_buffer_infos(std::slice::from_ref(cmd_info))
-        );
+        if let Some((semaphore, value)) = transfer_wait {
+            let wait_info = bump.alloc(vk::SemaphoreSubmitInfo::builder()
+                .semaphore(semaphore)
+                .value(value)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .build()
+            );
+
+            recorder.push(vk::SubmitInfo2::builder()
+                .wait_semaphore_infos(std::slice::from_ref(wait_info))
+                .command_buffer_infos(std::slice::from_ref(cmd_info))
+            );
+        } else {
+            recorder.push(vk::SubmitInfo2::builder()
+                .command_buffer_infos(std::slice::from_ref(cmd_info))
+            );
+        }
     }
 
     fn generate_buffer_post_barriers(&mut self) -> Vec<vk::BufferMemoryBarrier2> {
         let mut barriers = std::mem::replace(&mut self.staging_barriers, Vec::new());
 
-        for (mesh, old_state) in &self.used_global_meshes {
-            let handle = mesh.get_buffer_handle();
-
-            gob::generate_mesh_barriers(*old_state, gob::MeshState::Ready, handle, &mut barriers);
+        for (handle, old_state) in &self.mesh_buffer_states {
+            gob::generate_mesh_barriers(*old_state, gob::MeshState::Ready, *handle, &mut barriers);
         }
 
         barriers
@@ -749,11 +1686,16 @@ impl GlobalObjectsRecorder {
     fn generate_image_post_barriers(&mut self) -> Vec<vk::ImageMemoryBarrier2> {
         let mut barriers: Vec<vk::ImageMemoryBarrier2> = Vec::new();
 
-        for (image, old_state) in &self.used_global_images {
+        for (image, (old_state, range)) in &self.used_global_images {
             let handle = image.get_image_handle();
             let mip_levels = image.get_mip_levels();
+            let aspect_mask = image.get_aspect_mask();
 
-            gob::generate_image_barriers(*old_state, gob::ImageState::Ready, handle, mip_levels, &mut barriers);
+            if let Some(range) = range {
+                gob::generate_image_range_barriers(*old_state, gob::ImageState::Ready, handle, range.to_vk(aspect_mask), &mut barriers);
+            } else {
+                gob::generate_image_barriers(*old_state, gob::ImageState::Ready, handle, aspect_mask, mip_levels, &mut barriers);
+            }
         }
 
         barriers
@@ -761,50 +1703,61 @@ impl GlobalObjectsRecorder {
 
     fn push_staging(&mut self, alloc: StagingAllocationId, buffer: vk::Buffer, offset: vk::DeviceSize, size: vk::DeviceSize) {
         self.staging_allocations.push(alloc);
-        let barrier = vk::BufferMemoryBarrier2::builder()
+        self.staging_barriers.push(vk::BufferMemoryBarrier2::builder()
             .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
             .src_access_mask(vk::AccessFlags2::TRANSFER_READ)
             .dst_stage_mask(vk::PipelineStageFlags2::HOST)
             .dst_access_mask(vk::AccessFlags2::HOST_WRITE)
             .buffer(buffer)
             .offset(offset)
-            .size(size);
+            .size(size)
+            .build()
+        );
+    }
 
-        let info = vk::DependencyInfo::builder()
-            .buffer_memory_barriers(std::slice::from_ref(&barrier));
+    /// Records all pending barriers in a single barrier command followed by all deferred commands.
+    fn flush_pending(&mut self) {
+        let device = self.share.get_device();
 
-        unsafe {
-            self.share.get_device().synchronization_2_khr().cmd_pipeline_barrier2(self.cmd, &info)
-        };
+        if !self.pending_buffer_barriers.is_empty() || !self.pending_image_barriers.is_empty() {
+            let info = vk::DependencyInfo::builder()
+                .buffer_memory_barriers(self.pending_buffer_barriers.as_slice())
+                .image_memory_barriers(self.pending_image_barriers.as_slice());
+
+            unsafe {
+                device.synchronization_2().cmd_pipeline_barrier2(self.cmd, &info);
+            }
+        }
+
+        for command in self.pending_commands.drain(..) {
+            command.record(device, self.cmd);
+        }
+
+        self.pending_buffer_barriers.clear();
+        self.pending_image_barriers.clear();
+        self.pending_buffers.clear();
+        self.pending_images.clear();
     }
 
     /// Transitions a mesh to a new state and adds it to the used mesh list.
     ///
     /// If the mesh is not in the used mesh list the mesh is currently either uninitialized or
     /// ready. In that case if maybe_uninit is set the mesh is assumed to be uninitialized otherwise
-    /// it is assumed to be in the ready state.
+    /// it is assumed to be in the ready state. The state is tracked per buffer since meshes created
+    /// by [`GlobalMesh::new_packed`] share a buffer.
+    ///
+    /// The barriers are only recorded once the pending commands are flushed.
     fn transition_mesh(&mut self, mesh: Arc<GlobalMesh>, new_state: gob::MeshState, maybe_uninit: bool) {
         let handle = mesh.get_buffer_handle();
+        if self.pending_buffers.contains(&handle) {
+            self.flush_pending();
+        }
 
-        let old_state = self.used_global_meshes.insert(mesh, new_state).unwrap_or_else(|| {
-            if maybe_uninit {
-                gob::MeshState::Uninitialized
-            } else {
-                gob::MeshState::Ready
-            }
-        });
-
-        self.tmp_buffer_barriers.clear();
-        gob::generate_mesh_barriers(old_state, new_state, handle, &mut self.tmp_buffer_barriers);
-
-        if !self.tmp_buffer_barriers.is_empty() {
-            let info = vk::DependencyInfo::builder()
-                .buffer_memory_barriers(self.tmp_buffer_barriers.as_slice());
+        let old_state = gob::update_mesh_buffer_state(&mut self.mesh_buffer_states, handle, new_state, maybe_uninit);
+        self.used_global_meshes.push(mesh);
 
-            unsafe {
-                self.share.get_device().synchronization_2_khr().cmd_pipeline_barrier2(self.cmd, &info);
-            }
-        }
+        gob::generate_mesh_barriers(old_state, new_state, handle, &mut self.pending_buffer_barriers);
+        self.pending_buffers.insert(handle);
     }
 
     /// Transitions a image to a new state and adds it to the used image list.
@@ -812,27 +1765,123 @@ impl GlobalObjectsRecorder {
     /// If the image is not in the used image list the image is currently either uninitialized or
     /// ready. In that case if maybe_uninit is set the image is assumed to be uninitialized otherwise
     /// it is assumed to be in the ready state.
+    ///
+    /// The barriers are only recorded once the pending commands are flushed.
     fn transition_image(&mut self, image: Arc<GlobalImage>, new_state: gob::ImageState, maybe_uninit: bool) {
         let handle = image.get_image_handle();
+        if self.pending_images.contains(&handle) {
+            self.flush_pending();
+        }
         let mip_levels = image.get_mip_levels();
+        let aspect_mask = image.get_aspect_mask();
+
+        let old_state = match self.used_global_images.insert(image, (new_state, None)) {
+            Some((old_state, None)) => old_state,
+            Some((old_state, Some(range))) => {
+                // Only part of the image is in the old state. Return it to the ready state first
+                // so the whole image can be transitioned together.
+                gob::generate_image_range_barriers(old_state, gob::ImageState::Ready, handle, range.to_vk(aspect_mask), &mut self.pending_image_barriers);
+                self.flush_pending();
+                gob::ImageState::Ready
+            }
+            None => {
+                if maybe_uninit {
+                    gob::ImageState::Uninitialized
+                } else {
+                    gob::ImageState::Ready
+                }
+            }
+        };
 
-        let old_state = self.used_global_images.insert(image, new_state).unwrap_or_else(|| {
-            if maybe_uninit {
-                gob::ImageState::Uninitialized
-            } else {
+        gob::generate_image_barriers(old_state, new_state, handle, aspect_mask, mip_levels, &mut self.pending_image_barriers);
+        self.pending_images.insert(handle);
+    }
+
+    /// Transitions the subresources of an image touched by a write into the transfer write state.
+    ///
+    /// Only the range is transitioned if the rest of the image is known to be ready. If the image
+    /// may be uninitialized or the whole image is already in some other state the whole image is
+    /// transitioned instead.
+    fn transition_image_write_range(&mut self, image: Arc<GlobalImage>, range: gob::ImageRange, maybe_uninit: bool) {
+        let new_state = gob::ImageState::TransferWrite;
+        let current = self.used_global_images.get(&image).copied();
+        match current {
+            Some((_, Some(_))) => {},
+            None if !maybe_uninit => {},
+            _ => {
+                self.transition_image(image, new_state, maybe_uninit);
+                return;
+            }
+        }
+
+        let handle = image.get_image_handle();
+        if self.pending_images.contains(&handle) {
+            self.flush_pending();
+        }
+        let aspect_mask = image.get_aspect_mask();
+
+        let old_state = match current {
+            Some((old_state, Some(old_range))) if old_range == range => old_state,
+            Some((old_state, Some(old_range))) => {
+                // The rest of the image is ready so the previously written range must be returned
+                // to the ready state before a different range can be written.
+                gob::generate_image_range_barriers(old_state, gob::ImageState::Ready, handle, old_range.to_vk(aspect_mask), &mut self.pending_image_barriers);
+                self.flush_pending();
                 gob::ImageState::Ready
             }
-        });
+            _ => gob::ImageState::Ready,
+        };
+        self.used_global_images.insert(image, (new_state, Some(range)));
 
-        self.tmp_image_barriers.clear();
-        gob::generate_image_barriers(old_state, new_state, handle, mip_levels, &mut self.tmp_image_barriers);
+        gob::generate_image_range_barriers(old_state, new_state, handle, range.to_vk(aspect_mask), &mut self.pending_image_barriers);
+        self.pending_images.insert(handle);
+    }
+}
 
-        if !self.tmp_image_barriers.is_empty() {
-            let info = vk::DependencyInfo::builder()
-                .image_memory_barriers(self.tmp_image_barriers.as_slice());
+/// A transfer command recorded by a [`GlobalObjectsRecorder`] once the barriers it depends on
+/// have been flushed.
+enum DeferredCommand {
+    CopyBuffer {
+        src_buffer: vk::Buffer,
+        dst_buffer: vk::Buffer,
+        regions: Box<[vk::BufferCopy]>,
+    },
+    ClearColorImage {
+        dst_image: vk::Image,
+        clear_value: vk::ClearColorValue,
+    },
+    CopyBufferToImage {
+        src_buffer: vk::Buffer,
+        dst_image: vk::Image,
+        regions: Box<[vk::BufferImageCopy]>,
+    },
+}
 
-            unsafe {
-                self.share.get_device().synchronization_2_khr().cmd_pipeline_barrier2(self.cmd, &info);
+impl DeferredCommand {
+    fn record(&self, device: &DeviceContext, cmd: vk::CommandBuffer) {
+        unsafe {
+            match self {
+                DeferredCommand::CopyBuffer { src_buffer, dst_buffer, regions } => {
+                    device.vk().cmd_copy_buffer(cmd, *src_buffer, *dst_buffer, regions.as_ref());
+                }
+                DeferredCommand::ClearColorImage { dst_image, clear_value } => {
+                    device.vk().cmd_clear_color_image(
+                        cmd,
+                        *dst_image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        clear_value,
+                        std::slice::from_ref(&vk::ImageSubresourceRange {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            base_mip_level: 0,
+                            level_count: vk::REMAINING_MIP_LEVELS,
+                            base_array_layer: 0,
+                            layer_count: vk::REMAINING_ARRAY_LAYERS
+                        })
+                    );
+                }
+                DeferredCommand::CopyBufferToImage { src_buffer, dst_image, regions } => {
+                    device.vk().cmd_copy_buffer_to_image(cmd, *src_buffer, *dst_image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, regions.as_ref());
+                }
             }
         }
     }
@@ -840,6 +1889,13 @@ impl GlobalObjectsRecorder {
 
 impl Drop for GlobalObjectsRecorder {
     fn drop(&mut self) {
+        if self.submitted {
+            for signal in &self.read_back_signals {
+                // The requesting thread may have given up waiting
+                let _ = signal.send(());
+            }
+        }
+
         let mut guard = self.share.get_staging_pool().lock().unwrap_or_else(|_| {
             log::error!("Poisoned staging memory mutex in GlobalObjectsRecorder::drop");
             panic!();
@@ -854,8 +1910,14 @@ impl Drop for GlobalObjectsRecorder {
 mod gob {
     //! Utility functions to create barriers for global objects
 
+    use std::collections::HashMap;
+
     use ash::vk;
 
+    use crate::device::transfer::resource_state::{ImageUsage, PassDependencyTracker};
+    use crate::objects::id::{ImageId, ObjectId};
+    use crate::renderer::emulator::global_objects::GlobalImage;
+
     #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
     pub(super) enum MeshState {
         /// Mesh has not been initialized yet
@@ -866,6 +1928,18 @@ mod gob {
         TransferWrite,
     }
 
+    /// Sets the state of a mesh buffer and returns its previous state. Buffers without a tracked
+    /// state are either uninitialized or ready depending on `maybe_uninit`.
+    pub(super) fn update_mesh_buffer_state(states: &mut HashMap<vk::Buffer, MeshState>, buffer: vk::Buffer, new_state: MeshState, maybe_uninit: bool) -> MeshState {
+        states.insert(buffer, new_state).unwrap_or_else(|| {
+            if maybe_uninit {
+                MeshState::Uninitialized
+            } else {
+                MeshState::Ready
+            }
+        })
+    }
+
     pub(super) fn generate_mesh_barriers(old_state: MeshState, new_state: MeshState, buffer: vk::Buffer, barriers: &mut Vec<vk::BufferMemoryBarrier2>) {
         match (old_state, new_state) {
             (MeshState::Uninitialized, _) => {
@@ -944,14 +2018,18 @@ mod gob {
         TransferWrite,
         /// Image had previously generated its mipmaps
         GenerateMipmaps,
+        /// Image was previously read from
+        TransferRead,
+        /// Image had previously generated its mipmaps using a compute shader
+        ComputeMipmaps,
     }
 
-    pub(super) fn generate_image_barriers(old_state: ImageState, new_state: ImageState, image: vk::Image, mip_levels: u32, barriers: &mut Vec<vk::ImageMemoryBarrier2>) {
+    pub(super) fn generate_image_barriers(old_state: ImageState, new_state: ImageState, image: vk::Image, aspect_mask: vk::ImageAspectFlags, mip_levels: u32, barriers: &mut Vec<vk::ImageMemoryBarrier2>) {
         match (old_state, new_state) {
             (ImageState::Uninitialized, ImageState::TransferWrite) => {
                 let mut barrier = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_full_subresource_range(vk::ImageAspectFlags::COLOR));
+                    .subresource_range(make_full_subresource_range(aspect_mask));
                 barrier = IMAGE_UNINITIALIZED_INFO.write_src(barrier);
                 barrier = IMAGE_TRANSFER_WRITE_INFO.write_dst(barrier);
 
@@ -960,7 +2038,7 @@ mod gob {
             (ImageState::Ready, ImageState::TransferWrite) => {
                 let mut barrier = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_full_subresource_range(vk::ImageAspectFlags::COLOR));
+                    .subresource_range(make_full_subresource_range(aspect_mask));
                 barrier = IMAGE_READY_INFO.write_src(barrier);
                 barrier = IMAGE_TRANSFER_WRITE_INFO.write_dst(barrier);
 
@@ -969,7 +2047,7 @@ mod gob {
             (ImageState::Ready, ImageState::GenerateMipmaps) => {
                 let mut barrier0 = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_first_mip_subresource_range(vk::ImageAspectFlags::COLOR));
+                    .subresource_range(make_first_mip_subresource_range(aspect_mask));
                 barrier0 = IMAGE_READY_INFO.write_src(barrier0);
                 barrier0 = IMAGE_GENERATE_MIPMAPS_0_INFO.write_dst(barrier0);
 
@@ -977,7 +2055,7 @@ mod gob {
 
                 let mut barrier1 = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_exclude_first_mips_subresource_range(vk::ImageAspectFlags::COLOR));
+                    .subresource_range(make_exclude_first_mips_subresource_range(aspect_mask));
                 barrier1 = IMAGE_READY_INFO.write_src(barrier1);
                 barrier1 = IMAGE_GENERATE_MIPMAPS_1_INFO.write_dst(barrier1);
 
@@ -986,7 +2064,7 @@ mod gob {
             (ImageState::TransferWrite, ImageState::Ready) => {
                 let mut barrier = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_full_subresource_range(vk::ImageAspectFlags::COLOR));
+                    .subresource_range(make_full_subresource_range(aspect_mask));
                 barrier = IMAGE_TRANSFER_WRITE_INFO.write_src(barrier);
                 barrier = IMAGE_READY_INFO.write_dst(barrier);
 
@@ -995,7 +2073,7 @@ mod gob {
             (ImageState::TransferWrite, ImageState::TransferWrite) => {
                 let mut barrier = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_full_subresource_range(vk::ImageAspectFlags::COLOR));
+                    .subresource_range(make_full_subresource_range(aspect_mask));
                 barrier = IMAGE_TRANSFER_WRITE_INFO.write_src(barrier);
                 barrier = IMAGE_TRANSFER_WRITE_INFO.write_dst(barrier);
 
@@ -1004,7 +2082,7 @@ mod gob {
             (ImageState::TransferWrite, ImageState::GenerateMipmaps) => {
                 let mut barrier0 = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_first_mip_subresource_range(vk::ImageAspectFlags::COLOR));
+                    .subresource_range(make_first_mip_subresource_range(aspect_mask));
                 barrier0 = IMAGE_TRANSFER_WRITE_INFO.write_src(barrier0);
                 barrier0 = IMAGE_GENERATE_MIPMAPS_0_INFO.write_dst(barrier0);
 
@@ -1012,7 +2090,7 @@ mod gob {
 
                 let mut barrier1 = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_exclude_first_mips_subresource_range(vk::ImageAspectFlags::COLOR));
+                    .subresource_range(make_exclude_first_mips_subresource_range(aspect_mask));
                 barrier1 = IMAGE_TRANSFER_WRITE_INFO.write_src(barrier1);
                 barrier1 = IMAGE_GENERATE_MIPMAPS_1_INFO.write_dst(barrier1);
 
@@ -1021,7 +2099,7 @@ mod gob {
             (ImageState::GenerateMipmaps, ImageState::Ready) => {
                 let mut barrier0 = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_exclude_last_mips_subresource_range(vk::ImageAspectFlags::COLOR, mip_levels));
+                    .subresource_range(make_exclude_last_mips_subresource_range(aspect_mask, mip_levels));
                 barrier0 = IMAGE_GENERATE_MIPMAPS_0_INFO.write_src(barrier0);
                 barrier0 = IMAGE_READY_INFO.write_dst(barrier0);
 
@@ -1029,7 +2107,7 @@ mod gob {
 
                 let mut barrier1 = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_last_mip_subresource_range(vk::ImageAspectFlags::COLOR, mip_levels));
+                    .subresource_range(make_last_mip_subresource_range(aspect_mask, mip_levels));
                 barrier1 = IMAGE_GENERATE_MIPMAPS_1_INFO.write_src(barrier1);
                 barrier1 = IMAGE_READY_INFO.write_dst(barrier1);
 
@@ -1038,7 +2116,7 @@ mod gob {
             (ImageState::GenerateMipmaps, ImageState::TransferWrite) => {
                 let mut barrier0 = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_exclude_last_mips_subresource_range(vk::ImageAspectFlags::COLOR, mip_levels));
+                    .subresource_range(make_exclude_last_mips_subresource_range(aspect_mask, mip_levels));
                 barrier0 = IMAGE_GENERATE_MIPMAPS_0_INFO.write_src(barrier0);
                 barrier0 = IMAGE_TRANSFER_WRITE_INFO.write_dst(barrier0);
 
@@ -1046,12 +2124,85 @@ mod gob {
 
                 let mut barrier1 = vk::ImageMemoryBarrier2::builder()
                     .image(image)
-                    .subresource_range(make_last_mip_subresource_range(vk::ImageAspectFlags::COLOR, mip_levels));
+                    .subresource_range(make_last_mip_subresource_range(aspect_mask, mip_levels));
                 barrier1 = IMAGE_GENERATE_MIPMAPS_1_INFO.write_src(barrier1);
                 barrier1 = IMAGE_TRANSFER_WRITE_INFO.write_dst(barrier1);
 
                 barriers.push(barrier1.build());
             }
+            (ImageState::Ready, ImageState::TransferRead) => {
+                let mut barrier = vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(make_full_subresource_range(aspect_mask));
+                barrier = IMAGE_READY_INFO.write_src(barrier);
+                barrier = IMAGE_TRANSFER_READ_INFO.write_dst(barrier);
+
+                barriers.push(barrier.build());
+            }
+            (ImageState::TransferWrite, ImageState::TransferRead) => {
+                let mut barrier = vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(make_full_subresource_range(aspect_mask));
+                barrier = IMAGE_TRANSFER_WRITE_INFO.write_src(barrier);
+                barrier = IMAGE_TRANSFER_READ_INFO.write_dst(barrier);
+
+                barriers.push(barrier.build());
+            }
+            (ImageState::GenerateMipmaps, ImageState::TransferRead) => {
+                let mut barrier0 = vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(make_exclude_last_mips_subresource_range(aspect_mask, mip_levels));
+                barrier0 = IMAGE_GENERATE_MIPMAPS_0_INFO.write_src(barrier0);
+                barrier0 = IMAGE_TRANSFER_READ_INFO.write_dst(barrier0);
+
+                barriers.push(barrier0.build());
+
+                let mut barrier1 = vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(make_last_mip_subresource_range(aspect_mask, mip_levels));
+                barrier1 = IMAGE_GENERATE_MIPMAPS_1_INFO.write_src(barrier1);
+                barrier1 = IMAGE_TRANSFER_READ_INFO.write_dst(barrier1);
+
+                barriers.push(barrier1.build());
+            }
+            (ImageState::TransferRead, ImageState::Ready) => {
+                let mut barrier = vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(make_full_subresource_range(aspect_mask));
+                barrier = IMAGE_TRANSFER_READ_INFO.write_src(barrier);
+                barrier = IMAGE_READY_INFO.write_dst(barrier);
+
+                barriers.push(barrier.build());
+            }
+            (ImageState::TransferRead, ImageState::TransferWrite) => {
+                let mut barrier = vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(make_full_subresource_range(aspect_mask));
+                barrier = IMAGE_TRANSFER_READ_INFO.write_src(barrier);
+                barrier = IMAGE_TRANSFER_WRITE_INFO.write_dst(barrier);
+
+                barriers.push(barrier.build());
+            }
+            (ImageState::TransferRead, ImageState::GenerateMipmaps) => {
+                let mut barrier0 = vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(make_first_mip_subresource_range(aspect_mask));
+                barrier0 = IMAGE_TRANSFER_READ_INFO.write_src(barrier0);
+                barrier0 = IMAGE_GENERATE_MIPMAPS_0_INFO.write_dst(barrier0);
+
+                barriers.push(barrier0.build());
+
+                let mut barrier1 = vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(make_exclude_first_mips_subresource_range(aspect_mask));
+                barrier1 = IMAGE_TRANSFER_READ_INFO.write_src(barrier1);
+                barrier1 = IMAGE_GENERATE_MIPMAPS_1_INFO.write_dst(barrier1);
+
+                barriers.push(barrier1.build());
+            }
+            (ImageState::TransferRead, ImageState::TransferRead) => {
+                // Reads do not need to be synchronized with each other
+            }
             (ImageState::Ready, ImageState::Ready) => {
                 log::warn!("Transitioned image from ready to ready. Why?");
             }
@@ -1067,9 +2218,119 @@ mod gob {
                 log::error!("Image cannot be transitioned from generate mipmaps to generate mipmaps");
                 panic!();
             }
+            (ImageState::ComputeMipmaps, ImageState::ComputeMipmaps) => {
+                log::error!("Image cannot be transitioned from compute mipmaps to compute mipmaps");
+                panic!();
+            }
+            (ImageState::GenerateMipmaps, ImageState::ComputeMipmaps) | (ImageState::ComputeMipmaps, ImageState::GenerateMipmaps) => {
+                log::error!("Image cannot generate mipmaps using both blits and compute shaders");
+                panic!();
+            }
+            (old, ImageState::ComputeMipmaps) | (old @ ImageState::ComputeMipmaps, _) => {
+                // All mip levels use the same layout in the compute mipmaps state
+                let mut barrier = vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(make_full_subresource_range(aspect_mask));
+                barrier = get_single_range_info(old).write_src(barrier);
+                barrier = get_single_range_info(new_state).write_dst(barrier);
+
+                barriers.push(barrier.build());
+            }
+        }
+    }
+
+    /// A range of mip levels and array layers of an image.
+    #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+    pub(super) struct ImageRange {
+        pub base_mip_level: u32,
+        pub level_count: u32,
+        pub base_array_layer: u32,
+        pub layer_count: u32,
+    }
+
+    impl ImageRange {
+        /// Returns the smallest range containing all subresources written by the copy regions or
+        /// [`None`] if there are no regions.
+        pub(super) fn from_regions(regions: &[vk::BufferImageCopy]) -> Option<Self> {
+            let (min_level, max_level, min_layer, max_layer) = regions.iter().map(|region| {
+                let subresource = &region.image_subresource;
+                (subresource.mip_level, subresource.mip_level + 1, subresource.base_array_layer, subresource.base_array_layer + subresource.layer_count)
+            }).reduce(|a, b| {
+                (a.0.min(b.0), a.1.max(b.1), a.2.min(b.2), a.3.max(b.3))
+            })?;
+
+            Some(Self {
+                base_mip_level: min_level,
+                level_count: max_level - min_level,
+                base_array_layer: min_layer,
+                layer_count: max_layer - min_layer,
+            })
+        }
+
+        pub(super) fn to_vk(&self, aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+            vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: self.base_mip_level,
+                level_count: self.level_count,
+                base_array_layer: self.base_array_layer,
+                layer_count: self.layer_count
+            }
+        }
+    }
+
+    /// Generates the barriers transitioning a range of an image between two states which use the
+    /// same layout for all mip levels.
+    pub(super) fn generate_image_range_barriers(old_state: ImageState, new_state: ImageState, image: vk::Image, range: vk::ImageSubresourceRange, barriers: &mut Vec<vk::ImageMemoryBarrier2>) {
+        let mut barrier = vk::ImageMemoryBarrier2::builder()
+            .image(image)
+            .subresource_range(range);
+        barrier = get_single_range_info(old_state).write_src(barrier);
+        barrier = get_single_range_info(new_state).write_dst(barrier);
+
+        barriers.push(barrier.build());
+    }
+
+    /// Returns the access info of a state which uses the same layout for all mip levels.
+    fn get_single_range_info(state: ImageState) -> ImageAccessInfo {
+        match state {
+            ImageState::Ready => IMAGE_READY_INFO,
+            ImageState::TransferWrite => IMAGE_TRANSFER_WRITE_INFO,
+            ImageState::TransferRead => IMAGE_TRANSFER_READ_INFO,
+            ImageState::ComputeMipmaps => IMAGE_COMPUTE_MIPMAPS_INFO,
+            state => {
+                log::error!("Image state {:?} does not use a single layout for all mip levels", state);
+                panic!();
+            }
         }
     }
 
+    /// Declares a storage image write of a ready image by a pass and generates the barriers
+    /// required before the write. The image is registered with the tracker on its first use.
+    pub(super) fn declare_storage_image_write(tracker: &mut PassDependencyTracker, image: &GlobalImage, barriers: &mut Vec<vk::ImageMemoryBarrier2>) {
+        declare_storage_image_write_raw(tracker, get_tracked_image_id(image), image.get_image_handle(), image.get_aspect_mask(), barriers);
+    }
+
+    pub(super) fn declare_storage_image_write_raw(tracker: &mut PassDependencyTracker, id: ImageId, image: vk::Image, aspect_mask: vk::ImageAspectFlags, barriers: &mut Vec<vk::ImageMemoryBarrier2>) {
+        // Ready images may still be sampled by previous passes. Registering fails if the image
+        // has already been used by this pass which is fine.
+        let _ = tracker.register_raw_read(id, image, aspect_mask, IMAGE_READY_USAGE);
+        tracker.declare_write(id, IMAGE_STORAGE_USAGE, barriers);
+    }
+
+    /// Generates the barriers returning an image used by a pass into the ready state. Images which
+    /// have not been written by the pass are ignored.
+    pub(super) fn release_pass_image(tracker: &mut PassDependencyTracker, image: &GlobalImage, barriers: &mut Vec<vk::ImageMemoryBarrier2>) {
+        release_pass_image_raw(tracker, get_tracked_image_id(image), barriers);
+    }
+
+    pub(super) fn release_pass_image_raw(tracker: &mut PassDependencyTracker, id: ImageId, barriers: &mut Vec<vk::ImageMemoryBarrier2>) {
+        tracker.release_to(id, IMAGE_READY_INFO.layout, barriers);
+    }
+
+    fn get_tracked_image_id(image: &GlobalImage) -> ImageId {
+        ImageId::from_raw(image.get_id().as_uuid())
+    }
+
     #[inline]
     fn make_full_subresource_range(aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
         vk::ImageSubresourceRange {
@@ -1127,8 +2388,18 @@ mod gob {
 
     const IMAGE_UNINITIALIZED_INFO: ImageAccessInfo = ImageAccessInfo::new(vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE, vk::ImageLayout::UNDEFINED);
     const IMAGE_READY_INFO: ImageAccessInfo = ImageAccessInfo::new(vk::PipelineStageFlags2::FRAGMENT_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    const IMAGE_READY_USAGE: ImageUsage = ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER);
+    const IMAGE_STORAGE_USAGE: ImageUsage = ImageUsage::StorageWrite(
+        vk::PipelineStageFlags2::from_raw(vk::PipelineStageFlags2::VERTEX_SHADER.as_raw() | vk::PipelineStageFlags2::FRAGMENT_SHADER.as_raw() | vk::PipelineStageFlags2::COMPUTE_SHADER.as_raw())
+    );
     const IMAGE_TRANSFER_WRITE_INFO: ImageAccessInfo = ImageAccessInfo::new(vk::PipelineStageFlags2::TRANSFER, vk::AccessFlags2::TRANSFER_WRITE, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+    const IMAGE_TRANSFER_READ_INFO: ImageAccessInfo = ImageAccessInfo::new(vk::PipelineStageFlags2::TRANSFER, vk::AccessFlags2::TRANSFER_READ, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
     const IMAGE_GENERATE_MIPMAPS_0_INFO: ImageAccessInfo = ImageAccessInfo::new(vk::PipelineStageFlags2::TRANSFER, vk::AccessFlags2::TRANSFER_READ, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+    const IMAGE_COMPUTE_MIPMAPS_INFO: ImageAccessInfo = ImageAccessInfo::new(
+        vk::PipelineStageFlags2::COMPUTE_SHADER,
+        vk::AccessFlags2::from_raw(vk::AccessFlags2::SHADER_SAMPLED_READ.as_raw() | vk::AccessFlags2::SHADER_STORAGE_WRITE.as_raw()),
+        vk::ImageLayout::GENERAL
+    );
     const IMAGE_GENERATE_MIPMAPS_1_INFO: ImageAccessInfo = ImageAccessInfo::new(vk::PipelineStageFlags2::TRANSFER, vk::AccessFlags2::TRANSFER_WRITE, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
 
     struct ImageAccessInfo {
@@ -1163,4 +2434,135 @@ mod gob {
                 .new_layout(self.layout)
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use ash::vk;
+    use ash::vk::Handle;
+
+    use crate::device::transfer::resource_state::PassDependencyTracker;
+    use crate::objects::id::ImageId;
+
+    use super::gob;
+    use super::timestamp_duration;
+
+    #[test]
+    fn timestamp_durations() {
+        assert_eq!(timestamp_duration(100, 1100, 1f32), Duration::from_nanos(1000));
+        assert_eq!(timestamp_duration(10, 20, 2.5f32), Duration::from_nanos(25));
+        assert_eq!(timestamp_duration(u64::MAX - 1, 3, 1f32), Duration::from_nanos(5));
+    }
+
+    #[test]
+    fn depth_image_barriers() {
+        let image = vk::Image::from_raw(1);
+        let mut barriers = Vec::new();
+
+        gob::generate_image_barriers(gob::ImageState::Uninitialized, gob::ImageState::TransferWrite, image, vk::ImageAspectFlags::DEPTH, 1, &mut barriers);
+        gob::generate_image_barriers(gob::ImageState::TransferWrite, gob::ImageState::Ready, image, vk::ImageAspectFlags::DEPTH, 1, &mut barriers);
+
+        assert_eq!(barriers.len(), 2);
+        for barrier in &barriers {
+            assert_eq!(barrier.subresource_range.aspect_mask, vk::ImageAspectFlags::DEPTH);
+        }
+        assert_eq!(barriers[0].new_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        assert_eq!(barriers[1].new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    }
+
+    #[test]
+    fn packed_mesh_partial_update_state() {
+        // Two packed meshes share buffer 1 while buffer 2 belongs to a separate mesh
+        let shared = vk::Buffer::from_raw(1);
+        let separate = vk::Buffer::from_raw(2);
+        let mut states = HashMap::new();
+
+        // Partial update of the first packed mesh
+        assert_eq!(gob::update_mesh_buffer_state(&mut states, shared, gob::MeshState::TransferWrite, false), gob::MeshState::Ready);
+        // A partial update of the second mesh must wait for the first write
+        assert_eq!(gob::update_mesh_buffer_state(&mut states, shared, gob::MeshState::TransferWrite, false), gob::MeshState::TransferWrite);
+        assert_eq!(gob::update_mesh_buffer_state(&mut states, separate, gob::MeshState::TransferWrite, true), gob::MeshState::Uninitialized);
+        assert_eq!(states.len(), 2);
+
+        let mut barriers = Vec::new();
+        for (buffer, state) in &states {
+            gob::generate_mesh_barriers(*state, gob::MeshState::Ready, *buffer, &mut barriers);
+        }
+        assert_eq!(barriers.iter().filter(|barrier| barrier.buffer == shared).count(), 1);
+    }
+
+    #[test]
+    fn storage_image_barriers() {
+        let mut tracker = PassDependencyTracker::new();
+        let id = ImageId::new();
+        let mut pre_barriers = Vec::new();
+        let mut post_barriers = Vec::new();
+
+        tracker.begin_pass();
+        gob::declare_storage_image_write_raw(&mut tracker, id, vk::Image::from_raw(1), vk::ImageAspectFlags::COLOR, &mut pre_barriers);
+        // Repeated uses within the same pass do not need additional barriers
+        gob::declare_storage_image_write_raw(&mut tracker, id, vk::Image::from_raw(1), vk::ImageAspectFlags::COLOR, &mut pre_barriers);
+        gob::release_pass_image_raw(&mut tracker, id, &mut post_barriers);
+
+        assert_eq!(pre_barriers.len(), 1);
+        assert_eq!(post_barriers.len(), 1);
+        assert_eq!(pre_barriers[0].old_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(pre_barriers[0].new_layout, vk::ImageLayout::GENERAL);
+        assert!(pre_barriers[0].dst_access_mask.contains(vk::AccessFlags2::SHADER_STORAGE_WRITE));
+        assert_eq!(post_barriers[0].old_layout, vk::ImageLayout::GENERAL);
+        assert_eq!(post_barriers[0].new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert!(post_barriers[0].src_access_mask.contains(vk::AccessFlags2::SHADER_STORAGE_WRITE));
+    }
+
+    #[test]
+    fn compute_mipmaps_barriers() {
+        let image = vk::Image::from_raw(1);
+        let mut barriers = Vec::new();
+
+        gob::generate_image_barriers(gob::ImageState::TransferWrite, gob::ImageState::ComputeMipmaps, image, vk::ImageAspectFlags::COLOR, 4, &mut barriers);
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(barriers[0].old_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        assert_eq!(barriers[0].new_layout, vk::ImageLayout::GENERAL);
+        assert_eq!(barriers[0].dst_stage_mask, vk::PipelineStageFlags2::COMPUTE_SHADER);
+        assert_eq!(barriers[0].subresource_range.level_count, vk::REMAINING_MIP_LEVELS);
+
+        barriers.clear();
+        gob::generate_image_barriers(gob::ImageState::ComputeMipmaps, gob::ImageState::Ready, image, vk::ImageAspectFlags::COLOR, 4, &mut barriers);
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(barriers[0].old_layout, vk::ImageLayout::GENERAL);
+        assert_eq!(barriers[0].new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert!(barriers[0].src_access_mask.contains(vk::AccessFlags2::SHADER_STORAGE_WRITE));
+    }
+
+    #[test]
+    fn partial_image_write_range() {
+        let region = |mip_level, base_array_layer, layer_count| vk::BufferImageCopy {
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level,
+                base_array_layer,
+                layer_count
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(gob::ImageRange::from_regions(&[]), None);
+
+        let range = gob::ImageRange::from_regions(&[region(2, 1, 1), region(1, 3, 2)]).unwrap();
+        assert_eq!(range, gob::ImageRange { base_mip_level: 1, level_count: 2, base_array_layer: 1, layer_count: 4 });
+
+        let image = vk::Image::from_raw(1);
+        let mut barriers = Vec::new();
+        gob::generate_image_range_barriers(gob::ImageState::Ready, gob::ImageState::TransferWrite, image, range.to_vk(vk::ImageAspectFlags::COLOR), &mut barriers);
+        assert_eq!(barriers.len(), 1);
+        assert_eq!(barriers[0].old_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(barriers[0].new_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        assert_eq!(barriers[0].subresource_range.base_mip_level, 1);
+        assert_eq!(barriers[0].subresource_range.level_count, 2);
+        assert_eq!(barriers[0].subresource_range.base_array_layer, 1);
+        assert_eq!(barriers[0].subresource_range.layer_count, 4);
+    }
+}
@@ -2,7 +2,9 @@
 
 use ash::vk;
 
-
+/// Rounds `base` up to the next multiple of `alignment`. The alignment may be any non zero value.
+///
+/// Same as [`align_up`] but always uses a division.
 pub fn next_aligned(base: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
     let rem = base % alignment;
     if rem == 0 {
@@ -13,6 +15,30 @@ pub fn next_aligned(base: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::Devi
     }
 }
 
+/// Rounds `value` up to the next multiple of `alignment`. The alignment may be any non zero value.
+/// Power of 2 alignments avoid a division.
+#[inline]
+pub const fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    debug_assert!(alignment != 0);
+    if alignment.is_power_of_two() {
+        (value + (alignment - 1)) & !(alignment - 1)
+    } else {
+        align_down(value + (alignment - 1), alignment)
+    }
+}
+
+/// Rounds `value` down to the previous multiple of `alignment`. The alignment may be any non zero
+/// value. Power of 2 alignments avoid a division.
+#[inline]
+pub const fn align_down(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    debug_assert!(alignment != 0);
+    if alignment.is_power_of_two() {
+        value & !(alignment - 1)
+    } else {
+        value - (value % alignment)
+    }
+}
+
 pub struct RingAllocator {
     size: vk::DeviceSize,
     head: vk::DeviceSize,
@@ -52,9 +78,9 @@ impl RingAllocator {
         self.used_bytes
     }
 
+    /// Allocates a range of `size` bytes. The alignment may be any non zero value.
     pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<(vk::DeviceSize, u16)> {
-        assert_ne!(alignment, 0u64);
-        let next = next_aligned(self.head, alignment);
+        let next = align_up(self.head, alignment);
 
         let extra_used;
         let base;
@@ -266,6 +292,56 @@ mod tests {
     use rand::prelude::SliceRandom;
     use super::*;
 
+    #[test]
+    fn test_align() {
+        assert_eq!(align_up(0, 1), 0);
+        assert_eq!(align_up(0, 256), 0);
+        assert_eq!(align_up(1, 256), 256);
+        assert_eq!(align_up(255, 256), 256);
+        assert_eq!(align_up(256, 256), 256);
+        assert_eq!(align_up(257, 256), 512);
+        assert_eq!(align_up(13, 1), 13);
+
+        assert_eq!(align_down(0, 256), 0);
+        assert_eq!(align_down(255, 256), 0);
+        assert_eq!(align_down(256, 256), 256);
+        assert_eq!(align_down(511, 256), 256);
+        assert_eq!(align_down(13, 1), 13);
+
+        for alignment in [1u64, 3u64, 4u64, 12u64, 64u64] {
+            for value in 0..256u64 {
+                assert_eq!(align_up(value, alignment), next_aligned(value, alignment));
+                assert_eq!(align_down(value, alignment), value / alignment * alignment);
+            }
+        }
+
+        assert_eq!(next_aligned(0, 12), 0);
+        assert_eq!(next_aligned(13, 12), 24);
+        assert_eq!(next_aligned(24, 12), 24);
+    }
+
+    #[test]
+    fn test_align_non_power_of_two() {
+        assert_eq!(align_up(13, 12), 24);
+        assert_eq!(align_up(24, 12), 24);
+        assert_eq!(align_down(13, 12), 12);
+        assert_eq!(align_down(11, 12), 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn test_align_up_zero_alignment() {
+        align_up(13, 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn test_align_down_zero_alignment() {
+        align_down(13, 0);
+    }
+
     #[test]
     fn test_ring_allocator_slot() {
         let mut slot = RingAllocatorSlot::new(None);
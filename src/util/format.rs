@@ -4,100 +4,130 @@ use std::hash::{Hash, Hasher};
 
 use ash::vk;
 
+use crate::prelude::*;
+
 #[derive(Eq, Copy, Clone, Debug)]
 pub struct CompatibilityClass {
     name: &'static str,
+    texel_block_size: u32,
+    block_extent: [u32; 3],
 }
 
 macro_rules! define_compatibility_class {
-    ($name: ident) => {
-        pub const $name: CompatibilityClass = CompatibilityClass::new(stringify!($name));
+    ($name: ident, $texel_block_size: expr, $block_width: expr, $block_height: expr) => {
+        pub const $name: CompatibilityClass = CompatibilityClass::new(stringify!($name), $texel_block_size, [$block_width, $block_height, 1]);
     }
 }
 
 impl CompatibilityClass {
-    pub const fn new(name: &'static str) -> Self {
-        CompatibilityClass { name }
+    pub const fn new(name: &'static str, texel_block_size: u32, block_extent: [u32; 3]) -> Self {
+        CompatibilityClass { name, texel_block_size, block_extent }
     }
 
     pub const fn get_name(&self) -> &'static str {
         self.name
     }
 
-    define_compatibility_class!(BIT8);
-    define_compatibility_class!(BIT16);
-    define_compatibility_class!(BIT24);
-    define_compatibility_class!(BIT32);
-    define_compatibility_class!(BIT32_G8B8G8R8);
-    define_compatibility_class!(BIT32_B8G8R8G8);
-    define_compatibility_class!(BIT48);
-    define_compatibility_class!(BIT64);
-    define_compatibility_class!(BIT64_R10G10B10A10);
-    define_compatibility_class!(BIT64_G10B10G10R10);
-    define_compatibility_class!(BIT64_B10G10R10G10);
-    define_compatibility_class!(BIT64_R12G12B12A12);
-    define_compatibility_class!(BIT64_G12B12G12R12);
-    define_compatibility_class!(BIT64_B12G12R12G12);
-    define_compatibility_class!(BIT64_G16B16G16R16);
-    define_compatibility_class!(BIT64_B16G16R16G16);
-    define_compatibility_class!(BIT96);
-    define_compatibility_class!(BIT128);
-    define_compatibility_class!(BIT192);
-    define_compatibility_class!(BIT256);
-    define_compatibility_class!(BC1_RGB);
-    define_compatibility_class!(BC1_RGBA);
-    define_compatibility_class!(BC2);
-    define_compatibility_class!(BC3);
-    define_compatibility_class!(BC4);
-    define_compatibility_class!(BC5);
-    define_compatibility_class!(BC6H);
-    define_compatibility_class!(BC7);
-    define_compatibility_class!(ETC2_RGB);
-    define_compatibility_class!(ETC2_RGBA);
-    define_compatibility_class!(ETC2_EAC_RGBA);
-    define_compatibility_class!(EAC_R);
-    define_compatibility_class!(EAC_RG);
-    define_compatibility_class!(ASTC_4X4);
-    define_compatibility_class!(ASTC_5X4);
-    define_compatibility_class!(ASTC_5X5);
-    define_compatibility_class!(ASTC_6X5);
-    define_compatibility_class!(ASTC_6X6);
-    define_compatibility_class!(ASTC_8X5);
-    define_compatibility_class!(ASTC_8X6);
-    define_compatibility_class!(ASTC_8X8);
-    define_compatibility_class!(ASTC_10X5);
-    define_compatibility_class!(ASTC_10X6);
-    define_compatibility_class!(ASTC_10X8);
-    define_compatibility_class!(ASTC_10X10);
-    define_compatibility_class!(ASTC_12X10);
-    define_compatibility_class!(ASTC_12X12);
-    define_compatibility_class!(D16);
-    define_compatibility_class!(D24);
-    define_compatibility_class!(D32);
-    define_compatibility_class!(S8);
-    define_compatibility_class!(D16S8);
-    define_compatibility_class!(D24S8);
-    define_compatibility_class!(D32S8);
-    define_compatibility_class!(PLANE3_8BIT_420);
-    define_compatibility_class!(PLANE2_8BIT_420);
-    define_compatibility_class!(PLANE3_8BIT_422);
-    define_compatibility_class!(PLANE2_8BIT_422);
-    define_compatibility_class!(PLANE3_8BIT_444);
-    define_compatibility_class!(PLANE3_10BIT_420);
-    define_compatibility_class!(PLANE2_10BIT_420);
-    define_compatibility_class!(PLANE3_10BIT_422);
-    define_compatibility_class!(PLANE2_10BIT_422);
-    define_compatibility_class!(PLANE3_10BIT_444);
-    define_compatibility_class!(PLANE3_12BIT_420);
-    define_compatibility_class!(PLANE2_12BIT_420);
-    define_compatibility_class!(PLANE3_12BIT_422);
-    define_compatibility_class!(PLANE2_12BIT_422);
-    define_compatibility_class!(PLANE3_12BIT_444);
-    define_compatibility_class!(PLANE3_16BIT_420);
-    define_compatibility_class!(PLANE2_16BIT_420);
-    define_compatibility_class!(PLANE3_16BIT_422);
-    define_compatibility_class!(PLANE2_16BIT_422);
-    define_compatibility_class!(PLANE3_16BIT_444);
+    /// Returns the size of a single texel block in bytes. Multi-planar formats do not have a texel
+    /// block size and return 0.
+    pub const fn get_texel_block_size(&self) -> u32 {
+        self.texel_block_size
+    }
+
+    /// Returns the extent of a single texel block in texels.
+    pub const fn get_block_extent(&self) -> [u32; 3] {
+        self.block_extent
+    }
+
+    /// Returns true if this is the compatibility class of a block compressed format.
+    pub fn is_compressed(&self) -> bool {
+        self.name.starts_with("BC") || self.name.starts_with("ETC2") || self.name.starts_with("EAC") || self.name.starts_with("ASTC")
+    }
+
+    /// Returns true if this is the compatibility class of a depth and or stencil format.
+    pub fn is_depth_stencil(&self) -> bool {
+        match self.name {
+            "D16" | "D24" | "D32" | "S8" | "D16S8" | "D24S8" | "D32S8" => true,
+            _ => false,
+        }
+    }
+
+    define_compatibility_class!(BIT8, 1, 1, 1);
+    define_compatibility_class!(BIT16, 2, 1, 1);
+    define_compatibility_class!(BIT24, 3, 1, 1);
+    define_compatibility_class!(BIT32, 4, 1, 1);
+    define_compatibility_class!(BIT32_G8B8G8R8, 4, 2, 1);
+    define_compatibility_class!(BIT32_B8G8R8G8, 4, 2, 1);
+    define_compatibility_class!(BIT48, 6, 1, 1);
+    define_compatibility_class!(BIT64, 8, 1, 1);
+    define_compatibility_class!(BIT64_R10G10B10A10, 8, 1, 1);
+    define_compatibility_class!(BIT64_G10B10G10R10, 8, 2, 1);
+    define_compatibility_class!(BIT64_B10G10R10G10, 8, 2, 1);
+    define_compatibility_class!(BIT64_R12G12B12A12, 8, 1, 1);
+    define_compatibility_class!(BIT64_G12B12G12R12, 8, 2, 1);
+    define_compatibility_class!(BIT64_B12G12R12G12, 8, 2, 1);
+    define_compatibility_class!(BIT64_G16B16G16R16, 8, 2, 1);
+    define_compatibility_class!(BIT64_B16G16R16G16, 8, 2, 1);
+    define_compatibility_class!(BIT96, 12, 1, 1);
+    define_compatibility_class!(BIT128, 16, 1, 1);
+    define_compatibility_class!(BIT192, 24, 1, 1);
+    define_compatibility_class!(BIT256, 32, 1, 1);
+    define_compatibility_class!(BC1_RGB, 8, 4, 4);
+    define_compatibility_class!(BC1_RGBA, 8, 4, 4);
+    define_compatibility_class!(BC2, 16, 4, 4);
+    define_compatibility_class!(BC3, 16, 4, 4);
+    define_compatibility_class!(BC4, 8, 4, 4);
+    define_compatibility_class!(BC5, 16, 4, 4);
+    define_compatibility_class!(BC6H, 16, 4, 4);
+    define_compatibility_class!(BC7, 16, 4, 4);
+    define_compatibility_class!(ETC2_RGB, 8, 4, 4);
+    define_compatibility_class!(ETC2_RGBA, 8, 4, 4);
+    define_compatibility_class!(ETC2_EAC_RGBA, 16, 4, 4);
+    define_compatibility_class!(EAC_R, 8, 4, 4);
+    define_compatibility_class!(EAC_RG, 16, 4, 4);
+    define_compatibility_class!(ASTC_4X4, 16, 4, 4);
+    define_compatibility_class!(ASTC_5X4, 16, 5, 4);
+    define_compatibility_class!(ASTC_5X5, 16, 5, 5);
+    define_compatibility_class!(ASTC_6X5, 16, 6, 5);
+    define_compatibility_class!(ASTC_6X6, 16, 6, 6);
+    define_compatibility_class!(ASTC_8X5, 16, 8, 5);
+    define_compatibility_class!(ASTC_8X6, 16, 8, 6);
+    define_compatibility_class!(ASTC_8X8, 16, 8, 8);
+    define_compatibility_class!(ASTC_10X5, 16, 10, 5);
+    define_compatibility_class!(ASTC_10X6, 16, 10, 6);
+    define_compatibility_class!(ASTC_10X8, 16, 10, 8);
+    define_compatibility_class!(ASTC_10X10, 16, 10, 10);
+    define_compatibility_class!(ASTC_12X10, 16, 12, 10);
+    define_compatibility_class!(ASTC_12X12, 16, 12, 12);
+    define_compatibility_class!(D16, 2, 1, 1);
+    // X8_D24_UNORM_PACK32 stores the 24 depth bits in the low bits of a 32 bit word. The upper 8
+    // bits are unused.
+    define_compatibility_class!(D24, 4, 1, 1);
+    define_compatibility_class!(D32, 4, 1, 1);
+    define_compatibility_class!(S8, 1, 1, 1);
+    define_compatibility_class!(D16S8, 3, 1, 1);
+    define_compatibility_class!(D24S8, 4, 1, 1);
+    define_compatibility_class!(D32S8, 5, 1, 1);
+    define_compatibility_class!(PLANE3_8BIT_420, 0, 1, 1);
+    define_compatibility_class!(PLANE2_8BIT_420, 0, 1, 1);
+    define_compatibility_class!(PLANE3_8BIT_422, 0, 1, 1);
+    define_compatibility_class!(PLANE2_8BIT_422, 0, 1, 1);
+    define_compatibility_class!(PLANE3_8BIT_444, 0, 1, 1);
+    define_compatibility_class!(PLANE3_10BIT_420, 0, 1, 1);
+    define_compatibility_class!(PLANE2_10BIT_420, 0, 1, 1);
+    define_compatibility_class!(PLANE3_10BIT_422, 0, 1, 1);
+    define_compatibility_class!(PLANE2_10BIT_422, 0, 1, 1);
+    define_compatibility_class!(PLANE3_10BIT_444, 0, 1, 1);
+    define_compatibility_class!(PLANE3_12BIT_420, 0, 1, 1);
+    define_compatibility_class!(PLANE2_12BIT_420, 0, 1, 1);
+    define_compatibility_class!(PLANE3_12BIT_422, 0, 1, 1);
+    define_compatibility_class!(PLANE2_12BIT_422, 0, 1, 1);
+    define_compatibility_class!(PLANE3_12BIT_444, 0, 1, 1);
+    define_compatibility_class!(PLANE3_16BIT_420, 0, 1, 1);
+    define_compatibility_class!(PLANE2_16BIT_420, 0, 1, 1);
+    define_compatibility_class!(PLANE3_16BIT_422, 0, 1, 1);
+    define_compatibility_class!(PLANE2_16BIT_422, 0, 1, 1);
+    define_compatibility_class!(PLANE3_16BIT_444, 0, 1, 1);
 }
 
 impl PartialEq for CompatibilityClass {
@@ -106,6 +136,26 @@ impl PartialEq for CompatibilityClass {
     }
 }
 
+impl PartialOrd for CompatibilityClass {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CompatibilityClass {
+    /// Orders by name. Classes with equal names but distinct name pointers are ordered by pointer
+    /// to stay consistent with [`PartialEq`].
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name.cmp(other.name).then_with(|| (self.name.as_ptr() as usize).cmp(&(other.name.as_ptr() as usize)))
+    }
+}
+
+impl Hash for CompatibilityClass {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.name, state)
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 pub enum ClearColorType {
     Float,
@@ -139,6 +189,7 @@ impl ClearColorType {
 pub struct Format {
     format: vk::Format,
     compatibility_class: CompatibilityClass,
+    channel_count: u32,
     clear_color_type: Option<ClearColorType>,
 }
 
@@ -153,13 +204,39 @@ macro_rules! define_formats {
             }
         }
 
+        /// Returns the stable id of this format.
+        ///
+        /// The id is the index of this format in the format table of this crate and is independent
+        /// of the numeric value of the [`vk::Format`]. Ids are stable across crate versions and can
+        /// be used for serialization. See [`Format::from_stable_id`] for the inverse.
+        pub const fn stable_id(&self) -> u32 {
+            #[allow(non_camel_case_types)]
+            enum StableId {
+                $($name,)+
+            }
+
+            match self.format {
+                $(
+                ash::vk::Format::$name => StableId::$name as u32,
+                )+
+                _ => { panic!("Unknown format!") }
+            }
+        }
+
+        /// Returns the format with some stable id or [`None`] if no format with that id exists.
+        pub fn from_stable_id(id: u32) -> Option<&'static Format> {
+            const FORMATS: &[&'static Format] = &[$(&Format::$name,)+];
+
+            FORMATS.get(id as usize).copied()
+        }
+
         $(pub const $name : Format = Format::new(ash::vk::Format::$name, $compatibility_class, $channel_count, $clear_color_type);)+
     }
 }
 
 impl Format {
-    pub const fn new(format: vk::Format, compatibility_class: CompatibilityClass, _channel_count: u32, clear_color_type: Option<ClearColorType>) -> Self {
-        Format { format, compatibility_class, clear_color_type }
+    pub const fn new(format: vk::Format, compatibility_class: CompatibilityClass, channel_count: u32, clear_color_type: Option<ClearColorType>) -> Self {
+        Format { format, compatibility_class, channel_count, clear_color_type }
     }
 
     pub const fn get_format(&self) -> vk::Format {
@@ -174,10 +251,372 @@ impl Format {
         self.clear_color_type
     }
 
+    /// Returns the number of channels of this format. Depth stencil formats count the depth and
+    /// stencil components as channels.
+    pub const fn get_channel_count(&self) -> u32 {
+        self.channel_count
+    }
+
+    /// Returns the type of the values returned when sampling images of this format. Depth formats
+    /// are sampled as floating point values and stencil only formats as unsigned integers.
+    pub const fn get_numeric_type(&self) -> ClearColorType {
+        match self.clear_color_type {
+            Some(numeric_type) => numeric_type,
+            None if self.format.as_raw() == vk::Format::S8_UINT.as_raw() => ClearColorType::Uint32,
+            None => ClearColorType::Float,
+        }
+    }
+
     pub fn is_compatible_with(&self, other: &Format) -> bool {
         self.compatibility_class == other.compatibility_class
     }
 
+    /// Returns true if this is a integer (UINT or SINT) format.
+    ///
+    /// Integer formats do not support linear filtering.
+    pub const fn is_integer(&self) -> bool {
+        match self.clear_color_type {
+            Some(ClearColorType::Int32) | Some(ClearColorType::Uint32) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the filter that should be used by default when sampling images of this format.
+    ///
+    /// Returns [`vk::Filter::NEAREST`] for integer and depth stencil formats and
+    /// [`vk::Filter::LINEAR`] for all other formats.
+    pub const fn get_recommended_filter(&self) -> vk::Filter {
+        match self.clear_color_type {
+            Some(ClearColorType::Float) => vk::Filter::LINEAR,
+            _ => vk::Filter::NEAREST,
+        }
+    }
+
+    /// Returns true if the device supports linear filtering when sampling optimally tiled images
+    /// of this format.
+    ///
+    /// This is the device dependent counterpart of [`Format::is_integer`]. Some float formats
+    /// (for example 32 bit float formats) are not required to support linear filtering.
+    pub fn is_filterable(&self, device: &DeviceContext) -> bool {
+        device.get_format_properties(self).optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+    }
+
+    /// Returns the filter that should be used by default when sampling images of this format on
+    /// the device.
+    ///
+    /// Same as [`Format::get_recommended_filter`] except that [`vk::Filter::NEAREST`] is returned
+    /// if the device does not support linear filtering for this format.
+    pub fn get_supported_filter(&self, device: &DeviceContext) -> vk::Filter {
+        match self.get_recommended_filter() {
+            vk::Filter::LINEAR if !self.is_filterable(device) => vk::Filter::NEAREST,
+            filter => filter,
+        }
+    }
+
+    /// Returns the size of a single texel block in bytes. Multi-planar formats do not have a texel
+    /// block size and return 0.
+    pub const fn get_texel_block_size(&self) -> u32 {
+        self.compatibility_class.get_texel_block_size()
+    }
+
+    /// Returns the extent of a single texel block in texels.
+    pub const fn get_block_extent(&self) -> [u32; 3] {
+        self.compatibility_class.get_block_extent()
+    }
+
+    /// Returns true if this is a block compressed format.
+    pub fn is_compressed(&self) -> bool {
+        self.compatibility_class.is_compressed()
+    }
+
+    /// Returns the number of bytes of a tightly packed row of texel blocks covering `width` texels.
+    /// Partial blocks are rounded up. Multi-planar formats return 0, use [`Format::plane_info`] to
+    /// compute the pitch of each plane.
+    pub const fn row_pitch(&self, width: u32) -> vk::DeviceSize {
+        let block_width = self.get_block_extent()[0] as vk::DeviceSize;
+        ((width as vk::DeviceSize + block_width - 1) / block_width) * (self.get_texel_block_size() as vk::DeviceSize)
+    }
+
+    /// Returns the number of bytes of a tightly packed 2D image of the specified size. Partial
+    /// blocks are rounded up.
+    pub const fn image_2d_size(&self, width: u32, height: u32) -> vk::DeviceSize {
+        let block_height = self.get_block_extent()[1] as vk::DeviceSize;
+        ((height as vk::DeviceSize + block_height - 1) / block_height) * self.row_pitch(width)
+    }
+
+    /// Returns true if this format has a depth and or stencil component.
+    pub fn is_depth_stencil(&self) -> bool {
+        self.compatibility_class.is_depth_stencil()
+    }
+
+    /// Returns true if this is a color format. Color formats are the only formats which may use a
+    /// non identity component mapping in image views.
+    pub fn is_color(&self) -> bool {
+        !self.is_depth_stencil()
+    }
+
+    /// Returns the component mapping of a preset for image views of this format or [`None`] if the
+    /// mapping is not allowed for this format.
+    pub fn component_mapping(&self, preset: ComponentMappingPreset) -> Option<vk::ComponentMapping> {
+        let mapping = preset.get_mapping();
+        if self.is_swizzle_allowed(&mapping) {
+            Some(mapping)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if image views of this format may use the specified component mapping.
+    ///
+    /// Depth stencil formats must use the identity mapping.
+    pub fn is_swizzle_allowed(&self, mapping: &vk::ComponentMapping) -> bool {
+        self.is_color() || is_identity_mapping(mapping)
+    }
+
+    /// Returns true if this format has a depth component.
+    pub fn has_depth(&self) -> bool {
+        match self.compatibility_class.get_name() {
+            "D16" | "D24" | "D32" | "D16S8" | "D24S8" | "D32S8" => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the number of meaningful depth bits of this format or [`None`] if the format has no
+    /// depth component.
+    ///
+    /// This may differ from the texel size. For example [`Format::X8_D24_UNORM_PACK32`] uses 4
+    /// bytes per texel but only the low 24 bits contain depth data, the upper 8 bits are padding.
+    pub fn depth_bits(&self) -> Option<u32> {
+        match self.compatibility_class.get_name() {
+            "D16" | "D16S8" => Some(16),
+            "D24" | "D24S8" => Some(24),
+            "D32" | "D32S8" => Some(32),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this format has a stencil component.
+    pub fn has_stencil(&self) -> bool {
+        match self.compatibility_class.get_name() {
+            "S8" | "D16S8" | "D24S8" | "D32S8" => true,
+            _ => false,
+        }
+    }
+
+    /// Returns all aspects of this format. For depth stencil formats this includes both the depth
+    /// and stencil aspect. Use [`Format::depth_aspect`] or [`Format::stencil_aspect`] to select the
+    /// single aspect needed by a sampled image view.
+    pub fn aspect_mask(&self) -> vk::ImageAspectFlags {
+        if self.is_depth_stencil() {
+            self.depth_aspect().unwrap_or(vk::ImageAspectFlags::empty()) | self.stencil_aspect().unwrap_or(vk::ImageAspectFlags::empty())
+        } else {
+            vk::ImageAspectFlags::COLOR
+        }
+    }
+
+    /// Returns [`vk::ImageAspectFlags::DEPTH`] if this format has a depth component or [`None`]
+    /// otherwise.
+    pub fn depth_aspect(&self) -> Option<vk::ImageAspectFlags> {
+        if self.has_depth() {
+            Some(vk::ImageAspectFlags::DEPTH)
+        } else {
+            None
+        }
+    }
+
+    /// Returns [`vk::ImageAspectFlags::STENCIL`] if this format has a stencil component or [`None`]
+    /// otherwise.
+    pub fn stencil_aspect(&self) -> Option<vk::ImageAspectFlags> {
+        if self.has_stencil() {
+            Some(vk::ImageAspectFlags::STENCIL)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the format features required to create a image of this format with the specified
+    /// usage flags.
+    pub fn get_required_features(&self, usage: vk::ImageUsageFlags) -> vk::FormatFeatureFlags {
+        let mut features = vk::FormatFeatureFlags::empty();
+        if usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+            features |= vk::FormatFeatureFlags::TRANSFER_SRC;
+        }
+        if usage.contains(vk::ImageUsageFlags::TRANSFER_DST) {
+            features |= vk::FormatFeatureFlags::TRANSFER_DST;
+        }
+        if usage.contains(vk::ImageUsageFlags::SAMPLED) {
+            features |= vk::FormatFeatureFlags::SAMPLED_IMAGE;
+        }
+        if usage.contains(vk::ImageUsageFlags::STORAGE) {
+            features |= vk::FormatFeatureFlags::STORAGE_IMAGE;
+        }
+        if usage.contains(vk::ImageUsageFlags::COLOR_ATTACHMENT) {
+            features |= vk::FormatFeatureFlags::COLOR_ATTACHMENT;
+        }
+        if usage.contains(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+            features |= vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT;
+        }
+        if usage.contains(vk::ImageUsageFlags::INPUT_ATTACHMENT) {
+            if self.is_depth_stencil() {
+                features |= vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT;
+            } else {
+                features |= vk::FormatFeatureFlags::COLOR_ATTACHMENT;
+            }
+        }
+        features
+    }
+
+    /// Validates that the device supports images of this format with the specified usage and
+    /// tiling.
+    ///
+    /// If some required feature is not supported the first missing feature is returned.
+    pub fn check_support(&self, device: &DeviceContext, usage: vk::ImageUsageFlags, tiling: vk::ImageTiling) -> Result<(), vk::FormatFeatureFlags> {
+        let required = self.get_required_features(usage);
+
+        let properties = device.get_format_properties(self);
+        let supported = match tiling {
+            vk::ImageTiling::OPTIMAL => properties.optimal_tiling_features,
+            vk::ImageTiling::LINEAR => properties.linear_tiling_features,
+            _ => {
+                log::warn!("Called Format::check_support with unsupported tiling {:?}", tiling);
+                vk::FormatFeatureFlags::empty()
+            }
+        };
+
+        let missing = required & !supported;
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            // Isolate the lowest set bit
+            let raw = missing.as_raw();
+            Err(vk::FormatFeatureFlags::from_raw(raw & raw.wrapping_neg()))
+        }
+    }
+
+    /// Returns true if the device supports images of this format with the specified usage and
+    /// tiling. See [`Format::check_support`] for diagnostics.
+    pub fn is_supported_for(&self, device: &DeviceContext, usage: vk::ImageUsageFlags, tiling: vk::ImageTiling) -> bool {
+        self.check_support(device, usage, tiling).is_ok()
+    }
+
+    /// Returns the 4 channel format which can be used in place of this 24-bit 3 channel format
+    /// together with the byte value that has to be inserted as alpha channel to preserve the
+    /// meaning of the data. Returns [`None`] if this is not a 24-bit 3 channel format.
+    pub const fn get_rgba_padding(&self) -> Option<(&'static Format, u8)> {
+        match self.format {
+            vk::Format::R8G8B8_UNORM => Some((&Self::R8G8B8A8_UNORM, u8::MAX)),
+            vk::Format::R8G8B8_SNORM => Some((&Self::R8G8B8A8_SNORM, i8::MAX as u8)),
+            vk::Format::R8G8B8_USCALED => Some((&Self::R8G8B8A8_USCALED, 1u8)),
+            vk::Format::R8G8B8_SSCALED => Some((&Self::R8G8B8A8_SSCALED, 1u8)),
+            vk::Format::R8G8B8_UINT => Some((&Self::R8G8B8A8_UINT, 1u8)),
+            vk::Format::R8G8B8_SINT => Some((&Self::R8G8B8A8_SINT, 1u8)),
+            vk::Format::R8G8B8_SRGB => Some((&Self::R8G8B8A8_SRGB, u8::MAX)),
+            vk::Format::B8G8R8_UNORM => Some((&Self::B8G8R8A8_UNORM, u8::MAX)),
+            vk::Format::B8G8R8_SNORM => Some((&Self::B8G8R8A8_SNORM, i8::MAX as u8)),
+            vk::Format::B8G8R8_USCALED => Some((&Self::B8G8R8A8_USCALED, 1u8)),
+            vk::Format::B8G8R8_SSCALED => Some((&Self::B8G8R8A8_SSCALED, 1u8)),
+            vk::Format::B8G8R8_UINT => Some((&Self::B8G8R8A8_UINT, 1u8)),
+            vk::Format::B8G8R8_SINT => Some((&Self::B8G8R8A8_SINT, 1u8)),
+            vk::Format::B8G8R8_SRGB => Some((&Self::B8G8R8A8_SRGB, u8::MAX)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the color channels of this format are sRGB encoded.
+    pub const fn is_srgb(&self) -> bool {
+        matches!(self.format,
+            vk::Format::R8_SRGB |
+            vk::Format::R8G8_SRGB |
+            vk::Format::R8G8B8_SRGB |
+            vk::Format::B8G8R8_SRGB |
+            vk::Format::R8G8B8A8_SRGB |
+            vk::Format::B8G8R8A8_SRGB |
+            vk::Format::A8B8G8R8_SRGB_PACK32 |
+            vk::Format::BC1_RGB_SRGB_BLOCK |
+            vk::Format::BC1_RGBA_SRGB_BLOCK |
+            vk::Format::BC2_SRGB_BLOCK |
+            vk::Format::BC3_SRGB_BLOCK |
+            vk::Format::BC7_SRGB_BLOCK |
+            vk::Format::ETC2_R8G8B8_SRGB_BLOCK |
+            vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK |
+            vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK |
+            vk::Format::ASTC_4X4_SRGB_BLOCK |
+            vk::Format::ASTC_5X4_SRGB_BLOCK |
+            vk::Format::ASTC_5X5_SRGB_BLOCK |
+            vk::Format::ASTC_6X5_SRGB_BLOCK |
+            vk::Format::ASTC_6X6_SRGB_BLOCK |
+            vk::Format::ASTC_8X5_SRGB_BLOCK |
+            vk::Format::ASTC_8X6_SRGB_BLOCK |
+            vk::Format::ASTC_8X8_SRGB_BLOCK |
+            vk::Format::ASTC_10X5_SRGB_BLOCK |
+            vk::Format::ASTC_10X6_SRGB_BLOCK |
+            vk::Format::ASTC_10X8_SRGB_BLOCK |
+            vk::Format::ASTC_10X10_SRGB_BLOCK |
+            vk::Format::ASTC_12X10_SRGB_BLOCK |
+            vk::Format::ASTC_12X12_SRGB_BLOCK
+        )
+    }
+
+    /// Returns true if this format stores one byte per channel in either UNORM or sRGB encoding.
+    /// Data of these formats can be converted between encodings using [`convert_srgb_to_linear`]
+    /// and [`convert_linear_to_srgb`].
+    pub const fn is_8bit_unorm_or_srgb(&self) -> bool {
+        matches!(self.format,
+            vk::Format::R8_UNORM | vk::Format::R8_SRGB |
+            vk::Format::R8G8_UNORM | vk::Format::R8G8_SRGB |
+            vk::Format::R8G8B8_UNORM | vk::Format::R8G8B8_SRGB |
+            vk::Format::B8G8R8_UNORM | vk::Format::B8G8R8_SRGB |
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB |
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB |
+            vk::Format::A8B8G8R8_UNORM_PACK32 | vk::Format::A8B8G8R8_SRGB_PACK32
+        )
+    }
+
+    /// Returns the layout of a single plane of this multi-planar format or [`None`] if this is not a
+    /// multi-planar format or the format does not have the specified plane.
+    ///
+    /// The extent of the plane is the extent of the image divided by the divisors of the returned
+    /// [`PlaneInfo`].
+    pub fn plane_info(&self, plane: u32) -> Option<PlaneInfo> {
+        let (plane_count, rest) = self.compatibility_class.get_name().strip_prefix("PLANE")?.split_once('_')?;
+        let (bits, subsampling) = rest.split_once("BIT_")?;
+
+        let plane_count: u32 = plane_count.parse().ok()?;
+        if plane >= plane_count {
+            return None;
+        }
+
+        let (width_divisor, height_divisor) = match (plane, subsampling) {
+            (0, _) => (1, 1),
+            (_, "420") => (2, 2),
+            (_, "422") => (2, 1),
+            (_, "444") => (1, 1),
+            _ => return None,
+        };
+
+        // All planes but the first one of 2 plane formats store the 2 chroma channels interleaved
+        let interleaved = plane_count == 2 && plane == 1;
+        let format = match (bits, interleaved) {
+            ("8", false) => &Self::R8_UNORM,
+            ("8", true) => &Self::R8G8_UNORM,
+            ("10", false) => &Self::R10X6_UNORM_PACK16,
+            ("10", true) => &Self::R10X6G10X6_UNORM_2PACK16,
+            ("12", false) => &Self::R12X4_UNORM_PACK16,
+            ("12", true) => &Self::R12X4G12X4_UNORM_2PACK16,
+            ("16", false) => &Self::R16_UNORM,
+            ("16", true) => &Self::R16G16_UNORM,
+            _ => return None,
+        };
+
+        Some(PlaneInfo {
+            width_divisor,
+            height_divisor,
+            format,
+        })
+    }
+
+    // The order of this list determines the stable id of each format. New formats must only ever be
+    // appended to the end of the list.
     define_formats!(
     R4G4_UNORM_PACK8, CompatibilityClass::BIT8, 2, Some(ClearColorType::Float);
     R4G4B4A4_UNORM_PACK16, CompatibilityClass::BIT16, 4, Some(ClearColorType::Float);
@@ -434,4 +873,355 @@ impl Into<vk::Format> for &Format {
     fn into(self) -> vk::Format {
         self.format
     }
-}
\ No newline at end of file
+}
+
+/// Commonly used component mappings for image views.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ComponentMappingPreset {
+    /// Every component maps to itself.
+    Identity,
+    /// Every component reads the red channel. Useful to view single channel images as grayscale.
+    Rrrr,
+    /// Color components map to themselves while alpha is always 1.
+    Rgb1,
+    /// Swaps the red and blue channel.
+    Bgra,
+}
+
+impl ComponentMappingPreset {
+    pub const fn get_mapping(&self) -> vk::ComponentMapping {
+        let (r, g, b, a) = match self {
+            ComponentMappingPreset::Identity => (vk::ComponentSwizzle::IDENTITY, vk::ComponentSwizzle::IDENTITY, vk::ComponentSwizzle::IDENTITY, vk::ComponentSwizzle::IDENTITY),
+            ComponentMappingPreset::Rrrr => (vk::ComponentSwizzle::R, vk::ComponentSwizzle::R, vk::ComponentSwizzle::R, vk::ComponentSwizzle::R),
+            ComponentMappingPreset::Rgb1 => (vk::ComponentSwizzle::IDENTITY, vk::ComponentSwizzle::IDENTITY, vk::ComponentSwizzle::IDENTITY, vk::ComponentSwizzle::ONE),
+            ComponentMappingPreset::Bgra => (vk::ComponentSwizzle::B, vk::ComponentSwizzle::IDENTITY, vk::ComponentSwizzle::R, vk::ComponentSwizzle::IDENTITY),
+        };
+        vk::ComponentMapping { r, g, b, a }
+    }
+}
+
+/// Returns true if every component of the mapping maps to itself.
+pub fn is_identity_mapping(mapping: &vk::ComponentMapping) -> bool {
+    (mapping.r == vk::ComponentSwizzle::IDENTITY || mapping.r == vk::ComponentSwizzle::R) &&
+        (mapping.g == vk::ComponentSwizzle::IDENTITY || mapping.g == vk::ComponentSwizzle::G) &&
+        (mapping.b == vk::ComponentSwizzle::IDENTITY || mapping.b == vk::ComponentSwizzle::B) &&
+        (mapping.a == vk::ComponentSwizzle::IDENTITY || mapping.a == vk::ComponentSwizzle::A)
+}
+
+/// Describes a single plane of a multi-planar format.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PlaneInfo {
+    /// The factor by which the width of the plane is smaller than the width of the image.
+    pub width_divisor: u32,
+
+    /// The factor by which the height of the plane is smaller than the height of the image.
+    pub height_divisor: u32,
+
+    /// The format of the plane. Can be used to create views of or copy to and from the plane.
+    pub format: &'static Format,
+}
+
+/// Expands tightly packed 24-bit 3 channel texels into 32-bit 4 channel texels by inserting
+/// `alpha` after every texel. `dst` must be exactly `4/3` the size of `src`.
+pub fn pad_rgb_to_rgba(src: &[u8], dst: &mut [u8], alpha: u8) {
+    assert_eq!(src.len() % 3, 0);
+    assert_eq!(src.len() / 3 * 4, dst.len());
+
+    for (src, dst) in src.chunks_exact(3).zip(dst.chunks_exact_mut(4)) {
+        dst[0..3].copy_from_slice(src);
+        dst[3] = alpha;
+    }
+}
+
+/// Depth formats without a stencil aspect in order of preference. The device must support
+/// `D16_UNORM` and at least one of `X8_D24_UNORM_PACK32` or `D32_SFLOAT` as depth attachments.
+const DEPTH_FORMAT_CHAIN: [&Format; 3] = [&Format::D32_SFLOAT, &Format::X8_D24_UNORM_PACK32, &Format::D16_UNORM];
+
+/// Depth formats with a stencil aspect in order of preference.
+const DEPTH_STENCIL_FORMAT_CHAIN: [&Format; 3] = [&Format::D24_UNORM_S8_UINT, &Format::D32_SFLOAT_S8_UINT, &Format::D16_UNORM_S8_UINT];
+
+/// Selects the first depth format from `preferences` for which `is_supported` returns true. If no
+/// preference is supported the default chain is searched. If `prefer_stencil` is true formats with
+/// a stencil aspect are tried first otherwise formats without one.
+///
+/// Formats in `preferences` which do not have a depth aspect are ignored.
+pub fn select_depth_format<F: Fn(&Format) -> bool>(prefer_stencil: bool, preferences: &[&'static Format], is_supported: F) -> Option<&'static Format> {
+    let (first, second) = if prefer_stencil {
+        (&DEPTH_STENCIL_FORMAT_CHAIN, &DEPTH_FORMAT_CHAIN)
+    } else {
+        (&DEPTH_FORMAT_CHAIN, &DEPTH_STENCIL_FORMAT_CHAIN)
+    };
+
+    preferences.iter().filter(|format| format.has_depth())
+        .chain(first.iter())
+        .chain(second.iter())
+        .copied()
+        .find(|format| is_supported(format))
+}
+
+/// The encoding of color data.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ColorEncoding {
+    Linear,
+    Srgb,
+}
+
+impl ColorEncoding {
+    /// Returns the encoding used by images of the specified format.
+    pub const fn of(format: &Format) -> Self {
+        if format.is_srgb() {
+            ColorEncoding::Srgb
+        } else {
+            ColorEncoding::Linear
+        }
+    }
+}
+
+/// Decodes a sRGB encoded byte into a linear value in the range `[0, 1]`.
+pub fn srgb_to_linear(value: u8) -> f32 {
+    let value = (value as f32) / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear value in the range `[0, 1]` into a sRGB encoded byte. Values outside of the
+/// range are clamped.
+pub fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// Converts tightly packed sRGB encoded texels with one byte per channel into linear UNORM
+/// texels in place. If `channel_count` is 4 the last channel of every texel is treated as alpha
+/// and left unmodified.
+///
+/// Note that the conversion loses precision in dark colors since linear 8-bit values cannot
+/// represent them as accurately.
+pub fn convert_srgb_to_linear(data: &mut [u8], channel_count: u32) {
+    let mut table = [0u8; 256];
+    for (index, entry) in table.iter_mut().enumerate() {
+        *entry = (srgb_to_linear(index as u8) * 255.0).round() as u8;
+    }
+    convert_color_channels(data, channel_count, &table);
+}
+
+/// Converts tightly packed linear UNORM texels with one byte per channel into sRGB encoded
+/// texels in place. If `channel_count` is 4 the last channel of every texel is treated as alpha
+/// and left unmodified.
+pub fn convert_linear_to_srgb(data: &mut [u8], channel_count: u32) {
+    let mut table = [0u8; 256];
+    for (index, entry) in table.iter_mut().enumerate() {
+        *entry = linear_to_srgb((index as f32) / 255.0);
+    }
+    convert_color_channels(data, channel_count, &table);
+}
+
+fn convert_color_channels(data: &mut [u8], channel_count: u32, table: &[u8; 256]) {
+    let channel_count = channel_count as usize;
+    assert!(channel_count >= 1 && channel_count <= 4);
+
+    // Alpha is always stored as the last byte of a texel for the 8-bit formats
+    let color_channels = if channel_count == 4 { 3 } else { channel_count };
+    for texel in data.chunks_mut(channel_count) {
+        for channel in texel.iter_mut().take(color_channels) {
+            *channel = table[*channel as usize];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgba_padding() {
+        assert_eq!(Format::R8G8B8_SRGB.get_rgba_padding(), Some((&Format::R8G8B8A8_SRGB, u8::MAX)));
+        assert_eq!(Format::B8G8R8_UINT.get_rgba_padding(), Some((&Format::B8G8R8A8_UINT, 1u8)));
+        assert_eq!(Format::R8G8B8A8_UNORM.get_rgba_padding(), None);
+
+        let src = [1u8, 2u8, 3u8, 4u8, 5u8, 6u8];
+        let mut dst = [0u8; 8];
+        pad_rgb_to_rgba(&src, &mut dst, 255u8);
+        assert_eq!(dst, [1u8, 2u8, 3u8, 255u8, 4u8, 5u8, 6u8, 255u8]);
+    }
+
+    #[test]
+    fn depth_stencil_numeric_type() {
+        assert_eq!(Format::D32_SFLOAT.get_numeric_type(), ClearColorType::Float);
+        assert_eq!(Format::D24_UNORM_S8_UINT.get_numeric_type(), ClearColorType::Float);
+        assert_eq!(Format::S8_UINT.get_numeric_type(), ClearColorType::Uint32);
+        assert_eq!(Format::R8G8B8A8_SINT.get_numeric_type(), ClearColorType::Int32);
+    }
+
+    #[test]
+    fn srgb_conversion() {
+        assert!(Format::R8G8B8A8_SRGB.is_srgb());
+        assert!(Format::BC7_SRGB_BLOCK.is_srgb());
+        assert!(!Format::R8G8B8A8_UNORM.is_srgb());
+        assert!(Format::R8G8B8A8_UNORM.is_8bit_unorm_or_srgb());
+        assert!(!Format::R8G8B8A8_SNORM.is_8bit_unorm_or_srgb());
+
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert_eq!(srgb_to_linear(255), 1.0);
+        assert!((srgb_to_linear(188) - 0.5).abs() < 0.01);
+        for value in 0..=255u8 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(value)), value);
+        }
+
+        let mut data = [188u8, 0u8, 255u8, 188u8];
+        convert_srgb_to_linear(&mut data, 4);
+        assert_eq!(data, [128u8, 0u8, 255u8, 188u8]);
+        convert_linear_to_srgb(&mut data, 4);
+        assert_eq!(data, [188u8, 0u8, 255u8, 188u8]);
+    }
+
+    #[test]
+    fn stable_id_round_trip() {
+        assert_eq!(Format::R4G4_UNORM_PACK8.stable_id(), 0);
+        assert_eq!(Format::from_stable_id(0), Some(&Format::R4G4_UNORM_PACK8));
+        assert_eq!(Format::from_stable_id(u32::MAX), None);
+
+        let mut id = 0;
+        while let Some(format) = Format::from_stable_id(id) {
+            assert_eq!(format.stable_id(), id);
+            assert_eq!(Format::format_for(format.get_format()), format);
+            id += 1;
+        }
+        assert!(id > 100);
+    }
+
+    #[test]
+    fn recommended_filter() {
+        assert!(Format::R8G8B8A8_UINT.is_integer());
+        assert!(!Format::R8G8B8A8_UNORM.is_integer());
+        assert_eq!(Format::R16_SINT.get_recommended_filter(), vk::Filter::NEAREST);
+        assert_eq!(Format::R8G8B8A8_SRGB.get_recommended_filter(), vk::Filter::LINEAR);
+        assert_eq!(Format::D32_SFLOAT.get_recommended_filter(), vk::Filter::NEAREST);
+    }
+
+    #[test]
+    fn required_features() {
+        assert_eq!(Format::R8G8B8A8_UNORM.get_required_features(vk::ImageUsageFlags::empty()), vk::FormatFeatureFlags::empty());
+        assert_eq!(
+            Format::R8G8B8A8_UNORM.get_required_features(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST),
+            vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST
+        );
+        assert_eq!(Format::R8G8B8A8_UNORM.get_required_features(vk::ImageUsageFlags::INPUT_ATTACHMENT), vk::FormatFeatureFlags::COLOR_ATTACHMENT);
+        assert_eq!(Format::D32_SFLOAT.get_required_features(vk::ImageUsageFlags::INPUT_ATTACHMENT), vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT);
+    }
+
+    #[test]
+    fn depth_stencil_components() {
+        assert!(Format::D32_SFLOAT.has_depth());
+        assert!(!Format::D32_SFLOAT.has_stencil());
+        assert!(Format::D24_UNORM_S8_UINT.has_depth());
+        assert!(Format::D24_UNORM_S8_UINT.has_stencil());
+        assert!(!Format::S8_UINT.has_depth());
+        assert!(!Format::R32_SFLOAT.has_depth());
+    }
+
+    #[test]
+    fn depth_bits() {
+        assert_eq!(Format::D16_UNORM.depth_bits(), Some(16));
+        assert_eq!(Format::X8_D24_UNORM_PACK32.depth_bits(), Some(24));
+        assert_eq!(Format::X8_D24_UNORM_PACK32.get_texel_block_size(), 4);
+        assert_eq!(Format::D32_SFLOAT.depth_bits(), Some(32));
+        assert_eq!(Format::D16_UNORM_S8_UINT.depth_bits(), Some(16));
+        assert_eq!(Format::D24_UNORM_S8_UINT.depth_bits(), Some(24));
+        assert_eq!(Format::D32_SFLOAT_S8_UINT.depth_bits(), Some(32));
+        assert_eq!(Format::S8_UINT.depth_bits(), None);
+        assert_eq!(Format::R32_SFLOAT.depth_bits(), None);
+    }
+
+    #[test]
+    fn component_mappings() {
+        assert!(Format::R8_UNORM.component_mapping(ComponentMappingPreset::Rrrr).is_some());
+        assert!(Format::D32_SFLOAT.component_mapping(ComponentMappingPreset::Identity).is_some());
+        assert!(Format::D32_SFLOAT.component_mapping(ComponentMappingPreset::Rrrr).is_none());
+        assert!(Format::D24_UNORM_S8_UINT.component_mapping(ComponentMappingPreset::Rgb1).is_none());
+        assert!(is_identity_mapping(&vk::ComponentMapping {
+            r: vk::ComponentSwizzle::R,
+            g: vk::ComponentSwizzle::IDENTITY,
+            b: vk::ComponentSwizzle::B,
+            a: vk::ComponentSwizzle::A,
+        }));
+        assert!(!is_identity_mapping(&ComponentMappingPreset::Bgra.get_mapping()));
+    }
+
+    #[test]
+    fn compatibility_class_keys() {
+        let mut classes = std::collections::HashMap::new();
+        for format in [&Format::R8G8B8A8_UNORM, &Format::R8G8B8A8_SRGB, &Format::B8G8R8A8_UNORM, &Format::R16_SFLOAT, &Format::BC7_UNORM_BLOCK] {
+            classes.entry(format.get_compatibility_class()).or_insert_with(Vec::new).push(format);
+        }
+        assert_eq!(classes.len(), 3);
+        assert_eq!(classes.get(&Format::R8G8B8A8_UNORM.get_compatibility_class()).map(Vec::len), Some(3));
+
+        let sorted: std::collections::BTreeSet<_> = classes.keys().copied().collect();
+        let names: Vec<_> = sorted.iter().map(CompatibilityClass::get_name).collect();
+        let mut expected = names.clone();
+        expected.sort();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn row_pitch() {
+        assert_eq!(Format::R8G8B8A8_UNORM.row_pitch(13), 52);
+        assert_eq!(Format::R8G8B8A8_UNORM.image_2d_size(13, 7), 364);
+        assert_eq!(Format::BC1_RGB_UNORM_BLOCK.row_pitch(4), 8);
+        assert_eq!(Format::BC1_RGB_UNORM_BLOCK.row_pitch(5), 16);
+        assert_eq!(Format::BC7_UNORM_BLOCK.image_2d_size(5, 5), 64);
+        assert_eq!(Format::BC7_UNORM_BLOCK.image_2d_size(1, 1), 16);
+        assert_eq!(Format::ASTC_8X6_UNORM_BLOCK.image_2d_size(16, 7), 64);
+
+        assert_eq!(Format::R32G32B32A32_SFLOAT.row_pitch(u32::MAX), (u32::MAX as vk::DeviceSize) * 16);
+        assert_eq!(Format::R32G32B32A32_SFLOAT.image_2d_size(16384, 16384), 1 << 32);
+        assert_eq!(Format::BC1_RGB_UNORM_BLOCK.row_pitch(u32::MAX), (1 << 30) * 8);
+    }
+
+    #[test]
+    fn aspects() {
+        assert_eq!(Format::D24_UNORM_S8_UINT.aspect_mask(), vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL);
+        assert_eq!(Format::D24_UNORM_S8_UINT.depth_aspect(), Some(vk::ImageAspectFlags::DEPTH));
+        assert_eq!(Format::D24_UNORM_S8_UINT.stencil_aspect(), Some(vk::ImageAspectFlags::STENCIL));
+        assert_eq!(Format::D32_SFLOAT.stencil_aspect(), None);
+        assert_eq!(Format::S8_UINT.aspect_mask(), vk::ImageAspectFlags::STENCIL);
+        assert_eq!(Format::R8G8B8A8_UNORM.aspect_mask(), vk::ImageAspectFlags::COLOR);
+        assert_eq!(Format::R8G8B8A8_UNORM.depth_aspect(), None);
+    }
+
+    #[test]
+    fn plane_info() {
+        assert_eq!(Format::G8_B8R8_2PLANE_420_UNORM.plane_info(0), Some(PlaneInfo { width_divisor: 1, height_divisor: 1, format: &Format::R8_UNORM }));
+        assert_eq!(Format::G8_B8R8_2PLANE_420_UNORM.plane_info(1), Some(PlaneInfo { width_divisor: 2, height_divisor: 2, format: &Format::R8G8_UNORM }));
+        assert_eq!(Format::G8_B8R8_2PLANE_420_UNORM.plane_info(2), None);
+        assert_eq!(Format::G16_B16_R16_3PLANE_422_UNORM.plane_info(2), Some(PlaneInfo { width_divisor: 2, height_divisor: 1, format: &Format::R16_UNORM }));
+        assert_eq!(Format::G10X6_B10X6_R10X6_3PLANE_444_UNORM_3PACK16.plane_info(1), Some(PlaneInfo { width_divisor: 1, height_divisor: 1, format: &Format::R10X6_UNORM_PACK16 }));
+        assert_eq!(Format::R8G8B8A8_UNORM.plane_info(0), None);
+    }
+
+    #[test]
+    fn depth_format_selection() {
+        let all = |_: &Format| true;
+        assert_eq!(select_depth_format(false, &[], all), Some(&Format::D32_SFLOAT));
+        assert_eq!(select_depth_format(true, &[], all), Some(&Format::D24_UNORM_S8_UINT));
+        assert_eq!(select_depth_format(false, &[&Format::D16_UNORM], all), Some(&Format::D16_UNORM));
+
+        // Non depth preferences are ignored
+        assert_eq!(select_depth_format(false, &[&Format::R8G8B8A8_UNORM], all), Some(&Format::D32_SFLOAT));
+
+        let no_d24s8 = |format: &Format| format != &Format::D24_UNORM_S8_UINT && format != &Format::D32_SFLOAT;
+        assert_eq!(select_depth_format(true, &[&Format::D24_UNORM_S8_UINT], no_d24s8), Some(&Format::D32_SFLOAT_S8_UINT));
+        assert_eq!(select_depth_format(false, &[&Format::D32_SFLOAT], no_d24s8), Some(&Format::X8_D24_UNORM_PACK32));
+
+        assert_eq!(select_depth_format(false, &[], |_| false), None);
+    }
+}
@@ -1,35 +1,31 @@
 /// Utilities for globally unique identifiers.
-use std::cell::RefCell;
-
 use std::cmp::Ordering;
 use std::fmt::{Debug, Formatter};
 use std::hash::{Hash, Hasher};
 use std::num::NonZeroU64;
-use std::sync::{Arc, Mutex};
-
-use lazy_static::lazy_static;
-
-use crate::util::rand::Xoshiro256PlusPlus;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct UUID(NonZeroU64);
 
-lazy_static! {
-    static ref UUID_SEEDER : Mutex<Xoshiro256PlusPlus> = Mutex::new(Xoshiro256PlusPlus::from_seed([1u64, 1u64, 1u64, 1u64]));
-}
-
-thread_local! {
-    static THREAD_UUID_SEEDER : RefCell<Xoshiro256PlusPlus> = {
-        let mut seeder = UUID_SEEDER.lock().unwrap();
-        seeder.jump();
-
-        RefCell::new(*seeder)
-    }
-}
+/// The next id returned by [`UUID::new`].
+static NEXT_UUID: AtomicU64 = AtomicU64::new(1);
 
 impl UUID {
+    /// Creates a new unique id.
+    ///
+    /// Ids are taken from a global atomic counter so every call in this process returns a
+    /// different id regardless of the calling thread. Ids are not stable across processes and
+    /// should not be persisted (use [`NamedUUID`] for that instead). Ids returned by this function
+    /// may only collide with ids created by [`UUID::from_raw`] or hash based [`NamedUUID`]s.
     pub fn new() -> Self {
-        let id = THREAD_UUID_SEEDER.with(|seeder| seeder.borrow_mut().find(|id| *id != 0u64)).unwrap();
+        let id = NEXT_UUID.fetch_add(1, AtomicOrdering::Relaxed);
+        if id == 0u64 {
+            // We would need to create an id every nanosecond for over 500 years to get here
+            log::error!("UUID counter overflowed");
+            panic!()
+        }
 
         Self(NonZeroU64::new(id).unwrap())
     }
@@ -80,6 +76,10 @@ impl NameType {
 /// NamedUUIDs use a predefined global id with the local id being calculated as the hash of a
 /// string. The name is stored along side the UUID for easy debugging or printing. The name is
 /// stored by Arc enabling fast Copying of the struct.
+///
+/// Deterministic ids (see [`NamedUUID::from_name`]) are the 64-bit XXH3 hash with the default
+/// seed of the utf-8 bytes of the name. A hash of 0 is mapped to 1 since UUIDs must be non zero.
+/// This scheme is stable so different processes or sessions always agree on the id of a name.
 #[derive(Clone)]
 pub struct NamedUUID {
     name: NameType,
@@ -95,12 +95,20 @@ impl NamedUUID {
         xxhash_rust::xxh3::xxh3_64(name.as_bytes())
     }
 
+    const fn uuid_from_hash(hash: u64) -> UUID {
+        if hash == 0u64 {
+            UUID::from_raw(1u64)
+        } else {
+            UUID::from_raw(hash)
+        }
+    }
+
     /// Creates a new uuid based on the hash of the string. Calling this function with the same
     /// string will always return the same id.
     pub const fn from_str(name: &'static str) -> NamedUUID {
         let hash = Self::hash_str_const(name);
 
-        NamedUUID { name: NameType::new_static(name), id: UUID::from_raw(hash) }
+        NamedUUID { name: NameType::new_static(name), id: Self::uuid_from_hash(hash) }
     }
 
     /// Creates a new uuid based on the hash of the string. Calling this function with the same
@@ -108,7 +116,16 @@ impl NamedUUID {
     pub fn from_string(name: String) -> NamedUUID {
         let hash = Self::hash_str(name.as_str());
 
-        NamedUUID { name: NameType::new_string(name), id: UUID::from_raw(hash) }
+        NamedUUID { name: NameType::new_string(name), id: Self::uuid_from_hash(hash) }
+    }
+
+    /// Creates a new uuid based on the hash of a borrowed string. The name is copied.
+    ///
+    /// The id is identical to the one returned by [`NamedUUID::from_str`],
+    /// [`NamedUUID::from_string`] and [`NamedUUID::uuid_for`] for the same string, across
+    /// processes and sessions.
+    pub fn from_name(name: &str) -> NamedUUID {
+        Self::from_string(name.to_string())
     }
 
     /// Creates a new random uuid with a string attached. Calling this function with the same
@@ -126,7 +143,7 @@ impl NamedUUID {
     /// Generates the uuid for a string. Does not store the name to allow for parsing non static
     /// strings
     pub const fn uuid_for(name: &str) -> UUID {
-        UUID::from_raw(Self::hash_str_const(name))
+        Self::uuid_from_hash(Self::hash_str_const(name))
     }
 
     /// Returns the attached string
@@ -238,4 +255,44 @@ macro_rules! define_uuid_type {
     }
 }
 
-pub use define_uuid_type;
\ No newline at end of file
+pub use define_uuid_type;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn unique_across_threads() {
+        const THREAD_COUNT: usize = 8;
+        const IDS_PER_THREAD: usize = 250000;
+
+        let threads: Vec<_> = (0..THREAD_COUNT).map(|_| {
+            std::thread::spawn(|| {
+                (0..IDS_PER_THREAD).map(|_| UUID::new()).collect::<Vec<_>>()
+            })
+        }).collect();
+
+        let mut ids = HashSet::with_capacity(THREAD_COUNT * IDS_PER_THREAD);
+        for thread in threads {
+            for id in thread.join().unwrap() {
+                assert!(ids.insert(id), "Duplicate id {:?}", id);
+            }
+        }
+        assert_eq!(ids.len(), THREAD_COUNT * IDS_PER_THREAD);
+    }
+
+    #[test]
+    fn deterministic_named_uuid() {
+        let a = NamedUUID::from_name("blaze4d:test");
+        let b = NamedUUID::from_str("blaze4d:test");
+        let c = NamedUUID::from_string(String::from("blaze4d:test"));
+
+        assert_eq!(a, b);
+        assert_eq!(a, c);
+        assert_eq!(a.get_uuid(), NamedUUID::uuid_for("blaze4d:test"));
+        assert_eq!(a.get_name(), "blaze4d:test");
+        assert_ne!(a.get_uuid(), NamedUUID::uuid_for("blaze4d:other"));
+    }
+}
\ No newline at end of file
@@ -4,3 +4,7 @@ pub mod slice_splitter;
 pub mod alloc;
 pub mod vk;
 pub mod format;
+#[cfg(feature = "transcode")]
+pub mod transcode;
+#[cfg(feature = "spirv-reflect")]
+pub mod spirv;
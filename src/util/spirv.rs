@@ -0,0 +1,374 @@
+//! Validation of the resource interface of spirv modules against a pipeline layout.
+//!
+//! Only available with the `spirv-reflect` feature as it requires parsing the full module.
+
+use std::collections::HashMap;
+
+use ash::vk;
+use rspirv::dr::{Instruction, Module, Operand};
+use rspirv::spirv::{Decoration, Dim, Op, StorageClass, Word};
+
+/// A descriptor binding provided by a pipeline layout.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ExpectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub descriptor_count: u32,
+}
+
+/// The resource interface provided by a pipeline layout.
+#[derive(Copy, Clone, Debug)]
+pub struct ExpectedInterface<'a> {
+    pub bindings: &'a [ExpectedBinding],
+    /// The size in bytes of the push constant range available to the shader.
+    pub push_constant_size: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum InterfaceMismatch {
+    /// The shader uses a binding which does not exist in the layout.
+    MissingBinding { set: u32, binding: u32 },
+    /// The shader uses a binding with a different descriptor type than the layout.
+    DescriptorType { set: u32, binding: u32, expected: vk::DescriptorType, found: vk::DescriptorType },
+    /// The shader declares a larger descriptor array than the layout. Runtime arrays are reported
+    /// with a count of 0 and never mismatch.
+    DescriptorCount { set: u32, binding: u32, expected: u32, found: u32 },
+    /// The push constant block of the shader is larger than the push constant range of the layout.
+    PushConstantSize { expected: u32, found: u32 },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum InterfaceError {
+    /// The data is not a valid spirv module or uses constructs which cannot be reflected.
+    InvalidSpirv,
+    /// The module does not match the layout. Contains all detected mismatches.
+    Mismatch(Vec<InterfaceMismatch>),
+}
+
+/// Validates that every resource declared by a spirv module is provided by the layout with a
+/// compatible descriptor type and count and that its push constant block fits into the push
+/// constant range.
+///
+/// Bindings of the layout which are not used by the module are ignored.
+pub fn validate_interface(code: &[u8], expected: &ExpectedInterface) -> Result<(), InterfaceError> {
+    if code.len() % 4 != 0 {
+        return Err(InterfaceError::InvalidSpirv);
+    }
+    let words: Vec<u32> = code.chunks_exact(4).map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect();
+    let module = rspirv::dr::load_words(words).map_err(|_| InterfaceError::InvalidSpirv)?;
+
+    let reflection = Reflection::new(&module);
+    let mut mismatches = Vec::new();
+
+    for binding in reflection.collect_bindings()? {
+        match expected.bindings.iter().find(|b| b.set == binding.set && b.binding == binding.binding) {
+            None => mismatches.push(InterfaceMismatch::MissingBinding { set: binding.set, binding: binding.binding }),
+            Some(layout) => {
+                if !is_descriptor_type_compatible(layout.descriptor_type, binding.descriptor_type) {
+                    mismatches.push(InterfaceMismatch::DescriptorType { set: binding.set, binding: binding.binding, expected: layout.descriptor_type, found: binding.descriptor_type });
+                }
+                if binding.descriptor_count > layout.descriptor_count {
+                    mismatches.push(InterfaceMismatch::DescriptorCount { set: binding.set, binding: binding.binding, expected: layout.descriptor_count, found: binding.descriptor_count });
+                }
+            }
+        }
+    }
+
+    if let Some(size) = reflection.push_constant_size()? {
+        if size > expected.push_constant_size {
+            mismatches.push(InterfaceMismatch::PushConstantSize { expected: expected.push_constant_size, found: size });
+        }
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(InterfaceError::Mismatch(mismatches))
+    }
+}
+
+/// Returns true if a shader resource of type `found` can be bound to a layout binding of type
+/// `expected`. Dynamic buffer descriptors are compatible with the matching non dynamic type.
+fn is_descriptor_type_compatible(expected: vk::DescriptorType, found: vk::DescriptorType) -> bool {
+    match expected {
+        vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC => found == vk::DescriptorType::UNIFORM_BUFFER,
+        vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => found == vk::DescriptorType::STORAGE_BUFFER,
+        _ => expected == found,
+    }
+}
+
+struct ReflectedBinding {
+    set: u32,
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    descriptor_count: u32,
+}
+
+struct Reflection<'a> {
+    types: HashMap<Word, &'a Instruction>,
+    decorations: HashMap<Word, Vec<(Decoration, Option<u32>)>>,
+    member_decorations: HashMap<(Word, u32), Vec<(Decoration, Option<u32>)>>,
+    variables: Vec<&'a Instruction>,
+}
+
+impl<'a> Reflection<'a> {
+    fn new(module: &'a Module) -> Self {
+        let mut types = HashMap::new();
+        let mut variables = Vec::new();
+        for inst in &module.types_global_values {
+            if inst.class.opcode == Op::Variable {
+                variables.push(inst);
+            } else if let Some(id) = inst.result_id {
+                types.insert(id, inst);
+            }
+        }
+
+        let mut decorations: HashMap<Word, Vec<_>> = HashMap::new();
+        let mut member_decorations: HashMap<(Word, u32), Vec<_>> = HashMap::new();
+        for inst in &module.annotations {
+            match (inst.class.opcode, inst.operands.as_slice()) {
+                (Op::Decorate, [Operand::IdRef(target), Operand::Decoration(decoration), rest @ ..]) => {
+                    decorations.entry(*target).or_default().push((*decoration, Self::get_literal(rest.first())));
+                }
+                (Op::MemberDecorate, [Operand::IdRef(target), Operand::LiteralInt32(member), Operand::Decoration(decoration), rest @ ..]) => {
+                    member_decorations.entry((*target, *member)).or_default().push((*decoration, Self::get_literal(rest.first())));
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            types,
+            decorations,
+            member_decorations,
+            variables,
+        }
+    }
+
+    fn collect_bindings(&self) -> Result<Vec<ReflectedBinding>, InterfaceError> {
+        let mut bindings = Vec::new();
+        for variable in &self.variables {
+            let storage_class = match variable.operands.first() {
+                Some(Operand::StorageClass(class)) => *class,
+                _ => return Err(InterfaceError::InvalidSpirv),
+            };
+            if !matches!(storage_class, StorageClass::UniformConstant | StorageClass::Uniform | StorageClass::StorageBuffer) {
+                continue;
+            }
+
+            let id = variable.result_id.ok_or(InterfaceError::InvalidSpirv)?;
+            let set = self.get_decoration(id, Decoration::DescriptorSet);
+            let binding = self.get_decoration(id, Decoration::Binding);
+            let (set, binding) = match (set, binding) {
+                (Some(set), Some(binding)) => (set, binding),
+                // Not a descriptor (for example a uniform constant sampler without bindings)
+                _ => continue,
+            };
+
+            let pointee = self.get_pointee(variable.result_type.ok_or(InterfaceError::InvalidSpirv)?)?;
+            let (element, descriptor_count) = self.unwrap_array(pointee)?;
+            let descriptor_type = self.get_descriptor_type(element, storage_class)?;
+
+            bindings.push(ReflectedBinding {
+                set,
+                binding,
+                descriptor_type,
+                descriptor_count,
+            });
+        }
+        Ok(bindings)
+    }
+
+    fn push_constant_size(&self) -> Result<Option<u32>, InterfaceError> {
+        for variable in &self.variables {
+            if let Some(Operand::StorageClass(StorageClass::PushConstant)) = variable.operands.first() {
+                let pointee = self.get_pointee(variable.result_type.ok_or(InterfaceError::InvalidSpirv)?)?;
+                return self.get_type_size(pointee, None).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn get_descriptor_type(&self, type_id: Word, storage_class: StorageClass) -> Result<vk::DescriptorType, InterfaceError> {
+        let inst = self.get_type(type_id)?;
+        match inst.class.opcode {
+            Op::TypeSampler => Ok(vk::DescriptorType::SAMPLER),
+            Op::TypeSampledImage => Ok(vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            Op::TypeImage => {
+                let dim = match inst.operands.get(1) {
+                    Some(Operand::Dim(dim)) => *dim,
+                    _ => return Err(InterfaceError::InvalidSpirv),
+                };
+                let sampled = Self::get_literal(inst.operands.get(5)).ok_or(InterfaceError::InvalidSpirv)?;
+                Ok(match (dim, sampled) {
+                    (Dim::DimSubpassData, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                    (Dim::DimBuffer, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                    (Dim::DimBuffer, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                    (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                    (_, _) => vk::DescriptorType::SAMPLED_IMAGE,
+                })
+            }
+            Op::TypeStruct => {
+                if storage_class == StorageClass::StorageBuffer || self.get_decoration(type_id, Decoration::BufferBlock).is_some() {
+                    Ok(vk::DescriptorType::STORAGE_BUFFER)
+                } else {
+                    Ok(vk::DescriptorType::UNIFORM_BUFFER)
+                }
+            }
+            _ => Err(InterfaceError::InvalidSpirv),
+        }
+    }
+
+    /// Returns the element type and element count if the type is an array or the type itself and
+    /// a count of 1 otherwise.
+    fn unwrap_array(&self, type_id: Word) -> Result<(Word, u32), InterfaceError> {
+        let inst = self.get_type(type_id)?;
+        match (inst.class.opcode, inst.operands.as_slice()) {
+            (Op::TypeArray, [Operand::IdRef(element), Operand::IdRef(length)]) => Ok((*element, self.get_constant(*length)?)),
+            (Op::TypeRuntimeArray, [Operand::IdRef(element)]) => Ok((*element, 0)),
+            _ => Ok((type_id, 1)),
+        }
+    }
+
+    /// Returns the size in bytes of a type inside an explicitly laid out block. `matrix_stride` is
+    /// the stride decoration of the struct member containing the type if any.
+    fn get_type_size(&self, type_id: Word, matrix_stride: Option<u32>) -> Result<u32, InterfaceError> {
+        let inst = self.get_type(type_id)?;
+        match (inst.class.opcode, inst.operands.as_slice()) {
+            (Op::TypeInt, [Operand::LiteralInt32(width), ..]) |
+            (Op::TypeFloat, [Operand::LiteralInt32(width), ..]) => Ok(width / 8),
+            (Op::TypeBool, _) => Ok(4),
+            (Op::TypeVector, [Operand::IdRef(component), Operand::LiteralInt32(count)]) => Ok(self.get_type_size(*component, None)? * count),
+            (Op::TypeMatrix, [Operand::IdRef(column), Operand::LiteralInt32(count)]) => {
+                let column_size = self.get_type_size(*column, None)?;
+                Ok(matrix_stride.unwrap_or(column_size) * (count - 1) + column_size)
+            }
+            (Op::TypeArray, [Operand::IdRef(element), Operand::IdRef(length)]) => {
+                let element_size = self.get_type_size(*element, matrix_stride)?;
+                let stride = self.get_decoration(type_id, Decoration::ArrayStride).unwrap_or(element_size);
+                let length = self.get_constant(*length)?;
+                Ok(if length == 0 { 0 } else { stride * (length - 1) + element_size })
+            }
+            (Op::TypeStruct, members) => {
+                let mut size = 0;
+                for (index, member) in members.iter().enumerate() {
+                    let member_type = match member {
+                        Operand::IdRef(id) => *id,
+                        _ => return Err(InterfaceError::InvalidSpirv),
+                    };
+                    let offset = self.get_member_decoration(type_id, index as u32, Decoration::Offset).ok_or(InterfaceError::InvalidSpirv)?;
+                    let stride = self.get_member_decoration(type_id, index as u32, Decoration::MatrixStride);
+                    size = std::cmp::max(size, offset + self.get_type_size(member_type, stride)?);
+                }
+                Ok(size)
+            }
+            _ => Err(InterfaceError::InvalidSpirv),
+        }
+    }
+
+    fn get_pointee(&self, pointer: Word) -> Result<Word, InterfaceError> {
+        match self.get_type(pointer)?.operands.as_slice() {
+            [Operand::StorageClass(_), Operand::IdRef(pointee)] => Ok(*pointee),
+            _ => Err(InterfaceError::InvalidSpirv),
+        }
+    }
+
+    fn get_constant(&self, id: Word) -> Result<u32, InterfaceError> {
+        let inst = self.get_type(id)?;
+        if inst.class.opcode != Op::Constant {
+            // Specialization constant sized arrays cannot be validated
+            return Err(InterfaceError::InvalidSpirv);
+        }
+        Self::get_literal(inst.operands.first()).ok_or(InterfaceError::InvalidSpirv)
+    }
+
+    fn get_type(&self, id: Word) -> Result<&'a Instruction, InterfaceError> {
+        self.types.get(&id).copied().ok_or(InterfaceError::InvalidSpirv)
+    }
+
+    fn get_decoration(&self, id: Word, decoration: Decoration) -> Option<u32> {
+        Self::find_decoration(self.decorations.get(&id), decoration)
+    }
+
+    fn get_member_decoration(&self, id: Word, member: u32, decoration: Decoration) -> Option<u32> {
+        Self::find_decoration(self.member_decorations.get(&(id, member)), decoration)
+    }
+
+    /// Returns the literal of a decoration or 0 if the decoration has no literal.
+    fn find_decoration(decorations: Option<&Vec<(Decoration, Option<u32>)>>, decoration: Decoration) -> Option<u32> {
+        decorations?.iter().find(|(d, _)| *d == decoration).map(|(_, value)| value.unwrap_or(0))
+    }
+
+    fn get_literal(operand: Option<&Operand>) -> Option<u32> {
+        match operand {
+            Some(Operand::LiteralInt32(value)) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use include_bytes_aligned::include_bytes_aligned;
+
+    use crate::renderer::emulator::debug_pipeline::DRAW_SHADER_INTERFACE;
+
+    use super::*;
+
+    static DEBUG_POSITION_VERTEX_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/debug_position_vert.spv"));
+    static DEBUG_COLOR_VERTEX_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/debug_color_vert.spv"));
+    static DEBUG_UV_VERTEX_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/debug_uv_vert.spv"));
+    static DEBUG_NULL_VERTEX_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/debug_null_vert.spv"));
+    static DEBUG_FRAGMENT_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/debug_frag.spv"));
+    static TEXTURED_FRAGMENT_BIN: &'static [u8] = include_bytes_aligned!(4, concat!(env!("B4D_RESOURCE_DIR"), "emulator/textured_frag.spv"));
+
+    #[test]
+    fn debug_shaders_match_draw_layout() {
+        let modules = [
+            ("debug_position_vert", DEBUG_POSITION_VERTEX_BIN),
+            ("debug_color_vert", DEBUG_COLOR_VERTEX_BIN),
+            ("debug_uv_vert", DEBUG_UV_VERTEX_BIN),
+            ("debug_null_vert", DEBUG_NULL_VERTEX_BIN),
+            ("debug_frag", DEBUG_FRAGMENT_BIN),
+            ("textured_frag", TEXTURED_FRAGMENT_BIN),
+        ];
+        for (name, code) in modules {
+            assert_eq!(validate_interface(code, &DRAW_SHADER_INTERFACE), Ok(()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn mismatched_interface() {
+        let uniforms = ExpectedBinding { set: 0, binding: 0, descriptor_type: vk::DescriptorType::UNIFORM_BUFFER, descriptor_count: 1 };
+
+        let missing = ExpectedInterface { bindings: &[uniforms], push_constant_size: DRAW_SHADER_INTERFACE.push_constant_size };
+        match validate_interface(TEXTURED_FRAGMENT_BIN, &missing) {
+            Err(InterfaceError::Mismatch(mismatches)) => assert!(mismatches.contains(&InterfaceMismatch::MissingBinding { set: 0, binding: 1 })),
+            result => panic!("Expected missing binding but got {:?}", result),
+        }
+
+        let images = ExpectedBinding { set: 0, binding: 1, descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER, descriptor_count: 1 };
+        let too_small = ExpectedInterface { bindings: &[uniforms, images], push_constant_size: DRAW_SHADER_INTERFACE.push_constant_size };
+        match validate_interface(TEXTURED_FRAGMENT_BIN, &too_small) {
+            Err(InterfaceError::Mismatch(mismatches)) => assert!(mismatches.contains(&InterfaceMismatch::DescriptorCount { set: 0, binding: 1, expected: 1, found: 3 })),
+            result => panic!("Expected descriptor count mismatch but got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn descriptor_type_compatibility() {
+        assert!(is_descriptor_type_compatible(vk::DescriptorType::UNIFORM_BUFFER, vk::DescriptorType::UNIFORM_BUFFER));
+        assert!(is_descriptor_type_compatible(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, vk::DescriptorType::UNIFORM_BUFFER));
+        assert!(is_descriptor_type_compatible(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, vk::DescriptorType::STORAGE_BUFFER));
+        assert!(!is_descriptor_type_compatible(vk::DescriptorType::UNIFORM_BUFFER, vk::DescriptorType::STORAGE_BUFFER));
+        assert!(!is_descriptor_type_compatible(vk::DescriptorType::SAMPLED_IMAGE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER));
+    }
+
+    #[test]
+    fn invalid_spirv() {
+        let expected = ExpectedInterface { bindings: &[], push_constant_size: 0 };
+        assert_eq!(validate_interface(&[0u8; 3], &expected), Err(InterfaceError::InvalidSpirv));
+        assert_eq!(validate_interface(&[0u8; 20], &expected), Err(InterfaceError::InvalidSpirv));
+    }
+}
@@ -0,0 +1,212 @@
+//! Cpu side decompression of block compressed formats.
+//!
+//! Used as fallback if the device does not support a compressed format. The compressed data is
+//! decoded into 32-bit BGRA texels which every device is required to support.
+
+use ash::vk;
+
+use crate::prelude::*;
+use crate::util::format::Format;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TranscodeError {
+    /// The source format cannot be transcoded.
+    UnsupportedFormat,
+
+    /// The source data is too small for the specified extent and row stride.
+    InsufficientData,
+
+    /// The decoder failed to decode the data.
+    Decode(&'static str),
+}
+
+/// Returns the format that data of the specified compressed format is transcoded into or
+/// [`None`] if the format cannot be transcoded.
+pub fn get_transcode_target(format: &Format) -> Option<&'static Format> {
+    if get_decoder(format).is_none() {
+        return None;
+    }
+
+    if format.is_srgb() {
+        Some(&Format::B8G8R8A8_SRGB)
+    } else {
+        Some(&Format::B8G8R8A8_UNORM)
+    }
+}
+
+/// Returns the number of bytes needed to store a transcoded region of the specified extent.
+pub fn get_transcoded_size(extent: Vec2u32) -> usize {
+    (extent[0] as usize) * (extent[1] as usize) * 4
+}
+
+/// Decodes a region of compressed data into tightly packed 32-bit BGRA texels.
+///
+/// `row_stride` is the stride between 2 rows of blocks in texels. If 0 the data is assumed to be
+/// tightly packed. `dst` must be exactly [`get_transcoded_size`] bytes large.
+pub fn transcode_to_bgra8(format: &Format, data: &[u8], row_stride: u32, extent: Vec2u32, dst: &mut [u8]) -> Result<(), TranscodeError> {
+    assert_eq!(dst.len(), get_transcoded_size(extent));
+
+    let decoder = get_decoder(format).ok_or(TranscodeError::UnsupportedFormat)?;
+
+    let [block_width, block_height, _] = format.get_block_extent();
+    let block_size = format.get_texel_block_size() as usize;
+
+    let width = extent[0] as usize;
+    let height = extent[1] as usize;
+    if width == 0 || height == 0 {
+        return Ok(());
+    }
+
+    let blocks_x = (extent[0] + block_width - 1) / block_width;
+    let blocks_y = ((extent[1] + block_height - 1) / block_height) as usize;
+    let stride_blocks = if row_stride == 0 {
+        blocks_x
+    } else {
+        (row_stride + block_width - 1) / block_width
+    };
+
+    let row_size = blocks_x as usize * block_size;
+    let src_row_size = stride_blocks as usize * block_size;
+    if stride_blocks < blocks_x || data.len() < (blocks_y - 1) * src_row_size + row_size {
+        return Err(TranscodeError::InsufficientData);
+    }
+
+    // The decoders expect tightly packed blocks
+    let packed;
+    let src = if src_row_size == row_size {
+        &data[0..(blocks_y * row_size)]
+    } else {
+        let mut tmp = Vec::with_capacity(blocks_y * row_size);
+        for row in 0..blocks_y {
+            let start = row * src_row_size;
+            tmp.extend_from_slice(&data[start..(start + row_size)]);
+        }
+        packed = tmp;
+        packed.as_slice()
+    };
+
+    let mut texels = vec![0u32; width * height];
+    match decoder {
+        Decoder::Bc1 { opaque } => {
+            texture2ddecoder::decode_bc1(src, width, height, &mut texels).map_err(TranscodeError::Decode)?;
+            if opaque {
+                for texel in texels.iter_mut() {
+                    *texel |= 0xFF000000u32;
+                }
+            }
+        }
+        Decoder::Bc3 => texture2ddecoder::decode_bc3(src, width, height, &mut texels).map_err(TranscodeError::Decode)?,
+        Decoder::Bc7 => texture2ddecoder::decode_bc7(src, width, height, &mut texels).map_err(TranscodeError::Decode)?,
+        Decoder::Etc2Rgb => texture2ddecoder::decode_etc2_rgb(src, width, height, &mut texels).map_err(TranscodeError::Decode)?,
+        Decoder::Etc2Rgba1 => texture2ddecoder::decode_etc2_rgba1(src, width, height, &mut texels).map_err(TranscodeError::Decode)?,
+        Decoder::Etc2Rgba8 => texture2ddecoder::decode_etc2_rgba8(src, width, height, &mut texels).map_err(TranscodeError::Decode)?,
+        Decoder::Astc => texture2ddecoder::decode_astc(src, width, height, block_width as usize, block_height as usize, &mut texels).map_err(TranscodeError::Decode)?,
+    }
+
+    // The decoders produce BGRA texels packed into little endian u32s
+    for (texel, dst) in texels.iter().zip(dst.chunks_exact_mut(4)) {
+        dst.copy_from_slice(&texel.to_le_bytes());
+    }
+
+    Ok(())
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Decoder {
+    Bc1 { opaque: bool },
+    Bc3,
+    Bc7,
+    Etc2Rgb,
+    Etc2Rgba1,
+    Etc2Rgba8,
+    Astc,
+}
+
+fn get_decoder(format: &Format) -> Option<Decoder> {
+    match format.get_format() {
+        vk::Format::BC1_RGB_UNORM_BLOCK |
+        vk::Format::BC1_RGB_SRGB_BLOCK => Some(Decoder::Bc1 { opaque: true }),
+        vk::Format::BC1_RGBA_UNORM_BLOCK |
+        vk::Format::BC1_RGBA_SRGB_BLOCK => Some(Decoder::Bc1 { opaque: false }),
+        vk::Format::BC3_UNORM_BLOCK |
+        vk::Format::BC3_SRGB_BLOCK => Some(Decoder::Bc3),
+        vk::Format::BC7_UNORM_BLOCK |
+        vk::Format::BC7_SRGB_BLOCK => Some(Decoder::Bc7),
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK |
+        vk::Format::ETC2_R8G8B8_SRGB_BLOCK => Some(Decoder::Etc2Rgb),
+        vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK |
+        vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK => Some(Decoder::Etc2Rgba1),
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK |
+        vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => Some(Decoder::Etc2Rgba8),
+        vk::Format::ASTC_4X4_UNORM_BLOCK |
+        vk::Format::ASTC_4X4_SRGB_BLOCK |
+        vk::Format::ASTC_5X4_UNORM_BLOCK |
+        vk::Format::ASTC_5X4_SRGB_BLOCK |
+        vk::Format::ASTC_5X5_UNORM_BLOCK |
+        vk::Format::ASTC_5X5_SRGB_BLOCK |
+        vk::Format::ASTC_6X5_UNORM_BLOCK |
+        vk::Format::ASTC_6X5_SRGB_BLOCK |
+        vk::Format::ASTC_6X6_UNORM_BLOCK |
+        vk::Format::ASTC_6X6_SRGB_BLOCK |
+        vk::Format::ASTC_8X5_UNORM_BLOCK |
+        vk::Format::ASTC_8X5_SRGB_BLOCK |
+        vk::Format::ASTC_8X6_UNORM_BLOCK |
+        vk::Format::ASTC_8X6_SRGB_BLOCK |
+        vk::Format::ASTC_8X8_UNORM_BLOCK |
+        vk::Format::ASTC_8X8_SRGB_BLOCK |
+        vk::Format::ASTC_10X5_UNORM_BLOCK |
+        vk::Format::ASTC_10X5_SRGB_BLOCK |
+        vk::Format::ASTC_10X6_UNORM_BLOCK |
+        vk::Format::ASTC_10X6_SRGB_BLOCK |
+        vk::Format::ASTC_10X8_UNORM_BLOCK |
+        vk::Format::ASTC_10X8_SRGB_BLOCK |
+        vk::Format::ASTC_10X10_UNORM_BLOCK |
+        vk::Format::ASTC_10X10_SRGB_BLOCK |
+        vk::Format::ASTC_12X10_UNORM_BLOCK |
+        vk::Format::ASTC_12X10_SRGB_BLOCK |
+        vk::Format::ASTC_12X12_UNORM_BLOCK |
+        vk::Format::ASTC_12X12_SRGB_BLOCK => Some(Decoder::Astc),
+        // HDR ASTC data cannot be represented by 8-bit BGRA texels
+        vk::Format::ASTC_4X4_SFLOAT_BLOCK |
+        vk::Format::ASTC_5X4_SFLOAT_BLOCK |
+        vk::Format::ASTC_5X5_SFLOAT_BLOCK |
+        vk::Format::ASTC_6X5_SFLOAT_BLOCK |
+        vk::Format::ASTC_6X6_SFLOAT_BLOCK |
+        vk::Format::ASTC_8X5_SFLOAT_BLOCK |
+        vk::Format::ASTC_8X6_SFLOAT_BLOCK |
+        vk::Format::ASTC_8X8_SFLOAT_BLOCK |
+        vk::Format::ASTC_10X5_SFLOAT_BLOCK |
+        vk::Format::ASTC_10X6_SFLOAT_BLOCK |
+        vk::Format::ASTC_10X8_SFLOAT_BLOCK |
+        vk::Format::ASTC_10X10_SFLOAT_BLOCK |
+        vk::Format::ASTC_12X10_SFLOAT_BLOCK |
+        vk::Format::ASTC_12X12_SFLOAT_BLOCK => None,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::format::{ClearColorType, CompatibilityClass};
+
+    use super::*;
+
+    #[test]
+    fn transcode_targets() {
+        assert_eq!(get_transcode_target(&Format::BC7_SRGB_BLOCK), Some(&Format::B8G8R8A8_SRGB));
+        assert_eq!(get_transcode_target(&Format::ASTC_8X6_UNORM_BLOCK), Some(&Format::B8G8R8A8_UNORM));
+        assert_eq!(get_transcode_target(&Format::R8G8B8A8_UNORM), None);
+
+        let hdr = Format::new(vk::Format::ASTC_8X6_SFLOAT_BLOCK, CompatibilityClass::ASTC_8X6, 4, Some(ClearColorType::Float));
+        assert_eq!(get_transcode_target(&hdr), None);
+        let mut dst = vec![0u8; get_transcoded_size(Vec2u32::new(8, 6))];
+        assert_eq!(transcode_to_bgra8(&hdr, &[0u8; 16], 0, Vec2u32::new(8, 6), &mut dst), Err(TranscodeError::UnsupportedFormat));
+    }
+
+    #[test]
+    fn insufficient_data() {
+        let data = [0u8; 8];
+        let mut dst = vec![0u8; get_transcoded_size(Vec2u32::new(8, 4))];
+        assert_eq!(transcode_to_bgra8(&Format::BC1_RGB_UNORM_BLOCK, &data, 0, Vec2u32::new(8, 4), &mut dst), Err(TranscodeError::InsufficientData));
+    }
+}
@@ -1,3 +1,5 @@
+use std::ffi::{CStr, CString};
+
 use ash::vk;
 
 use crate::prelude::*;
@@ -20,4 +22,57 @@ pub fn make_full_rect(size: Vec2u32) -> vk::Rect2D {
         offset: vk::Offset2D{ x: 0, y: 0 },
         extent: vk::Extent2D{ width: size[0], height: size[1] }
     }
-}
\ No newline at end of file
+}
+
+/// The color of debug labels which do not specify a color. Capture tools ignore labels with all
+/// color components set to 0 so a neutral gray is used instead.
+pub const DEFAULT_LABEL_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+/// Opens a debug label region in a command buffer. The region may be closed in a different command
+/// buffer as long as both are submitted to the same queue in order. Does nothing if
+/// `VK_EXT_debug_utils` is not enabled.
+pub fn begin_label(device: &DeviceFunctions, command_buffer: vk::CommandBuffer, name: &CStr, color: [f32; 4]) {
+    if let Some(debug_utils) = device.instance.debug_utils_ext() {
+        let info = vk::DebugUtilsLabelEXT::builder()
+            .label_name(name)
+            .color(color);
+
+        unsafe {
+            debug_utils.cmd_begin_debug_utils_label(command_buffer, &info)
+        };
+    }
+}
+
+/// Closes the most recently opened debug label region. Does nothing if `VK_EXT_debug_utils` is not
+/// enabled.
+pub fn end_label(device: &DeviceFunctions, command_buffer: vk::CommandBuffer) {
+    if let Some(debug_utils) = device.instance.debug_utils_ext() {
+        unsafe {
+            debug_utils.cmd_end_debug_utils_label(command_buffer)
+        };
+    }
+}
+
+/// Sets the debug name of a vulkan object. Does nothing if `VK_EXT_debug_utils` is not enabled.
+pub fn set_object_name<H: vk::Handle>(device: &DeviceFunctions, handle: H, name: &str) {
+    if let Some(debug_utils) = device.instance.debug_utils_ext() {
+        let name = match CString::new(name) {
+            Ok(name) => name,
+            Err(_) => {
+                log::warn!("Object name {:?} contains a nul byte in set_object_name", name);
+                return;
+            }
+        };
+
+        let info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(H::TYPE)
+            .object_handle(handle.as_raw())
+            .object_name(&name);
+
+        if let Err(err) = unsafe {
+            debug_utils.debug_utils_set_object_name(device.vk.handle(), &info)
+        } {
+            log::warn!("vkSetDebugUtilsObjectNameEXT returned {:?} in set_object_name", err);
+        }
+    }
+}
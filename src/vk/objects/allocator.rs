@@ -1,6 +1,7 @@
 use std::ffi::c_void;
 use std::ptr::NonNull;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use ash::vk;
 use gpu_allocator::MemoryLocation;
@@ -10,6 +11,11 @@ use crate::prelude::DeviceFunctions;
 #[derive(Debug)]
 pub enum AllocationError {
     GpuAllocator(gpu_allocator::AllocationError),
+    /// Host visible image memory was requested for an image that does not use linear tiling.
+    IncompatibleTiling,
+    /// The memory requirements of the resource have a size of 0. Usually caused by creating a
+    /// zero sized buffer.
+    ZeroSize,
 }
 
 impl From<gpu_allocator::AllocationError> for AllocationError {
@@ -18,20 +24,74 @@ impl From<gpu_allocator::AllocationError> for AllocationError {
     }
 }
 
+/// The memory budget and usage of a single memory heap as reported by VK_EXT_memory_budget.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct HeapBudget {
+    pub heap_index: u32,
+
+    /// The estimated amount of memory the process can allocate from the heap before allocations
+    /// may fail or cause performance degradation.
+    pub budget: vk::DeviceSize,
+
+    /// The estimated amount of memory currently used by the process in the heap.
+    pub usage: vk::DeviceSize,
+}
+
+/// The number of live allocations and the total amount of memory allocated through an [`Allocator`].
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct AllocationStats {
+    pub buffer_allocations: u64,
+
+    /// Includes allocations of sparse image pages.
+    pub image_allocations: u64,
+
+    /// The total size of all live allocations in bytes. Does not include memory blocks reserved by
+    /// the allocator but not yet used by any allocation.
+    pub allocated_memory: vk::DeviceSize,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum AllocationKind {
+    Buffer,
+    Image,
+}
+
 pub enum AllocationStrategy {
     /// Automatically select memory that is only used by the gpu
     AutoGpuOnly,
 
     /// Automatically select memory that is used by both gpu and cpu
     AutoGpuCpu,
+
+    /// Automatically select memory that is written by the gpu and read back by the cpu
+    AutoGpuToCpu,
 }
 
+impl AllocationStrategy {
+    /// Returns true if memory allocated with this strategy is accessible by the cpu. Images using
+    /// such memory must be created with [`vk::ImageTiling::LINEAR`].
+    pub fn is_host_visible(&self) -> bool {
+        match self {
+            AllocationStrategy::AutoGpuOnly => false,
+            AllocationStrategy::AutoGpuCpu => true,
+            AllocationStrategy::AutoGpuToCpu => true,
+        }
+    }
+}
+
+/// Callback invoked when an allocation fails because the device is out of memory.
+pub type OomHandler = dyn Fn() + Send + Sync;
+
 /// Manages memory allocation for vulkan object
 ///
 /// Currently just uses the [`gpu_allocator::vulkan::Allocator`] struct.
 pub struct Allocator {
     device: Arc<DeviceFunctions>,
-    allocator: Mutex<gpu_allocator::vulkan::Allocator>
+    allocator: Mutex<gpu_allocator::vulkan::Allocator>,
+    oom_handler: Mutex<Option<Arc<OomHandler>>>,
+    buffer_allocations: AtomicU64,
+    image_allocations: AtomicU64,
+    allocated_memory: AtomicU64,
 }
 
 impl Allocator {
@@ -47,13 +107,34 @@ impl Allocator {
         Self {
             device,
             allocator: Mutex::new(allocator),
+            oom_handler: Mutex::new(None),
+            buffer_allocations: AtomicU64::new(0),
+            image_allocations: AtomicU64::new(0),
+            allocated_memory: AtomicU64::new(0),
         }
     }
 
+    /// Sets a handler which is invoked if an allocation fails because the device is out of memory.
+    /// The allocation is retried once after the handler returns. This can be used to free caches
+    /// before giving up. Passing [`None`] removes the current handler.
+    ///
+    /// The handler is called on the thread performing the allocation without holding any internal
+    /// locks so it may free allocations. However it must not allocate memory itself.
+    pub fn set_oom_handler(&self, handler: Option<Box<OomHandler>>) {
+        *self.oom_handler.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned oom handler mutex in Allocator::set_oom_handler!");
+            panic!()
+        }) = handler.map(Arc::from);
+    }
+
+    /// Allocates memory for a buffer.
+    ///
+    /// The memory requirements of the buffer must not have a size of 0.
     pub fn allocate_buffer_memory(&self, buffer: vk::Buffer, strategy: &AllocationStrategy) -> Result<Allocation, AllocationError> {
         let location = match strategy {
             AllocationStrategy::AutoGpuOnly => MemoryLocation::GpuOnly,
             AllocationStrategy::AutoGpuCpu => MemoryLocation::CpuToGpu,
+            AllocationStrategy::AutoGpuToCpu => MemoryLocation::GpuToCpu,
         };
 
         let requirements = unsafe {
@@ -67,15 +148,23 @@ impl Allocator {
             linear: true
         };
 
-        let alloc = self.allocator.lock().unwrap().allocate(&alloc_desc)?;
-
-        Ok(Allocation::new(alloc))
+        self.allocate(&alloc_desc, AllocationKind::Buffer)
     }
 
-    pub fn allocate_image_memory(&self, image: vk::Image, strategy: &AllocationStrategy) -> Result<Allocation, AllocationError> {
+    /// Allocates memory for a image. `tiling` must be the tiling the image was created with.
+    ///
+    /// Host visible memory can only be allocated for images using [`vk::ImageTiling::LINEAR`]. The
+    /// memory requirements of the image must not have a size of 0.
+    pub fn allocate_image_memory(&self, image: vk::Image, tiling: vk::ImageTiling, strategy: &AllocationStrategy) -> Result<Allocation, AllocationError> {
+        if strategy.is_host_visible() && tiling != vk::ImageTiling::LINEAR {
+            log::error!("Attempted to allocate host visible memory for image with tiling {:?}", tiling);
+            return Err(AllocationError::IncompatibleTiling);
+        }
+
         let location = match strategy {
             AllocationStrategy::AutoGpuOnly => MemoryLocation::GpuOnly,
             AllocationStrategy::AutoGpuCpu => MemoryLocation::CpuToGpu,
+            AllocationStrategy::AutoGpuToCpu => MemoryLocation::GpuToCpu,
         };
 
         let requirements = unsafe {
@@ -86,32 +175,136 @@ impl Allocator {
             name: "",
             requirements,
             location,
-            // If image is accessed by the cpu it has to be linear
-            linear: location == MemoryLocation::CpuToGpu,
+            linear: tiling == vk::ImageTiling::LINEAR,
         };
 
-        let alloc = self.allocator.lock().unwrap().allocate(&alloc_desc)?;
+        self.allocate(&alloc_desc, AllocationKind::Image)
+    }
+
+    /// Queries the current memory budget of all memory heaps. Returns [`None`] if
+    /// VK_EXT_memory_budget is not enabled.
+    ///
+    /// The values are estimates which may change at any time, for example if other processes
+    /// allocate memory.
+    pub fn memory_budget(&self) -> Option<Vec<HeapBudget>> {
+        if !self.device.memory_budget_ext {
+            return None;
+        }
+
+        let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut properties = vk::PhysicalDeviceMemoryProperties2::builder()
+            .push_next(&mut budget_properties);
+
+        unsafe {
+            self.device.instance.vk().get_physical_device_memory_properties2(self.device.physical_device, &mut properties)
+        };
 
-        Ok(Allocation::new(alloc))
+        let heap_count = properties.memory_properties.memory_heap_count as usize;
+        Some((0..heap_count).map(|index| HeapBudget {
+            heap_index: index as u32,
+            budget: budget_properties.heap_budget[index],
+            usage: budget_properties.heap_usage[index],
+        }).collect())
+    }
+
+    /// Allocates device local memory for a page of a sparse resource. The requirements must be the
+    /// size and alignment of a single sparse block and the memory type bits of the resource.
+    pub fn allocate_sparse_memory(&self, requirements: vk::MemoryRequirements) -> Result<Allocation, AllocationError> {
+        let alloc_desc = AllocationCreateDesc{
+            name: "",
+            requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: false,
+        };
+
+        self.allocate(&alloc_desc, AllocationKind::Image)
+    }
+
+    /// Returns the number of live allocations and the total allocated memory.
+    pub fn get_allocation_stats(&self) -> AllocationStats {
+        AllocationStats {
+            buffer_allocations: self.buffer_allocations.load(Ordering::Relaxed),
+            image_allocations: self.image_allocations.load(Ordering::Relaxed),
+            allocated_memory: self.allocated_memory.load(Ordering::Relaxed),
+        }
     }
 
     pub fn free(&self, allocation: Allocation) {
+        self.get_kind_counter(allocation.kind).fetch_sub(1, Ordering::Relaxed);
+        self.allocated_memory.fetch_sub(allocation.alloc.size(), Ordering::Relaxed);
+
         self.allocator.lock().unwrap().free(allocation.alloc).unwrap()
     }
+
+    fn get_kind_counter(&self, kind: AllocationKind) -> &AtomicU64 {
+        match kind {
+            AllocationKind::Buffer => &self.buffer_allocations,
+            AllocationKind::Image => &self.image_allocations,
+        }
+    }
+
+    /// Allocates memory retrying once after invoking the oom handler if the device is out of
+    /// memory.
+    fn allocate(&self, desc: &AllocationCreateDesc, kind: AllocationKind) -> Result<Allocation, AllocationError> {
+        if desc.requirements.size == 0 {
+            log::error!("Attempted to allocate memory with a size of 0");
+            return Err(AllocationError::ZeroSize);
+        }
+
+        let result = self.allocator.lock().unwrap().allocate(desc);
+        let alloc = match result {
+            Err(gpu_allocator::AllocationError::OutOfMemory) => {
+                // Cloned so the handler is called without holding the lock
+                let handler = self.oom_handler.lock().unwrap_or_else(|_| {
+                    log::error!("Poisoned oom handler mutex in Allocator::allocate!");
+                    panic!()
+                }).clone();
+
+                if let Some(handler) = handler {
+                    log::warn!("Allocation of {:?} bytes failed with out of memory. Invoking oom handler and retrying", desc.requirements.size);
+                    handler();
+                    self.allocator.lock().unwrap().allocate(desc)?
+                } else {
+                    return Err(gpu_allocator::AllocationError::OutOfMemory.into());
+                }
+            }
+            result => result?,
+        };
+
+        self.get_kind_counter(kind).fetch_add(1, Ordering::Relaxed);
+        self.allocated_memory.fetch_add(alloc.size(), Ordering::Relaxed);
+
+        Ok(Allocation::new(alloc, kind))
+    }
 }
 
 #[derive(Debug)]
 pub struct Allocation {
     alloc: gpu_allocator::vulkan::Allocation,
+    kind: AllocationKind,
+    tag: u64,
 }
 
 impl Allocation {
-    fn new(alloc: gpu_allocator::vulkan::Allocation) -> Self {
+    fn new(alloc: gpu_allocator::vulkan::Allocation, kind: AllocationKind) -> Self {
         Self {
             alloc,
+            kind,
+            tag: 0,
         }
     }
 
+    /// Sets an opaque user defined tag. The tag is never used by the allocator and is only carried
+    /// alongside the allocation to allow callers to associate it with their own objects.
+    pub fn set_tag(&mut self, tag: u64) {
+        self.tag = tag;
+    }
+
+    /// Returns the user defined tag of this allocation. Defaults to 0 if no tag has been set.
+    pub fn tag(&self) -> u64 {
+        self.tag
+    }
+
     pub fn mapped_ptr(&self) -> Option<std::ptr::NonNull<c_void>> {
         self.alloc.mapped_ptr()
     }
@@ -5,8 +5,9 @@ use ash::vk;
 use ash::vk::Handle;
 use crate::objects::id::{BufferId, ObjectId};
 
-use crate::vk::objects::allocator::MappedMemory;
+use crate::vk::objects::allocator::{Allocation, AllocationStrategy, MappedMemory};
 use crate::vk::objects::Format;
+use crate::vk::objects::types::SharingMode;
 
 use crate::prelude::*;
 
@@ -115,14 +116,187 @@ pub struct BufferRange {
 pub struct BufferDescription {
     pub size: u64,
     pub usage_flags: vk::BufferUsageFlags,
+    pub sharing_mode: SharingMode,
 }
 
 impl BufferDescription {
     pub fn new_simple(size: u64, usage_flags: vk::BufferUsageFlags) -> Self {
-        BufferDescription { size, usage_flags }
+        BufferDescription { size, usage_flags, sharing_mode: SharingMode::Exclusive }
+    }
+
+    pub fn builder() -> BufferDescriptionBuilder {
+        BufferDescriptionBuilder::new()
+    }
+
+    /// Validates that a range of this buffer can be bound as a descriptor of every descriptor
+    /// usage of the buffer.
+    ///
+    /// The range must lie within the buffer and its offset must be aligned to the minimum offset
+    /// alignment of every uniform, storage or texel buffer usage. Uniform and storage buffer
+    /// ranges must not exceed `maxUniformBufferRange` and `maxStorageBufferRange` respectively.
+    pub fn validate_range(&self, range: &BufferRange, limits: &vk::PhysicalDeviceLimits) -> Result<(), BufferDescriptionError> {
+        if range.length == 0 || range.offset.checked_add(range.length).map_or(true, |end| end > self.size) {
+            return Err(BufferDescriptionError::RangeOutOfBounds);
+        }
+
+        let is_aligned = |alignment: vk::DeviceSize| alignment == 0 || range.offset % alignment == 0;
+        if self.usage_flags.contains(vk::BufferUsageFlags::UNIFORM_BUFFER) {
+            if !is_aligned(limits.min_uniform_buffer_offset_alignment) {
+                return Err(BufferDescriptionError::UnalignedOffset);
+            }
+            if range.length > (limits.max_uniform_buffer_range as u64) {
+                return Err(BufferDescriptionError::UniformBufferRangeExceeded);
+            }
+        }
+        if self.usage_flags.contains(vk::BufferUsageFlags::STORAGE_BUFFER) {
+            if !is_aligned(limits.min_storage_buffer_offset_alignment) {
+                return Err(BufferDescriptionError::UnalignedOffset);
+            }
+            if range.length > (limits.max_storage_buffer_range as u64) {
+                return Err(BufferDescriptionError::StorageBufferRangeExceeded);
+            }
+        }
+        if self.usage_flags.intersects(vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER | vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER) && !is_aligned(limits.min_texel_buffer_offset_alignment) {
+            return Err(BufferDescriptionError::UnalignedOffset);
+        }
+
+        Ok(())
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BufferDescriptionError {
+    /// The buffer size is 0
+    InvalidSize,
+    /// The usage flags are empty
+    InvalidUsage,
+    /// The buffer is used as a uniform buffer but exceeds `maxUniformBufferRange`
+    UniformBufferRangeExceeded,
+    /// The buffer is used as a storage buffer but exceeds `maxStorageBufferRange`
+    StorageBufferRangeExceeded,
+    /// The range is empty or does not lie within the buffer
+    RangeOutOfBounds,
+    /// The range offset is not a multiple of the minimum offset alignment of a buffer usage
+    UnalignedOffset,
+}
+
+/// Builder for [`BufferDescription`]s.
+///
+/// The sharing mode defaults to [`SharingMode::Exclusive`].
+#[derive(Copy, Clone, Debug)]
+pub struct BufferDescriptionBuilder {
+    size: u64,
+    usage_flags: vk::BufferUsageFlags,
+    sharing_mode: SharingMode,
+}
+
+impl BufferDescriptionBuilder {
+    pub fn new() -> Self {
+        Self {
+            size: 0,
+            usage_flags: vk::BufferUsageFlags::empty(),
+            sharing_mode: SharingMode::Exclusive,
+        }
+    }
+
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn usage(mut self, usage_flags: vk::BufferUsageFlags) -> Self {
+        self.usage_flags = usage_flags;
+        self
+    }
+
+    pub fn sharing_mode(mut self, sharing_mode: SharingMode) -> Self {
+        self.sharing_mode = sharing_mode;
+        self
+    }
+
+    /// Validates the parameters and builds the [`BufferDescription`].
+    ///
+    /// This does not validate any device limits. Use [`BufferDescriptionBuilder::build_for_device`]
+    /// for that.
+    pub fn build(&self) -> Result<BufferDescription, BufferDescriptionError> {
+        if self.size == 0 {
+            return Err(BufferDescriptionError::InvalidSize);
+        }
+        if self.usage_flags.is_empty() {
+            return Err(BufferDescriptionError::InvalidUsage);
+        }
+
+        Ok(BufferDescription {
+            size: self.size,
+            usage_flags: self.usage_flags,
+            sharing_mode: self.sharing_mode,
+        })
+    }
+
+    /// Validates the parameters including the limits of a device and builds the [`BufferDescription`].
+    pub fn build_for_device(&self, device: &DeviceContext) -> Result<BufferDescription, BufferDescriptionError> {
+        self.build_for_limits(device.get_limits())
+    }
+
+    fn build_for_limits(&self, limits: &vk::PhysicalDeviceLimits) -> Result<BufferDescription, BufferDescriptionError> {
+        let description = self.build()?;
+
+        if self.usage_flags.contains(vk::BufferUsageFlags::UNIFORM_BUFFER) && self.size > (limits.max_uniform_buffer_range as u64) {
+            return Err(BufferDescriptionError::UniformBufferRangeExceeded);
+        }
+        if self.usage_flags.contains(vk::BufferUsageFlags::STORAGE_BUFFER) && self.size > (limits.max_storage_buffer_range as u64) {
+            return Err(BufferDescriptionError::StorageBufferRangeExceeded);
+        }
+
+        Ok(description)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BufferCreateError {
+    Vulkan(vk::Result),
+    Allocation,
+}
+
+impl From<vk::Result> for BufferCreateError {
+    fn from(err: vk::Result) -> Self {
+        BufferCreateError::Vulkan(err)
+    }
+}
+
+/// Creates a buffer from a description and allocates and binds memory for it.
+pub fn create_buffer(device: &DeviceContext, description: &BufferDescription, strategy: &AllocationStrategy) -> Result<(vk::Buffer, Allocation), BufferCreateError> {
+    let info = vk::BufferCreateInfo::builder()
+        .size(description.size)
+        .usage(description.usage_flags)
+        .sharing_mode(description.sharing_mode.get_vk_sharing_mode())
+        .queue_family_indices(description.sharing_mode.get_queue_families());
+
+    let buffer = unsafe {
+        device.vk().create_buffer(&info, None)
+    }.map_err(|err| {
+        log::error!("vkCreateBuffer returned {:?} in create_buffer", err);
+        err
+    })?;
+
+    let allocation = device.get_allocator().allocate_buffer_memory(buffer, strategy).map_err(|err| {
+        log::error!("Failed to allocate buffer memory in create_buffer {:?}", err);
+        unsafe { device.vk().destroy_buffer(buffer, None) };
+        BufferCreateError::Allocation
+    })?;
+
+    if let Err(err) = unsafe {
+        device.vk().bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+    } {
+        log::error!("vkBindBufferMemory returned {:?} in create_buffer", err);
+        unsafe { device.vk().destroy_buffer(buffer, None) };
+        device.get_allocator().free(allocation);
+        return Err(BufferCreateError::Vulkan(err));
+    }
+
+    Ok((buffer, allocation))
+}
+
 /// Contains a description for a vulkan buffer.
 ///
 /// This only contains static information relevant to vulkan (i.e. range or format, however not the
@@ -138,6 +312,75 @@ impl BufferViewDescription {
     pub fn new_simple(range: BufferRange, format: &'static Format) -> Self {
         Self { range, format }
     }
+
+    /// Validates that the format of this view supports the buffer features required by the usage
+    /// flags of the source buffer on some device.
+    ///
+    /// Only the [`vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER`] and
+    /// [`vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER`] usage flags are considered. If the format is
+    /// missing a feature the missing feature is returned as error.
+    pub fn validate_format_support(&self, device: &DeviceContext, usage_flags: vk::BufferUsageFlags) -> Result<(), vk::FormatFeatureFlags> {
+        let supported = device.get_format_properties(self.format).buffer_features;
+        let missing = get_missing_buffer_features(usage_flags, supported);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+}
+
+/// Returns the texel buffer features required by the usage flags which are not supported.
+fn get_missing_buffer_features(usage_flags: vk::BufferUsageFlags, supported: vk::FormatFeatureFlags) -> vk::FormatFeatureFlags {
+    let mut required = vk::FormatFeatureFlags::empty();
+    if usage_flags.contains(vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER) {
+        required |= vk::FormatFeatureFlags::UNIFORM_TEXEL_BUFFER;
+    }
+    if usage_flags.contains(vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER) {
+        required |= vk::FormatFeatureFlags::STORAGE_TEXEL_BUFFER;
+    }
+
+    required & !supported
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BufferViewCreateError {
+    Vulkan(vk::Result),
+    /// The format does not support the contained buffer features required by the usage flags of
+    /// the source buffer
+    UnsupportedFormat(vk::FormatFeatureFlags),
+    /// The range of the view is not a valid texel buffer range of the source buffer
+    InvalidRange(BufferDescriptionError),
+}
+
+impl From<vk::Result> for BufferViewCreateError {
+    fn from(err: vk::Result) -> Self {
+        BufferViewCreateError::Vulkan(err)
+    }
+}
+
+/// Creates a view of a buffer created from `buffer_description`.
+///
+/// The format support and the range of the view are validated before the view is created.
+pub fn create_buffer_view(device: &DeviceContext, buffer: vk::Buffer, buffer_description: &BufferDescription, description: &BufferViewDescription) -> Result<vk::BufferView, BufferViewCreateError> {
+    description.validate_format_support(device, buffer_description.usage_flags).map_err(|missing| {
+        log::error!("Format {:?} is missing buffer features {:?} in create_buffer_view", description.format, missing);
+        BufferViewCreateError::UnsupportedFormat(missing)
+    })?;
+    buffer_description.validate_range(&description.range, device.get_limits()).map_err(BufferViewCreateError::InvalidRange)?;
+
+    let info = vk::BufferViewCreateInfo::builder()
+        .buffer(buffer)
+        .format(description.format.get_format())
+        .offset(description.range.offset)
+        .range(description.range.length);
+
+    unsafe {
+        device.vk().create_buffer_view(&info, None)
+    }.map_err(|err| {
+        log::error!("vkCreateBufferView returned {:?} in create_buffer_view", err);
+        BufferViewCreateError::Vulkan(err)
+    })
 }
 
 pub struct BufferInstanceData {
@@ -182,4 +425,77 @@ impl BufferViewInstanceData {
     pub unsafe fn get_handle(&self) -> vk::BufferView {
         self.handle
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_limits() -> vk::PhysicalDeviceLimits {
+        vk::PhysicalDeviceLimits {
+            max_uniform_buffer_range: 16384,
+            max_storage_buffer_range: 1 << 27,
+            min_uniform_buffer_offset_alignment: 256,
+            min_storage_buffer_offset_alignment: 64,
+            min_texel_buffer_offset_alignment: 16,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn description_builder() {
+        let limits = make_limits();
+
+        assert_eq!(BufferDescription::builder().usage(vk::BufferUsageFlags::VERTEX_BUFFER).build(), Err(BufferDescriptionError::InvalidSize));
+        assert_eq!(BufferDescription::builder().size(64).build(), Err(BufferDescriptionError::InvalidUsage));
+
+        let description = BufferDescription::builder().size(64).usage(vk::BufferUsageFlags::VERTEX_BUFFER).build().unwrap();
+        assert_eq!(description, BufferDescription::new_simple(64, vk::BufferUsageFlags::VERTEX_BUFFER));
+
+        let uniform = BufferDescription::builder().size(16385).usage(vk::BufferUsageFlags::UNIFORM_BUFFER);
+        assert!(uniform.build().is_ok());
+        assert_eq!(uniform.build_for_limits(&limits), Err(BufferDescriptionError::UniformBufferRangeExceeded));
+        assert!(uniform.size(16384).build_for_limits(&limits).is_ok());
+
+        let storage = BufferDescription::builder().size((1 << 27) + 1).usage(vk::BufferUsageFlags::STORAGE_BUFFER);
+        assert_eq!(storage.build_for_limits(&limits), Err(BufferDescriptionError::StorageBufferRangeExceeded));
+    }
+
+    #[test]
+    fn range_validation() {
+        let limits = make_limits();
+        let range = |offset, length| BufferRange { offset, length };
+
+        let uniform = BufferDescription::new_simple(1 << 16, vk::BufferUsageFlags::UNIFORM_BUFFER);
+        assert_eq!(uniform.validate_range(&range(512, 256), &limits), Ok(()));
+        assert_eq!(uniform.validate_range(&range(64, 256), &limits), Err(BufferDescriptionError::UnalignedOffset));
+        assert_eq!(uniform.validate_range(&range(0, 16385), &limits), Err(BufferDescriptionError::UniformBufferRangeExceeded));
+        assert_eq!(uniform.validate_range(&range(0, 0), &limits), Err(BufferDescriptionError::RangeOutOfBounds));
+        assert_eq!(uniform.validate_range(&range((1 << 16) - 256, 512), &limits), Err(BufferDescriptionError::RangeOutOfBounds));
+        assert_eq!(uniform.validate_range(&range(u64::MAX, 2), &limits), Err(BufferDescriptionError::RangeOutOfBounds));
+
+        let storage = BufferDescription::new_simple(1 << 16, vk::BufferUsageFlags::STORAGE_BUFFER);
+        assert_eq!(storage.validate_range(&range(64, 1024), &limits), Ok(()));
+        assert_eq!(storage.validate_range(&range(32, 1024), &limits), Err(BufferDescriptionError::UnalignedOffset));
+
+        let texel = BufferDescription::new_simple(1 << 16, vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER);
+        assert_eq!(texel.validate_range(&range(16, 1024), &limits), Ok(()));
+        assert_eq!(texel.validate_range(&range(8, 1024), &limits), Err(BufferDescriptionError::UnalignedOffset));
+
+        // Unrestricted usages only require the range to be in bounds
+        let vertex = BufferDescription::new_simple(1 << 16, vk::BufferUsageFlags::VERTEX_BUFFER);
+        assert_eq!(vertex.validate_range(&range(3, 1024), &limits), Ok(()));
+    }
+
+    #[test]
+    fn buffer_view_format_support() {
+        let uniform_texel = vk::FormatFeatureFlags::UNIFORM_TEXEL_BUFFER;
+        let both = vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER | vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER;
+
+        assert!(get_missing_buffer_features(vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER, uniform_texel).is_empty());
+        assert_eq!(get_missing_buffer_features(both, uniform_texel), vk::FormatFeatureFlags::STORAGE_TEXEL_BUFFER);
+        assert_eq!(get_missing_buffer_features(both, vk::FormatFeatureFlags::empty()), vk::FormatFeatureFlags::UNIFORM_TEXEL_BUFFER | vk::FormatFeatureFlags::STORAGE_TEXEL_BUFFER);
+        // Usages which do not create texel buffer views do not require any feature
+        assert!(get_missing_buffer_features(vk::BufferUsageFlags::VERTEX_BUFFER, vk::FormatFeatureFlags::empty()).is_empty());
+    }
+}
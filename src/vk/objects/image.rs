@@ -1,11 +1,15 @@
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
 use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
 
 use ash::vk;
 use ash::vk::Handle;
 use crate::objects::id::{ImageId, ObjectId};
 use crate::vk::objects::Format;
+use crate::vk::objects::allocator::{Allocation, AllocationStrategy};
+use crate::vk::objects::types::SharingMode;
 
 use crate::prelude::*;
 
@@ -185,6 +189,77 @@ impl ImageSize {
     pub fn fill_extent_3d(&self, extent: &mut ash::vk::Extent3D) {
         *extent = self.as_extent_3d();
     }
+
+    /// Returns the extent of a mip level.
+    pub const fn get_mip_extent_3d(&self, mip_level: u32) -> ash::vk::Extent3D {
+        let extent = self.as_extent_3d();
+        ash::vk::Extent3D {
+            width: if (extent.width >> mip_level) > 1 { extent.width >> mip_level } else { 1 },
+            height: if (extent.height >> mip_level) > 1 { extent.height >> mip_level } else { 1 },
+            depth: if (extent.depth >> mip_level) > 1 { extent.depth >> mip_level } else { 1 },
+        }
+    }
+}
+
+/// Generates the copy regions needed to upload all mip levels and array layers of an image from
+/// a tightly packed staging buffer.
+///
+/// The data in the buffer must start at `buffer_offset` and contain each mip level in ascending
+/// order. Inside each mip level all array layers are stored consecutively. Rows and slices are
+/// padded to full texel blocks.
+///
+/// Returns the copy regions and the total number of bytes used in the buffer.
+///
+/// Combined depth stencil and multi-planar formats must be copied per aspect or plane and are not
+/// supported by this function.
+pub fn make_staged_upload_regions(format: &Format, size: &ImageSize, buffer_offset: vk::DeviceSize) -> (Vec<vk::BufferImageCopy>, vk::DeviceSize) {
+    let texel_block_size = format.get_texel_block_size() as vk::DeviceSize;
+    if texel_block_size == 0 {
+        log::error!("Called make_staged_upload_regions with multi-planar format {:?}", format);
+        panic!()
+    }
+
+    let aspect_mask = match format.get_compatibility_class().get_name() {
+        "D16" | "D24" | "D32" => vk::ImageAspectFlags::DEPTH,
+        "S8" => vk::ImageAspectFlags::STENCIL,
+        _ if format.is_depth_stencil() => {
+            log::error!("Called make_staged_upload_regions with combined depth stencil format {:?}", format);
+            panic!()
+        }
+        _ => vk::ImageAspectFlags::COLOR,
+    };
+
+    let block_extent = format.get_block_extent();
+    let array_layers = size.get_array_layers();
+
+    let mip_levels = size.get_mip_levels();
+    let mut regions = Vec::with_capacity(mip_levels as usize);
+    let mut current_offset = buffer_offset;
+    for mip_level in 0..mip_levels {
+        let extent = size.get_mip_extent_3d(mip_level);
+
+        let blocks_x = (extent.width + block_extent[0] - 1) / block_extent[0];
+        let blocks_y = (extent.height + block_extent[1] - 1) / block_extent[1];
+        let blocks_z = (extent.depth + block_extent[2] - 1) / block_extent[2];
+
+        regions.push(vk::BufferImageCopy {
+            buffer_offset: current_offset,
+            buffer_row_length: blocks_x * block_extent[0],
+            buffer_image_height: blocks_y * block_extent[1],
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask,
+                mip_level,
+                base_array_layer: 0,
+                layer_count: array_layers
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: extent
+        });
+
+        current_offset += (blocks_x as vk::DeviceSize) * (blocks_y as vk::DeviceSize) * (blocks_z as vk::DeviceSize) * (array_layers as vk::DeviceSize) * texel_block_size;
+    }
+
+    (regions, current_offset - buffer_offset)
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -270,11 +345,675 @@ impl From<ImageSubresourceRange> for ash::vk::ImageSubresourceRange {
 pub struct ImageDescription {
     pub spec: ImageSpec,
     pub usage_flags: vk::ImageUsageFlags,
+    pub tiling: vk::ImageTiling,
+    pub sharing_mode: SharingMode,
 }
 
 impl ImageDescription {
     pub fn new_simple(spec: ImageSpec, usage: vk::ImageUsageFlags) -> Self {
-        Self{ spec, usage_flags: usage }
+        Self{ spec, usage_flags: usage, tiling: vk::ImageTiling::OPTIMAL, sharing_mode: SharingMode::Exclusive }
+    }
+
+    pub fn builder() -> ImageDescriptionBuilder {
+        ImageDescriptionBuilder::new()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ImageDescriptionError {
+    /// No format was specified
+    MissingFormat,
+    /// No extent was specified
+    MissingExtent,
+    /// The mip level count is 0 or exceeds the number of mip levels possible for the extent
+    InvalidMipLevels,
+    /// The array layer count is 0 or array layers were requested for a 3D image
+    InvalidArrayLayers,
+    /// The sample count is not a single valid sample count or multisampling is not possible for
+    /// the image
+    InvalidSampleCount,
+    /// The tiling is not supported for the image
+    InvalidTiling,
+    /// The usage flags are empty or not compatible with the format
+    IncompatibleUsage,
+}
+
+/// Builder for [`ImageDescription`]s.
+///
+/// If not explicitly set the mip level and array layer counts are taken from the [`ImageSize`]. The
+/// sample count defaults to 1, the tiling to [`vk::ImageTiling::OPTIMAL`] and the sharing mode to
+/// [`SharingMode::Exclusive`].
+#[derive(Copy, Clone, Debug)]
+pub struct ImageDescriptionBuilder {
+    format: Option<&'static Format>,
+    size: Option<ImageSize>,
+    usage_flags: vk::ImageUsageFlags,
+    mip_levels: Option<u32>,
+    array_layers: Option<u32>,
+    sample_count: vk::SampleCountFlags,
+    tiling: vk::ImageTiling,
+    sharing_mode: SharingMode,
+}
+
+impl ImageDescriptionBuilder {
+    pub fn new() -> Self {
+        Self {
+            format: None,
+            size: None,
+            usage_flags: vk::ImageUsageFlags::empty(),
+            mip_levels: None,
+            array_layers: None,
+            sample_count: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            sharing_mode: SharingMode::Exclusive,
+        }
+    }
+
+    pub fn format(mut self, format: &'static Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn extent(mut self, size: ImageSize) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn usage(mut self, usage_flags: vk::ImageUsageFlags) -> Self {
+        self.usage_flags = usage_flags;
+        self
+    }
+
+    pub fn mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = Some(mip_levels);
+        self
+    }
+
+    pub fn array_layers(mut self, array_layers: u32) -> Self {
+        self.array_layers = Some(array_layers);
+        self
+    }
+
+    pub fn samples(mut self, sample_count: vk::SampleCountFlags) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub fn tiling(mut self, tiling: vk::ImageTiling) -> Self {
+        self.tiling = tiling;
+        self
+    }
+
+    pub fn sharing_mode(mut self, sharing_mode: SharingMode) -> Self {
+        self.sharing_mode = sharing_mode;
+        self
+    }
+
+    /// Validates the parameters and builds the [`ImageDescription`].
+    pub fn build(&self) -> Result<ImageDescription, ImageDescriptionError> {
+        let format = self.format.ok_or(ImageDescriptionError::MissingFormat)?;
+        let size = self.build_size()?;
+
+        let valid_samples = vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_2 | vk::SampleCountFlags::TYPE_4 | vk::SampleCountFlags::TYPE_8 |
+            vk::SampleCountFlags::TYPE_16 | vk::SampleCountFlags::TYPE_32 | vk::SampleCountFlags::TYPE_64;
+        if self.sample_count.as_raw().count_ones() != 1 || !valid_samples.contains(self.sample_count) {
+            return Err(ImageDescriptionError::InvalidSampleCount);
+        }
+        if self.sample_count != vk::SampleCountFlags::TYPE_1 {
+            if !matches!(size, ImageSize::Type2D { .. }) || size.get_mip_levels() != 1 || self.tiling != vk::ImageTiling::OPTIMAL || format.is_compressed() {
+                return Err(ImageDescriptionError::InvalidSampleCount);
+            }
+        }
+
+        if self.tiling == vk::ImageTiling::LINEAR && !supports_linear_tiling(&size, format) {
+            return Err(ImageDescriptionError::InvalidTiling);
+        }
+
+        if self.usage_flags.is_empty() {
+            return Err(ImageDescriptionError::IncompatibleUsage);
+        }
+        if format.is_compressed() && self.usage_flags.intersects(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+            return Err(ImageDescriptionError::IncompatibleUsage);
+        }
+        if format.is_depth_stencil() && self.usage_flags.intersects(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::COLOR_ATTACHMENT) {
+            return Err(ImageDescriptionError::IncompatibleUsage);
+        }
+        if !format.is_depth_stencil() && self.usage_flags.contains(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+            return Err(ImageDescriptionError::IncompatibleUsage);
+        }
+
+        Ok(ImageDescription {
+            spec: ImageSpec::new(size, format, self.sample_count),
+            usage_flags: self.usage_flags,
+            tiling: self.tiling,
+            sharing_mode: self.sharing_mode,
+        })
+    }
+
+    fn build_size(&self) -> Result<ImageSize, ImageDescriptionError> {
+        let size = self.size.ok_or(ImageDescriptionError::MissingExtent)?;
+        let mip_levels = self.mip_levels.unwrap_or(size.get_mip_levels());
+        let array_layers = self.array_layers.unwrap_or(size.get_array_layers());
+
+        let max_dim = std::cmp::max(size.get_width(), std::cmp::max(size.get_height(), size.get_depth()));
+        if max_dim == 0 {
+            return Err(ImageDescriptionError::MissingExtent);
+        }
+        let max_mip_levels = 32 - max_dim.leading_zeros();
+        if mip_levels == 0 || mip_levels > max_mip_levels {
+            return Err(ImageDescriptionError::InvalidMipLevels);
+        }
+        if array_layers == 0 {
+            return Err(ImageDescriptionError::InvalidArrayLayers);
+        }
+
+        match size {
+            ImageSize::Type1D { width, .. } => Ok(ImageSize::Type1D { width, mip_levels, array_layers }),
+            ImageSize::Type2D { width, height, .. } => Ok(ImageSize::Type2D { width, height, mip_levels, array_layers }),
+            ImageSize::Type3D { width, height, depth, .. } => {
+                if array_layers != 1 {
+                    return Err(ImageDescriptionError::InvalidArrayLayers);
+                }
+                Ok(ImageSize::Type3D { width, height, depth, mip_levels })
+            }
+        }
+    }
+}
+
+/// Returns true if the size and format are within the limits vulkan guarantees for linear tiling.
+fn supports_linear_tiling(size: &ImageSize, format: &Format) -> bool {
+    matches!(size, ImageSize::Type2D { .. }) && size.get_mip_levels() == 1 && size.get_array_layers() == 1 && !format.is_depth_stencil()
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ImageCreateError {
+    Vulkan(vk::Result),
+    Allocation,
+    /// The image must use linear tiling (for example because it is allocated in host visible
+    /// memory) but the description is not compatible with linear tiling.
+    InvalidTiling,
+    /// The format does not support the requested usage with the used tiling. Contains the first
+    /// missing format feature.
+    UnsupportedFormat(vk::FormatFeatureFlags),
+}
+
+impl From<vk::Result> for ImageCreateError {
+    fn from(err: vk::Result) -> Self {
+        ImageCreateError::Vulkan(err)
+    }
+}
+
+/// Creates a image from a description and allocates and binds memory for it.
+///
+/// Images allocated with a host visible [`AllocationStrategy`] are always created with
+/// [`vk::ImageTiling::LINEAR`] and in the [`vk::ImageLayout::PREINITIALIZED`] layout so the cpu can
+/// write to them directly. The format support is validated against the tiling actually used.
+pub fn create_image(device: &DeviceContext, description: &ImageDescription, strategy: &AllocationStrategy) -> Result<(vk::Image, Allocation), ImageCreateError> {
+    let spec = &description.spec;
+
+    let tiling = if strategy.is_host_visible() {
+        vk::ImageTiling::LINEAR
+    } else {
+        description.tiling
+    };
+    if tiling == vk::ImageTiling::LINEAR && (!supports_linear_tiling(&spec.size, spec.format) || spec.sample_count != vk::SampleCountFlags::TYPE_1) {
+        log::error!("Image description {:?} is not compatible with linear tiling in create_image", description);
+        return Err(ImageCreateError::InvalidTiling);
+    }
+
+    spec.format.check_support(device, description.usage_flags, tiling).map_err(|missing| {
+        log::error!("Format {:?} does not support usage {:?} with tiling {:?} in create_image. Missing feature {:?}", spec.format, description.usage_flags, tiling, missing);
+        ImageCreateError::UnsupportedFormat(missing)
+    })?;
+
+    let initial_layout = if strategy.is_host_visible() {
+        vk::ImageLayout::PREINITIALIZED
+    } else {
+        vk::ImageLayout::UNDEFINED
+    };
+
+    let info = vk::ImageCreateInfo::builder()
+        .image_type(spec.size.get_vulkan_type())
+        .format(spec.format.get_format())
+        .extent(spec.size.as_extent_3d())
+        .mip_levels(spec.size.get_mip_levels())
+        .array_layers(spec.size.get_array_layers())
+        .samples(spec.sample_count)
+        .tiling(tiling)
+        .usage(description.usage_flags)
+        .sharing_mode(description.sharing_mode.get_vk_sharing_mode())
+        .queue_family_indices(description.sharing_mode.get_queue_families())
+        .initial_layout(initial_layout);
+
+    let image = unsafe {
+        device.vk().create_image(&info, None)
+    }.map_err(|err| {
+        log::error!("vkCreateImage returned {:?} in create_image", err);
+        err
+    })?;
+
+    let allocation = device.get_allocator().allocate_image_memory(image, tiling, strategy).map_err(|err| {
+        log::error!("Failed to allocate image memory in create_image {:?}", err);
+        unsafe { device.vk().destroy_image(image, None) };
+        ImageCreateError::Allocation
+    })?;
+
+    if let Err(err) = unsafe {
+        device.vk().bind_image_memory(image, allocation.memory(), allocation.offset())
+    } {
+        log::error!("vkBindImageMemory returned {:?} in create_image", err);
+        unsafe { device.vk().destroy_image(image, None) };
+        device.get_allocator().free(allocation);
+        return Err(ImageCreateError::Vulkan(err));
+    }
+
+    Ok((image, allocation))
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SparseImageError {
+    /// The device does not support sparse residency for 2D images.
+    Unsupported,
+    /// The format does not support sparse residency or the requested usage.
+    UnsupportedFormat,
+    /// The region is outside of the image, not aligned to the page extent or inside the mip tail.
+    InvalidRegion,
+    Allocation,
+    Vulkan(vk::Result),
+}
+
+impl From<vk::Result> for SparseImageError {
+    fn from(err: vk::Result) -> Self {
+        SparseImageError::Vulkan(err)
+    }
+}
+
+/// The location of a single page of a [`SparseImage`]. The x and y coordinates are in pages not
+/// texels.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct SparsePage {
+    pub mip_level: u32,
+    pub array_layer: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// A 2D image using sparse residency. Memory is bound to individual pages on demand.
+///
+/// The mip tail (all mip levels smaller than a page) is always resident. All other mip levels are
+/// not resident after creation. Reading non resident regions returns undefined values.
+///
+/// Binding operations are submitted to the main queue and the calling thread waits until they have
+/// completed. Pages must not be unbound while they are accessed by any submitted work.
+pub struct SparseImage {
+    device: Arc<DeviceContext>,
+    image: vk::Image,
+    spec: ImageSpec,
+    page_extent: vk::Extent3D,
+    page_requirements: vk::MemoryRequirements,
+    mip_tail_first_lod: u32,
+    mip_tail_allocations: Vec<Allocation>,
+    pages: Mutex<HashMap<SparsePage, Allocation>>,
+}
+
+impl SparseImage {
+    /// Creates a new sparse 2D image. Requires [`DeviceContext::supports_sparse_residency_image_2d`].
+    pub fn new(device: Arc<DeviceContext>, size: ImageSize, format: &'static Format, usage: vk::ImageUsageFlags) -> Result<Self, SparseImageError> {
+        if !device.supports_sparse_residency_image_2d() {
+            log::error!("Attempted to create sparse image on device without sparse residency support");
+            return Err(SparseImageError::Unsupported);
+        }
+        if size.get_vulkan_type() != vk::ImageType::TYPE_2D {
+            log::error!("Sparse images must be 2D but got size {:?}", size);
+            return Err(SparseImageError::Unsupported);
+        }
+
+        let format_properties = unsafe {
+            device.get_instance().vk().get_physical_device_sparse_image_format_properties(
+                device.get_functions().physical_device,
+                format.get_format(),
+                vk::ImageType::TYPE_2D,
+                vk::SampleCountFlags::TYPE_1,
+                usage,
+                vk::ImageTiling::OPTIMAL
+            )
+        };
+        if format_properties.is_empty() || format.check_support(&device, usage, vk::ImageTiling::OPTIMAL).is_err() {
+            log::error!("Format {:?} does not support sparse residency with usage {:?}", format, usage);
+            return Err(SparseImageError::UnsupportedFormat);
+        }
+
+        let info = vk::ImageCreateInfo::builder()
+            .flags(vk::ImageCreateFlags::SPARSE_BINDING | vk::ImageCreateFlags::SPARSE_RESIDENCY)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format.get_format())
+            .extent(size.as_extent_3d())
+            .mip_levels(size.get_mip_levels())
+            .array_layers(size.get_array_layers())
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let image = unsafe {
+            device.vk().create_image(&info, None)
+        }.map_err(|err| {
+            log::error!("vkCreateImage returned {:?} in SparseImage::new", err);
+            err
+        })?;
+
+        let requirements = unsafe {
+            device.vk().get_image_memory_requirements(image)
+        };
+        let sparse_requirements = unsafe {
+            device.vk().get_image_sparse_memory_requirements(image)
+        };
+        let sparse_requirements = match sparse_requirements.iter().find(|req| req.format_properties.aspect_mask.contains(vk::ImageAspectFlags::COLOR)) {
+            Some(req) => *req,
+            None => {
+                log::error!("Sparse image of format {:?} has no color aspect memory requirements", format);
+                unsafe { device.vk().destroy_image(image, None) };
+                return Err(SparseImageError::UnsupportedFormat);
+            }
+        };
+
+        // Each page is backed by a single sparse block
+        let page_requirements = vk::MemoryRequirements {
+            size: requirements.alignment,
+            alignment: requirements.alignment,
+            memory_type_bits: requirements.memory_type_bits,
+        };
+
+        let mut sparse_image = Self {
+            device,
+            image,
+            spec: ImageSpec::new_single_sample(size, format),
+            page_extent: sparse_requirements.format_properties.image_granularity,
+            page_requirements,
+            mip_tail_first_lod: sparse_requirements.image_mip_tail_first_lod,
+            mip_tail_allocations: Vec::new(),
+            pages: Mutex::new(HashMap::new()),
+        };
+
+        if sparse_requirements.image_mip_tail_first_lod < size.get_mip_levels() {
+            sparse_image.bind_mip_tail(&sparse_requirements)?;
+        }
+
+        Ok(sparse_image)
+    }
+
+    pub fn get_handle(&self) -> vk::Image {
+        self.image
+    }
+
+    pub fn get_spec(&self) -> &ImageSpec {
+        &self.spec
+    }
+
+    /// Returns the size of a single page in texels.
+    pub fn get_page_extent(&self) -> Vec2u32 {
+        Vec2u32::new(self.page_extent.width, self.page_extent.height)
+    }
+
+    /// Returns the first mip level which is part of the always resident mip tail.
+    pub fn get_mip_tail_first_lod(&self) -> u32 {
+        self.mip_tail_first_lod
+    }
+
+    /// Makes all pages overlapping a region resident. The offset must be aligned to the page
+    /// extent. Pages which are already resident are left unchanged.
+    pub fn bind_pages(&self, mip_level: u32, array_layer: u32, offset: Vec2u32, extent: Vec2u32) -> Result<(), SparseImageError> {
+        let pages = self.get_region_pages(mip_level, array_layer, offset, extent)?;
+
+        let mut guard = self.pages.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned pages mutex in SparseImage::bind_pages!");
+            panic!()
+        });
+
+        let mut new_pages = Vec::new();
+        for page in pages {
+            if guard.contains_key(&page) {
+                continue;
+            }
+            match self.device.get_allocator().allocate_sparse_memory(self.page_requirements) {
+                Ok(allocation) => new_pages.push((page, allocation)),
+                Err(err) => {
+                    log::error!("Failed to allocate sparse page memory {:?}", err);
+                    for (_, allocation) in new_pages {
+                        self.device.get_allocator().free(allocation);
+                    }
+                    return Err(SparseImageError::Allocation);
+                }
+            }
+        }
+
+        let binds: Vec<_> = new_pages.iter().map(|(page, allocation)| {
+            self.make_page_bind(page, allocation.memory(), allocation.offset())
+        }).collect();
+
+        if let Err(err) = self.submit_binds(&binds) {
+            for (_, allocation) in new_pages {
+                self.device.get_allocator().free(allocation);
+            }
+            return Err(err);
+        }
+
+        guard.extend(new_pages);
+        Ok(())
+    }
+
+    /// Makes all pages overlapping a region non resident and frees their memory. The offset must be
+    /// aligned to the page extent.
+    ///
+    /// The pages must not be accessed by any submitted work which has not completed execution yet.
+    pub fn unbind_pages(&self, mip_level: u32, array_layer: u32, offset: Vec2u32, extent: Vec2u32) -> Result<(), SparseImageError> {
+        let pages = self.get_region_pages(mip_level, array_layer, offset, extent)?;
+
+        let mut guard = self.pages.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned pages mutex in SparseImage::unbind_pages!");
+            panic!()
+        });
+
+        let pages: Vec<_> = pages.into_iter().filter(|page| guard.contains_key(page)).collect();
+        let binds: Vec<_> = pages.iter().map(|page| {
+            self.make_page_bind(page, vk::DeviceMemory::null(), 0)
+        }).collect();
+
+        self.submit_binds(&binds)?;
+
+        for page in pages {
+            if let Some(allocation) = guard.remove(&page) {
+                self.device.get_allocator().free(allocation);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if all pages overlapping a region are resident. Regions inside the mip tail are
+    /// always resident.
+    pub fn is_resident(&self, mip_level: u32, array_layer: u32, offset: Vec2u32, extent: Vec2u32) -> bool {
+        if mip_level >= self.mip_tail_first_lod && mip_level < self.spec.size.get_mip_levels() {
+            return true;
+        }
+
+        let pages = match self.get_region_pages(mip_level, array_layer, offset, extent) {
+            Ok(pages) => pages,
+            Err(_) => return false,
+        };
+
+        let guard = self.pages.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned pages mutex in SparseImage::is_resident!");
+            panic!()
+        });
+        pages.iter().all(|page| guard.contains_key(page))
+    }
+
+    /// Returns a list of all currently resident pages excluding the mip tail.
+    pub fn get_resident_pages(&self) -> Vec<SparsePage> {
+        let guard = self.pages.lock().unwrap_or_else(|_| {
+            log::error!("Poisoned pages mutex in SparseImage::get_resident_pages!");
+            panic!()
+        });
+        let mut pages: Vec<_> = guard.keys().copied().collect();
+        pages.sort();
+        pages
+    }
+
+    /// Returns all pages overlapping a region validating that the region can be bound.
+    fn get_region_pages(&self, mip_level: u32, array_layer: u32, offset: Vec2u32, extent: Vec2u32) -> Result<Vec<SparsePage>, SparseImageError> {
+        let size = &self.spec.size;
+        if mip_level >= self.mip_tail_first_lod || mip_level >= size.get_mip_levels() || array_layer >= size.get_array_layers() {
+            return Err(SparseImageError::InvalidRegion);
+        }
+
+        let mip_extent = self.get_mip_extent(mip_level);
+        let page_width = self.page_extent.width;
+        let page_height = self.page_extent.height;
+        if offset[0] % page_width != 0 || offset[1] % page_height != 0
+            || (offset[0] as u64) + (extent[0] as u64) > (mip_extent[0] as u64)
+            || (offset[1] as u64) + (extent[1] as u64) > (mip_extent[1] as u64) {
+            return Err(SparseImageError::InvalidRegion);
+        }
+
+        let start_x = offset[0] / page_width;
+        let start_y = offset[1] / page_height;
+        let end_x = (offset[0] + extent[0] + page_width - 1) / page_width;
+        let end_y = (offset[1] + extent[1] + page_height - 1) / page_height;
+
+        let mut pages = Vec::with_capacity(((end_x - start_x) * (end_y - start_y)) as usize);
+        for y in start_y..end_y {
+            for x in start_x..end_x {
+                pages.push(SparsePage { mip_level, array_layer, x, y });
+            }
+        }
+        Ok(pages)
+    }
+
+    fn get_mip_extent(&self, mip_level: u32) -> Vec2u32 {
+        let extent = self.spec.size.get_mip_extent_3d(mip_level);
+        Vec2u32::new(extent.width, extent.height)
+    }
+
+    fn make_page_bind(&self, page: &SparsePage, memory: vk::DeviceMemory, memory_offset: vk::DeviceSize) -> vk::SparseImageMemoryBind {
+        let mip_extent = self.get_mip_extent(page.mip_level);
+        let x = page.x * self.page_extent.width;
+        let y = page.y * self.page_extent.height;
+
+        // Pages at the edge of the image may be partial
+        vk::SparseImageMemoryBind {
+            subresource: vk::ImageSubresource {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: page.mip_level,
+                array_layer: page.array_layer,
+            },
+            offset: vk::Offset3D { x: x as i32, y: y as i32, z: 0 },
+            extent: vk::Extent3D {
+                width: std::cmp::min(self.page_extent.width, mip_extent[0] - x),
+                height: std::cmp::min(self.page_extent.height, mip_extent[1] - y),
+                depth: 1,
+            },
+            memory,
+            memory_offset,
+            flags: vk::SparseMemoryBindFlags::empty(),
+        }
+    }
+
+    fn bind_mip_tail(&mut self, requirements: &vk::SparseImageMemoryRequirements) -> Result<(), SparseImageError> {
+        let tail_count = if requirements.format_properties.flags.contains(vk::SparseImageFormatFlags::SINGLE_MIPTAIL) {
+            1
+        } else {
+            self.spec.size.get_array_layers()
+        };
+
+        let tail_requirements = vk::MemoryRequirements {
+            size: requirements.image_mip_tail_size,
+            alignment: self.page_requirements.alignment,
+            memory_type_bits: self.page_requirements.memory_type_bits,
+        };
+
+        let mut binds = Vec::with_capacity(tail_count as usize);
+        for layer in 0..tail_count {
+            let allocation = self.device.get_allocator().allocate_sparse_memory(tail_requirements).map_err(|err| {
+                log::error!("Failed to allocate sparse mip tail memory {:?}", err);
+                SparseImageError::Allocation
+            })?;
+
+            binds.push(vk::SparseMemoryBind {
+                resource_offset: requirements.image_mip_tail_offset + (layer as vk::DeviceSize) * requirements.image_mip_tail_stride,
+                size: requirements.image_mip_tail_size,
+                memory: allocation.memory(),
+                memory_offset: allocation.offset(),
+                flags: vk::SparseMemoryBindFlags::empty(),
+            });
+            self.mip_tail_allocations.push(allocation);
+        }
+
+        let opaque_info = vk::SparseImageOpaqueMemoryBindInfo::builder()
+            .image(self.image)
+            .binds(&binds);
+
+        let info = vk::BindSparseInfo::builder()
+            .image_opaque_binds(std::slice::from_ref(&opaque_info));
+
+        self.submit_bind_info(&info)
+    }
+
+    fn submit_binds(&self, binds: &[vk::SparseImageMemoryBind]) -> Result<(), SparseImageError> {
+        if binds.is_empty() {
+            return Ok(());
+        }
+
+        let image_info = vk::SparseImageMemoryBindInfo::builder()
+            .image(self.image)
+            .binds(binds);
+
+        let info = vk::BindSparseInfo::builder()
+            .image_binds(std::slice::from_ref(&image_info));
+
+        self.submit_bind_info(&info)
+    }
+
+    /// Submits a bind operation to the main queue and waits for it to complete.
+    fn submit_bind_info(&self, info: &vk::BindSparseInfo) -> Result<(), SparseImageError> {
+        let fence = unsafe {
+            self.device.vk().create_fence(&vk::FenceCreateInfo::builder(), None)
+        }?;
+
+        let result = unsafe {
+            self.device.get_main_queue().bind_sparse(std::slice::from_ref(info), Some(fence))
+        }.and_then(|_| unsafe {
+            self.device.vk().wait_for_fences(std::slice::from_ref(&fence), true, u64::MAX)
+        });
+
+        unsafe {
+            self.device.vk().destroy_fence(fence, None);
+        }
+
+        result.map_err(|err| {
+            log::error!("Sparse bind failed with {:?} in SparseImage::submit_bind_info", err);
+            SparseImageError::Vulkan(err)
+        })
+    }
+}
+
+impl Drop for SparseImage {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.vk().destroy_image(self.image, None);
+        }
+
+        let allocator = self.device.get_allocator();
+        for allocation in self.mip_tail_allocations.drain(..) {
+            allocator.free(allocation);
+        }
+        let pages = self.pages.get_mut().unwrap_or_else(|_| {
+            log::error!("Poisoned pages mutex in SparseImage::drop!");
+            panic!()
+        });
+        for (_, allocation) in pages.drain() {
+            allocator.free(allocation);
+        }
     }
 }
 
@@ -366,4 +1105,69 @@ impl ImageViewInstanceData {
     pub unsafe fn get_handle(&self) -> vk::ImageView {
         self.handle
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn description_builder_defaults() {
+        let description = ImageDescription::builder()
+            .format(&Format::R8G8B8A8_UNORM)
+            .extent(ImageSize::make_2d(256, 128))
+            .usage(vk::ImageUsageFlags::SAMPLED)
+            .build()
+            .unwrap();
+
+        assert_eq!(description.spec.get_size(), ImageSize::make_2d(256, 128));
+        assert_eq!(description.spec.get_sample_count(), vk::SampleCountFlags::TYPE_1);
+        assert_eq!(description.tiling, vk::ImageTiling::OPTIMAL);
+        assert_eq!(description.sharing_mode, SharingMode::Exclusive);
+
+        let description = ImageDescription::builder()
+            .format(&Format::R8G8B8A8_UNORM)
+            .extent(ImageSize::make_2d(256, 128))
+            .mip_levels(9)
+            .usage(vk::ImageUsageFlags::SAMPLED)
+            .build()
+            .unwrap();
+
+        assert_eq!(description.spec.get_size(), ImageSize::make_2d_mip(256, 128, 9));
+    }
+
+    #[test]
+    fn staged_upload_regions() {
+        let (regions, size) = make_staged_upload_regions(&Format::R8G8B8A8_UNORM, &ImageSize::make_2d_array_mip(4, 2, 3, 3), 16);
+        assert_eq!(regions.len(), 3);
+        assert_eq!(size, (4 * 2 + 2 * 1 + 1 * 1) * 3 * 4);
+        assert_eq!(regions[0].buffer_offset, 16);
+        assert_eq!(regions[1].buffer_offset, 16 + 4 * 2 * 3 * 4);
+        assert_eq!(regions[2].buffer_offset, 16 + (4 * 2 + 2 * 1) * 3 * 4);
+        assert_eq!(regions[2].image_extent, vk::Extent3D { width: 1, height: 1, depth: 1 });
+        assert_eq!(regions[1].image_subresource.layer_count, 3);
+
+        let (regions, size) = make_staged_upload_regions(&Format::BC1_RGB_UNORM_BLOCK, &ImageSize::make_2d_mip(8, 8, 4), 0);
+        assert_eq!(size, (4 + 1 + 1 + 1) * 8);
+        assert_eq!(regions[1].buffer_row_length, 4);
+        assert_eq!(regions[2].buffer_row_length, 4);
+        assert_eq!(regions[2].image_extent, vk::Extent3D { width: 2, height: 2, depth: 1 });
+    }
+
+    #[test]
+    fn description_builder_validation() {
+        let base = ImageDescription::builder()
+            .format(&Format::R8G8B8A8_UNORM)
+            .extent(ImageSize::make_2d(256, 256))
+            .usage(vk::ImageUsageFlags::SAMPLED);
+
+        assert_eq!(ImageDescription::builder().extent(ImageSize::make_2d(1, 1)).build(), Err(ImageDescriptionError::MissingFormat));
+        assert_eq!(base.mip_levels(10).build(), Err(ImageDescriptionError::InvalidMipLevels));
+        assert_eq!(base.mip_levels(0).build(), Err(ImageDescriptionError::InvalidMipLevels));
+        assert_eq!(base.extent(ImageSize::make_3d(4, 4, 4)).array_layers(2).build(), Err(ImageDescriptionError::InvalidArrayLayers));
+        assert_eq!(base.samples(vk::SampleCountFlags::TYPE_4).mip_levels(2).build(), Err(ImageDescriptionError::InvalidSampleCount));
+        assert_eq!(base.format(&Format::BC1_RGB_UNORM_BLOCK).usage(vk::ImageUsageFlags::STORAGE).build(), Err(ImageDescriptionError::IncompatibleUsage));
+        assert_eq!(base.format(&Format::D32_SFLOAT).usage(vk::ImageUsageFlags::COLOR_ATTACHMENT).build(), Err(ImageDescriptionError::IncompatibleUsage));
+        assert!(base.samples(vk::SampleCountFlags::TYPE_4).usage(vk::ImageUsageFlags::COLOR_ATTACHMENT).build().is_ok());
+    }
+}
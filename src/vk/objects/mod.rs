@@ -1,13 +1,25 @@
+pub use buffer::BufferCreateError;
 pub use buffer::BufferDescription;
+pub use buffer::BufferDescriptionBuilder;
+pub use buffer::BufferDescriptionError;
 pub use buffer::BufferRange;
 pub use buffer::BufferSpec;
+pub use buffer::BufferViewCreateError;
 pub use buffer::BufferViewDescription;
 pub use crate::util::format::Format;
+pub use image::ImageCreateError;
 pub use image::ImageDescription;
+pub use image::ImageDescriptionBuilder;
+pub use image::ImageDescriptionError;
 pub use image::ImageSize;
 pub use image::ImageSpec;
 pub use image::ImageSubresourceRange;
 pub use image::ImageViewDescription;
+pub use image::SparseImage;
+pub use image::SparseImageError;
+pub use image::SparsePage;
+pub use types::QueueFamilySet;
+pub use types::SharingMode;
 
 pub mod image;
 pub mod buffer;
@@ -60,7 +60,7 @@ impl From<vk::Result> for SurfaceInitError {
 }
 
 pub trait SurfaceProvider: Send + Sync {
-    fn get_required_instance_extensions(&self) -> Vec<CString>;
+    fn get_required_instance_extensions(&self) -> Result<Vec<CString>, SurfaceInitError>;
 
     fn init(&mut self, entry: &ash::Entry, instance: &ash::Instance) -> Result<vk::SurfaceKHR, SurfaceInitError>;
 
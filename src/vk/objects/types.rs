@@ -3,6 +3,8 @@ use std::hash::{Hash, Hasher};
 use std::num::NonZeroU64;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+use ash::vk;
+
 /// An identifier for object sets
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ObjectSetId(NonZeroU64);
@@ -218,4 +220,110 @@ pub type SemaphoreId = ObjectId<{ ObjectType::SEMAPHORE }>;
 pub type EventId = ObjectId<{ ObjectType::EVENT }>;
 pub type FenceId = ObjectId<{ ObjectType::FENCE }>;
 pub type SurfaceId = ObjectId<{ ObjectType::SURFACE }>;
-pub type SwapchainId = ObjectId<{ ObjectType::SWAPCHAIN }>;
\ No newline at end of file
+pub type SwapchainId = ObjectId<{ ObjectType::SWAPCHAIN }>;
+
+/// The queue family sharing mode of a buffer or image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SharingMode {
+    /// The resource is owned by one queue family at a time. Using it on a different queue family
+    /// requires a queue family ownership transfer.
+    Exclusive,
+    /// The resource can be accessed by all listed queue families without ownership transfers at a
+    /// possible performance cost.
+    Concurrent(QueueFamilySet),
+}
+
+impl SharingMode {
+    /// Creates a concurrent sharing mode for the provided queue families. Duplicate families are
+    /// removed. If less than 2 unique families remain [`SharingMode::Exclusive`] is returned as
+    /// concurrent sharing would have no effect.
+    ///
+    /// # Panics
+    /// If more than [`QueueFamilySet::MAX_FAMILIES`] unique families are provided.
+    pub fn concurrent(families: &[u32]) -> Self {
+        QueueFamilySet::new(families).map_or(Self::Exclusive, Self::Concurrent)
+    }
+
+    pub fn get_vk_sharing_mode(&self) -> vk::SharingMode {
+        match self {
+            Self::Exclusive => vk::SharingMode::EXCLUSIVE,
+            Self::Concurrent(_) => vk::SharingMode::CONCURRENT,
+        }
+    }
+
+    /// Returns the queue families which may access the resource. This is empty for exclusive sharing.
+    pub fn get_queue_families(&self) -> &[u32] {
+        match self {
+            Self::Exclusive => &[],
+            Self::Concurrent(set) => set.get_families(),
+        }
+    }
+}
+
+impl Default for SharingMode {
+    fn default() -> Self {
+        Self::Exclusive
+    }
+}
+
+/// A small set of unique queue family indices used for concurrent sharing. The set always contains
+/// at least 2 families.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct QueueFamilySet {
+    families: [u32; Self::MAX_FAMILIES],
+    count: u8,
+}
+
+impl QueueFamilySet {
+    pub const MAX_FAMILIES: usize = 4;
+
+    /// Creates a new set from the provided families removing any duplicates. Returns [`None`] if
+    /// less than 2 unique families are provided.
+    ///
+    /// # Panics
+    /// If more than [`QueueFamilySet::MAX_FAMILIES`] unique families are provided.
+    pub fn new(families: &[u32]) -> Option<Self> {
+        let mut set = Self { families: [0; Self::MAX_FAMILIES], count: 0 };
+        for family in families {
+            if set.get_families().contains(family) {
+                continue;
+            }
+            if set.count as usize == Self::MAX_FAMILIES {
+                log::error!("Too many queue families passed to QueueFamilySet::new {:?}", families);
+                panic!();
+            }
+            set.families[set.count as usize] = *family;
+            set.count += 1;
+        }
+
+        if set.count < 2 {
+            None
+        } else {
+            Some(set)
+        }
+    }
+
+    pub fn get_families(&self) -> &[u32] {
+        &self.families[0..(self.count as usize)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_sharing_mode() {
+        assert_eq!(SharingMode::concurrent(&[]), SharingMode::Exclusive);
+        assert_eq!(SharingMode::concurrent(&[2, 2]), SharingMode::Exclusive);
+
+        let mode = SharingMode::concurrent(&[0, 2, 0, 1]);
+        assert_eq!(mode.get_vk_sharing_mode(), vk::SharingMode::CONCURRENT);
+        assert_eq!(mode.get_queue_families(), &[0, 2, 1]);
+        assert!(SharingMode::Exclusive.get_queue_families().is_empty());
+
+        assert!(QueueFamilySet::new(&[3]).is_none());
+        assert!(QueueFamilySet::new(&[3, 3, 3]).is_none());
+        assert_eq!(QueueFamilySet::new(&[3, 1, 3]).unwrap().get_families(), &[3, 1]);
+    }
+}
@@ -1,15 +1,73 @@
 use std::ffi::CString;
 use ash::{Entry, Instance, vk};
 use ash::vk::SurfaceKHR;
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use winit::dpi::LogicalSize;
 use winit::event_loop::EventLoop;
-use winit::window::WindowBuilder;
+use winit::monitor::{MonitorHandle, VideoMode};
+use winit::window::{Fullscreen, WindowBuilder};
 use crate::vk::objects::surface::{SurfaceInitError, SurfaceProvider};
 
+use crate::prelude::*;
+
+/// Information about a connected monitor.
+#[derive(Clone, Debug)]
+pub struct MonitorInfo {
+    pub name: Option<String>,
+
+    /// The native resolution of the monitor in physical pixels.
+    pub size: Vec2u32,
+
+    /// The highest refresh rate in Hz supported at the native resolution or [`None`] if the
+    /// platform does not report any video modes.
+    pub refresh_rate: Option<u16>,
+
+    pub handle: MonitorHandle,
+}
+
+impl MonitorInfo {
+    /// Returns the video mode with the highest refresh rate at the native resolution.
+    fn find_native_video_mode(&self) -> Option<VideoMode> {
+        self.handle.video_modes()
+            .filter(|mode| mode.size().width == self.size[0] && mode.size().height == self.size[1])
+            .max_by_key(|mode| (mode.refresh_rate(), mode.bit_depth()))
+    }
+}
+
+/// Returns a list of all monitors currently connected.
+pub fn enumerate_monitors<E>(event_loop: &EventLoop<E>) -> Vec<MonitorInfo> {
+    event_loop.available_monitors().map(|handle| {
+        let size = handle.size();
+        let mut info = MonitorInfo {
+            name: handle.name(),
+            size: Vec2u32::new(size.width, size.height),
+            refresh_rate: None,
+            handle
+        };
+        info.refresh_rate = info.find_native_video_mode().map(|mode| mode.refresh_rate());
+        info
+    }).collect()
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FullscreenMode {
+    /// A borderless window covering the whole monitor.
+    Borderless,
+
+    /// Exclusive full screen at the native resolution of the monitor. Falls back to
+    /// [`FullscreenMode::Borderless`] if the monitor does not report any matching video mode.
+    ///
+    /// Swapchains for the window should set
+    /// [`crate::device::surface::SwapchainConfig::full_screen_exclusive`] to allow the vulkan
+    /// implementation to bypass the compositor.
+    Exclusive,
+}
+
 pub struct WinitWindow {
+    // Must be declared before the window handle so that the surface is destroyed first
+    surface: Option<WindowSurface>,
     handle: winit::window::Window,
-    ash_surface: Option<ash::extensions::khr::Surface>,
-    khr_surface: Option<vk::SurfaceKHR>,
+    fullscreen_mode: Option<FullscreenMode>,
 }
 
 impl WinitWindow {
@@ -23,38 +81,160 @@ impl WinitWindow {
 
         Self {
             handle: window,
-            ash_surface: None,
-            khr_surface: None,
+            fullscreen_mode: None,
+            surface: None,
+        }
+    }
+
+    /// Creates a full screen window on the specified monitor.
+    pub fn new_fullscreen<E>(title: &str, monitor: &MonitorInfo, mode: FullscreenMode, event_loop: &EventLoop<E>) -> Self {
+        let (fullscreen, mode) = match mode {
+            FullscreenMode::Exclusive => match monitor.find_native_video_mode() {
+                Some(video_mode) => (Fullscreen::Exclusive(video_mode), FullscreenMode::Exclusive),
+                None => {
+                    log::warn!("Monitor {:?} does not report a native video mode. Falling back to borderless full screen", monitor.name);
+                    (Fullscreen::Borderless(Some(monitor.handle.clone())), FullscreenMode::Borderless)
+                }
+            },
+            FullscreenMode::Borderless => (Fullscreen::Borderless(Some(monitor.handle.clone())), FullscreenMode::Borderless),
+        };
+
+        let window = WindowBuilder::new()
+            .with_title(title)
+            .with_fullscreen(Some(fullscreen))
+            .build(&event_loop)
+            .unwrap();
+        window.set_visible(true);
+
+        Self {
+            handle: window,
+            fullscreen_mode: Some(mode),
+            surface: None,
         }
     }
+
+    /// Returns the full screen mode of the window or [`None`] if the window is not full screen.
+    pub fn get_fullscreen_mode(&self) -> Option<FullscreenMode> {
+        self.fullscreen_mode
+    }
 }
 
 impl SurfaceProvider for WinitWindow {
-    fn get_required_instance_extensions(&self) -> Vec<CString> {
-        ash_window::enumerate_required_extensions(&self.handle).unwrap().into_iter().map(|str| {
-            CString::from(str)
-        }).collect()
+    fn get_required_instance_extensions(&self) -> Result<Vec<CString>, SurfaceInitError> {
+        WindowSurface::get_required_instance_extensions(&self.handle)
     }
 
     fn init(&mut self, entry: &Entry, instance: &Instance) -> Result<SurfaceKHR, SurfaceInitError> {
-        let surface = unsafe { ash_window::create_surface(entry, instance, &self.handle, None)? };
+        let surface = unsafe { WindowSurface::new(entry, instance, &self.handle) }?;
+        let handle = surface.khr_surface;
+        self.surface = Some(surface);
 
-        self.khr_surface = Some(surface);
-        self.ash_surface = Some(ash::extensions::khr::Surface::new(entry, instance));
+        Ok(handle)
+    }
 
-        Ok(surface)
+    fn get_handle(&self) -> Option<SurfaceKHR> {
+        self.surface.as_ref().map(|surface| surface.khr_surface)
+    }
+}
+
+/// A surface provider for windows owned by some external host application.
+///
+/// The surface is created using the platform surface extension matching the raw window handle
+/// without going through glfw or winit.
+pub struct RawWindowSurfaceProvider {
+    handle: RawHandle,
+    surface: Option<WindowSurface>,
+}
+
+impl RawWindowSurfaceProvider {
+    /// Creates a new surface provider for a window.
+    ///
+    /// # Safety
+    /// The window must remain valid for the lifetime of the returned surface provider. See
+    /// [`RawWindowSurfaceProvider::from_raw_window_handle`] for the threading requirements.
+    pub unsafe fn from_raw_handle<W: HasRawWindowHandle>(window: &W) -> Self {
+        Self::from_raw_window_handle(window.raw_window_handle())
+    }
+
+    /// Creates a new surface provider for a raw window handle.
+    ///
+    /// # Safety
+    /// The window referenced by the handle must remain valid for the lifetime of the returned
+    /// surface provider. Since the provider is [`Send`] and [`Sync`] the platform must allow a
+    /// surface to be created for the window from whichever thread calls
+    /// [`SurfaceProvider::init`]. For example on Xlib the display connection must have been
+    /// initialized with `XInitThreads`.
+    pub unsafe fn from_raw_window_handle(handle: RawWindowHandle) -> Self {
+        Self {
+            handle: RawHandle(handle),
+            surface: None,
+        }
+    }
+}
+
+impl SurfaceProvider for RawWindowSurfaceProvider {
+    fn get_required_instance_extensions(&self) -> Result<Vec<CString>, SurfaceInitError> {
+        WindowSurface::get_required_instance_extensions(&self.handle)
+    }
+
+    fn init(&mut self, entry: &Entry, instance: &Instance) -> Result<SurfaceKHR, SurfaceInitError> {
+        let surface = unsafe { WindowSurface::new(entry, instance, &self.handle) }?;
+        let handle = surface.khr_surface;
+        self.surface = Some(surface);
+
+        Ok(handle)
     }
 
     fn get_handle(&self) -> Option<SurfaceKHR> {
-        self.khr_surface
+        self.surface.as_ref().map(|surface| surface.khr_surface)
     }
 }
 
-impl Drop for WinitWindow {
+// RawWindowHandle is not Send or Sync because it contains raw pointers. The provider only reads
+// the handle in get_required_instance_extensions, which only inspects the handle variant, and
+// passes it to the platform in init, which requires &mut self and as such can never run
+// concurrently. from_raw_window_handle requires the caller to guarantee that the window is valid
+// and that a surface may be created from any thread. Every other field is a vulkan handle or
+// function table which is only accessed through &mut self or on drop.
+unsafe impl Send for RawWindowSurfaceProvider {
+}
+unsafe impl Sync for RawWindowSurfaceProvider {
+}
+
+/// A vulkan surface created for a window. The surface is destroyed when this is dropped.
+struct WindowSurface {
+    ash_surface: ash::extensions::khr::Surface,
+    khr_surface: vk::SurfaceKHR,
+}
+
+impl WindowSurface {
+    fn get_required_instance_extensions<W: HasRawWindowHandle>(window: &W) -> Result<Vec<CString>, SurfaceInitError> {
+        let extensions = ash_window::enumerate_required_extensions(window)?;
+        Ok(extensions.into_iter().map(|str| CString::from(str)).collect())
+    }
+
+    /// # Safety
+    /// The window must remain valid until the returned surface is dropped.
+    unsafe fn new<W: HasRawWindowHandle>(entry: &Entry, instance: &Instance, window: &W) -> Result<Self, SurfaceInitError> {
+        let khr_surface = ash_window::create_surface(entry, instance, window, None)?;
+
+        Ok(Self {
+            ash_surface: ash::extensions::khr::Surface::new(entry, instance),
+            khr_surface,
+        })
+    }
+}
+
+impl Drop for WindowSurface {
     fn drop(&mut self) {
-        if let Some(surface) = self.khr_surface.take() {
-            let khr = self.ash_surface.take().unwrap();
-            unsafe { khr.destroy_surface(surface, None) };
-        }
+        unsafe { self.ash_surface.destroy_surface(self.khr_surface, None) };
     }
-}
\ No newline at end of file
+}
+
+struct RawHandle(RawWindowHandle);
+
+unsafe impl HasRawWindowHandle for RawHandle {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        self.0
+    }
+}